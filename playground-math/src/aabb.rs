@@ -0,0 +1,182 @@
+use cgmath::{Matrix4, Point3, Vector3};
+
+// Axis aligned bounding box. An empty box has its minimum above its maximum, so growing it by
+// a point or another box needs no special case.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::empty(), |aabb, point| aabb.grow(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(&self, point: Point3<f32>) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(point.x),
+                self.min.y.min(point.y),
+                self.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(point.x),
+                self.max.y.max(point.y),
+                self.max.z.max(point.z),
+            ),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        Point3::new(
+            (self.min.x + self.max.x) * 0.5,
+            (self.min.y + self.max.y) * 0.5,
+            (self.min.z + self.max.z) * 0.5,
+        )
+    }
+
+    // Half the size along each axis
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+            && point.z >= self.min.z
+            && point.z <= self.max.z
+    }
+
+    // The outward normal of the side closest to `point`, for points on or near the surface, like
+    // where a ray hit the box. Sides are compared relative to the box's size, so flat boxes work.
+    pub fn face_normal(&self, point: Point3<f32>) -> Vector3<f32> {
+        let offset = point - self.center();
+        let extents = self.extents();
+        let relative = [
+            offset.x / extents.x.max(f32::EPSILON),
+            offset.y / extents.y.max(f32::EPSILON),
+            offset.z / extents.z.max(f32::EPSILON),
+        ];
+        let axis = (0..3)
+            .max_by(|&a, &b| relative[a].abs().partial_cmp(&relative[b].abs()).unwrap())
+            .unwrap();
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = relative[axis].signum();
+        normal
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // The box around the transformed corners, which can be larger than the transformed box
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        use cgmath::Transform;
+
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points(
+            self.corners()
+                .iter()
+                .map(|corner| matrix.transform_point(*corner)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_points_encloses_all_of_them() {
+        let aabb = Aabb::from_points(vec![
+            Point3::new(1.0, -2.0, 0.5),
+            Point3::new(-1.0, 3.0, 0.0),
+            Point3::new(0.0, 0.0, 2.0),
+        ]);
+        assert_eq!(aabb.min, Point3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Point3::new(1.0, 3.0, 2.0));
+        assert_eq!(aabb.center(), Point3::new(0.0, 0.5, 1.0));
+        assert_eq!(aabb.extents(), Vector3::new(1.0, 2.5, 1.0));
+    }
+
+    #[test]
+    fn empty_box_grows_into_a_point() {
+        let empty = Aabb::empty();
+        assert!(empty.is_empty());
+
+        let point = Point3::new(1.0, 2.0, 3.0);
+        let aabb = empty.grow(point);
+        assert!(!aabb.is_empty());
+        assert!(aabb.contains(point));
+        assert_eq!(Aabb::from_points(Vec::new()), empty);
+    }
+
+    #[test]
+    fn union_and_contains() {
+        let a = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let b = Aabb::new(Point3::new(2.0, -1.0, 0.0), Point3::new(3.0, 0.0, 1.0));
+        let union = a.union(&b);
+        assert_eq!(union.min, Point3::new(0.0, -1.0, 0.0));
+        assert_eq!(union.max, Point3::new(3.0, 1.0, 1.0));
+        assert!(union.contains(Point3::new(1.5, 0.0, 0.5)));
+        assert!(!a.contains(Point3::new(1.5, 0.0, 0.5)));
+    }
+
+    #[test]
+    fn transform_moves_the_corners() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let moved = aabb.transform(&Matrix4::from_translation(Vector3::new(5.0, 0.0, 0.0)));
+        assert_eq!(moved.min, Point3::new(4.0, -1.0, -1.0));
+        assert_eq!(moved.max, Point3::new(6.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn face_normal_points_out_of_the_closest_side() {
+        let aabb = Aabb::new(Point3::new(-4.0, -0.5, -4.0), Point3::new(4.0, 0.5, 4.0));
+        // Further along x than y, but the box is much wider than it is high
+        assert_eq!(
+            aabb.face_normal(Point3::new(3.0, 0.5, 0.0)),
+            Vector3::new(0.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            aabb.face_normal(Point3::new(0.0, 0.0, -4.0)),
+            Vector3::new(0.0, 0.0, -1.0)
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::transform::Transform;
+use cgmath::{ElementWise, InnerSpace, One, Quaternion, Vector3};
+
+pub trait Interpolate: Copy {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self;
+}
+
+impl Interpolate for Vector3<f32> {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+}
+
+// Normalized lerp along the shorter way around, close enough to slerp between nearby keys
+impl Interpolate for Quaternion<f32> {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        let b = if a.dot(b) < 0.0 { -b } else { b };
+        (a * (1.0 - t) + b * t).normalize()
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(a: Self, b: Self, t: f32) -> Self {
+        Self {
+            translation: Vector3::interpolate(a.translation, b.translation, t),
+            rotation: Quaternion::interpolate(a.rotation, b.rotation, t),
+            scale: Vector3::interpolate(a.scale, b.scale, t),
+        }
+    }
+}
+
+// Values at increasing times. Sampling between two keys interpolates, before the first or after
+// the last holds the first or last value.
+#[derive(Clone, Debug)]
+pub struct Keyframes<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+}
+
+impl<T: Interpolate> Keyframes<T> {
+    pub fn new(keys: Vec<(f32, T)>) -> Self {
+        let (times, values) = keys.into_iter().unzip();
+        Self { times, values }
+    }
+
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let last = self.times.len().checked_sub(1)?;
+        let next = self.times.iter().position(|&key| key > time);
+        Some(match next {
+            Some(0) => self.values[0],
+            None => self.values[last],
+            Some(next) => {
+                let (start, end) = (self.times[next - 1], self.times[next]);
+                let t = (time - start) / (end - start);
+                T::interpolate(self.values[next - 1], self.values[next], t)
+            }
+        })
+    }
+}
+
+impl Keyframes<Vector3<f32>> {
+    // Like `sample`, but along a Catmull-Rom spline through the keys, so the direction doesn't
+    // change abruptly at every key. The tangents are per second, which keeps the speed smooth
+    // across keys that aren't evenly spaced in time.
+    pub fn sample_smooth(&self, time: f32) -> Option<Vector3<f32>> {
+        let last = self.times.len().checked_sub(1)?;
+        let next = match self.times.iter().position(|&key| key > time) {
+            Some(0) => return Some(self.values[0]),
+            None => return Some(self.values[last]),
+            Some(next) => next,
+        };
+
+        // At the ends the key itself stands in for the missing neighbour
+        let tangent = |key: usize| {
+            let (before, after) = (key.saturating_sub(1), (key + 1).min(last));
+            (self.values[after] - self.values[before]) / (self.times[after] - self.times[before])
+        };
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let duration = end - start;
+        let t = (time - start) / duration;
+
+        // Cubic Hermite basis
+        let t2 = t * t;
+        let t3 = t2 * t;
+        Some(
+            self.values[next - 1] * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + tangent(next - 1) * duration * (t3 - 2.0 * t2 + t)
+                + self.values[next] * (-2.0 * t3 + 3.0 * t2)
+                + tangent(next) * duration * (t3 - t2),
+        )
+    }
+}
+
+// What a clip does to one joint, the parts without keyframes keep whatever the pose had
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint: usize,
+    pub translation: Option<Keyframes<Vector3<f32>>>,
+    pub rotation: Option<Keyframes<Quaternion<f32>>>,
+    pub scale: Option<Keyframes<Vector3<f32>>>,
+}
+
+impl Channel {
+    pub fn new(joint: usize) -> Self {
+        Self {
+            joint,
+            translation: None,
+            rotation: None,
+            scale: None,
+        }
+    }
+}
+
+fn sample<T: Interpolate>(keys: &Option<Keyframes<T>>, time: f32) -> Option<T> {
+    keys.as_ref().and_then(|keys| keys.sample(time))
+}
+
+// An animation over a skeleton's joints, looping every `duration` seconds
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl Clip {
+    // Writes the clip at `time` into the animated joints of `pose`
+    pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+        let time = wrap(time, self.duration);
+        for channel in &self.channels {
+            let local = &mut pose[channel.joint];
+            if let Some(translation) = sample(&channel.translation, time) {
+                local.translation = translation;
+            }
+            if let Some(rotation) = sample(&channel.rotation, time) {
+                local.rotation = rotation;
+            }
+            if let Some(scale) = sample(&channel.scale, time) {
+                local.scale = scale;
+            }
+        }
+    }
+}
+
+fn wrap(time: f32, duration: f32) -> f32 {
+    if duration > 0.0 {
+        time.rem_euclid(duration)
+    } else {
+        0.0
+    }
+}
+
+fn joint_weight(weight: f32, mask: Option<&[f32]>, joint: usize) -> f32 {
+    weight * mask.map_or(1.0, |mask| mask[joint])
+}
+
+// Moves every joint of `pose` towards `target` by `weight`. A mask scales the weight per joint,
+// so a layer can be limited to part of the skeleton.
+pub fn blend(pose: &mut [Transform], target: &[Transform], weight: f32, mask: Option<&[f32]>) {
+    for (joint, (local, target)) in pose.iter_mut().zip(target).enumerate() {
+        *local = Transform::interpolate(*local, *target, joint_weight(weight, mask, joint));
+    }
+}
+
+// Puts how far `additive` is from `reference` on top of `pose`, for clips like leaning that only
+// make sense on top of another one
+pub fn add(
+    pose: &mut [Transform],
+    additive: &[Transform],
+    reference: &[Transform],
+    weight: f32,
+    mask: Option<&[f32]>,
+) {
+    for (joint, local) in pose.iter_mut().enumerate() {
+        let weight = joint_weight(weight, mask, joint);
+        let (additive, reference) = (additive[joint], reference[joint]);
+        local.translation += (additive.translation - reference.translation) * weight;
+        // Joint rotations are unit quaternions, the conjugate is the inverse
+        let rotation = reference.rotation.conjugate() * additive.rotation;
+        local.rotation =
+            local.rotation * Quaternion::interpolate(Quaternion::one(), rotation, weight);
+        let scale = additive.scale.div_element_wise(reference.scale);
+        local.scale = local.scale.mul_element_wise(Vector3::interpolate(
+            Vector3::new(1.0, 1.0, 1.0),
+            scale,
+            weight,
+        ));
+    }
+}
+
+// A clip by its index into the list the player is given, and how far into it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Playback {
+    pub clip: usize,
+    pub time: f32,
+}
+
+// Plays one clip at a time, crossfading from the one before for a while after switching
+#[derive(Clone, Debug)]
+pub struct Player {
+    pub current: Playback,
+    pub speed: f32,
+    // The clip fading out
+    previous: Option<Playback>,
+    fade_time: f32,
+    fade_duration: f32,
+    sync: bool,
+}
+
+impl Player {
+    pub fn new(clip: usize) -> Self {
+        Self {
+            current: Playback { clip, time: 0.0 },
+            speed: 1.0,
+            previous: None,
+            fade_time: 0.0,
+            fade_duration: 0.0,
+            sync: false,
+        }
+    }
+
+    // Fades over to `clip` in `fade` seconds. `sync` keeps both clips equally far into their
+    // loops, so gaits of different lengths stay in step while they fade.
+    pub fn play(&mut self, clips: &[Clip], clip: usize, fade: f32, sync: bool) {
+        if clip == self.current.clip {
+            return;
+        }
+
+        let time = if sync {
+            self.phase(clips) * clips[clip].duration
+        } else {
+            0.0
+        };
+        self.previous = if fade > 0.0 { Some(self.current) } else { None };
+        self.current = Playback { clip, time };
+        self.fade_time = 0.0;
+        self.fade_duration = fade;
+        self.sync = sync;
+    }
+
+    // How far into its loop the current clip is, from 0 to 1
+    pub fn phase(&self, clips: &[Clip]) -> f32 {
+        let duration = clips[self.current.clip].duration;
+        if duration > 0.0 {
+            self.current.time / duration
+        } else {
+            0.0
+        }
+    }
+
+    pub fn advance(&mut self, clips: &[Clip], dt: f32) {
+        let dt = dt * self.speed;
+        self.current.time = wrap(self.current.time + dt, clips[self.current.clip].duration);
+        let phase = self.phase(clips);
+        if let Some(previous) = &mut self.previous {
+            let duration = clips[previous.clip].duration;
+            previous.time = if self.sync {
+                phase * duration
+            } else {
+                wrap(previous.time + dt, duration)
+            };
+            self.fade_time += dt.abs();
+            if self.fade_time >= self.fade_duration {
+                self.previous = None;
+            }
+        }
+    }
+
+    // How much of the current clip is in the pose, the rest comes from the one fading out
+    pub fn weight(&self) -> f32 {
+        match self.previous {
+            Some(_) => (self.fade_time / self.fade_duration).min(1.0),
+            None => 1.0,
+        }
+    }
+
+    // Writes the blended clips into `pose`, which should start out in the rest pose
+    pub fn sample(&self, clips: &[Clip], pose: &mut [Transform]) {
+        match self.previous {
+            Some(previous) => {
+                let mut current = pose.to_vec();
+                clips[self.current.clip].sample(self.current.time, &mut current);
+                clips[previous.clip].sample(previous.time, pose);
+                blend(pose, &current, self.weight(), None);
+            }
+            None => clips[self.current.clip].sample(self.current.time, pose),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Rotation3};
+
+    #[test]
+    fn keyframes_interpolate_and_hold_at_the_ends() {
+        let keys = Keyframes::new(vec![
+            (1.0, Vector3::new(0.0, 0.0, 0.0)),
+            (2.0, Vector3::new(4.0, 0.0, 0.0)),
+        ]);
+        assert_eq!(keys.sample(0.0), Some(Vector3::new(0.0, 0.0, 0.0)));
+        assert_eq!(keys.sample(1.25), Some(Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(keys.sample(3.0), Some(Vector3::new(4.0, 0.0, 0.0)));
+        assert_eq!(Keyframes::<Vector3<f32>>::new(Vec::new()).sample(1.0), None);
+    }
+
+    #[test]
+    fn smooth_keyframes_pass_through_every_key() {
+        let keys = Keyframes::new(vec![
+            (0.0, Vector3::new(0.0, 0.0, 0.0)),
+            (1.0, Vector3::new(1.0, 1.0, 0.0)),
+            (3.0, Vector3::new(2.0, 0.0, 0.0)),
+        ]);
+        for (&time, &value) in keys.times.iter().zip(&keys.values) {
+            assert!((keys.sample_smooth(time).unwrap() - value).magnitude() < 1e-5);
+        }
+        assert_eq!(keys.sample_smooth(5.0), Some(Vector3::new(2.0, 0.0, 0.0)));
+
+        // Evenly spaced keys on a line are walked at a constant speed
+        let line = Keyframes::new(vec![
+            (0.0, Vector3::new(0.0, 0.0, 0.0)),
+            (1.0, Vector3::new(1.0, 0.0, 0.0)),
+            (2.0, Vector3::new(2.0, 0.0, 0.0)),
+        ]);
+        let halfway = line.sample_smooth(1.5).unwrap();
+        assert!((halfway - Vector3::new(1.5, 0.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn rotations_take_the_short_way() {
+        let a = Quaternion::from_angle_y(Deg(10.0));
+        // The same rotation as 30 degrees, with every component negated
+        let b = -Quaternion::from_angle_y(Deg(30.0));
+        let halfway = Quaternion::interpolate(a, b, 0.5);
+        let expected = Quaternion::from_angle_y(Deg(20.0));
+        assert!(halfway.dot(expected).abs() > 0.9999);
+    }
+
+    #[test]
+    fn clips_loop_and_leave_other_joints_alone() {
+        let mut channel = Channel::new(1);
+        channel.translation = Some(Keyframes::new(vec![
+            (0.0, Vector3::new(0.0, 0.0, 0.0)),
+            (2.0, Vector3::new(2.0, 0.0, 0.0)),
+        ]));
+        let clip = Clip {
+            name: "slide".to_string(),
+            duration: 2.0,
+            channels: vec![channel],
+        };
+
+        let mut pose = vec![Transform::from_translation(Vector3::new(5.0, 0.0, 0.0)); 2];
+        clip.sample(3.0, &mut pose);
+        assert_eq!(pose[0].translation, Vector3::new(5.0, 0.0, 0.0));
+        assert_eq!(pose[1].translation, Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    fn slide(duration: f32) -> Clip {
+        let mut channel = Channel::new(0);
+        channel.translation = Some(Keyframes::new(vec![
+            (0.0, Vector3::new(0.0, 0.0, 0.0)),
+            (duration, Vector3::new(duration, 0.0, 0.0)),
+        ]));
+        Clip {
+            name: "slide".to_string(),
+            duration,
+            channels: vec![channel],
+        }
+    }
+
+    #[test]
+    fn masks_limit_blends_to_some_joints() {
+        let mut pose = vec![Transform::identity(); 2];
+        let target = vec![Transform::from_translation(Vector3::new(2.0, 0.0, 0.0)); 2];
+        blend(&mut pose, &target, 0.5, Some(&[0.0, 1.0]));
+        assert_eq!(pose[0].translation, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(pose[1].translation, Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn additive_poses_add_their_difference_to_the_reference() {
+        let reference = vec![Transform::from_translation(Vector3::new(1.0, 0.0, 0.0))];
+        let mut additive = reference.clone();
+        additive[0].translation.y = 2.0;
+        additive[0].rotation = Quaternion::from_angle_z(Deg(20.0));
+
+        let mut pose = vec![Transform::from_translation(Vector3::new(5.0, 0.0, 0.0))];
+        pose[0].rotation = Quaternion::from_angle_z(Deg(30.0));
+        add(&mut pose, &additive, &reference, 1.0, None);
+        assert!((pose[0].translation - Vector3::new(5.0, 2.0, 0.0)).magnitude() < 1e-5);
+        assert!(pose[0].rotation.dot(Quaternion::from_angle_z(Deg(50.0))) > 0.9999);
+    }
+
+    #[test]
+    fn players_crossfade_in_step() {
+        let clips = vec![slide(1.0), slide(2.0)];
+        let mut player = Player::new(0);
+        player.advance(&clips, 0.25);
+        player.play(&clips, 1, 0.5, true);
+        // A quarter into the first clip is a quarter into the second
+        assert_eq!(player.current.time, 0.5);
+        assert_eq!(player.weight(), 0.0);
+
+        player.advance(&clips, 0.25);
+        assert_eq!(player.weight(), 0.5);
+        let mut pose = vec![Transform::identity()];
+        player.sample(&clips, &mut pose);
+        // Halfway between 0.375 and 0.75
+        assert!((pose[0].translation.x - 0.5625).abs() < 1e-5);
+
+        player.advance(&clips, 0.25);
+        assert_eq!(player.weight(), 1.0);
+    }
+}
@@ -0,0 +1,398 @@
+// The matrix work behind cameras, transforms and skinning, done natively by cgmath, glam or
+// nalgebra. Each library is a type implementing `Backend` with its own matrix, vector and
+// quaternion types, so code generic over it does all of its math in that library and converts
+// only at the edges, from and to the cgmath types everything else passes around. The `glam` and
+// `nalgebra` features compile those two in, together or on their own, and `Cgmath` is always
+// there. `MathBackend` picks one of them at runtime, for comparing them on the same build.
+//
+// All projections come out with wgpu's 0..1 depth, whichever library builds them.
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, Quaternion, Rad, SquareMatrix, Vector3};
+
+// Maps OpenGL's -1..1 clip space depth to the 0..1 range wgpu (and Vulkan, Metal, DX) use
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+pub trait Backend {
+    // For telling benchmark runs apart
+    const NAME: &'static str;
+
+    type Matrix: Copy;
+    type Vector: Copy;
+    // Of unit length
+    type Quaternion: Copy;
+
+    // From and to cgmath, at the edges
+    fn matrix(matrix: &Matrix4<f32>) -> Self::Matrix;
+    fn vector(vector: Vector3<f32>) -> Self::Vector;
+    fn quaternion(quaternion: Quaternion<f32>) -> Self::Quaternion;
+    fn to_cgmath(matrix: &Self::Matrix) -> Matrix4<f32>;
+
+    // Right-handed, looking from `eye` at `target`
+    fn look_at(eye: Self::Vector, target: Self::Vector, up: Self::Vector) -> Self::Matrix;
+
+    // Right-handed, with the depth mapped to wgpu's 0..1. `fovy` is in radians.
+    fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Self::Matrix;
+
+    // Centered on the view direction, `width` by `height` units, with the depth mapped to 0..1
+    // as well
+    fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self::Matrix;
+
+    // Scales, then rotates, then translates
+    fn compose(
+        translation: Self::Vector,
+        rotation: Self::Quaternion,
+        scale: Self::Vector,
+    ) -> Self::Matrix;
+
+    fn multiply(a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix;
+
+    fn invert(matrix: &Self::Matrix) -> Option<Self::Matrix>;
+}
+
+pub struct Cgmath;
+
+impl Backend for Cgmath {
+    const NAME: &'static str = "cgmath";
+
+    type Matrix = Matrix4<f32>;
+    type Vector = Vector3<f32>;
+    type Quaternion = Quaternion<f32>;
+
+    fn matrix(matrix: &Matrix4<f32>) -> Self::Matrix {
+        *matrix
+    }
+
+    fn vector(vector: Vector3<f32>) -> Self::Vector {
+        vector
+    }
+
+    fn quaternion(quaternion: Quaternion<f32>) -> Self::Quaternion {
+        quaternion
+    }
+
+    fn to_cgmath(matrix: &Self::Matrix) -> Matrix4<f32> {
+        *matrix
+    }
+
+    fn look_at(eye: Self::Vector, target: Self::Vector, up: Self::Vector) -> Self::Matrix {
+        Matrix4::look_at(Point3::from_vec(eye), Point3::from_vec(target), up)
+    }
+
+    fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Self::Matrix {
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(Rad(fovy), aspect, near, far)
+    }
+
+    fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self::Matrix {
+        let (x, y) = (width / 2.0, height / 2.0);
+        OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-x, x, -y, y, near, far)
+    }
+
+    fn compose(
+        translation: Self::Vector,
+        rotation: Self::Quaternion,
+        scale: Self::Vector,
+    ) -> Self::Matrix {
+        Matrix4::from_translation(translation)
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+    }
+
+    fn multiply(a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix {
+        a * b
+    }
+
+    fn invert(matrix: &Self::Matrix) -> Option<Self::Matrix> {
+        matrix.invert()
+    }
+}
+
+// SIMD where the target has it
+#[cfg(feature = "glam")]
+pub struct Glam;
+
+#[cfg(feature = "glam")]
+impl Backend for Glam {
+    const NAME: &'static str = "glam";
+
+    type Matrix = glam::Mat4;
+    type Vector = glam::Vec3;
+    type Quaternion = glam::Quat;
+
+    fn matrix(matrix: &Matrix4<f32>) -> Self::Matrix {
+        glam::Mat4::from_cols_array_2d(&(*matrix).into())
+    }
+
+    fn vector(vector: Vector3<f32>) -> Self::Vector {
+        glam::Vec3::new(vector.x, vector.y, vector.z)
+    }
+
+    fn quaternion(quaternion: Quaternion<f32>) -> Self::Quaternion {
+        let Quaternion { s, v } = quaternion;
+        glam::Quat::from_xyzw(v.x, v.y, v.z, s)
+    }
+
+    fn to_cgmath(matrix: &Self::Matrix) -> Matrix4<f32> {
+        matrix.to_cols_array_2d().into()
+    }
+
+    fn look_at(eye: Self::Vector, target: Self::Vector, up: Self::Vector) -> Self::Matrix {
+        glam::Mat4::look_at_rh(eye, target, up)
+    }
+
+    // Already 0..1
+    fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Self::Matrix {
+        glam::Mat4::perspective_rh(fovy, aspect, near, far)
+    }
+
+    // Already 0..1 too
+    fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self::Matrix {
+        let (x, y) = (width / 2.0, height / 2.0);
+        glam::Mat4::orthographic_rh(-x, x, -y, y, near, far)
+    }
+
+    fn compose(
+        translation: Self::Vector,
+        rotation: Self::Quaternion,
+        scale: Self::Vector,
+    ) -> Self::Matrix {
+        glam::Mat4::from_scale_rotation_translation(scale, rotation, translation)
+    }
+
+    fn multiply(a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix {
+        *a * *b
+    }
+
+    fn invert(matrix: &Self::Matrix) -> Option<Self::Matrix> {
+        if matrix.determinant() == 0.0 {
+            return None;
+        }
+        Some(matrix.inverse())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+pub struct Nalgebra;
+
+#[cfg(feature = "nalgebra")]
+impl Backend for Nalgebra {
+    const NAME: &'static str = "nalgebra";
+
+    type Matrix = nalgebra::Matrix4<f32>;
+    type Vector = nalgebra::Vector3<f32>;
+    type Quaternion = nalgebra::UnitQuaternion<f32>;
+
+    fn matrix(matrix: &Matrix4<f32>) -> Self::Matrix {
+        let columns: &[f32; 16] = matrix.as_ref();
+        nalgebra::Matrix4::from_column_slice(columns)
+    }
+
+    fn vector(vector: Vector3<f32>) -> Self::Vector {
+        nalgebra::Vector3::new(vector.x, vector.y, vector.z)
+    }
+
+    fn quaternion(quaternion: Quaternion<f32>) -> Self::Quaternion {
+        let Quaternion { s, v } = quaternion;
+        nalgebra::UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(s, v.x, v.y, v.z))
+    }
+
+    fn to_cgmath(matrix: &Self::Matrix) -> Matrix4<f32> {
+        let mut columns = [0.0; 16];
+        columns.copy_from_slice(matrix.as_slice());
+        let matrix: &Matrix4<f32> = (&columns).into();
+        *matrix
+    }
+
+    fn look_at(eye: Self::Vector, target: Self::Vector, up: Self::Vector) -> Self::Matrix {
+        nalgebra::Matrix4::look_at_rh(&eye.into(), &target.into(), &up)
+    }
+
+    fn perspective(fovy: f32, aspect: f32, near: f32, far: f32) -> Self::Matrix {
+        let perspective = nalgebra::Perspective3::new(aspect, fovy, near, far);
+        Self::matrix(&OPENGL_TO_WGPU_MATRIX) * perspective.to_homogeneous()
+    }
+
+    fn orthographic(width: f32, height: f32, near: f32, far: f32) -> Self::Matrix {
+        let (x, y) = (width / 2.0, height / 2.0);
+        let orthographic = nalgebra::Orthographic3::new(-x, x, -y, y, near, far);
+        Self::matrix(&OPENGL_TO_WGPU_MATRIX) * orthographic.to_homogeneous()
+    }
+
+    fn compose(
+        translation: Self::Vector,
+        rotation: Self::Quaternion,
+        scale: Self::Vector,
+    ) -> Self::Matrix {
+        nalgebra::Matrix4::new_translation(&translation)
+            * rotation.to_homogeneous()
+            * nalgebra::Matrix4::new_nonuniform_scaling(&scale)
+    }
+
+    fn multiply(a: &Self::Matrix, b: &Self::Matrix) -> Self::Matrix {
+        a * b
+    }
+
+    fn invert(matrix: &Self::Matrix) -> Option<Self::Matrix> {
+        matrix.try_inverse()
+    }
+}
+
+// The backends this build has, for picking one at runtime
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MathBackend {
+    #[default]
+    Cgmath,
+    #[cfg(feature = "glam")]
+    Glam,
+    #[cfg(feature = "nalgebra")]
+    Nalgebra,
+}
+
+impl MathBackend {
+    pub const ALL: &'static [MathBackend] = &[
+        MathBackend::Cgmath,
+        #[cfg(feature = "glam")]
+        MathBackend::Glam,
+        #[cfg(feature = "nalgebra")]
+        MathBackend::Nalgebra,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MathBackend::Cgmath => Cgmath::NAME,
+            #[cfg(feature = "glam")]
+            MathBackend::Glam => Glam::NAME,
+            #[cfg(feature = "nalgebra")]
+            MathBackend::Nalgebra => Nalgebra::NAME,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, InnerSpace, Rotation3, Transform, Vector4};
+
+    fn assert_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let a: &[f32; 16] = a.as_ref();
+        let b: &[f32; 16] = b.as_ref();
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    // The same tests for every backend, each checked against cgmath
+    macro_rules! backend_tests {
+        ($module:ident, $backend:ty) => {
+            mod $module {
+                use super::*;
+
+                type B = $backend;
+
+                fn compose(
+                    translation: Vector3<f32>,
+                    rotation: Quaternion<f32>,
+                    scale: Vector3<f32>,
+                ) -> <B as Backend>::Matrix {
+                    B::compose(
+                        B::vector(translation),
+                        B::quaternion(rotation),
+                        B::vector(scale),
+                    )
+                }
+
+                #[test]
+                fn perspective_maps_near_and_far_to_0_and_1() {
+                    let fovy = Rad::from(Deg(60.0)).0;
+                    let projection = B::to_cgmath(&B::perspective(fovy, 1.5, 0.1, 100.0));
+                    let depth = |z: f32| {
+                        let clip = projection * Vector4::new(0.0, 0.0, -z, 1.0);
+                        clip.z / clip.w
+                    };
+                    assert!(depth(0.1).abs() < 1e-5);
+                    assert!((depth(100.0) - 1.0).abs() < 1e-5);
+                }
+
+                #[test]
+                fn orthographic_maps_the_box_to_clip_space() {
+                    let projection = B::to_cgmath(&B::orthographic(8.0, 4.0, 0.5, 50.0));
+                    let ndc = |x: f32, y: f32, z: f32| projection * Vector4::new(x, y, -z, 1.0);
+                    let corner = ndc(4.0, -2.0, 0.5);
+                    assert!((corner.x - 1.0).abs() < 1e-5 && (corner.y + 1.0).abs() < 1e-5);
+                    assert!(corner.z.abs() < 1e-5 && (corner.w - 1.0).abs() < 1e-5);
+                    assert!((ndc(0.0, 0.0, 50.0).z - 1.0).abs() < 1e-5);
+                }
+
+                #[test]
+                fn look_at_puts_the_target_straight_ahead() {
+                    let eye = Point3::new(1.0, 2.0, 3.0);
+                    let target = Point3::new(-2.0, 0.0, 1.0);
+                    let view = B::to_cgmath(&B::look_at(
+                        B::vector(eye.to_vec()),
+                        B::vector(target.to_vec()),
+                        B::vector(Vector3::unit_y()),
+                    ));
+                    assert!(view.transform_point(eye).to_vec().magnitude() < 1e-5);
+
+                    let ahead = view.transform_point(target);
+                    assert!(ahead.x.abs() < 1e-5 && ahead.y.abs() < 1e-5);
+                    assert!(ahead.z < 0.0);
+                }
+
+                #[test]
+                fn compose_matches_translate_rotate_scale() {
+                    let translation = Vector3::new(1.0, -2.0, 3.0);
+                    let rotation = Quaternion::from_angle_y(Deg(30.0));
+                    let scale = Vector3::new(2.0, 1.0, 0.5);
+                    assert_close(
+                        B::to_cgmath(&compose(translation, rotation, scale)),
+                        Matrix4::from_translation(translation)
+                            * Matrix4::from(rotation)
+                            * Matrix4::from_nonuniform_scale(2.0, 1.0, 0.5),
+                    );
+                }
+
+                #[test]
+                fn multiply_and_invert_agree_with_cgmath() {
+                    let a = compose(
+                        Vector3::new(1.0, 2.0, 3.0),
+                        Quaternion::from_angle_x(Deg(45.0)),
+                        Vector3::new(1.0, 2.0, 1.0),
+                    );
+                    let b = B::perspective(Rad::from(Deg(45.0)).0, 1.0, 0.5, 20.0);
+                    let (a_cgmath, b_cgmath) = (B::to_cgmath(&a), B::to_cgmath(&b));
+                    assert_close(B::to_cgmath(&B::multiply(&a, &b)), a_cgmath * b_cgmath);
+                    assert_close(
+                        B::to_cgmath(&B::invert(&a).unwrap()),
+                        a_cgmath.invert().unwrap(),
+                    );
+                    assert!(B::invert(&B::matrix(&Matrix4::from_scale(0.0))).is_none());
+                }
+            }
+        };
+    }
+
+    backend_tests!(cgmath_backend, Cgmath);
+    #[cfg(feature = "glam")]
+    backend_tests!(glam_backend, Glam);
+    #[cfg(feature = "nalgebra")]
+    backend_tests!(nalgebra_backend, Nalgebra);
+
+    #[test]
+    fn every_compiled_backend_can_be_picked() {
+        let names: Vec<&str> = MathBackend::ALL
+            .iter()
+            .map(|backend| backend.name())
+            .collect();
+        assert_eq!(names[0], "cgmath");
+        assert_eq!(
+            names.len(),
+            1 + cfg!(feature = "glam") as usize + cfg!(feature = "nalgebra") as usize
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::backend::{Backend, Cgmath};
+use crate::frustum::Plane;
+use crate::sphere::Sphere;
+use cgmath::{
+    Angle, Deg, EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Rad, SquareMatrix, Vector3,
+    Vector4,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    // Without perspective, seeing as much around the target as the perspective view does, so
+    // switching keeps it the same size on the screen. Zooming changes the distance either way.
+    Orthographic,
+}
+
+impl Projection {
+    pub fn toggled(self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Projection::Perspective => "Perspective",
+            Projection::Orthographic => "Orthographic",
+        }
+    }
+}
+
+pub struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+}
+
+// Replaces the near plane of `projection` with `clip_plane`, given in view space with its normal
+// pointing at what stays visible. The far plane moves along, so depth precision suffers the
+// further the plane is from the original near plane (Lengyel, "Oblique view frustum depth
+// projection and clipping").
+pub fn oblique_projection(projection: Matrix4<f32>, clip_plane: Vector4<f32>) -> Matrix4<f32> {
+    let inverse = match projection.invert() {
+        Some(inverse) => inverse,
+        None => return projection,
+    };
+    // The corner of the frustum furthest from the clip plane, which stays on the far plane
+    let corner = inverse * Vector4::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0);
+    let row = clip_plane / clip_plane.dot(corner);
+
+    let mut projection = projection;
+    projection.x.z = row.x;
+    projection.y.z = row.y;
+    projection.z.z = row.z;
+    projection.w.z = row.w;
+    projection
+}
+
+// Looks along `direction` at all of `bounds` without perspective, for a directional light's
+// shadow map. The whole sphere fits between the near and far planes, whatever the light's angle.
+pub fn directional_light_view_projection(direction: Vector3<f32>, bounds: &Sphere) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    let radius = bounds.radius.max(0.001);
+    let eye = bounds.center - direction * 2.0 * radius;
+    // Anything not along the light works as up
+    let up = if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let view = Cgmath::look_at(eye.to_vec(), bounds.center.to_vec(), up);
+    Cgmath::orthographic(2.0 * radius, 2.0 * radius, radius, 3.0 * radius) * view
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        self.view_matrix_with::<Cgmath>()
+    }
+
+    pub fn view_matrix_with<B: Backend>(&self) -> B::Matrix {
+        B::look_at(
+            B::vector(self.eye.to_vec()),
+            B::vector(self.target.to_vec()),
+            B::vector(self.up),
+        )
+    }
+
+    // With the depth mapped to wgpu's 0..1
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix_with::<Cgmath>()
+    }
+
+    pub fn projection_matrix_with<B: Backend>(&self) -> B::Matrix {
+        match self.projection {
+            Projection::Perspective => B::perspective(
+                Rad::from(Deg(self.fovy)).0,
+                self.aspect,
+                self.znear,
+                self.zfar,
+            ),
+            Projection::Orthographic => {
+                let height = self.ortho_height();
+                B::orthographic(height * self.aspect, height, self.znear, self.zfar)
+            }
+        }
+    }
+
+    // How much the orthographic projection sees vertically, what the perspective one sees at the
+    // target
+    pub fn ortho_height(&self) -> f32 {
+        2.0 * (self.target - self.eye).magnitude() * (Deg(self.fovy) / 2.0).tan()
+    }
+
+    // Roughly how much of the view's height `sphere` covers, 1 when it's as tall as the view.
+    // Measured at its center, from inside it this is as if it touched the eye.
+    pub fn projected_size(&self, sphere: &Sphere) -> f32 {
+        match self.projection {
+            Projection::Perspective => {
+                let distance = (sphere.center - self.eye).magnitude().max(sphere.radius);
+                sphere.radius / (distance * (Deg(self.fovy) / 2.0).tan())
+            }
+            Projection::Orthographic => 2.0 * sphere.radius / self.ortho_height(),
+        }
+    }
+
+    // Moves the eye toward the target by `factor`, or away from it above 1. It stops short of
+    // the near plane.
+    pub fn zoom(&mut self, factor: f32) {
+        let offset = self.eye - self.target;
+        let distance = offset.magnitude();
+        if distance == 0.0 {
+            return;
+        }
+        let zoomed = (distance * factor).max(self.znear * 2.0);
+        self.eye = self.target + offset * (zoomed / distance);
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    // What the camera sees in a mirror on `mirror`. With `clip` set, the near plane lies on the
+    // mirror, so nothing on the far side of it shows up in the reflection. Mirroring flips the
+    // winding of every triangle.
+    pub fn mirrored_view_projection(&self, mirror: &Plane, clip: bool) -> Matrix4<f32> {
+        let view = self.view_matrix() * mirror.reflection();
+        let projection = self.projection_matrix();
+        if !clip {
+            return projection * view;
+        }
+
+        // Planes transform with the inverse transpose
+        let clip_plane = match view.invert() {
+            Some(inverse) => inverse.transpose() * mirror.coefficients(),
+            None => return projection * view,
+        };
+        oblique_projection(projection, clip_plane) * view
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Vector4};
+
+    fn camera() -> Camera {
+        Camera {
+            eye: (0.0, 1.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: 1.5,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        }
+    }
+
+    fn project(camera: &Camera, point: Point3<f32>) -> Vector4<f32> {
+        let clip =
+            camera.build_view_projection_matrix() * Vector4::new(point.x, point.y, point.z, 1.0);
+        clip / clip.w
+    }
+
+    #[test]
+    fn target_ends_up_in_the_middle() {
+        let camera = camera();
+        let ndc = project(&camera, camera.target);
+        assert!(ndc.x.abs() < 1e-5);
+        assert!(ndc.y.abs() < 1e-5);
+    }
+
+    #[test]
+    fn mirrors_clip_at_the_mirror() {
+        let camera = camera();
+        let mirror = Plane::from_point_normal(Point3::new(0.0, -0.5, 0.0), Vector3::unit_y());
+        let view_proj = camera.mirrored_view_projection(&mirror, true);
+        let depth = |point: Point3<f32>| {
+            let clip = view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+            clip.z / clip.w
+        };
+
+        // On the mirror is the near plane, above it visible and below it clipped
+        assert!(depth(Point3::new(0.0, -0.5, 0.0)).abs() < 1e-4);
+        let above = depth(Point3::new(0.0, 0.0, 0.0));
+        assert!(above > 0.0 && above < 1.0);
+        assert!(depth(Point3::new(0.0, -1.0, 0.0)) < 0.0);
+    }
+
+    #[test]
+    fn orthographic_keeps_the_target_the_same_size() {
+        let mut camera = camera();
+        let point = camera.target + Vector3::new(0.5, 0.0, 0.0);
+        let perspective = project(&camera, point);
+        camera.projection = Projection::Orthographic;
+        let orthographic = project(&camera, point);
+        assert!((perspective.x - orthographic.x).abs() < 1e-4);
+
+        // Zooming in makes it bigger on the screen
+        camera.zoom(0.5);
+        assert!((project(&camera, point).x - orthographic.x * 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn projected_size_halves_with_twice_the_distance() {
+        let mut camera = camera();
+        let direction = (camera.target - camera.eye).normalize();
+        let sphere = |distance: f32| Sphere {
+            center: camera.eye + direction * distance,
+            radius: 0.5,
+        };
+        let near = camera.projected_size(&sphere(2.0));
+        assert!((camera.projected_size(&sphere(4.0)) - near / 2.0).abs() < 1e-5);
+
+        // Matches where the top of it ends up on the screen, with the center in the middle
+        let screen_up = direction.cross(camera.up).cross(direction).normalize();
+        let top = project(&camera, sphere(2.0).center + screen_up * 0.5);
+        assert!((near - top.y).abs() < 1e-4);
+
+        // The same as perspective at the target
+        let at_target = Sphere {
+            center: camera.target,
+            radius: 0.5,
+        };
+        let perspective = camera.projected_size(&at_target);
+        camera.projection = Projection::Orthographic;
+        assert!((camera.projected_size(&at_target) - perspective).abs() < 1e-5);
+    }
+
+    #[test]
+    fn light_view_projection_fits_the_bounds() {
+        let bounds = Sphere::new(Point3::new(1.0, 2.0, 3.0), 4.0);
+        let direction = Vector3::new(0.3, -1.0, 0.2).normalize();
+        let view_proj = directional_light_view_projection(direction, &bounds);
+        let project = |point: Point3<f32>| view_proj * Vector4::new(point.x, point.y, point.z, 1.0);
+
+        // The center in the middle of the map, and the sphere's ends along the light on the near
+        // and far planes
+        let center = project(bounds.center);
+        assert!(center.x.abs() < 1e-4 && center.y.abs() < 1e-4);
+        assert!((center.z - 0.5).abs() < 1e-4);
+        assert!(project(bounds.center - direction * 4.0).z.abs() < 1e-4);
+        assert!((project(bounds.center + direction * 4.0).z - 1.0).abs() < 1e-4);
+
+        // Straight down works too
+        let down = directional_light_view_projection(-Vector3::unit_y(), &bounds);
+        let center = down * Vector4::new(1.0, 2.0, 3.0, 1.0);
+        assert!((center.z - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn zoom_stops_before_the_near_plane() {
+        let mut camera = camera();
+        camera.zoom(0.0);
+        assert!((camera.eye - camera.target).magnitude() >= camera.znear);
+    }
+
+    #[test]
+    fn depth_goes_from_zero_to_one() {
+        let mut camera = camera();
+        let direction = (camera.target - camera.eye).normalize();
+
+        for &projection in &[Projection::Perspective, Projection::Orthographic] {
+            camera.projection = projection;
+            let near = project(&camera, camera.eye + direction * camera.znear);
+            let far = project(&camera, camera.eye + direction * camera.zfar);
+            assert!(near.z.abs() < 1e-4);
+            assert!((far.z - 1.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// Linear RGBA color. Conversions to and from 8 bit sRGB go through the sRGB transfer function.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+fn linear_from_srgb(srgb: f32) -> f32 {
+    if srgb <= 0.04045 {
+        srgb / 12.92
+    } else {
+        ((srgb + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_from_linear(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0, 1.0);
+    pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    // Alpha is stored linearly in sRGB formats as well
+    pub fn from_srgb8(rgba: [u8; 4]) -> Self {
+        let channel = |value: u8| linear_from_srgb(value as f32 / 255.0);
+        Self::new(
+            channel(rgba[0]),
+            channel(rgba[1]),
+            channel(rgba[2]),
+            rgba[3] as f32 / 255.0,
+        )
+    }
+
+    pub fn to_srgb8(&self) -> [u8; 4] {
+        let quantize = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [
+            quantize(srgb_from_linear(self.r)),
+            quantize(srgb_from_linear(self.g)),
+            quantize(srgb_from_linear(self.b)),
+            quantize(self.a),
+        ]
+    }
+
+    pub fn lerp(&self, other: &Color, t: f32) -> Self {
+        Self::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+
+    pub fn to_array(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip() {
+        for value in 0..=255u8 {
+            let rgba = [value, 255 - value, value / 2, value];
+            assert_eq!(Color::from_srgb8(rgba).to_srgb8(), rgba);
+        }
+    }
+
+    #[test]
+    fn srgb_mid_gray_is_darker_in_linear() {
+        let gray = Color::from_srgb8([128, 128, 128, 255]);
+        assert!((gray.r - 0.2158).abs() < 1e-3);
+        assert_eq!(gray.a, 1.0);
+    }
+
+    #[test]
+    fn lerp_ends_and_middle() {
+        assert_eq!(Color::BLACK.lerp(&Color::WHITE, 0.0), Color::BLACK);
+        assert_eq!(Color::BLACK.lerp(&Color::WHITE, 1.0), Color::WHITE);
+        assert_eq!(
+            Color::BLACK.lerp(&Color::WHITE, 0.5),
+            Color::new(0.5, 0.5, 0.5, 1.0)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::aabb::Aabb;
+use crate::sphere::Sphere;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+// Plane with its normal pointing into the frustum, points with a positive distance are inside
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    // Through `point`, `normal` has to be unit length
+    pub fn from_point_normal(point: Point3<f32>, normal: Vector3<f32>) -> Self {
+        Self {
+            normal,
+            distance: -normal.dot(point.to_vec()),
+        }
+    }
+
+    fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(Vector3::new(point.x, point.y, point.z)) + self.distance
+    }
+
+    // The normal and distance, for shaders and the like
+    pub fn coefficients(&self) -> Vector4<f32> {
+        self.normal.extend(self.distance)
+    }
+
+    // Mirrors points on the plane
+    pub fn reflection(&self) -> Matrix4<f32> {
+        let n = self.normal;
+        let d = self.distance;
+        #[rustfmt::skip]
+        let reflection = Matrix4::new(
+            1.0 - 2.0 * n.x * n.x, -2.0 * n.x * n.y, -2.0 * n.x * n.z, 0.0,
+            -2.0 * n.y * n.x, 1.0 - 2.0 * n.y * n.y, -2.0 * n.y * n.z, 0.0,
+            -2.0 * n.z * n.x, -2.0 * n.z * n.y, 1.0 - 2.0 * n.z * n.z, 0.0,
+            -2.0 * d * n.x, -2.0 * d * n.y, -2.0 * d * n.z, 1.0,
+        );
+        reflection
+    }
+}
+
+// The six planes of a view projection, for culling
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    // Left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Expects the 0..1 depth range the camera's matrix uses
+    pub fn from_view_proj(view_proj: &Matrix4<f32>) -> Self {
+        let row = |index| view_proj.row(index);
+        Self {
+            planes: [
+                Plane::from_coefficients(row(3) + row(0)),
+                Plane::from_coefficients(row(3) - row(0)),
+                Plane::from_coefficients(row(3) + row(1)),
+                Plane::from_coefficients(row(3) - row(1)),
+                Plane::from_coefficients(row(2)),
+                Plane::from_coefficients(row(3) - row(2)),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    // Conservative, boxes near the frustum's edges can pass without actually being visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let furthest = |normal: f32, min: f32, max: f32| if normal >= 0.0 { max } else { min };
+            let corner = Point3::new(
+                furthest(plane.normal.x, aabb.min.x, aabb.max.x),
+                furthest(plane.normal.y, aabb.min.y, aabb.max.y),
+                furthest(plane.normal.z, aabb.min.z, aabb.max.z),
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+
+    // Conservative the same way as `intersects_aabb`
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, Projection};
+    use cgmath::Transform;
+
+    fn frustum() -> Frustum {
+        let camera = Camera {
+            eye: (0.0, 0.0, 5.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+        Frustum::from_view_proj(&camera.build_view_projection_matrix())
+    }
+
+    #[test]
+    fn reflections_mirror_across_the_plane() {
+        let plane = Plane::from_point_normal(Point3::new(0.0, 0.5, 0.0), Vector3::unit_y());
+        let mirrored = plane.reflection().transform_point(Point3::new(1.0, 2.0, 3.0));
+        assert!((mirrored - Point3::new(1.0, -1.0, 3.0)).magnitude() < 1e-5);
+        assert!((plane.signed_distance(mirrored) + 1.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn points_inside_and_outside() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Point3::new(0.0, 0.0, 0.0)));
+        // Behind the camera, past the far plane and off to the side
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, 6.0)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, -200.0)));
+        assert!(!frustum.contains_point(Point3::new(50.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn boxes_inside_straddling_and_outside() {
+        let frustum = frustum();
+        let inside = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let straddling = Aabb::new(Point3::new(1.0, -1.0, -1.0), Point3::new(50.0, 1.0, 1.0));
+        let outside = Aabb::new(Point3::new(40.0, -1.0, -1.0), Point3::new(50.0, 1.0, 1.0));
+
+        assert!(frustum.intersects_aabb(&inside));
+        assert!(frustum.intersects_aabb(&straddling));
+        assert!(!frustum.intersects_aabb(&outside));
+    }
+
+    #[test]
+    fn spheres_inside_straddling_and_outside() {
+        let frustum = frustum();
+        let inside = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let straddling = Sphere::new(Point3::new(0.0, 0.0, 6.0), 1.5);
+        let outside = Sphere::new(Point3::new(50.0, 0.0, 0.0), 1.0);
+
+        assert!(frustum.intersects_sphere(&inside));
+        assert!(frustum.intersects_sphere(&straddling));
+        assert!(!frustum.intersects_sphere(&outside));
+    }
+}
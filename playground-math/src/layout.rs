@@ -0,0 +1,436 @@
+use cgmath::{Matrix3, Matrix4, Point3, Vector2, Vector3, Vector4};
+
+// The memory layouts of GLSL blocks. std140 is the one uniform blocks use, std430 the tighter one
+// of storage buffers. Both pad a vec3 to 16 bytes before the next vector, which a `#[repr(C)]`
+// struct of `[f32; 3]`s doesn't, and std140 also pads array elements and nested structs to 16.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    Std140,
+    Std430,
+}
+
+fn align_to(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+// A value that can be a member of a block, with its base alignment and size in `layout`
+pub trait Member {
+    fn alignment(layout: Layout) -> usize;
+    fn size(layout: Layout) -> usize;
+    // Appends exactly `size` bytes
+    fn write(&self, layout: Layout, bytes: &mut Vec<u8>);
+}
+
+fn write_floats(values: &[f32], bytes: &mut Vec<u8>) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+}
+
+macro_rules! scalar_member {
+    ($ty:ty) => {
+        impl Member for $ty {
+            fn alignment(_: Layout) -> usize {
+                4
+            }
+
+            fn size(_: Layout) -> usize {
+                4
+            }
+
+            fn write(&self, _: Layout, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_ne_bytes());
+            }
+        }
+    };
+}
+
+scalar_member!(f32);
+scalar_member!(i32);
+scalar_member!(u32);
+
+// vecN, ivecN and uvecN
+macro_rules! vector_members {
+    ($scalar:ty) => {
+        impl Member for Vector2<$scalar> {
+            fn alignment(_: Layout) -> usize {
+                8
+            }
+
+            fn size(_: Layout) -> usize {
+                8
+            }
+
+            fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+                for component in &[self.x, self.y] {
+                    component.write(layout, bytes);
+                }
+            }
+        }
+
+        // Aligned like a vec4, but only 12 bytes, so a scalar can follow in the last 4
+        impl Member for Vector3<$scalar> {
+            fn alignment(_: Layout) -> usize {
+                16
+            }
+
+            fn size(_: Layout) -> usize {
+                12
+            }
+
+            fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+                for component in &[self.x, self.y, self.z] {
+                    component.write(layout, bytes);
+                }
+            }
+        }
+
+        impl Member for Vector4<$scalar> {
+            fn alignment(_: Layout) -> usize {
+                16
+            }
+
+            fn size(_: Layout) -> usize {
+                16
+            }
+
+            fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+                for component in &[self.x, self.y, self.z, self.w] {
+                    component.write(layout, bytes);
+                }
+            }
+        }
+    };
+}
+
+vector_members!(f32);
+vector_members!(i32);
+vector_members!(u32);
+
+impl Member for Point3<f32> {
+    fn alignment(layout: Layout) -> usize {
+        Vector3::<f32>::alignment(layout)
+    }
+
+    fn size(layout: Layout) -> usize {
+        Vector3::<f32>::size(layout)
+    }
+
+    fn write(&self, _: Layout, bytes: &mut Vec<u8>) {
+        write_floats(&[self.x, self.y, self.z], bytes);
+    }
+}
+
+// Column-major like cgmath, each column padded to a vec4 in both layouts
+impl Member for Matrix3<f32> {
+    fn alignment(_: Layout) -> usize {
+        16
+    }
+
+    fn size(_: Layout) -> usize {
+        48
+    }
+
+    fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+        for column in &[self.x, self.y, self.z] {
+            column.write(layout, bytes);
+            write_floats(&[0.0], bytes);
+        }
+    }
+}
+
+impl Member for Matrix4<f32> {
+    fn alignment(_: Layout) -> usize {
+        16
+    }
+
+    fn size(_: Layout) -> usize {
+        64
+    }
+
+    fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+        for column in &[self.x, self.y, self.z, self.w] {
+            column.write(layout, bytes);
+        }
+    }
+}
+
+// Elements are `stride` apart, which std140 rounds up to 16
+impl<T: Member, const N: usize> Member for [T; N] {
+    fn alignment(layout: Layout) -> usize {
+        match layout {
+            Layout::Std140 => align_to(T::alignment(layout), 16),
+            Layout::Std430 => T::alignment(layout),
+        }
+    }
+
+    fn size(layout: Layout) -> usize {
+        N * align_to(T::size(layout), Self::alignment(layout))
+    }
+
+    fn write(&self, layout: Layout, bytes: &mut Vec<u8>) {
+        let stride = align_to(T::size(layout), Self::alignment(layout));
+        for element in self {
+            let start = bytes.len();
+            element.write(layout, bytes);
+            bytes.resize(start + stride, 0);
+        }
+    }
+}
+
+// A struct that gets uploaded as a GLSL block, writing its members in declaration order
+pub trait Block {
+    fn write(&self, writer: &mut BlockWriter);
+
+    fn to_bytes(&self, layout: Layout) -> Vec<u8> {
+        let mut writer = BlockWriter::new(layout);
+        self.write(&mut writer);
+        writer.finish()
+    }
+
+    fn std140(&self) -> Vec<u8> {
+        self.to_bytes(Layout::Std140)
+    }
+
+    fn std430(&self) -> Vec<u8> {
+        self.to_bytes(Layout::Std430)
+    }
+
+    // Of any value, for allocating space before there's one to upload
+    fn size(layout: Layout) -> usize
+    where
+        Self: Default,
+    {
+        Self::default().to_bytes(layout).len()
+    }
+}
+
+// Lays out the members of a block one after the other, padding each to its alignment
+pub struct BlockWriter {
+    layout: Layout,
+    bytes: Vec<u8>,
+    // The largest alignment of any member so far
+    alignment: usize,
+}
+
+impl BlockWriter {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            layout,
+            bytes: Vec::new(),
+            alignment: 4,
+        }
+    }
+
+    fn align(&mut self, alignment: usize) -> usize {
+        self.alignment = self.alignment.max(alignment);
+        let offset = align_to(self.bytes.len(), alignment);
+        self.bytes.resize(offset, 0);
+        offset
+    }
+
+    // Returns the offset the member ended up at
+    pub fn member<T: Member>(&mut self, value: &T) -> usize {
+        let offset = self.align(T::alignment(self.layout));
+        value.write(self.layout, &mut self.bytes);
+        offset
+    }
+
+    // A nested struct, aligned to its largest member, and to 16 in std140
+    pub fn block<B: Block>(&mut self, value: &B) -> usize {
+        self.blocks(std::slice::from_ref(value))
+    }
+
+    // An array of structs, each padded to the struct's alignment. Also for the runtime sized array
+    // at the end of a storage buffer.
+    pub fn blocks<B: Block>(&mut self, values: &[B]) -> usize {
+        let mut offset = None;
+        for value in values {
+            let mut writer = BlockWriter::new(self.layout);
+            value.write(&mut writer);
+            let alignment = writer.block_alignment();
+            let start = self.align(alignment);
+            self.bytes.extend_from_slice(&writer.finish());
+            offset.get_or_insert(start);
+        }
+        offset.unwrap_or(self.bytes.len())
+    }
+
+    fn block_alignment(&self) -> usize {
+        match self.layout {
+            Layout::Std140 => align_to(self.alignment, 16),
+            Layout::Std430 => self.alignment,
+        }
+    }
+
+    // The block's bytes, padded at the end to its alignment
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = align_to(self.bytes.len(), self.block_alignment());
+        self.bytes.resize(size, 0);
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    fn member_offsets(
+        layout: Layout,
+        write: impl FnOnce(&mut BlockWriter) -> Vec<usize>,
+    ) -> Vec<usize> {
+        write(&mut BlockWriter::new(layout))
+    }
+
+    #[derive(Default)]
+    struct Single(f32);
+
+    impl Block for Single {
+        fn write(&self, writer: &mut BlockWriter) {
+            writer.member(&self.0);
+        }
+    }
+
+    struct Light {
+        position: Vector3<f32>,
+        range: f32,
+        color: Vector3<f32>,
+    }
+
+    impl Block for Light {
+        fn write(&self, writer: &mut BlockWriter) {
+            writer.member(&self.position);
+            writer.member(&self.range);
+            writer.member(&self.color);
+        }
+    }
+
+    #[test]
+    fn scalars_pack_into_the_rest_of_a_vec3() {
+        for &layout in &[Layout::Std140, Layout::Std430] {
+            let offsets = member_offsets(layout, |writer| {
+                vec![
+                    writer.member(&Vector3::new(1.0f32, 2.0, 3.0)),
+                    writer.member(&4.0f32),
+                    writer.member(&5u32),
+                    writer.member(&Vector2::new(6.0f32, 7.0)),
+                ]
+            });
+            assert_eq!(offsets, vec![0, 12, 16, 24]);
+        }
+    }
+
+    #[test]
+    fn vectors_after_scalars_get_aligned() {
+        let offsets = member_offsets(Layout::Std140, |writer| {
+            vec![
+                writer.member(&1.0f32),
+                writer.member(&Vector3::new(1.0f32, 2.0, 3.0)),
+                writer.member(&Vector3::new(4.0f32, 5.0, 6.0)),
+                writer.member(&Vector4::new(1.0f32, 2.0, 3.0, 4.0)),
+            ]
+        });
+        assert_eq!(offsets, vec![0, 16, 32, 48]);
+    }
+
+    #[test]
+    fn integer_vectors_are_laid_out_like_float_ones() {
+        let offsets = member_offsets(Layout::Std140, |writer| {
+            vec![
+                writer.member(&1u32),
+                writer.member(&Vector4::new(1u32, 2, 3, 4)),
+                writer.member(&Vector3::new(1i32, 2, 3)),
+                writer.member(&1u32),
+                writer.member(&Vector2::new(1u32, 2)),
+            ]
+        });
+        assert_eq!(offsets, vec![0, 16, 32, 44, 48]);
+    }
+
+    #[test]
+    fn matrices_are_padded_columns() {
+        let mut writer = BlockWriter::new(Layout::Std140);
+        assert_eq!(writer.member(&Matrix4::<f32>::identity()), 0);
+        assert_eq!(writer.member(&Matrix3::<f32>::identity()), 64);
+        assert_eq!(writer.member(&1.0f32), 112);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 128);
+
+        // The padding after the first column of the mat3 stays zero, the second column starts
+        // with its 0 and then the 1 on the diagonal
+        let float = |offset: usize| {
+            let mut value = [0; 4];
+            value.copy_from_slice(&bytes[offset..offset + 4]);
+            f32::from_ne_bytes(value)
+        };
+        assert_eq!(float(64), 1.0);
+        assert_eq!(float(76), 0.0);
+        assert_eq!(float(84), 1.0);
+    }
+
+    #[test]
+    fn std140_pads_array_elements_to_16() {
+        let floats = [1.0f32, 2.0, 3.0];
+        assert_eq!(<[f32; 3]>::size(Layout::Std140), 48);
+        assert_eq!(<[f32; 3]>::size(Layout::Std430), 12);
+
+        let offsets = member_offsets(Layout::Std140, |writer| {
+            vec![writer.member(&floats), writer.member(&4.0f32)]
+        });
+        assert_eq!(offsets, vec![0, 48]);
+        let offsets = member_offsets(Layout::Std430, |writer| {
+            vec![writer.member(&floats), writer.member(&4.0f32)]
+        });
+        assert_eq!(offsets, vec![0, 12]);
+    }
+
+    #[test]
+    fn vec3_arrays_have_a_stride_of_16_in_both_layouts() {
+        for &layout in &[Layout::Std140, Layout::Std430] {
+            assert_eq!(<[Vector3<f32>; 2]>::size(layout), 32);
+        }
+    }
+
+    #[test]
+    fn nested_structs_are_aligned_to_16_only_in_std140() {
+        let offsets = member_offsets(Layout::Std140, |writer| {
+            vec![writer.block(&Single(1.0)), writer.member(&2.0f32)]
+        });
+        assert_eq!(offsets, vec![0, 16]);
+        let offsets = member_offsets(Layout::Std430, |writer| {
+            vec![writer.block(&Single(1.0)), writer.member(&2.0f32)]
+        });
+        assert_eq!(offsets, vec![0, 4]);
+    }
+
+    #[test]
+    fn block_sizes_round_up_to_their_alignment() {
+        assert_eq!(Single::size(Layout::Std140), 16);
+        assert_eq!(Single::size(Layout::Std430), 4);
+
+        let light = Light {
+            position: Vector3::new(1.0, 2.0, 3.0),
+            range: 4.0,
+            color: Vector3::new(5.0, 6.0, 7.0),
+        };
+        assert_eq!(light.std140().len(), 32);
+        assert_eq!(light.std430().len(), 32);
+    }
+
+    #[test]
+    fn arrays_of_structs_follow_each_other_by_their_padded_size() {
+        let light = |range| Light {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            range,
+            color: Vector3::new(1.0, 1.0, 1.0),
+        };
+        let mut writer = BlockWriter::new(Layout::Std430);
+        assert_eq!(writer.member(&3u32), 0);
+        assert_eq!(writer.blocks(&[light(1.0), light(2.0)]), 16);
+        let bytes = writer.finish();
+        assert_eq!(bytes.len(), 80);
+        // The second light's range
+        assert_eq!(&bytes[60..64], &2.0f32.to_ne_bytes());
+    }
+}
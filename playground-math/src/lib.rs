@@ -0,0 +1,31 @@
+// Math shared between the playground crates, built on top of cgmath. Everything here is
+// independent of the graphics API, apart from the clip space conventions picked in `backend`.
+
+pub mod aabb;
+pub mod animation;
+pub mod backend;
+pub mod camera;
+pub mod color;
+pub mod frustum;
+pub mod layout;
+pub mod lod;
+pub mod noise;
+pub mod ray;
+pub mod skeleton;
+pub mod sphere;
+pub mod sun;
+pub mod transform;
+
+pub use aabb::Aabb;
+pub use animation::{Channel, Clip, Keyframes, Playback, Player};
+pub use backend::{Backend, MathBackend};
+pub use camera::{Camera, Projection};
+pub use color::Color;
+pub use frustum::{Frustum, Plane};
+pub use layout::{Block, BlockWriter, Layout, Member};
+pub use lod::LodDistances;
+pub use noise::Perlin;
+pub use ray::Ray;
+pub use skeleton::{Joint, Skeleton};
+pub use sphere::Sphere;
+pub use transform::Transform;
@@ -0,0 +1,66 @@
+// Distances at which a model switches to coarser levels of detail. Level 0 is used up close and
+// level n from `switch[n - 1]` on, so the distances have to be increasing.
+//
+// With hysteresis, switching to a coarser level waits until the distance is that fraction past
+// the switch distance, and switching back until it's that fraction short of it. Something sitting
+// right at a switch distance then doesn't pop back and forth.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodDistances {
+    pub switch: Vec<f32>,
+    pub hysteresis: f32,
+}
+
+impl LodDistances {
+    pub fn new(switch: Vec<f32>, hysteresis: f32) -> Self {
+        Self { switch, hysteresis }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.switch.len() + 1
+    }
+
+    // The level to use at `distance` for something currently at level `current`
+    pub fn select(&self, distance: f32, current: usize) -> usize {
+        let mut level = current.min(self.switch.len());
+        while level < self.switch.len() && distance > self.switch[level] * (1.0 + self.hysteresis) {
+            level += 1;
+        }
+        while level > 0 && distance < self.switch[level - 1] * (1.0 - self.hysteresis) {
+            level -= 1;
+        }
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_level_for_the_distance() {
+        let lod = LodDistances::new(vec![10.0, 20.0], 0.0);
+        assert_eq!(lod.levels(), 3);
+        assert_eq!(lod.select(5.0, 0), 0);
+        assert_eq!(lod.select(15.0, 0), 1);
+        assert_eq!(lod.select(50.0, 0), 2);
+        assert_eq!(lod.select(5.0, 2), 0);
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_current_level_near_a_switch() {
+        let lod = LodDistances::new(vec![10.0], 0.1);
+        // Inside the band either level sticks
+        assert_eq!(lod.select(10.5, 0), 0);
+        assert_eq!(lod.select(9.5, 1), 1);
+        // Past it they switch
+        assert_eq!(lod.select(11.5, 0), 1);
+        assert_eq!(lod.select(8.5, 1), 0);
+    }
+
+    #[test]
+    fn out_of_range_levels_get_clamped() {
+        let lod = LodDistances::new(vec![10.0], 0.0);
+        assert_eq!(lod.select(15.0, 7), 1);
+        assert_eq!(lod.select(5.0, 7), 0);
+    }
+}
@@ -0,0 +1,137 @@
+// 2D gradient noise after Ken Perlin's improved noise, with a permutation shuffled by a seed
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    // Doubled, so lookups of index + 1 don't need wrapping
+    permutation: [u8; 512],
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Dot product of the offset with one of eight gradient directions
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Self {
+        let mut values = [0u8; 256];
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = index as u8;
+        }
+
+        // Fisher-Yates with a xorshift generator, never seeded with 0
+        let mut state = seed ^ 0x9e37_79b9;
+        if state == 0 {
+            state = 1;
+        }
+        for index in (1..values.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            values.swap(index, state as usize % (index + 1));
+        }
+
+        let mut permutation = [0u8; 512];
+        for (index, value) in permutation.iter_mut().enumerate() {
+            *value = values[index % 256];
+        }
+        Self { permutation }
+    }
+
+    // Roughly in -1..1, and 0 on every integer coordinate
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let xi = (x0 as i32 & 255) as usize;
+        let yi = (y0 as i32 & 255) as usize;
+
+        let p = &self.permutation;
+        let corner = |ox: usize, oy: usize| p[p[xi + ox] as usize + yi + oy];
+        let (u, v) = (fade(dx), fade(dy));
+
+        lerp(
+            lerp(
+                gradient(corner(0, 0), dx, dy),
+                gradient(corner(1, 0), dx - 1.0, dy),
+                u,
+            ),
+            lerp(
+                gradient(corner(0, 1), dx, dy - 1.0),
+                gradient(corner(1, 1), dx - 1.0, dy - 1.0),
+                u,
+            ),
+            v,
+        )
+    }
+
+    // Fractal Brownian motion: `octaves` layers of noise, each `lacunarity` times the frequency
+    // and `gain` times the amplitude of the one before. Normalized to roughly -1..1.
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..octaves {
+            sum += self.noise(x * frequency, y * frequency) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+
+        if total_amplitude > 0.0 {
+            sum / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_noise() {
+        let a = Perlin::new(7);
+        let b = Perlin::new(7);
+        let c = Perlin::new(8);
+        assert_eq!(a.noise(1.3, 4.7), b.noise(1.3, 4.7));
+        assert_ne!(a.noise(1.3, 4.7), c.noise(1.3, 4.7));
+    }
+
+    #[test]
+    fn zero_on_the_lattice_and_bounded_between() {
+        let perlin = Perlin::new(1);
+        for y in -20..20 {
+            for x in -20..20 {
+                assert_eq!(perlin.noise(x as f32, y as f32), 0.0);
+                let value = perlin.fbm(x as f32 * 0.37, y as f32 * 0.53, 5, 2.0, 0.5);
+                assert!(value.abs() <= 1.0, "fbm out of range: {}", value);
+            }
+        }
+    }
+
+    #[test]
+    fn continuous() {
+        let perlin = Perlin::new(3);
+        let (x, y) = (2.4, -7.9);
+        let step = 0.001;
+        let difference = (perlin.noise(x + step, y) - perlin.noise(x, y)).abs();
+        assert!(difference < 0.01, "jumped by {}", difference);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::aabb::Aabb;
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // Normalized
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    // The ray through a point on the screen, given in normalized device coordinates (-1..1, y
+    // up). `view_proj` has to map depth to 0..1 like the camera's matrix does.
+    pub fn from_ndc(view_proj: &Matrix4<f32>, x: f32, y: f32) -> Option<Self> {
+        let inverse = view_proj.invert()?;
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(x, y, z, 1.0);
+            Point3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+        };
+
+        let near = unproject(0.0);
+        let far = unproject(1.0);
+        Some(Self::new(near, far - near))
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    // Distance to where the ray enters the box, zero if it starts inside of it
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+
+        for axis in 0..3 {
+            // Dividing by a zero direction gives infinities, which the min/max below handle
+            let inverse = 1.0 / self.direction[axis];
+            let t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+            if near > far {
+                return None;
+            }
+        }
+
+        Some(near)
+    }
+
+    // Möller–Trumbore, hits on either side of the triangle count
+    pub fn intersect_triangle(
+        &self,
+        a: Point3<f32>,
+        b: Point3<f32>,
+        c: Point3<f32>,
+    ) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(q) * inverse;
+        if distance >= 0.0 {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+
+    // Distance to where the ray crosses the plane through `point`, from either side
+    pub fn intersect_plane(&self, point: Point3<f32>, normal: Vector3<f32>) -> Option<f32> {
+        let denominator = self.direction.dot(normal);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let distance = (point - self.origin).dot(normal) / denominator;
+        if distance >= 0.0 {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+
+    // The closest points between the ray and the infinite line through `point` along
+    // `direction`, as distances along each. None if they're parallel.
+    pub fn closest_to_line(
+        &self,
+        point: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<(f32, f32)> {
+        let direction = direction.normalize();
+        let facing = self.direction.dot(direction);
+        let denominator = 1.0 - facing * facing;
+        if denominator < 1e-8 {
+            return None;
+        }
+
+        let offset = self.origin - point;
+        let along_ray = offset.dot(self.direction);
+        let along_line = offset.dot(direction);
+        let ray_distance = (facing * along_line - along_ray) / denominator;
+        let line_distance = (along_line - facing * along_ray) / denominator;
+        Some((ray_distance.max(0.0), line_distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Camera, Projection};
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn hits_box_in_front() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(4.0));
+    }
+
+    #[test]
+    fn misses_box_behind_or_beside() {
+        let behind = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(behind.intersect_aabb(&unit_box()), None);
+
+        let beside = Ray::new(Point3::new(3.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(beside.intersect_aabb(&unit_box()), None);
+    }
+
+    #[test]
+    fn starting_inside_the_box_hits_at_zero() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.0));
+    }
+
+    #[test]
+    fn triangle_hit_and_miss() {
+        let a = Point3::new(-1.0, -1.0, 0.0);
+        let b = Point3::new(1.0, -1.0, 0.0);
+        let c = Point3::new(0.0, 1.0, 0.0);
+
+        let hit = Ray::new(Point3::new(0.0, 0.0, 2.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(hit.intersect_triangle(a, b, c), Some(2.0));
+
+        let miss = Ray::new(Point3::new(2.0, 0.0, 2.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(miss.intersect_triangle(a, b, c), None);
+    }
+
+    #[test]
+    fn plane_hit_from_either_side() {
+        let normal = Vector3::unit_y();
+        let above = Ray::new(Point3::new(0.0, 2.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        assert_eq!(
+            above.intersect_plane(Point3::new(0.0, 0.0, 0.0), normal),
+            Some(2.0)
+        );
+
+        let below = Ray::new(Point3::new(1.0, -3.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            below.intersect_plane(Point3::new(0.0, 0.0, 0.0), normal),
+            Some(3.0)
+        );
+
+        let away = Ray::new(Point3::new(0.0, 2.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            away.intersect_plane(Point3::new(0.0, 0.0, 0.0), normal),
+            None
+        );
+
+        let parallel = Ray::new(Point3::new(0.0, 2.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(
+            parallel.intersect_plane(Point3::new(0.0, 0.0, 0.0), normal),
+            None
+        );
+    }
+
+    #[test]
+    fn closest_point_on_a_crossing_line() {
+        // Passes one unit above the X axis, crossing it at x = 3
+        let ray = Ray::new(Point3::new(3.0, 1.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        let (ray_distance, line_distance) = ray
+            .closest_to_line(Point3::new(0.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0))
+            .unwrap();
+        assert!((ray_distance - 5.0).abs() < 1e-5);
+        assert!((line_distance - 3.0).abs() < 1e-5);
+
+        let parallel = Ray::new(Point3::new(0.0, 1.0, 0.0), Vector3::unit_x());
+        assert_eq!(
+            parallel.closest_to_line(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x()),
+            None
+        );
+    }
+
+    #[test]
+    fn center_of_the_screen_looks_at_the_target() {
+        let camera = Camera {
+            eye: (0.0, 1.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+        let ray = Ray::from_ndc(&camera.build_view_projection_matrix(), 0.0, 0.0).unwrap();
+
+        let expected = (camera.target - camera.eye).normalize();
+        assert!((ray.direction - expected).magnitude() < 1e-4);
+        assert!((ray.origin - camera.eye).magnitude() < camera.znear + 1e-4);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::backend::{Backend, Cgmath, MathBackend};
+use crate::transform::Transform;
+use cgmath::{Matrix4, SquareMatrix};
+
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    // Relative to the parent, in the pose the mesh was modelled in
+    pub rest: Transform,
+    // Takes mesh space to the joint's space in the rest pose
+    pub inverse_bind: Matrix4<f32>,
+}
+
+// Joints in a hierarchy, parents always before their children. A pose is one local transform per
+// joint, in the same order.
+#[derive(Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the joint's index. The inverse bind matrix comes from the rest pose.
+    pub fn add_joint(&mut self, name: &str, parent: Option<usize>, rest: Transform) -> usize {
+        let parent_world = match parent {
+            Some(parent) => {
+                assert!(parent < self.joints.len(), "Parents go before children");
+                self.rest_world(parent)
+            }
+            None => Matrix4::identity(),
+        };
+        let inverse_bind = (parent_world * rest.matrix())
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+
+        self.joints.push(Joint {
+            name: name.to_string(),
+            parent,
+            rest,
+            inverse_bind,
+        });
+        self.joints.len() - 1
+    }
+
+    fn rest_world(&self, joint: usize) -> Matrix4<f32> {
+        let local = self.joints[joint].rest.matrix();
+        match self.joints[joint].parent {
+            Some(parent) => self.rest_world(parent) * local,
+            None => local,
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    // A weight of 1 for the named joints and everything below them and 0 for the rest, for
+    // blending a clip into part of the skeleton
+    pub fn mask(&self, names: &[&str]) -> Vec<f32> {
+        let mut mask: Vec<f32> = Vec::with_capacity(self.joints.len());
+        for joint in &self.joints {
+            let masked = names.contains(&joint.name.as_str())
+                || joint.parent.is_some_and(|parent| mask[parent] > 0.0);
+            mask.push(if masked { 1.0 } else { 0.0 });
+        }
+        mask
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    // Every joint's transform in mesh space
+    pub fn world_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        self.world_matrices_with::<Cgmath>(pose)
+    }
+
+    pub fn world_matrices_with<B: Backend>(&self, pose: &[Transform]) -> Vec<B::Matrix> {
+        let mut world: Vec<B::Matrix> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose) {
+            let local = local.matrix_with::<B>();
+            world.push(match joint.parent {
+                Some(parent) => B::multiply(&world[parent], &local),
+                None => local,
+            });
+        }
+        world
+    }
+
+    // What the vertex shader blends: takes a vertex from where it was modelled to where the pose
+    // puts it, per joint
+    pub fn skinning_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        self.skinning_matrices_with::<Cgmath>(pose)
+    }
+
+    pub fn skinning_matrices_with<B: Backend>(&self, pose: &[Transform]) -> Vec<B::Matrix> {
+        self.world_matrices_with::<B>(pose)
+            .iter()
+            .zip(&self.joints)
+            .map(|(world, joint)| B::multiply(world, &B::matrix(&joint.inverse_bind)))
+            .collect()
+    }
+
+    // Worked out by `backend`, and handed back as cgmath for uploading
+    pub fn skinning_matrices_in(
+        &self,
+        backend: MathBackend,
+        pose: &[Transform],
+    ) -> Vec<Matrix4<f32>> {
+        fn run<B: Backend>(skeleton: &Skeleton, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+            skeleton
+                .skinning_matrices_with::<B>(pose)
+                .iter()
+                .map(B::to_cgmath)
+                .collect()
+        }
+        match backend {
+            MathBackend::Cgmath => run::<Cgmath>(self, pose),
+            #[cfg(feature = "glam")]
+            MathBackend::Glam => run::<crate::backend::Glam>(self, pose),
+            #[cfg(feature = "nalgebra")]
+            MathBackend::Nalgebra => run::<crate::backend::Nalgebra>(self, pose),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, InnerSpace, Point3, Quaternion, Rotation3, Transform as _, Vector3};
+
+    fn arm() -> Skeleton {
+        let mut skeleton = Skeleton::new();
+        let shoulder = skeleton.add_joint(
+            "shoulder",
+            None,
+            Transform::from_translation(Vector3::new(0.0, 1.0, 0.0)),
+        );
+        skeleton.add_joint(
+            "elbow",
+            Some(shoulder),
+            Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)),
+        );
+        skeleton
+    }
+
+    #[test]
+    fn rest_pose_skins_to_identity() {
+        let skeleton = arm();
+        for matrix in skeleton.skinning_matrices(&skeleton.rest_pose()) {
+            let difference = matrix - Matrix4::identity();
+            let columns = [difference.x, difference.y, difference.z, difference.w];
+            assert!(columns.iter().all(|column| column.magnitude() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn masks_include_children() {
+        let skeleton = arm();
+        assert_eq!(skeleton.mask(&["shoulder"]), vec![1.0, 1.0]);
+        assert_eq!(skeleton.mask(&["elbow"]), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        pose[0].rotation = Quaternion::from_angle_z(Deg(90.0));
+
+        // The hand, modelled at (2, 1, 0), ends up above the shoulder
+        let elbow = skeleton.find("elbow").unwrap();
+        let hand =
+            skeleton.skinning_matrices(&pose)[elbow].transform_point(Point3::new(2.0, 1.0, 0.0));
+        assert!((hand - Point3::new(0.0, 3.0, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn every_backend_skins_the_same() {
+        let skeleton = arm();
+        let mut pose = skeleton.rest_pose();
+        pose[0].rotation = Quaternion::from_angle_z(Deg(90.0));
+        pose[1].scale = Vector3::new(1.0, 2.0, 0.5);
+
+        let expected = skeleton.skinning_matrices(&pose);
+        for backend in MathBackend::ALL {
+            let matrices = skeleton.skinning_matrices_in(*backend, &pose);
+            for (matrix, expected) in matrices.iter().zip(&expected) {
+                let difference = matrix - expected;
+                let columns = [difference.x, difference.y, difference.z, difference.w];
+                assert!(columns.iter().all(|column| column.magnitude() < 1e-5));
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::aabb::Aabb;
+use cgmath::{InnerSpace, Matrix4, MetricSpace, Point3, Transform};
+
+// Bounding sphere, cheaper to test against planes than a box but usually looser
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Point3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    // Centered on the points' bounding box, which isn't the smallest sphere but close enough.
+    // No points give a sphere of radius 0 at the origin.
+    pub fn from_points(points: &[Point3<f32>]) -> Self {
+        if points.is_empty() {
+            return Self::new(Point3::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let center = Aabb::from_points(points.iter().cloned()).center();
+        let radius = points
+            .iter()
+            .map(|point| center.distance2(*point))
+            .fold(0.0, f32::max)
+            .sqrt();
+        Self::new(center, radius)
+    }
+
+    // Scaled by the largest axis scale, so it still encloses everything after non-uniform scaling
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let scale = matrix
+            .x
+            .truncate()
+            .magnitude2()
+            .max(matrix.y.truncate().magnitude2())
+            .max(matrix.z.truncate().magnitude2())
+            .sqrt();
+        Self::new(matrix.transform_point(self.center), self.radius * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+
+    #[test]
+    fn from_points_encloses_all_of_them() {
+        let points = [
+            Point3::new(1.0, -2.0, 0.5),
+            Point3::new(-3.0, 4.0, 2.0),
+            Point3::new(0.0, 0.0, -1.0),
+        ];
+        let sphere = Sphere::from_points(&points);
+        for point in &points {
+            assert!(sphere.center.distance(*point) <= sphere.radius + 1e-5);
+        }
+    }
+
+    #[test]
+    fn transform_moves_and_scales_by_the_largest_axis() {
+        let sphere = Sphere::new(Point3::new(1.0, 0.0, 0.0), 2.0);
+        let matrix = Matrix4::from_translation(Vector3::new(0.0, 5.0, 0.0))
+            * Matrix4::from_nonuniform_scale(1.0, 3.0, 0.5);
+        let transformed = sphere.transform(&matrix);
+        assert_eq!(transformed.center, Point3::new(1.0, 5.0, 0.0));
+        assert!((transformed.radius - 6.0).abs() < 1e-5);
+    }
+}
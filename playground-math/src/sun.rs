@@ -0,0 +1,66 @@
+use crate::color::Color;
+use cgmath::{InnerSpace, Vector3};
+use std::f32::consts::PI;
+
+// How far the sun's path leans away from straight overhead, towards -z. Noon is this far from
+// the zenith.
+const PATH_TILT: f32 = 25.0 * PI / 180.0;
+
+// Optical depth of the whole atmosphere straight up, for red, green and blue. Rayleigh scattering
+// by the air, which takes out much more blue, and Mie scattering by haze, the same for all.
+const RAYLEIGH_DEPTH: [f32; 3] = [0.0464, 0.108, 0.265];
+const MIE_DEPTH: f32 = 0.0277;
+
+// Towards the sun at `time_of_day` hours, from 0 to 24. It rises at +x at 6, is highest at noon
+// and sets at -x at 18, and spends the night below the horizon.
+pub fn direction(time_of_day: f32) -> Vector3<f32> {
+    let angle = (time_of_day - 6.0) / 12.0 * PI;
+    let (sin, cos) = angle.sin_cos();
+    Vector3::new(cos, sin * PATH_TILT.cos(), -sin * PATH_TILT.sin())
+}
+
+// How much of the sun's light makes it through the atmosphere towards `direction`, reddening as
+// the light goes through more air near the horizon. Nothing once it's set.
+pub fn transmittance(direction: Vector3<f32>) -> Color {
+    let elevation = direction.normalize().y.asin().to_degrees();
+    if elevation <= 0.0 {
+        return Color::BLACK;
+    }
+    // Kasten and Young's relative air mass, which stays finite at the horizon
+    let zenith = 90.0 - elevation;
+    let air_mass = 1.0 / (zenith.to_radians().cos() + 0.50572 * (96.07995 - zenith).powf(-1.6364));
+    let channel = |rayleigh: f32| (-(rayleigh + MIE_DEPTH) * air_mass).exp();
+    Color::new(
+        channel(RAYLEIGH_DEPTH[0]),
+        channel(RAYLEIGH_DEPTH[1]),
+        channel(RAYLEIGH_DEPTH[2]),
+        1.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rises_peaks_and_sets() {
+        assert!(direction(6.0).y.abs() < 1e-5);
+        assert!(direction(18.0).y.abs() < 1e-5);
+        assert!(direction(6.0).x > 0.99 && direction(18.0).x < -0.99);
+        assert!((direction(12.0).y - PATH_TILT.cos()).abs() < 1e-5);
+        assert!(direction(0.0).y < -0.9);
+        for hour in 0..24 {
+            assert!((direction(hour as f32).magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn low_sun_is_dimmer_and_redder() {
+        let noon = transmittance(direction(12.0));
+        let evening = transmittance(direction(17.8));
+        assert!(noon.r > evening.r && noon.b > evening.b);
+        assert!(evening.b / evening.r < noon.b / noon.r);
+        assert!(noon.r < 1.0 && noon.r > 0.8);
+        assert_eq!(transmittance(direction(22.0)), Color::BLACK);
+    }
+}
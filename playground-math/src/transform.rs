@@ -0,0 +1,72 @@
+use crate::backend::{Backend, Cgmath};
+use cgmath::{Matrix4, One, Quaternion, Vector3};
+
+// Translation, rotation and scale of an object, applied in reverse order
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        self.matrix_with::<Cgmath>()
+    }
+
+    // The same matrix, built by `B`
+    pub fn matrix_with<B: Backend>(&self) -> B::Matrix {
+        B::compose(
+            B::vector(self.translation),
+            B::quaternion(self.rotation),
+            B::vector(self.scale),
+        )
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, InnerSpace, Point3, Rotation3, SquareMatrix, Transform as _};
+
+    #[test]
+    fn identity_matrix() {
+        assert_eq!(Transform::identity().matrix(), Matrix4::identity());
+    }
+
+    #[test]
+    fn scales_then_rotates_then_translates() {
+        let transform = Transform {
+            translation: Vector3::new(10.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_z(Deg(90.0)),
+            scale: Vector3::new(2.0, 1.0, 1.0),
+        };
+
+        // (1, 0, 0) -> (2, 0, 0) -> (0, 2, 0) -> (10, 2, 0)
+        let point = transform
+            .matrix()
+            .transform_point(Point3::new(1.0, 0.0, 0.0));
+        assert!((point - Point3::new(10.0, 2.0, 0.0)).magnitude() < 1e-5);
+    }
+}
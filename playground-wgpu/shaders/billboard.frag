@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0)
+uniform BillboardUniforms {
+    mat4 u_view_proj;
+    vec4 u_camera_right;
+    vec4 u_camera_up;
+    vec4 u_camera_position;
+    uint u_mode;
+    float u_alpha_cutoff;
+};
+
+layout(set = 1, binding = 0) uniform texture2D t_atlas;
+layout(set = 1, binding = 1) uniform sampler s_atlas;
+
+void main() {
+    vec4 color = texture(sampler2D(t_atlas, s_atlas), v_tex_coords) * v_color;
+    if (color.a < u_alpha_cutoff) {
+        discard;
+    }
+    f_color = color;
+}
@@ -0,0 +1,59 @@
+#version 450
+
+// Per billboard
+layout(location = 0) in vec3 a_position;
+// Width and height
+layout(location = 1) in vec2 a_size;
+// Where its cell is in the atlas, the top left corner in xy and the bottom right in zw
+layout(location = 2) in vec4 a_cell;
+layout(location = 3) in vec4 a_color;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
+
+layout(set = 0, binding = 0)
+uniform BillboardUniforms {
+    mat4 u_view_proj;
+    vec4 u_camera_right;
+    vec4 u_camera_up;
+    vec4 u_camera_position;
+    // 0 spherical, 1 cylindrical
+    uint u_mode;
+    // Fragments less opaque than this get discarded
+    float u_alpha_cutoff;
+};
+
+const uint MODE_CYLINDRICAL = 1;
+
+// Two triangles per billboard, expanded from the vertex index
+const vec2 CORNERS[6] = vec2[6](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, 1.0)
+);
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    v_tex_coords = mix(a_cell.xy, a_cell.zw, vec2(corner.x * 0.5 + 0.5, 0.5 - corner.y * 0.5));
+    v_color = a_color;
+
+    // Spherical billboards lie in the view plane, cylindrical ones stand upright and only turn
+    // around y towards the camera
+    vec3 right = u_camera_right.xyz;
+    vec3 up = u_camera_up.xyz;
+    if (u_mode == MODE_CYLINDRICAL) {
+        vec3 to_camera = u_camera_position.xyz - a_position;
+        vec3 across = vec3(to_camera.z, 0.0, -to_camera.x);
+        // Straight above or below, where any way around would do
+        if (dot(across, across) > 1e-8) {
+            right = normalize(across);
+        }
+        up = vec3(0.0, 1.0, 0.0);
+    }
+
+    vec3 offset = (right * corner.x * a_size.x + up * corner.y * a_size.y) * 0.5;
+    gl_Position = u_view_proj * vec4(a_position + offset, 1.0);
+}
@@ -0,0 +1,30 @@
+#version 450
+
+// The dart shape, pointing along +z
+layout(location = 0) in vec3 a_position;
+// Per boid, straight out of the simulation's storage buffer
+layout(location = 1) in vec4 a_boid_position;
+layout(location = 2) in vec4 a_boid_velocity;
+
+layout(location = 0) out vec4 v_color;
+
+layout(set = 0, binding = 0)
+uniform BoidRenderUniforms {
+    mat4 u_view_proj;
+    float u_size;
+};
+
+void main() {
+    vec3 forward = vec3(0.0, 0.0, 1.0);
+    if (length(a_boid_velocity.xyz) > 0.0) {
+        forward = normalize(a_boid_velocity.xyz);
+    }
+    vec3 up = abs(forward.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(forward, up));
+    up = cross(right, forward);
+
+    vec3 offset = (right * a_position.x + up * a_position.y + forward * a_position.z) * u_size;
+    // Colored by heading, darker toward the tail
+    v_color = vec4((0.5 + 0.5 * forward) * (0.6 + 0.4 * a_position.z), 1.0);
+    gl_Position = u_view_proj * vec4(a_boid_position.xyz + offset, 1.0);
+}
@@ -0,0 +1,36 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+
+layout(set = 1, binding = 0) uniform texture3D t_lut;
+layout(set = 1, binding = 1) uniform sampler s_lut;
+
+layout(set = 2, binding = 0) uniform Grading {
+    float u_strength;
+    float u_lut_size;
+};
+
+vec3 srgb_from_linear(vec3 linear) {
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+    // The tonemapped frame, already in 0..1
+    vec3 color = texture(sampler2D(t_source, s_source), v_tex_coords).rgb;
+
+    // LUTs map sRGB encoded colors. The outermost texels sit half a texel in from the edges of
+    // the cube, where 0 and 1 have to end up.
+    vec3 encoded = srgb_from_linear(clamp(color, 0.0, 1.0));
+    vec3 coords = encoded * ((u_lut_size - 1.0) / u_lut_size) + 0.5 / u_lut_size;
+    // The LUT texture is sRGB too, so this comes out linear
+    vec3 graded = texture(sampler3D(t_lut, s_lut), coords).rgb;
+
+    f_color = vec4(mix(color, graded, u_strength), 1.0);
+}
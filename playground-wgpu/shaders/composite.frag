@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+
+vec3 linear_from_srgb(vec3 srgb) {
+    bvec3 cutoff = lessThan(srgb, vec3(0.04045));
+    vec3 lower = srgb / vec3(12.92);
+    vec3 higher = pow((srgb + vec3(0.055)) / vec3(1.055), vec3(2.4));
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+    // The source holds premultiplied sRGB colors. Un-premultiply before converting to linear,
+    // converting premultiplied values directly darkens every partially covered edge.
+    vec4 color = texture(sampler2D(t_source, s_source), v_tex_coords);
+    if (color.a <= 0.0) {
+        discard;
+    }
+
+    vec3 linear = linear_from_srgb(color.rgb / color.a);
+    f_color = vec4(linear * color.a, color.a);
+}
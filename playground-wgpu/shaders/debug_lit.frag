@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 1) in vec3 v_position;
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec3 normal = normalize(cross(dFdy(v_position), dFdx(v_position)));
+    vec3 sun_direction = normalize(vec3(0.4, 0.8, 0.3));
+    // The derived normal always faces the camera, so both sides get lit the same
+    float diffuse = abs(dot(normal, sun_direction));
+    f_color = vec4(vec3(0.3 + 0.7 * diffuse), 1.0);
+}
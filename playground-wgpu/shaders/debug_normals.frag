@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 1) in vec3 v_position;
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // Flat normals from the screen space derivatives, facing the camera with y going down
+    vec3 normal = normalize(cross(dFdy(v_position), dFdx(v_position)));
+    f_color = vec4(normal * 0.5 + 0.5, 1.0);
+}
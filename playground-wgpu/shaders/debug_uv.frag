@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+// Squares along each side of the texture
+const float CHECKS = 8.0;
+
+void main() {
+    vec2 check = floor(v_tex_coords * CHECKS);
+    float shade = mod(check.x + check.y, 2.0) == 0.0 ? 1.0 : 0.5;
+    // Red grows along u and green along v, so flipped coordinates stand out
+    f_color = vec4(vec3(v_tex_coords, 0.0) * 0.6 + shade * 0.4, 1.0);
+}
@@ -0,0 +1,82 @@
+#version 450
+
+layout(location = 0) flat in mat4 v_world_to_decal;
+layout(location = 4) flat in vec4 v_cell;
+layout(location = 5) flat in vec4 v_color;
+layout(location = 6) flat in vec3 v_tangent;
+layout(location = 7) flat in vec3 v_bitangent;
+layout(location = 8) flat in vec3 v_normal;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0)
+uniform DecalUniforms {
+    mat4 u_view_proj;
+    mat4 u_inverse_view_proj;
+    vec4 u_camera_position;
+    vec2 u_viewport_size;
+    float u_normal_strength;
+    float u_angle_fade;
+};
+
+layout(set = 1, binding = 0) uniform texture2D t_depth;
+layout(set = 1, binding = 1) uniform sampler s_depth;
+
+layout(set = 2, binding = 0) uniform texture2D t_atlas;
+layout(set = 2, binding = 1) uniform sampler s_atlas;
+
+layout(set = 3, binding = 0)
+uniform LightUniforms {
+    // The way the light travels, in w nothing
+    vec4 u_light_direction;
+    // Already scaled by the intensity
+    vec4 u_light_color;
+    vec4 u_ambient;
+};
+
+void main() {
+    vec2 screen_uv = gl_FragCoord.xy / u_viewport_size;
+    float depth = texture(sampler2D(t_depth, s_depth), screen_uv).r;
+    vec2 ndc = vec2(screen_uv.x * 2.0 - 1.0, 1.0 - screen_uv.y * 2.0);
+    vec4 world = u_inverse_view_proj * vec4(ndc, depth, 1.0);
+    vec3 position = world.xyz / world.w;
+
+    // The surface's normal from how its position changes between neighbouring pixels, worked
+    // out before any of them get discarded. Which way the cross product goes depends on the
+    // screen's axes, so turn it towards the camera.
+    vec3 surface_normal = normalize(cross(dFdx(position), dFdy(position)));
+    if (dot(surface_normal, u_camera_position.xyz - position) < 0.0) {
+        surface_normal = -surface_normal;
+    }
+
+    vec3 local = (v_world_to_decal * vec4(position, 1.0)).xyz;
+    if (any(greaterThan(abs(local), vec3(0.5)))) {
+        discard;
+    }
+
+    // Gone on surfaces turning away from the decal, and fading out towards the front and back
+    // of its box
+    float fade = smoothstep(u_angle_fade, u_angle_fade + 0.2, dot(surface_normal, v_normal))
+        * (1.0 - smoothstep(0.35, 0.5, abs(local.z)));
+    if (fade <= 0.0) {
+        discard;
+    }
+
+    // The top of the cell is along the decal's y axis
+    vec2 uv = mix(v_cell.xy, v_cell.zw, vec2(local.x + 0.5, 0.5 - local.y));
+    vec4 texel = texture(sampler2D(t_atlas, s_atlas), uv);
+
+    // Taking coverage as height, the decal's edges bend the normal away from where it rises.
+    // Texture v goes down while the bitangent goes up.
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2D(t_atlas, s_atlas), 0));
+    float right = texture(sampler2D(t_atlas, s_atlas), uv + vec2(texel_size.x, 0.0)).a;
+    float below = texture(sampler2D(t_atlas, s_atlas), uv + vec2(0.0, texel_size.y)).a;
+    vec2 slope = vec2(right - texel.a, below - texel.a) * u_normal_strength;
+    vec3 normal = normalize(surface_normal - slope.x * v_tangent + slope.y * v_bitangent);
+
+    // The decal takes the place of the surface's albedo, lit once by the scene's own light
+    vec4 albedo = texel * v_color;
+    float diffuse = max(dot(normal, -u_light_direction.xyz), 0.0);
+    vec3 light = u_ambient.rgb + u_light_color.rgb * diffuse;
+    f_color = vec4(albedo.rgb * light, albedo.a * fade);
+}
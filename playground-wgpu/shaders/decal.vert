@@ -0,0 +1,64 @@
+#version 450
+
+layout(location = 0) in vec4 a_decal_to_world_0;
+layout(location = 1) in vec4 a_decal_to_world_1;
+layout(location = 2) in vec4 a_decal_to_world_2;
+layout(location = 3) in vec4 a_decal_to_world_3;
+layout(location = 4) in vec4 a_world_to_decal_0;
+layout(location = 5) in vec4 a_world_to_decal_1;
+layout(location = 6) in vec4 a_world_to_decal_2;
+layout(location = 7) in vec4 a_world_to_decal_3;
+layout(location = 8) in vec4 a_cell;
+layout(location = 9) in vec4 a_color;
+
+layout(location = 0) flat out mat4 v_world_to_decal;
+layout(location = 4) flat out vec4 v_cell;
+layout(location = 5) flat out vec4 v_color;
+layout(location = 6) flat out vec3 v_tangent;
+layout(location = 7) flat out vec3 v_bitangent;
+layout(location = 8) flat out vec3 v_normal;
+
+layout(set = 0, binding = 0)
+uniform DecalUniforms {
+    mat4 u_view_proj;
+    mat4 u_inverse_view_proj;
+    vec4 u_camera_position;
+    vec2 u_viewport_size;
+    float u_normal_strength;
+    float u_angle_fade;
+};
+
+// Corner i of the unit cube is at the bits of i, x first, and every side winds counter-clockwise
+// seen from outside
+const int INDICES[36] = int[36](
+    0, 2, 1, 1, 2, 3,
+    4, 5, 6, 5, 7, 6,
+    0, 4, 2, 4, 6, 2,
+    1, 3, 5, 5, 3, 7,
+    0, 1, 4, 1, 5, 4,
+    2, 6, 3, 3, 6, 7
+);
+
+void main() {
+    int corner = INDICES[gl_VertexIndex];
+    vec3 position = vec3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1) - 0.5;
+
+    mat4 decal_to_world = mat4(
+        a_decal_to_world_0,
+        a_decal_to_world_1,
+        a_decal_to_world_2,
+        a_decal_to_world_3
+    );
+    v_world_to_decal = mat4(
+        a_world_to_decal_0,
+        a_world_to_decal_1,
+        a_world_to_decal_2,
+        a_world_to_decal_3
+    );
+    v_cell = a_cell;
+    v_color = a_color;
+    v_tangent = normalize(a_decal_to_world_0.xyz);
+    v_bitangent = normalize(a_decal_to_world_1.xyz);
+    v_normal = normalize(a_decal_to_world_2.xyz);
+    gl_Position = u_view_proj * decal_to_world * vec4(position, 1.0);
+}
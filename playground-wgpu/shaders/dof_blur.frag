@@ -0,0 +1,70 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+// The blurred scene, and in alpha how much of it to show over the sharp one
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_color;
+layout(set = 0, binding = 1) uniform texture2D t_depth;
+layout(set = 0, binding = 2) uniform sampler s_targets;
+
+layout(set = 1, binding = 0)
+uniform DepthOfFieldUniforms {
+    mat4 u_inverse_projection;
+    float u_focus_distance;
+    float u_aperture;
+    // In pixels
+    float u_max_radius;
+    uint u_samples;
+};
+
+const float GOLDEN_ANGLE = 2.39996323;
+
+// Distance from the camera along its view direction
+float view_depth(vec2 uv) {
+    float depth = texture(sampler2D(t_depth, s_targets), uv).r;
+    vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    vec4 position = u_inverse_projection * vec4(ndc, depth, 1.0);
+    return -position.z / position.w;
+}
+
+// The radius of the circle of confusion in pixels, negative in front of the focus
+float circle_of_confusion(vec2 uv) {
+    float depth = view_depth(uv);
+    float coc = u_aperture * (depth - u_focus_distance) / max(depth, 0.0001);
+    return clamp(coc, -1.0, 1.0) * u_max_radius;
+}
+
+void main() {
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2D(t_color, s_targets), 0));
+    float center = circle_of_confusion(v_tex_coords);
+
+    vec3 sum = texture(sampler2D(t_color, s_targets), v_tex_coords).rgb;
+    float total = 1.0;
+    float foreground = 0.0;
+
+    // Gathers from a disc as big as the largest circle, spread evenly along a spiral. Each sample
+    // counts if its own circle reaches this pixel, which is what scattering it would have done.
+    for (uint i = 0; i < u_samples; i++) {
+        float radius = sqrt((float(i) + 0.5) / float(u_samples)) * u_max_radius;
+        float angle = float(i) * GOLDEN_ANGLE;
+        vec2 uv = v_tex_coords + vec2(cos(angle), sin(angle)) * radius * texel_size;
+        float coc = circle_of_confusion(uv);
+
+        // Something behind this pixel can't blur over it further than this pixel is blurred
+        float reach = coc > center ? min(abs(coc), abs(center)) : abs(coc);
+        float weight = smoothstep(radius - 1.0, radius + 1.0, reach);
+        sum += texture(sampler2D(t_color, s_targets), uv).rgb * weight;
+        total += weight;
+        if (coc < center) {
+            foreground += weight;
+        }
+    }
+
+    // Out of focus pixels show the blur, and so do sharp ones that something blurry in front of
+    // them reaches over
+    float blend = max(
+        smoothstep(0.5, 1.5, abs(center)),
+        clamp(4.0 * foreground / max(float(u_samples), 1.0), 0.0, 1.0));
+    f_color = vec4(sum / total, blend);
+}
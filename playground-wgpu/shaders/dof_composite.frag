@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_color;
+// What dof_blur.frag wrote, with the blend in alpha
+layout(set = 0, binding = 1) uniform texture2D t_blurred;
+layout(set = 0, binding = 2) uniform sampler s_targets;
+
+void main() {
+    vec3 sharp = texture(sampler2D(t_color, s_targets), v_tex_coords).rgb;
+    vec4 blurred = texture(sampler2D(t_blurred, s_targets), v_tex_coords);
+    f_color = vec4(mix(sharp, blurred.rgb, blurred.a), 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0)
+uniform FrameUniforms {
+    float u_time;
+    float u_delta_time;
+    uint u_frame;
+};
+
+float hash(vec2 p) {
+    vec3 p3 = fract(vec3(p.xyx) * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+void main() {
+    // Different noise every frame, around 1 so the frame keeps its brightness
+    float noise = hash(gl_FragCoord.xy + float(u_frame % 1024u) * vec2(17.0, 59.0));
+    float factor = 1.0 + 0.12 * (noise - 0.5);
+
+    f_color = vec4(vec3(factor), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec3 v_position;
+
+layout(location = 0) out vec4 f_color;
+layout(location = 1) out float f_roughness;
+
+layout(set = 0, binding = 0)
+uniform MaterialUniforms {
+    vec4 u_tint;
+    float u_roughness;
+};
+
+void main() {
+    // Lit from above by the same sun as the shapes
+    vec3 sun_direction = normalize(vec3(0.4, 0.8, 0.3));
+    vec2 tile = floor(v_position.xz);
+    float checker = mod(tile.x + tile.y, 2.0);
+    vec3 base = u_tint.rgb * (0.6 + 0.4 * checker);
+    f_color = vec4(base * (0.3 + 0.7 * sun_direction.y), 1.0);
+    f_roughness = u_roughness;
+}
@@ -0,0 +1,50 @@
+#version 450
+
+layout(location = 0) out vec2 v_corner;
+layout(location = 1) out vec4 v_color;
+
+struct Particle {
+    // w is the age in seconds, negative until the particle is born
+    vec4 position;
+    vec4 velocity;
+};
+
+layout(set = 0, binding = 0)
+uniform GpuParticleUniforms {
+    mat4 u_view_proj;
+    vec4 u_camera_right;
+    vec4 u_camera_up;
+    vec4 u_start_color;
+    vec4 u_end_color;
+    float u_lifetime;
+    float u_size;
+};
+
+layout(set = 1, binding = 0)
+readonly buffer Particles {
+    Particle particles[];
+};
+
+// Two triangles per particle, expanded from the vertex index
+const vec2 CORNERS[6] = vec2[6](
+    vec2(-1.0, -1.0),
+    vec2(1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, -1.0),
+    vec2(1.0, 1.0),
+    vec2(-1.0, 1.0)
+);
+
+void main() {
+    Particle particle = particles[gl_InstanceIndex];
+    vec2 corner = CORNERS[gl_VertexIndex];
+    v_corner = corner;
+
+    float age = particle.position.w;
+    v_color = mix(u_start_color, u_end_color, clamp(age / u_lifetime, 0.0, 1.0));
+    // Unborn particles get a zero sized quad
+    float size = age < 0.0 ? 0.0 : u_size;
+
+    vec3 offset = (u_camera_right.xyz * corner.x + u_camera_up.xyz * corner.y) * size * 0.5;
+    gl_Position = u_view_proj * vec4(particle.position.xyz + offset, 1.0);
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+
+layout(location = 0) flat out uint v_id;
+
+layout(set = 0, binding = 0)
+uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+layout(set = 1, binding = 0)
+uniform ObjectUniforms {
+    mat4 u_model;
+};
+
+// Objects are drawn as a single instance starting at their ID
+void main() {
+    v_id = uint(gl_InstanceIndex);
+    gl_Position = u_view_proj * u_model * vec4(a_position, 1.0);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in vec4 v_outline_color;
+// Outline width and softness, in distance field units
+layout(location = 3) in vec2 v_style;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform texture2D t_distance;
+layout(set = 1, binding = 1) uniform sampler s_distance;
+
+void main() {
+    // 0.5 on the glyph's edge, higher inside
+    float distance = texture(sampler2D(t_distance, s_distance), v_tex_coords).a;
+    // Keeps edges one pixel wide at any scale
+    float aa = fwidth(distance);
+    float outline = v_style.x;
+    float softness = v_style.y;
+
+    float fill = smoothstep(0.5 - aa - softness, 0.5 + aa, distance);
+    float outer = smoothstep(0.5 - outline - aa - softness, 0.5 - outline + aa, distance);
+
+    vec4 color = mix(v_outline_color, v_color, fill);
+    f_color = vec4(color.rgb, color.a * outer);
+}
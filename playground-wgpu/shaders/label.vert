@@ -0,0 +1,31 @@
+#version 450
+
+layout(location = 0) in vec3 a_anchor;
+layout(location = 1) in vec2 a_offset;
+layout(location = 2) in vec2 a_tex_coords;
+layout(location = 3) in vec4 a_color;
+layout(location = 4) in vec4 a_outline_color;
+layout(location = 5) in vec2 a_style;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) out vec4 v_outline_color;
+layout(location = 3) out vec2 v_style;
+
+layout(set = 0, binding = 0)
+uniform LabelUniforms {
+    mat4 u_view_proj;
+    vec4 u_camera_right;
+    vec4 u_camera_up;
+};
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_color = a_color;
+    v_outline_color = a_outline_color;
+    v_style = a_style;
+
+    // Offsets are in the plane facing the camera, so labels always face it
+    vec3 position = a_anchor + u_camera_right.xyz * a_offset.x + u_camera_up.xyz * a_offset.y;
+    gl_Position = u_view_proj * vec4(position, 1.0);
+}
@@ -0,0 +1,73 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in vec3 v_position;
+
+layout(location = 0) out vec4 f_color;
+
+// Matching `lights::LightKind`
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+struct Light {
+    // The kind in w
+    vec4 position_kind;
+    // The range in w, unused by directional lights
+    vec4 direction_range;
+    vec4 color_intensity;
+    // Cosines of the inner and outer cone angles of spot lights
+    vec4 cone;
+};
+
+layout(set = 1, binding = 0)
+uniform LightUniforms {
+    uint u_light_count;
+    float u_ambient;
+    float u_pulse;
+};
+
+layout(set = 1, binding = 1)
+readonly buffer Lights {
+    Light b_lights[];
+};
+
+layout(set = 2, binding = 0)
+uniform FrameUniforms {
+    float u_time;
+    float u_delta_time;
+    uint u_frame;
+};
+
+void main() {
+    vec3 normal = normalize(v_normal);
+    vec3 lit = vec3(u_ambient * (1.0 + u_pulse * sin(u_time * 2.0)));
+
+    for (uint i = 0u; i < u_light_count; i++) {
+        Light light = b_lights[i];
+        uint kind = uint(light.position_kind.w);
+        vec3 direction = normalize(light.direction_range.xyz);
+
+        vec3 to_light = -direction;
+        float attenuation = 1.0;
+        if (kind != DIRECTIONAL) {
+            vec3 offset = light.position_kind.xyz - v_position;
+            float light_distance = length(offset);
+            to_light = offset / max(light_distance, 1e-4);
+            // Inverse square, smoothly reaching zero at the range
+            float falloff = pow(light_distance / light.direction_range.w, 4.0);
+            float window = clamp(1.0 - falloff, 0.0, 1.0);
+            attenuation = window * window / (light_distance * light_distance + 1.0);
+        }
+        if (kind == SPOT) {
+            float cos_angle = dot(-to_light, direction);
+            attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+        }
+
+        float diffuse = max(dot(normal, to_light), 0.0);
+        lit += light.color_intensity.rgb * light.color_intensity.w * diffuse * attenuation;
+    }
+
+    f_color = vec4(v_color.rgb * lit, v_color.a);
+}
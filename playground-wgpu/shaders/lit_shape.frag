@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in vec3 v_position;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0)
+uniform LightUniforms {
+    // The way the light travels, in w nothing
+    vec4 u_light_direction;
+    // Already scaled by the intensity
+    vec4 u_light_color;
+    vec4 u_ambient;
+};
+
+void main() {
+    float diffuse = max(dot(normalize(v_normal), -u_light_direction.xyz), 0.0);
+    vec3 light = u_ambient.rgb + u_light_color.rgb * diffuse;
+    f_color = vec4(v_color.rgb * light, v_color.a);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec3 v_position;
+layout(location = 0) out vec4 f_color;
+
+// The scene rendered from the mirrored camera, the same size as the frame
+layout(set = 0, binding = 0) uniform texture2D t_reflection;
+layout(set = 0, binding = 1) uniform sampler s_reflection;
+
+layout(set = 3, binding = 0)
+uniform MirrorUniforms {
+    vec4 u_tint;
+    float u_reflectivity;
+};
+
+void main() {
+    // What's reflected here ended up at the same spot on the screen in the reflection
+    vec2 size = vec2(textureSize(sampler2D(t_reflection, s_reflection), 0));
+    vec3 reflection = texture(sampler2D(t_reflection, s_reflection), gl_FragCoord.xy / size).rgb;
+
+    // Tiles under the reflection, so the floor doesn't disappear where little gets reflected
+    vec2 tile = floor(v_position.xz);
+    float checker = mod(tile.x + tile.y, 2.0);
+    vec3 base = u_tint.rgb * (0.6 + 0.4 * checker);
+
+    f_color = vec4(mix(base, reflection, u_reflectivity), 1.0);
+}
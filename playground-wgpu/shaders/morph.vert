@@ -0,0 +1,42 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+layout(location = 2) in vec4 a_color;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec4 v_color;
+
+// Has to match MAX_TARGETS, four weights per vec4
+layout(set = 0, binding = 0)
+uniform MorphUniforms {
+    mat4 u_view_proj;
+    mat4 u_model;
+    // x is the vertex count, y the target count
+    uvec4 u_counts;
+    vec4 u_weights[2];
+};
+
+// Per target, the position and normal delta of every vertex in a row
+layout(set = 1, binding = 0)
+readonly buffer Deltas {
+    vec4 deltas[];
+};
+
+void main() {
+    vec3 position = a_position;
+    vec3 normal = a_normal;
+    uint vertex_count = u_counts.x;
+    for (uint morph = 0; morph < u_counts.y; morph++) {
+        float weight = u_weights[morph / 4][morph % 4];
+        if (weight != 0.0) {
+            uint index = (morph * vertex_count + gl_VertexIndex) * 2;
+            position += weight * deltas[index].xyz;
+            normal += weight * deltas[index + 1].xyz;
+        }
+    }
+
+    v_normal = mat3(u_model) * normal;
+    v_color = a_color;
+    gl_Position = u_view_proj * u_model * vec4(position, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_accum;
+layout(set = 0, binding = 1) uniform texture2D t_revealage;
+layout(set = 0, binding = 2) uniform sampler s_targets;
+
+void main() {
+    // Same size as the frame, so no filtering
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    float revealage = texelFetch(sampler2D(t_revealage, s_targets), texel, 0).r;
+    if (revealage >= 1.0) {
+        // Nothing transparent here
+        discard;
+    }
+
+    vec4 accum = texelFetch(sampler2D(t_accum, s_targets), texel, 0);
+    vec3 color = accum.rgb / max(accum.a, 1e-5);
+    // Blended over the opaque frame by 1 - revealage
+    f_color = vec4(color, 1.0 - revealage);
+}
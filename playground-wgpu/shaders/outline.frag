@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 2, binding = 0)
+uniform OutlineUniforms {
+    vec4 u_color;
+    float u_scale;
+};
+
+void main() {
+    f_color = u_color;
+}
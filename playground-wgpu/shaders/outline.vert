@@ -0,0 +1,24 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+
+layout(set = 0, binding = 0)
+uniform Uniforms {
+    mat4 u_view_proj;
+};
+
+layout(set = 1, binding = 0)
+uniform ObjectUniforms {
+    mat4 u_model;
+};
+
+layout(set = 2, binding = 0)
+uniform OutlineUniforms {
+    vec4 u_color;
+    float u_scale;
+};
+
+void main() {
+    // Grown around the mesh's origin, which is about its middle for the meshes around here
+    gl_Position = u_view_proj * u_model * vec4(a_position * u_scale, 1.0);
+}
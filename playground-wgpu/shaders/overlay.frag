@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform texture2D t_overlay;
+layout(set = 1, binding = 1) uniform sampler s_overlay;
+
+void main() {
+    f_color = v_color * texture(sampler2D(t_overlay, s_overlay), v_tex_coords);
+}
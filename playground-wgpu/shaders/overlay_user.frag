@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform texture2D t_overlay;
+layout(set = 1, binding = 1) uniform sampler s_overlay;
+
+vec3 srgb_from_linear(vec3 linear) {
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+    // User textures are sampled as linear values, but the UI is drawn in sRGB space
+    vec4 texel = texture(sampler2D(t_overlay, s_overlay), v_tex_coords);
+    f_color = v_color * vec4(srgb_from_linear(texel.rgb), texel.a);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in vec2 v_corner;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // Round and soft, fading out toward the edge of the quad
+    float falloff = 1.0 - smoothstep(0.0, 1.0, length(v_corner));
+    f_color = vec4(v_color.rgb, v_color.a * falloff);
+}
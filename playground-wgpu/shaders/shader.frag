@@ -6,6 +6,13 @@ layout(location = 0) out vec4 f_color;
 layout(set = 0, binding = 0) uniform texture2D t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
+layout(set = 2, binding = 0)
+uniform ObjectUniforms {
+    mat4 u_model;
+    float u_opacity;
+};
+
 void main() {
     f_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
+    f_color.a *= u_opacity;
 }
\ No newline at end of file
@@ -4,13 +4,23 @@ layout(location = 0) in vec3 a_position;
 layout(location = 1) in vec2 a_tex_coords;
 
 layout(location = 0) out vec2 v_tex_coords;
+// In world space, for the debug render modes
+layout(location = 1) out vec3 v_position;
 
 layout(set = 1, binding = 0)
 uniform Uniforms {
     mat4 u_view_proj;
 };
 
+layout(set = 2, binding = 0)
+uniform ObjectUniforms {
+    mat4 u_model;
+    float u_opacity;
+};
+
 void main() {
+    vec4 position = u_model * vec4(a_position, 1.0);
     v_tex_coords = a_tex_coords;
-    gl_Position = u_view_proj * vec4(a_position, 1.0);
+    v_position = position.xyz;
+    gl_Position = u_view_proj * position;
 }
\ No newline at end of file
@@ -0,0 +1,30 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_accum;
+layout(location = 1) out float f_revealage;
+
+layout(set = 0, binding = 0) uniform texture2D t_diffuse;
+layout(set = 0, binding = 1) uniform sampler s_diffuse;
+
+layout(set = 2, binding = 0)
+uniform ObjectUniforms {
+    mat4 u_model;
+    float u_opacity;
+};
+
+void main() {
+    vec4 color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coords);
+    color.a *= u_opacity;
+
+    // Weighted blended order independent transparency (McGuire and Bavoil 2013). Nearer and more
+    // opaque fragments count for more in the average, clamped to keep the sums in half floats.
+    float coverage = min(1.0, color.a * 10.0) + 0.01;
+    float distance = 1.0 - gl_FragCoord.z * 0.9;
+    float weight = clamp(coverage * coverage * coverage * 1e8 * distance * distance * distance,
+        1e-2, 3e3);
+
+    f_accum = vec4(color.rgb * color.a, color.a) * weight;
+    // Multiplied into the revealage target by the blend state
+    f_revealage = color.a;
+}
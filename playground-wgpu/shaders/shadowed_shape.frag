@@ -0,0 +1,135 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in vec3 v_position;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform texture2D t_shadow;
+layout(set = 1, binding = 1) uniform samplerShadow s_shadow;
+layout(set = 1, binding = 2)
+uniform ShadowUniforms {
+    mat4 u_light_view_proj;
+    // 0 hard, 1 PCF, 2 PCSS
+    uint u_filter;
+    // Texels either side for PCF
+    uint u_kernel_radius;
+    float u_depth_bias;
+    float u_slope_bias;
+    // In world units
+    float u_normal_offset;
+    float u_penumbra_scale;
+    float u_texel_size;
+};
+layout(set = 1, binding = 3) uniform sampler s_shadow_depth;
+
+layout(set = 2, binding = 0)
+uniform LightUniforms {
+    // The way the light travels, in w nothing
+    vec4 u_light_direction;
+    // Already scaled by the intensity
+    vec4 u_light_color;
+    vec4 u_ambient;
+};
+
+const uint FILTER_PCF = 1;
+const uint FILTER_PCSS = 2;
+
+const float PI = 3.14159265;
+
+const int DISK_SAMPLES = 16;
+const vec2 POISSON_DISK[DISK_SAMPLES] = vec2[](
+    vec2(-0.94201624, -0.39906216), vec2(0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870), vec2(0.34495938, 0.29387760),
+    vec2(-0.91588581, 0.45771432), vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543, 0.27676845), vec2(0.97484398, 0.75648379),
+    vec2(0.44323325, -0.97511554), vec2(0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023), vec2(0.79197514, 0.19090188),
+    vec2(-0.24188840, 0.99706507), vec2(-0.81409955, 0.91437590),
+    vec2(0.19984126, 0.78641367), vec2(0.14383161, -0.14100790)
+);
+
+// The widest PCSS gets, in texels, to keep the blocker search from wandering off
+const float MAX_PENUMBRA_TEXELS = 32.0;
+
+// Blends the comparisons of the nearest four texels
+float lookup(vec2 uv, float depth) {
+    return texture(sampler2DShadow(t_shadow, s_shadow), vec3(uv, depth));
+}
+
+float pcf(vec2 uv, float depth) {
+    int radius = int(u_kernel_radius);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += lookup(uv + vec2(x, y) * u_texel_size, depth);
+        }
+    }
+    float side = float(2 * radius + 1);
+    return lit / (side * side);
+}
+
+// Jorge Jimenez's interleaved gradient noise, for turning the disk differently at every pixel
+float noise(vec2 pixel) {
+    return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+}
+
+float pcss(vec2 uv, float depth) {
+    float angle = 2.0 * PI * noise(gl_FragCoord.xy);
+    mat2 rotation = mat2(cos(angle), sin(angle), -sin(angle), cos(angle));
+    float max_radius = MAX_PENUMBRA_TEXELS * u_texel_size;
+
+    // Anything that could cast a penumbra this far, which at most is a blocker at the near plane
+    float search_radius = clamp(depth * u_penumbra_scale, u_texel_size, max_radius);
+    float blocker_depth = 0.0;
+    float blockers = 0.0;
+    for (int i = 0; i < DISK_SAMPLES; i++) {
+        vec2 offset = rotation * POISSON_DISK[i] * search_radius;
+        float sample_depth = texture(sampler2D(t_shadow, s_shadow_depth), uv + offset).r;
+        if (sample_depth < depth) {
+            blocker_depth += sample_depth;
+            blockers += 1.0;
+        }
+    }
+    if (blockers == 0.0) {
+        return 1.0;
+    }
+
+    // Wider the further the receiver is behind the average blocker
+    blocker_depth /= blockers;
+    float radius = clamp((depth - blocker_depth) * u_penumbra_scale, u_texel_size, max_radius);
+    float lit = 0.0;
+    for (int i = 0; i < DISK_SAMPLES; i++) {
+        lit += lookup(uv + rotation * POISSON_DISK[i] * radius, depth);
+    }
+    return lit / float(DISK_SAMPLES);
+}
+
+// 1 where the light reaches `position`, 0 in shadow. Everything outside the map is lit.
+float shadow(vec3 position, vec3 normal, float ndotl) {
+    vec3 offset_position = position + normal * u_normal_offset;
+    vec4 clip = u_light_view_proj * vec4(offset_position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    vec2 uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // Surfaces at a grazing angle to the light need more bias to not shadow themselves
+    float depth = ndc.z - (u_depth_bias + u_slope_bias * (1.0 - ndotl));
+    if (u_filter == FILTER_PCF) {
+        return pcf(uv, depth);
+    } else if (u_filter == FILTER_PCSS) {
+        return pcss(uv, depth);
+    }
+    return lookup(uv, depth);
+}
+
+void main() {
+    vec3 normal = normalize(v_normal);
+    float diffuse = max(dot(normal, -u_light_direction.xyz), 0.0);
+    float lit = diffuse > 0.0 ? shadow(v_position, normal, diffuse) : 0.0;
+    vec3 light = u_ambient.rgb + u_light_color.rgb * diffuse * lit;
+    f_color = vec4(v_color.rgb * light, v_color.a);
+}
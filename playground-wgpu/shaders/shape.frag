@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec3 sun_direction = normalize(vec3(0.4, 0.8, 0.3));
+    float diffuse = max(dot(normalize(v_normal), sun_direction), 0.0);
+    f_color = vec4(v_color.rgb * (0.3 + 0.7 * diffuse), v_color.a);
+}
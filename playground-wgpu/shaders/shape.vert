@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+// Per instance, xyz is where it sits and w its size
+layout(location = 2) in vec4 a_offset_scale;
+layout(location = 3) in vec4 a_color;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec4 v_color;
+// In world space, for lighting
+layout(location = 2) out vec3 v_position;
+
+// The same depth in the depth pre-pass and the color pass, which tests for equal depth
+invariant gl_Position;
+
+layout(set = 0, binding = 0)
+uniform ShapeUniforms {
+    mat4 u_view_proj;
+};
+
+void main() {
+    v_normal = a_normal;
+    v_color = a_color;
+    v_position = a_position * a_offset_scale.w + a_offset_scale.xyz;
+    gl_Position = u_view_proj * vec4(v_position, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+// Up to four joints and how much each of them moves the vertex, the weights add up to 1
+layout(location = 2) in uvec4 a_joints;
+layout(location = 3) in vec4 a_weights;
+layout(location = 4) in vec4 a_color;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec4 v_color;
+
+// Has to match MAX_JOINTS
+layout(set = 0, binding = 0)
+uniform SkinUniforms {
+    mat4 u_view_proj;
+    mat4 u_model;
+    mat4 u_joints[32];
+};
+
+void main() {
+    mat4 skin = a_weights.x * u_joints[a_joints.x]
+        + a_weights.y * u_joints[a_joints.y]
+        + a_weights.z * u_joints[a_joints.z]
+        + a_weights.w * u_joints[a_joints.w];
+    mat4 model = u_model * skin;
+
+    // Fine for rotations and uniform scales, which is all the joints do
+    v_normal = mat3(model) * a_normal;
+    v_color = a_color;
+    gl_Position = u_view_proj * model * vec4(a_position, 1.0);
+}
@@ -0,0 +1,81 @@
+#version 450
+
+layout(location = 0) in vec2 v_ndc;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0)
+uniform SkyUniforms {
+    mat4 u_inverse_view_proj;
+    vec4 u_camera_position;
+    // Towards the sun
+    vec4 u_sun_direction;
+    // What's left of the sun's light after the atmosphere, for its disc
+    vec4 u_sun_color;
+    // How hazy the air is, from 2 for a clear day to about 10
+    float u_turbidity;
+    // Scales the sky's luminance, which is in thousands of cd/m^2
+    float u_brightness;
+};
+
+const float PI = 3.14159265;
+const vec3 NIGHT = vec3(0.002, 0.003, 0.008);
+
+// Preetham, Shirley and Smits' fit of how the sky's brightness and chromaticity spread out from
+// the zenith and around the sun
+vec3 perez(float cos_theta, float gamma, float cos_gamma, vec3 A, vec3 B, vec3 C, vec3 D, vec3 E) {
+    return (1.0 + A * exp(B / max(cos_theta, 0.01)))
+        * (1.0 + C * exp(D * gamma) + E * cos_gamma * cos_gamma);
+}
+
+vec3 xyY_to_rgb(vec3 xyY) {
+    vec3 XYZ = vec3(xyY.x / xyY.y * xyY.z, xyY.z, (1.0 - xyY.x - xyY.y) / xyY.y * xyY.z);
+    return mat3(
+        3.2406, -0.9689, 0.0557,
+        -1.5372, 1.8758, -0.2040,
+        -0.4986, 0.0415, 1.0570
+    ) * XYZ;
+}
+
+void main() {
+    vec4 far = u_inverse_view_proj * vec4(v_ndc, 1.0, 1.0);
+    vec3 view = normalize(far.xyz / far.w - u_camera_position.xyz);
+    vec3 sun = normalize(u_sun_direction.xyz);
+    float T = u_turbidity;
+
+    // The fit only holds with the sun up, so it stays at the horizon and the sky darkens instead
+    float theta_s = min(acos(clamp(sun.y, -1.0, 1.0)), PI / 2.0);
+    float night = smoothstep(0.0, -0.15, sun.y);
+
+    vec3 A = vec3(0.1787 * T - 1.4630, -0.0193 * T - 0.2592, -0.0167 * T - 0.2608);
+    vec3 B = vec3(-0.3554 * T + 0.4275, -0.0665 * T + 0.0008, -0.0950 * T + 0.0092);
+    vec3 C = vec3(-0.0227 * T + 5.3251, -0.0004 * T + 0.2125, -0.0079 * T + 0.2102);
+    vec3 D = vec3(0.1206 * T - 2.5771, -0.0641 * T - 0.8989, -0.0441 * T - 1.6537);
+    vec3 E = vec3(-0.0670 * T + 0.3703, -0.0033 * T + 0.0452, -0.0109 * T + 0.0529);
+
+    float chi = (4.0 / 9.0 - T / 120.0) * (PI - 2.0 * theta_s);
+    float zenith_Y = (4.0453 * T - 4.9710) * tan(chi) - 0.2155 * T + 2.4192;
+    vec3 theta3 = vec3(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s);
+    float zenith_x = dot(vec3(0.00166, -0.00375, 0.00209), theta3) * T * T
+        + (dot(vec3(-0.02903, 0.06377, -0.03202), theta3) + 0.00394) * T
+        + dot(vec3(0.11693, -0.21196, 0.06052), theta3) + 0.25886;
+    float zenith_y = dot(vec3(0.00275, -0.00610, 0.00317), theta3) * T * T
+        + (dot(vec3(-0.04214, 0.08970, -0.04153), theta3) + 0.00516) * T
+        + dot(vec3(0.15346, -0.26756, 0.06670), theta3) + 0.26688;
+
+    // Below the horizon looks like the horizon
+    float cos_theta = max(view.y, 0.0);
+    vec3 sun_above = normalize(vec3(sun.x, cos(theta_s), sun.z));
+    float cos_gamma = clamp(dot(view, sun_above), -1.0, 1.0);
+    float gamma = acos(cos_gamma);
+    vec3 ratio = perez(cos_theta, gamma, cos_gamma, A, B, C, D, E)
+        / perez(1.0, theta_s, cos(theta_s), A, B, C, D, E);
+    vec3 xyY = vec3(zenith_x, zenith_y, zenith_Y) * ratio;
+    xyY.z *= u_brightness * (1.0 - night);
+
+    // The sun's disc, about half a degree across
+    float disc = smoothstep(0.99996, 0.99999, dot(view, sun)) * (1.0 - night) * step(0.0, view.y);
+    vec3 color = max(xyY_to_rgb(xyY), vec3(0.0)) + u_sun_color.rgb * disc * 50.0 * u_brightness;
+    color += NIGHT * night;
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) out vec2 v_ndc;
+
+void main() {
+    // The fullscreen triangle, on the far plane so only what nothing got drawn over is sky
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+
+    v_ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    gl_Position = vec4(v_ndc, 1.0, 1.0);
+}
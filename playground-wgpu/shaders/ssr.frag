@@ -0,0 +1,112 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+// The lit scene, its roughness and its depth, all the same size as the frame
+layout(set = 0, binding = 0) uniform texture2D t_color;
+layout(set = 0, binding = 1) uniform texture2D t_material;
+layout(set = 0, binding = 2) uniform texture2D t_depth;
+layout(set = 0, binding = 3) uniform sampler s_targets;
+
+layout(set = 1, binding = 0)
+uniform ReflectionUniforms {
+    mat4 u_projection;
+    mat4 u_inverse_projection;
+    uint u_steps;
+    uint u_refine_steps;
+    float u_max_distance;
+    float u_thickness;
+    float u_strength;
+};
+
+// Clamped to the edges, the neighbours of the outermost texels are read too
+float depth_at(ivec2 texel) {
+    ivec2 last = textureSize(sampler2D(t_depth, s_targets), 0) - 1;
+    return texelFetch(sampler2D(t_depth, s_targets), clamp(texel, ivec2(0), last), 0).r;
+}
+
+// The view space position seen at `texel`
+vec3 view_position(ivec2 texel) {
+    vec2 uv = (vec2(texel) + 0.5) / vec2(textureSize(sampler2D(t_depth, s_targets), 0));
+    vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    vec4 position = u_inverse_projection * vec4(ndc, depth_at(texel), 1.0);
+    return position.xyz / position.w;
+}
+
+// Where a view space position ends up on the screen, in texture coordinates
+vec2 project(vec3 position) {
+    vec4 clip = u_projection * vec4(position, 1.0);
+    vec2 ndc = clip.xy / clip.w;
+    return vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// The normal from the neighbouring positions, on whichever side continues the same surface
+vec3 reconstruct_normal(ivec2 texel, vec3 position) {
+    vec3 right = view_position(texel + ivec2(1, 0)) - position;
+    vec3 left = position - view_position(texel - ivec2(1, 0));
+    vec3 down = view_position(texel + ivec2(0, 1)) - position;
+    vec3 up = position - view_position(texel - ivec2(0, 1));
+    vec3 dx = abs(right.z) < abs(left.z) ? right : left;
+    vec3 dy = abs(down.z) < abs(up.z) ? down : up;
+    return normalize(cross(dy, dx));
+}
+
+// How far in front of the scene `position` is, negative once the ray went behind it
+float depth_difference(vec3 position, vec2 uv, vec2 size) {
+    ivec2 texel = ivec2(uv * size);
+    return position.z - view_position(texel).z;
+}
+
+void main() {
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    vec2 size = vec2(textureSize(sampler2D(t_depth, s_targets), 0));
+    vec4 color = texelFetch(sampler2D(t_color, s_targets), texel, 0);
+    float roughness = texelFetch(sampler2D(t_material, s_targets), texel, 0).r;
+    f_color = color;
+    // Nothing drawn here, or nothing sharp enough to reflect
+    if (depth_at(texel) >= 1.0 || roughness >= 1.0 || u_strength <= 0.0) {
+        return;
+    }
+
+    vec3 position = view_position(texel);
+    vec3 normal = reconstruct_normal(texel, position);
+    vec3 ray = normalize(reflect(normalize(position), normal));
+    float step_length = u_max_distance / float(u_steps);
+
+    vec3 previous = position;
+    for (uint i = 1u; i <= u_steps; i++) {
+        vec3 current = position + ray * step_length * float(i);
+        vec2 uv = project(current);
+        if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || current.z >= 0.0) {
+            break;
+        }
+
+        float difference = depth_difference(current, uv, size);
+        if (difference < 0.0 && difference > -u_thickness) {
+            // Went behind something, halve the last step toward where it crossed
+            vec3 front = previous;
+            vec3 back = current;
+            for (uint j = 0u; j < u_refine_steps; j++) {
+                vec3 middle = (front + back) * 0.5;
+                if (depth_difference(middle, project(middle), size) < 0.0) {
+                    back = middle;
+                } else {
+                    front = middle;
+                }
+            }
+
+            vec2 hit = project(back);
+            vec3 reflection = texelFetch(sampler2D(t_color, s_targets), ivec2(hit * size), 0).rgb;
+            // Fades out toward the screen's edges, the end of the ray and rougher surfaces, where
+            // what's reflected gets less certain
+            vec2 edge = min(hit, 1.0 - hit);
+            float fade = clamp(min(edge.x, edge.y) * 10.0, 0.0, 1.0);
+            fade *= 1.0 - float(i) / float(u_steps);
+            float weight = u_strength * fade * (1.0 - roughness);
+            f_color = vec4(mix(color.rgb, reflection, weight), color.a);
+            break;
+        }
+        previous = current;
+    }
+}
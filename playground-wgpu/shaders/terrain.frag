@@ -0,0 +1,30 @@
+#version 450
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec4 v_splat;
+layout(location = 2) in vec2 v_layer_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform texture2D t_layer0;
+layout(set = 1, binding = 1) uniform texture2D t_layer1;
+layout(set = 1, binding = 2) uniform texture2D t_layer2;
+layout(set = 1, binding = 3) uniform texture2D t_layer3;
+layout(set = 1, binding = 4) uniform sampler s_layer;
+
+void main() {
+    // Normalized again, rounding and interpolation don't keep the sum at exactly 1
+    vec4 weights = v_splat / max(dot(v_splat, vec4(1.0)), 0.0001);
+
+    vec3 color = texture(sampler2D(t_layer0, s_layer), v_layer_uv).rgb * weights.x
+        + texture(sampler2D(t_layer1, s_layer), v_layer_uv).rgb * weights.y
+        + texture(sampler2D(t_layer2, s_layer), v_layer_uv).rgb * weights.z
+        + texture(sampler2D(t_layer3, s_layer), v_layer_uv).rgb * weights.w;
+
+    // A warm sun and a cool sky
+    vec3 sun_direction = normalize(vec3(0.4, 0.8, 0.3));
+    float diffuse = max(dot(normalize(v_normal), sun_direction), 0.0);
+    vec3 light = diffuse * vec3(1.0, 0.95, 0.85) + vec3(0.25, 0.3, 0.35);
+
+    f_color = vec4(color * light, 1.0);
+}
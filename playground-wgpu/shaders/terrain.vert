@@ -0,0 +1,24 @@
+#version 450
+
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec3 a_normal;
+// Weights of the sand, grass, rock and snow layers
+layout(location = 2) in vec4 a_splat;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec4 v_splat;
+layout(location = 2) out vec2 v_layer_uv;
+
+layout(set = 0, binding = 0)
+uniform TerrainUniforms {
+    mat4 u_view_proj;
+    // World size of one repeat of the layer textures
+    float u_layer_repeat;
+};
+
+void main() {
+    v_normal = a_normal;
+    v_splat = a_splat;
+    v_layer_uv = a_position.xz / u_layer_repeat;
+    gl_Position = u_view_proj * vec4(a_position, 1.0);
+}
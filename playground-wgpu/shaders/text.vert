@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec2 a_position;
+layout(location = 1) in vec2 a_tex_coords;
+layout(location = 2) in vec4 a_color;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
+
+layout(set = 0, binding = 0)
+uniform TextUniforms {
+    vec2 u_target_size;
+};
+
+void main() {
+    v_tex_coords = a_tex_coords;
+    v_color = a_color;
+
+    // Positions are in pixels with the origin in the top left corner
+    gl_Position = vec4(
+        2.0 * a_position.x / u_target_size.x - 1.0,
+        1.0 - 2.0 * a_position.y / u_target_size.y,
+        0.0,
+        1.0
+    );
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_source;
+layout(set = 0, binding = 1) uniform sampler s_source;
+
+layout(set = 1, binding = 0)
+uniform PreviewUniforms {
+    // 1.0 for every channel that should be shown
+    vec4 u_channels;
+};
+
+void main() {
+    vec4 texel = texture(sampler2D(t_source, s_source), v_tex_coords);
+
+    if (dot(u_channels, vec4(1.0)) == 1.0) {
+        // A single channel is shown as grayscale
+        f_color = vec4(vec3(dot(texel, u_channels)), 1.0);
+    } else {
+        f_color = vec4(texel.rgb * u_channels.rgb, 1.0);
+    }
+}
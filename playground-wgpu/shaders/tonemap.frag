@@ -0,0 +1,76 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_hdr;
+layout(set = 0, binding = 1) uniform sampler s_hdr;
+
+layout(set = 1, binding = 0) uniform Tonemap {
+    // Linear factor, already raised from stops
+    float u_exposure;
+    uint u_operator;
+    // What the adapted luminance gets exposed to, 0 while auto exposure is off
+    float u_key;
+};
+
+layout(set = 2, binding = 0) readonly buffer Histogram {
+    uint bins[256];
+    // Adapted over the last frames by the auto exposure
+    float luminance;
+};
+
+// Same order as `tonemap::Operator`
+const uint NONE = 0;
+const uint REINHARD = 1;
+const uint ACES_FILMIC = 2;
+const uint UNCHARTED2 = 3;
+
+// Krzysztof Narkowicz's fit of the ACES reference transform
+vec3 aces_filmic(vec3 x) {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+// John Hable's filmic curve from Uncharted 2
+vec3 uncharted2_curve(vec3 x) {
+    const float a = 0.15; // Shoulder strength
+    const float b = 0.50; // Linear strength
+    const float c = 0.10; // Linear angle
+    const float d = 0.20; // Toe strength
+    const float e = 0.02; // Toe numerator
+    const float f = 0.30; // Toe denominator
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+vec3 uncharted2(vec3 x) {
+    // The curve maps this white point to 1, and expects twice the exposure of the others
+    const float white = 11.2;
+    return uncharted2_curve(2.0 * x) / uncharted2_curve(vec3(white));
+}
+
+void main() {
+    float exposure = u_exposure;
+    if (u_key > 0.0) {
+        exposure *= u_key / max(luminance, 0.0001);
+    }
+    vec3 color = texture(sampler2D(t_hdr, s_hdr), v_tex_coords).rgb * exposure;
+
+    vec3 mapped;
+    switch (u_operator) {
+    case REINHARD:
+        mapped = color / (1.0 + color);
+        break;
+    case ACES_FILMIC:
+        mapped = aces_filmic(color);
+        break;
+    case UNCHARTED2:
+        mapped = uncharted2(color);
+        break;
+    default:
+        mapped = color;
+        break;
+    }
+
+    // The frame is sRGB, the encoding happens when it gets written
+    f_color = vec4(clamp(mapped, 0.0, 1.0), 1.0);
+}
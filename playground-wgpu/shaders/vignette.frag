@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    // 1 in the middle, falling off towards the corners
+    vec2 centered = v_tex_coords * 2.0 - 1.0;
+    float factor = 1.0 - 0.5 * smoothstep(0.4, 1.4, length(centered));
+
+    f_color = vec4(vec3(factor), 1.0);
+}
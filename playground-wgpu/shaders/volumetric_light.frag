@@ -0,0 +1,83 @@
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+// Added onto the scene
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D t_depth;
+layout(set = 0, binding = 1) uniform sampler s_depth;
+
+layout(set = 1, binding = 0) uniform texture2D t_shadow;
+layout(set = 1, binding = 1) uniform samplerShadow s_shadow;
+layout(set = 1, binding = 2)
+uniform ShadowUniforms {
+    mat4 u_light_view_proj;
+};
+
+layout(set = 2, binding = 0)
+uniform VolumetricUniforms {
+    mat4 u_inverse_view_proj;
+    vec4 u_camera_position;
+    // The way the light travels
+    vec4 u_light_direction;
+    // Already scaled by the intensity
+    vec4 u_light_color;
+    // How much of the medium there is to scatter and absorb light, per unit
+    float u_density;
+    // Henyey-Greenstein, from -1 scattering back towards the light to 1 scattering onwards
+    float u_anisotropy;
+    // How far rays go without hitting anything
+    float u_max_distance;
+    uint u_steps;
+};
+
+const float PI = 3.14159265;
+
+// Lit or not, without any filtering
+float lit(vec3 position) {
+    vec4 clip = u_light_view_proj * vec4(position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    vec2 uv = vec2(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    return texture(sampler2DShadow(t_shadow, s_shadow), vec3(uv, ndc.z));
+}
+
+float henyey_greenstein(float cos_theta, float g) {
+    float g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// Jorge Jimenez's interleaved gradient noise, a different start for neighbouring pixels
+float noise(vec2 pixel) {
+    return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+}
+
+void main() {
+    float depth = texture(sampler2D(t_depth, s_depth), v_tex_coords).r;
+    vec2 ndc = vec2(v_tex_coords.x * 2.0 - 1.0, 1.0 - v_tex_coords.y * 2.0);
+    vec4 end = u_inverse_view_proj * vec4(ndc, depth, 1.0);
+
+    vec3 start = u_camera_position.xyz;
+    vec3 ray = end.xyz / end.w - start;
+    float distance = min(length(ray), u_max_distance);
+    vec3 direction = normalize(ray);
+    float step_size = distance / float(u_steps);
+
+    // The light comes in along its direction and goes out towards the camera
+    float phase = henyey_greenstein(dot(u_light_direction.xyz, -direction), u_anisotropy);
+    float transmittance_step = exp(-u_density * step_size);
+
+    // Starting every pixel somewhere else along its first step turns banding into noise
+    float offset = noise(gl_FragCoord.xy);
+    float transmittance = 1.0;
+    float scattered = 0.0;
+    for (uint i = 0; i < u_steps; i++) {
+        vec3 position = start + direction * (float(i) + offset) * step_size;
+        scattered += transmittance * lit(position) * u_density * phase * step_size;
+        transmittance *= transmittance_step;
+    }
+
+    f_color = vec4(u_light_color.rgb * scattered, 0.0);
+}
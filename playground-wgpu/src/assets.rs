@@ -0,0 +1,1067 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::sampler::{SamplerCache, SamplerSettings};
+use crate::scheduler::{Scheduler, TaskStatus};
+use crate::texture::{self, Placeholders, Texture};
+use cgmath::Point3;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use log::{info, warn};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use playground_math::{Aabb, Sphere};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource, Buffer,
+    CommandEncoderDescriptor, Device, Origin3d, Queue, TextureFormat,
+};
+
+// Refers to an asset in `Assets`. Handles stay valid until the last user releases the asset,
+// slots are never reused so a stale handle can't end up pointing at a different asset.
+pub struct Handle<T> {
+    index: usize,
+    marker: PhantomData<T>,
+}
+
+// Derives would require T to implement these too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Handle({})", self.index)
+    }
+}
+
+struct Slot<T> {
+    key: String,
+    asset: Arc<T>,
+    users: usize,
+    // Still showing a placeholder
+    loading: bool,
+}
+
+// Assets of one type, keyed by path (or name) and counting their users
+struct Storage<T> {
+    slots: Vec<Option<Slot<T>>>,
+    by_key: HashMap<String, usize>,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            by_key: HashMap::new(),
+        }
+    }
+
+    fn find(&self, key: &str) -> Option<Handle<T>> {
+        self.by_key.get(key).map(|&index| Handle {
+            index,
+            marker: PhantomData,
+        })
+    }
+
+    // Another user for an asset that's already loaded (or loading)
+    fn acquire(&mut self, key: &str) -> Option<Handle<T>> {
+        let index = *self.by_key.get(key)?;
+        self.slots[index].as_mut().unwrap().users += 1;
+        Some(Handle {
+            index,
+            marker: PhantomData,
+        })
+    }
+
+    fn insert(&mut self, key: &str, asset: Arc<T>, loading: bool) -> Handle<T> {
+        let index = self.slots.len();
+        self.slots.push(Some(Slot {
+            key: key.to_string(),
+            asset,
+            users: 1,
+            loading,
+        }));
+        self.by_key.insert(key.to_string(), index);
+        Handle {
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    fn slot(&self, handle: Handle<T>) -> &Slot<T> {
+        self.slots[handle.index]
+            .as_ref()
+            .expect("asset was already released")
+    }
+
+    // None if the asset got released in the meantime
+    fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+        self.slots[handle.index].as_mut()
+    }
+
+    fn get(&self, handle: Handle<T>) -> &Arc<T> {
+        &self.slot(handle).asset
+    }
+
+    // Returns the slot when its last user let go of it
+    fn release(&mut self, handle: Handle<T>) -> Option<Slot<T>> {
+        let slot = self.slots[handle.index]
+            .as_mut()
+            .expect("asset was already released");
+        slot.users -= 1;
+        if slot.users > 0 {
+            return None;
+        }
+
+        let slot = self.slots[handle.index].take().unwrap();
+        self.by_key.remove(&slot.key);
+        Some(slot)
+    }
+
+    fn len(&self) -> usize {
+        self.by_key.len()
+    }
+}
+
+// Geometry in the geometry pool, plus the triangles on the CPU for picking
+pub struct Mesh {
+    pub vertex_buffer: Arc<Buffer>,
+    pub vertex_allocation: Allocation,
+    pub index_buffer: Arc<Buffer>,
+    pub index_allocation: Allocation,
+    pub num_indices: u32,
+    // Every edge of the triangles once, for drawing a wireframe as a line list. In the same
+    // buffer as the triangle indices.
+    pub line_index_allocation: Allocation,
+    pub num_line_indices: u32,
+    // In model space
+    pub bounds: Aabb,
+    pub bounding_sphere: Sphere,
+    pub triangles: Vec<[Point3<f32>; 3]>,
+}
+
+impl Mesh {
+    fn upload(
+        device: &Device,
+        queue: &Queue,
+        geometry_pool: &mut BufferPool,
+        prepared: PreparedMesh,
+    ) -> Self {
+        let data = &prepared.data;
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("mesh_upload_encoder"),
+        });
+        let vertex_allocation = geometry_pool.upload(device, &mut encoder, &data.vertices, 0);
+        let index_allocation =
+            geometry_pool.upload(device, &mut encoder, bytemuck::cast_slice(&data.indices), 0);
+        let line_index_allocation = geometry_pool.upload(
+            device,
+            &mut encoder,
+            bytemuck::cast_slice(&prepared.line_indices),
+            0,
+        );
+        queue.submit(&[encoder.finish()]);
+
+        Self {
+            vertex_buffer: geometry_pool.shared_buffer(&vertex_allocation),
+            vertex_allocation,
+            index_buffer: geometry_pool.shared_buffer(&index_allocation),
+            index_allocation,
+            num_indices: data.indices.len() as u32,
+            line_index_allocation,
+            num_line_indices: prepared.line_indices.len() as u32,
+            bounds: prepared.bounds,
+            bounding_sphere: prepared.bounding_sphere,
+            triangles: prepared.triangles,
+        }
+    }
+}
+
+// Pairs of indices for the edges of a triangle list, with the edges triangles share only once
+fn line_indices(indices: &[u16]) -> Vec<u16> {
+    let mut edges = BTreeSet::new();
+    for triangle in indices.chunks(3) {
+        for corner in 0..triangle.len() {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % triangle.len()]);
+            edges.insert((a.min(b), a.max(b)));
+        }
+    }
+    edges.into_iter().flat_map(|(a, b)| vec![a, b]).collect()
+}
+
+// What a mesh gets created from. Vertices can have any layout, the positions are only kept on
+// the CPU.
+pub struct MeshData {
+    pub vertices: Vec<u8>,
+    pub indices: Vec<u16>,
+    pub positions: Vec<Point3<f32>>,
+}
+
+// Mesh data with everything the CPU side of a `Mesh` needs worked out already, on the thread
+// pool, so all that's left for the main thread is the upload
+struct PreparedMesh {
+    data: MeshData,
+    line_indices: Vec<u16>,
+    bounds: Aabb,
+    bounding_sphere: Sphere,
+    triangles: Vec<[Point3<f32>; 3]>,
+}
+
+impl PreparedMesh {
+    fn new(data: MeshData) -> Self {
+        let positions = &data.positions;
+        let triangles = data
+            .indices
+            .chunks(3)
+            .map(|triangle| {
+                [
+                    positions[triangle[0] as usize],
+                    positions[triangle[1] as usize],
+                    positions[triangle[2] as usize],
+                ]
+            })
+            .collect();
+        Self {
+            line_indices: line_indices(&data.indices),
+            bounds: Aabb::from_points(positions.iter().cloned()),
+            bounding_sphere: Sphere::from_points(positions),
+            triangles,
+            data,
+        }
+    }
+
+    // Bytes the upload copies to the GPU
+    fn size(&self) -> usize {
+        self.data.vertices.len() + 2 * (self.data.indices.len() + self.line_indices.len())
+    }
+}
+
+// A texture decoded on the thread pool, with the first level that gets uploaded already
+// downsampled
+struct DecodedTexture {
+    base: Arc<RgbaImage>,
+    first_level: u32,
+    first: RgbaImage,
+}
+
+impl DecodedTexture {
+    fn new(base: RgbaImage) -> Self {
+        let mip_level_count = texture::mip_level_count(base.width(), base.height());
+        let first_level =
+            mip_level_count.saturating_sub(texture::mip_level_count(UP_FRONT_SIZE, UP_FRONT_SIZE));
+        Self {
+            first: downsample(&base, first_level),
+            base: Arc::new(base),
+            first_level,
+        }
+    }
+}
+
+// `base` scaled down to the size of `mip_level`
+fn downsample(base: &RgbaImage, mip_level: u32) -> RgbaImage {
+    if mip_level == 0 {
+        return base.clone();
+    }
+    let width = texture::mip_size(base.width(), mip_level);
+    let height = texture::mip_size(base.height(), mip_level);
+    imageops::resize(base, width, height, FilterType::Triangle)
+}
+
+// Results coming back from the thread pool
+enum Loaded {
+    Texture(Handle<Texture>, Result<DecodedTexture, failure::Error>),
+    Mesh(Handle<Mesh>, Result<PreparedMesh, failure::Error>),
+}
+
+impl Loaded {
+    // Bytes uploading it will take, failures take none
+    fn size(&self) -> usize {
+        match self {
+            Loaded::Texture(_, Ok(decoded)) => decoded.first.len(),
+            Loaded::Mesh(_, Ok(prepared)) => prepared.size(),
+            _ => 0,
+        }
+    }
+}
+
+// Bytes of finished loads uploaded per frame, the rest waits for the next frames instead of
+// making one frame take as long as all of them. Always at least one load per frame, however
+// big it is.
+const UPLOAD_BUDGET: usize = 16 << 20;
+
+// Textures start out with the mip levels this size and smaller, the finer ones are streamed in
+// once something drawn with them gets close enough to need them
+const UP_FRONT_SIZE: u32 = 64;
+// Mip levels downsampled on the thread pool at once
+const MAX_STREAMING: usize = 2;
+
+// A finer mip level for a streamed texture, from the thread pool
+struct StreamedLevel {
+    handle: Handle<Texture>,
+    generation: u32,
+    mip_level: u32,
+    rgba: RgbaImage,
+}
+
+// What streaming knows about a loaded texture. Only the levels from `resident` down to 1x1 are
+// on the GPU, as levels 0.. of the texture its handle resolves to.
+struct Streamed {
+    // Decoded at full size, the levels that get streamed in are downsampled from it
+    base: Arc<RgbaImage>,
+    mip_level_count: u32,
+    resident: u32,
+    // Finest level anything drawn with it asked for since the last poll
+    wanted: Cell<Option<u32>>,
+    // Level being downsampled on the thread pool
+    streaming: Option<u32>,
+    // Set once the scheduler filled in the levels below the first upload, until then there's
+    // nothing to copy over into a replacement
+    mips_ready: Arc<AtomicBool>,
+    // Tells streamed levels apart from ones started before a reload
+    generation: u32,
+}
+
+impl Streamed {
+    // Bytes of the levels from `mip_level` down to 1x1
+    fn bytes_from(&self, mip_level: u32) -> usize {
+        (mip_level..self.mip_level_count)
+            .map(|level| {
+                let width = texture::mip_size(self.base.width(), level) as usize;
+                let height = texture::mip_size(self.base.height(), level) as usize;
+                4 * width * height
+            })
+            .sum()
+    }
+
+    // On the GPU, counting the level being streamed in already
+    fn bytes(&self) -> usize {
+        self.bytes_from(self.streaming.unwrap_or(self.resident))
+    }
+
+    // Where streaming heads, the coarsest level if nothing asked for any
+    fn target(&self) -> u32 {
+        self.wanted.get().unwrap_or(self.mip_level_count - 1)
+    }
+
+    // Whether it can be replaced with more or fewer levels right now
+    fn idle(&self) -> bool {
+        self.streaming.is_none() && self.mips_ready.load(Ordering::Acquire)
+    }
+}
+
+// Sends the changes to anything under `root`, until the watcher gets dropped
+pub fn watch(
+    root: &Path,
+    sender: Sender<DebouncedEvent>,
+) -> Result<RecommendedWatcher, notify::Error> {
+    let mut watcher = notify::watcher(sender, Duration::from_millis(200))?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
+
+// Owns every loaded texture and mesh, so two demos (or two objects) asking for the same file
+// share one copy on the GPU. Users get handles and have to release them once they're done.
+//
+// Decoding and parsing happen on a thread pool, as many at once as there are cores. Until that's
+// done a handle refers to a placeholder, `poll` uploads finished loads as they come in, a few
+// megabytes a frame, and swaps them in. Resolve handles every frame instead of holding on to what
+// they pointed at.
+//
+// Textures are also reloaded when their file under `root` changes, the handles stay the same.
+//
+// Only a texture's coarse mip levels get uploaded when it loads. Demos tell `want_texture` how
+// big they draw it, and `poll` streams in finer levels from the decoded image kept in memory,
+// one level at a time. While over `texture_budget` it drops levels nothing asked for lately,
+// and nothing more gets streamed in past it.
+pub struct Assets {
+    // Texture paths are relative to this
+    root: PathBuf,
+    pub placeholders: Placeholders,
+    // A single degenerate triangle, stands in for meshes that are still loading
+    loading_mesh: Arc<Mesh>,
+    textures: Storage<Texture>,
+    // For every texture and each way it gets sampled, in the `bind_group::TEXTURE_LAYOUT` layout
+    texture_bind_groups: HashMap<Handle<Texture>, HashMap<SamplerSettings, Arc<BindGroup>>>,
+    // Asked for by `texture_bind_group` while drawing, created by the next `poll`
+    missing_bind_groups: RefCell<HashSet<(Handle<Texture>, SamplerSettings)>>,
+    texture_layout: Arc<BindGroupLayout>,
+    pub samplers: SamplerCache,
+    meshes: Storage<Mesh>,
+    streamed: HashMap<Handle<Texture>, Streamed>,
+    // Bytes the streamed textures may take up on the GPU
+    pub texture_budget: usize,
+
+    pool: ThreadPool,
+    sender: Sender<Loaded>,
+    receiver: Receiver<Loaded>,
+    level_sender: Sender<StreamedLevel>,
+    level_receiver: Receiver<StreamedLevel>,
+    // Finished on the thread pool, waiting for their upload
+    ready: VecDeque<Loaded>,
+    // Loads not swapped in yet
+    pending: usize,
+    // Loads started since the last time nothing was pending, and when the first of them was
+    started: usize,
+    batch_start: Instant,
+
+    // Only kept alive for `changes`, None if watching failed
+    _watcher: Option<RecommendedWatcher>,
+    changes: Receiver<DebouncedEvent>,
+}
+
+impl Assets {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        geometry_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        root: PathBuf,
+    ) -> Self {
+        // 64 bytes is enough for one vertex of any layout we use
+        let loading_mesh = Mesh::upload(
+            device,
+            queue,
+            geometry_pool,
+            PreparedMesh::new(MeshData {
+                vertices: vec![0; 64],
+                indices: vec![0, 0, 0],
+                positions: vec![Point3::new(0.0, 0.0, 0.0)],
+            }),
+        );
+        let (sender, receiver) = mpsc::channel();
+        let (level_sender, level_receiver) = mpsc::channel();
+        let (change_sender, changes) = mpsc::channel();
+        let watcher = watch(&root, change_sender)
+            .map_err(|err| warn!("Not watching {} for changes: {}", root.display(), err))
+            .ok();
+
+        Self {
+            root,
+            placeholders: Placeholders::new(device, queue),
+            loading_mesh: Arc::new(loading_mesh),
+            textures: Storage::new(),
+            texture_bind_groups: HashMap::new(),
+            missing_bind_groups: RefCell::new(HashSet::new()),
+            texture_layout: bind_groups.layout(
+                device,
+                "texture_bind_group_layout",
+                bind_group::TEXTURE_LAYOUT,
+            ),
+            samplers: SamplerCache::new(),
+            meshes: Storage::new(),
+            streamed: HashMap::new(),
+            texture_budget: 64 << 20,
+            pool: ThreadPoolBuilder::new()
+                .thread_name(|index| format!("asset loader {}", index))
+                .build()
+                .unwrap(),
+            sender,
+            receiver,
+            level_sender,
+            level_receiver,
+            ready: VecDeque::new(),
+            pending: 0,
+            started: 0,
+            batch_start: Instant::now(),
+            _watcher: watcher,
+            changes,
+        }
+    }
+
+    // Where a file relative to the assets root is, for data that gets loaded without going
+    // through the asset storage
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    // Starts decoding the image in the background, the handle shows the white placeholder until
+    // `poll` picks up the result (or the missing texture if decoding failed). The coarse levels
+    // of the mip chain are filled in by a deferred task after that, the finer ones get streamed.
+    // `sampler` is how the first user samples it, others can pick their own.
+    pub fn load_texture(
+        &mut self,
+        device: &Device,
+        path: &str,
+        sampler: SamplerSettings,
+    ) -> Handle<Texture> {
+        if let Some(handle) = self.textures.acquire(path) {
+            self.create_bind_group(device, handle, sampler);
+            return handle;
+        }
+
+        let handle = self
+            .textures
+            .insert(path, self.placeholders.white.clone(), true);
+        self.texture_bind_groups.insert(handle, HashMap::new());
+        self.create_bind_group(device, handle, sampler);
+        self.decode_texture(handle, path);
+
+        handle
+    }
+
+    // Counts another load toward the progress
+    fn start_load(&mut self) {
+        if self.pending == 0 {
+            self.started = 0;
+            self.batch_start = Instant::now();
+        }
+        self.pending += 1;
+        self.started += 1;
+    }
+
+    // Loads finished and loads started since the last time everything was done, None while
+    // nothing is loading
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        if self.pending == 0 {
+            return None;
+        }
+        Some((self.started - self.pending, self.started))
+    }
+
+    fn decode_texture(&mut self, handle: Handle<Texture>, path: &str) {
+        let full_path = self.root.join(path);
+        let sender = self.sender.clone();
+        self.start_load();
+        self.pool.spawn(move || {
+            let rgba = fs::read(full_path)
+                .map_err(failure::Error::from)
+                // Grayscale, RGB and 16 bit images get converted instead of rejected
+                .and_then(|bytes| Ok(image::load_from_memory(&bytes)?.to_rgba()))
+                .map(DecodedTexture::new);
+            // Only fails if the assets were dropped, and then nobody is interested anymore
+            sender.send(Loaded::Texture(handle, rgba)).ok();
+        });
+    }
+
+    // Decodes the texture behind a changed file again, `poll` swaps it in like the first time
+    fn reload(&mut self, full_path: &Path) {
+        let relative = match full_path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return,
+        };
+        // Keys always use forward slashes
+        let path = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if let Some(handle) = self.textures.find(&path) {
+            info!("Reloading {}", path);
+            self.decode_texture(handle, &path);
+        }
+    }
+
+    // Swaps in `texture` and binds it again with every sampler it was bound with
+    fn set_texture(&mut self, device: &Device, handle: Handle<Texture>, texture: Arc<Texture>) {
+        self.textures.slot_mut(handle).unwrap().asset = texture;
+        let samplers: Vec<_> = self.texture_bind_groups[&handle].keys().cloned().collect();
+        for sampler in samplers {
+            self.create_bind_group(device, handle, sampler);
+        }
+    }
+
+    // (Re)creates the bind group of the texture as it is now, sampled with `sampler`
+    fn create_bind_group(
+        &mut self,
+        device: &Device,
+        handle: Handle<Texture>,
+        sampler_settings: SamplerSettings,
+    ) {
+        let sampler = self.samplers.get(device, sampler_settings);
+        let slot = self.textures.slot(handle);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.texture_layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&slot.asset.view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some(&slot.key),
+        });
+
+        self.texture_bind_groups
+            .get_mut(&handle)
+            .unwrap()
+            .insert(sampler_settings, Arc::new(bind_group));
+    }
+
+    // The texture and a sampler, for binding as a material. A sampler the texture hasn't been
+    // bound with yet takes until the next `poll`, until then it's one it has.
+    pub fn texture_bind_group(
+        &self,
+        handle: Handle<Texture>,
+        sampler: SamplerSettings,
+    ) -> &Arc<BindGroup> {
+        let bind_groups = &self.texture_bind_groups[&handle];
+        match bind_groups.get(&sampler) {
+            Some(bind_group) => bind_group,
+            None => {
+                self.missing_bind_groups
+                    .borrow_mut()
+                    .insert((handle, sampler));
+                bind_groups.values().next().unwrap()
+            }
+        }
+    }
+
+    pub fn release_texture(&mut self, handle: Handle<Texture>) {
+        if self.textures.release(handle).is_some() {
+            self.texture_bind_groups.remove(&handle);
+            self.streamed.remove(&handle);
+        }
+    }
+
+    // Asks for the detail a texture needs to be drawn `pixels` across on the screen, which is
+    // what decides the mip levels streaming keeps around. Meant to be called for every draw,
+    // every frame, the finest since the last `poll` counts.
+    pub fn want_texture(&self, handle: Handle<Texture>, pixels: f32) {
+        let streamed = match self.streamed.get(&handle) {
+            Some(streamed) => streamed,
+            None => return,
+        };
+        let size = streamed.base.width().max(streamed.base.height()) as f32;
+        let level = (size / pixels.max(1.0)).log2().floor().max(0.0) as u32;
+        let level = level.min(streamed.mip_level_count - 1);
+        let wanted = streamed
+            .wanted
+            .get()
+            .map_or(level, |wanted| wanted.min(level));
+        streamed.wanted.set(Some(wanted));
+    }
+
+    // `parse` runs on the thread pool, and only if there isn't a mesh called `name` yet. The
+    // handle refers to an invisible placeholder until `poll` uploads the result.
+    pub fn load_mesh(
+        &mut self,
+        name: &str,
+        parse: impl FnOnce() -> Result<MeshData, failure::Error> + Send + 'static,
+    ) -> Handle<Mesh> {
+        if let Some(handle) = self.meshes.acquire(name) {
+            return handle;
+        }
+
+        let handle = self.meshes.insert(name, self.loading_mesh.clone(), true);
+
+        let sender = self.sender.clone();
+        self.start_load();
+        self.pool.spawn(move || {
+            sender
+                .send(Loaded::Mesh(handle, parse().map(PreparedMesh::new)))
+                .ok();
+        });
+
+        handle
+    }
+
+    pub fn mesh(&self, handle: Handle<Mesh>) -> &Arc<Mesh> {
+        self.meshes.get(handle)
+    }
+
+    // The mesh's allocations go back to the pool once nobody uses it anymore
+    pub fn release_mesh(&mut self, handle: Handle<Mesh>, geometry_pool: &mut BufferPool) {
+        if let Some(slot) = self.meshes.release(handle) {
+            if !slot.loading {
+                geometry_pool.free(slot.asset.vertex_allocation);
+                geometry_pool.free(slot.asset.index_allocation);
+                geometry_pool.free(slot.asset.line_index_allocation);
+            }
+        }
+    }
+
+    // Uploads what finished loading, up to the frame's budget, and returns the textures that got
+    // swapped in so they can be shown in the texture inspector
+    pub fn poll(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        geometry_pool: &mut BufferPool,
+        scheduler: &mut Scheduler,
+    ) -> Vec<(String, Arc<Texture>)> {
+        while let Ok(event) = self.changes.try_recv() {
+            match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => self.reload(&path),
+                _ => (),
+            }
+        }
+
+        let missing = mem::take(&mut *self.missing_bind_groups.borrow_mut());
+        for (handle, sampler) in missing {
+            if self.texture_bind_groups.contains_key(&handle) {
+                self.create_bind_group(device, handle, sampler);
+            }
+        }
+
+        let mut loaded_textures = Vec::new();
+
+        self.ready.extend(self.receiver.try_iter());
+        let mut uploaded = 0;
+        while uploaded < UPLOAD_BUDGET {
+            let loaded = match self.ready.pop_front() {
+                Some(loaded) => loaded,
+                None => break,
+            };
+            uploaded += loaded.size();
+            self.pending -= 1;
+            if self.pending == 0 && self.started > 1 {
+                info!(
+                    "Loaded {} assets in {:.2} s",
+                    self.started,
+                    self.batch_start.elapsed().as_secs_f32()
+                );
+            }
+
+            match loaded {
+                Loaded::Texture(handle, decoded) => {
+                    let (path, loading) = match self.textures.slot_mut(handle) {
+                        Some(slot) => (slot.key.clone(), mem::replace(&mut slot.loading, false)),
+                        None => continue,
+                    };
+
+                    let decoded = match decoded {
+                        Ok(decoded) => decoded,
+                        // Editors can save in several steps, the next change event retries
+                        Err(err) if !loading => {
+                            warn!(
+                                "Failed to reload {}, keeping the old version: {}",
+                                path, err
+                            );
+                            continue;
+                        }
+                        Err(err) => {
+                            warn!("Failed to load {}, using a placeholder: {}", path, err);
+                            let missing = self.placeholders.missing.clone();
+                            self.set_texture(device, handle, missing);
+                            continue;
+                        }
+                    };
+
+                    let DecodedTexture {
+                        base,
+                        first_level,
+                        first,
+                    } = decoded;
+                    let mip_level_count = texture::mip_level_count(base.width(), base.height());
+                    let levels = mip_level_count - first_level;
+                    let (texture, cmd_buffer) =
+                        Texture::from_rgba(device, &first, TextureFormat::Rgba8UnormSrgb, levels);
+                    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some("mip_fill_encoder"),
+                    });
+                    texture::fill_mips_nearest(
+                        device,
+                        &mut encoder,
+                        &texture.texture,
+                        &first,
+                        levels,
+                    );
+                    queue.submit(&[cmd_buffer, encoder.finish()]);
+
+                    let mips_ready = Arc::new(AtomicBool::new(levels == 1));
+                    if levels > 1 {
+                        let mut task =
+                            texture::generate_mips(texture.texture.clone(), first, levels);
+                        let mips_ready = mips_ready.clone();
+                        scheduler.add(&format!("mips: {}", path), move |ctx| {
+                            let status = task(ctx);
+                            if let TaskStatus::Done = status {
+                                mips_ready.store(true, Ordering::Release);
+                            }
+                            status
+                        });
+                    }
+
+                    let generation = self
+                        .streamed
+                        .get(&handle)
+                        .map_or(0, |streamed| streamed.generation + 1);
+                    self.streamed.insert(
+                        handle,
+                        Streamed {
+                            base,
+                            mip_level_count,
+                            resident: first_level,
+                            wanted: Cell::new(None),
+                            streaming: None,
+                            mips_ready,
+                            generation,
+                        },
+                    );
+
+                    let texture = Arc::new(texture);
+                    self.set_texture(device, handle, texture.clone());
+                    loaded_textures.push((path, texture));
+                }
+                Loaded::Mesh(handle, data) => {
+                    let slot = match self.meshes.slot_mut(handle) {
+                        Some(slot) => slot,
+                        None => continue,
+                    };
+
+                    match data {
+                        Ok(prepared) => {
+                            let mesh = Mesh::upload(device, queue, geometry_pool, prepared);
+                            slot.asset = Arc::new(mesh);
+                            slot.loading = false;
+                        }
+                        // Keeps the placeholder, so there's nothing to free on release
+                        Err(err) => warn!("Failed to load mesh {}: {}", slot.key, err),
+                    }
+                }
+            }
+        }
+
+        self.stream_textures(device, queue, &mut loaded_textures);
+        loaded_textures
+    }
+
+    // Swaps in finer levels that finished downsampling, drops levels while over the budget and
+    // starts on the next levels to stream in. Textures that got replaced go on `replaced`.
+    fn stream_textures(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        replaced: &mut Vec<(String, Arc<Texture>)>,
+    ) {
+        while let Ok(level) = self.level_receiver.try_recv() {
+            let streamed = match self.streamed.get_mut(&level.handle) {
+                Some(streamed) if streamed.generation == level.generation => streamed,
+                _ => continue,
+            };
+            streamed.streaming = None;
+            if level.mip_level + 1 != streamed.resident {
+                continue;
+            }
+            let texture = self.set_resident_level(
+                device,
+                queue,
+                level.handle,
+                level.mip_level,
+                Some(&level.rgba),
+            );
+            replaced.push((self.textures.slot(level.handle).key.clone(), texture));
+        }
+
+        let mut used: usize = self.streamed.values().map(Streamed::bytes).sum();
+
+        // Detail nothing needs goes first, from the textures with the most of it
+        while used > self.texture_budget {
+            let handle = self
+                .streamed
+                .iter()
+                .filter(|(_, streamed)| streamed.idle() && streamed.resident < streamed.target())
+                .max_by_key(|(_, streamed)| streamed.target() - streamed.resident)
+                .map(|(&handle, _)| handle);
+            let handle = match handle {
+                Some(handle) => handle,
+                None => break,
+            };
+            let streamed = &self.streamed[&handle];
+            let mip_level = streamed.resident + 1;
+            used -= streamed.bytes() - streamed.bytes_from(mip_level);
+            let texture = self.set_resident_level(device, queue, handle, mip_level, None);
+            replaced.push((self.textures.slot(handle).key.clone(), texture));
+        }
+
+        // The textures furthest from what they need get their next level first
+        let mut streaming = self
+            .streamed
+            .values()
+            .filter(|streamed| streamed.streaming.is_some())
+            .count();
+        let mut wanting: Vec<_> = self
+            .streamed
+            .iter()
+            .filter(|(_, streamed)| streamed.idle() && streamed.target() < streamed.resident)
+            .map(|(&handle, streamed)| (streamed.resident - streamed.target(), handle))
+            .collect();
+        wanting.sort_by_key(|&(missing, _)| Reverse(missing));
+        for (_, handle) in wanting {
+            if streaming >= MAX_STREAMING {
+                break;
+            }
+            let streamed = self.streamed.get_mut(&handle).unwrap();
+            let mip_level = streamed.resident - 1;
+            let more = streamed.bytes_from(mip_level) - streamed.bytes();
+            if used + more > self.texture_budget {
+                continue;
+            }
+            used += more;
+            streaming += 1;
+            streamed.streaming = Some(mip_level);
+
+            let base = streamed.base.clone();
+            let generation = streamed.generation;
+            let sender = self.level_sender.clone();
+            self.pool.spawn(move || {
+                let level = StreamedLevel {
+                    handle,
+                    generation,
+                    mip_level,
+                    rgba: downsample(&base, mip_level),
+                };
+                sender.send(level).ok();
+            });
+        }
+
+        for streamed in self.streamed.values() {
+            streamed.wanted.set(None);
+        }
+    }
+
+    // Replaces the texture behind `handle` with one that starts at `mip_level`, one level finer
+    // or coarser than what's resident. The levels both have get copied over on the GPU, a finer
+    // one has to come with the texels of its new top level.
+    fn set_resident_level(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        handle: Handle<Texture>,
+        mip_level: u32,
+        top: Option<&RgbaImage>,
+    ) -> Arc<Texture> {
+        let old = self.textures.get(handle).clone();
+        let streamed = self.streamed.get_mut(&handle).unwrap();
+        let width = texture::mip_size(streamed.base.width(), mip_level);
+        let height = texture::mip_size(streamed.base.height(), mip_level);
+        let levels = streamed.mip_level_count - mip_level;
+        streamed.resident = mip_level;
+
+        let texture = Texture::empty(device, width, height, old.info.format, levels);
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("texture_streaming_encoder"),
+        });
+        match top {
+            Some(top) => {
+                texture::copy_texels_to_texture(
+                    device,
+                    &mut encoder,
+                    top,
+                    width,
+                    height,
+                    &texture.texture,
+                    0,
+                    Origin3d::ZERO,
+                );
+                texture::copy_mip_levels(
+                    &mut encoder,
+                    &old.texture,
+                    0,
+                    &texture.texture,
+                    1,
+                    old.info.mip_level_count,
+                    old.info.size.width,
+                    old.info.size.height,
+                );
+            }
+            None => texture::copy_mip_levels(
+                &mut encoder,
+                &old.texture,
+                1,
+                &texture.texture,
+                0,
+                levels,
+                width,
+                height,
+            ),
+        }
+        queue.submit(&[encoder.finish()]);
+
+        let texture = Arc::new(texture);
+        self.set_texture(device, handle, texture.clone());
+        texture
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "{} textures, {} meshes, {} samplers",
+            self.textures.len(),
+            self.meshes.len(),
+            self.samplers.len()
+        ));
+
+        let used: usize = self.streamed.values().map(Streamed::bytes).sum();
+        let mut budget = self.texture_budget as f32 / (1 << 20) as f32;
+        ui.add(
+            egui::Slider::new(&mut budget, 0.25..=512.0)
+                .logarithmic(true)
+                .text("Texture budget (MB)"),
+        );
+        self.texture_budget = (budget * (1 << 20) as f32) as usize;
+        ui.label(format!(
+            "{:.2} MB of streamed textures resident",
+            used as f32 / (1 << 20) as f32
+        ));
+
+        if let Some((done, started)) = self.progress() {
+            ui.add(
+                egui::ProgressBar::new(done as f32 / started as f32).text(format!(
+                    "Loading {} of {}",
+                    done + 1,
+                    started
+                )),
+            );
+        }
+        for (index, slot) in self.textures.slots.iter().enumerate() {
+            let slot = match slot {
+                Some(slot) => slot,
+                None => continue,
+            };
+            let handle = Handle {
+                index,
+                marker: PhantomData,
+            };
+            match self.streamed.get(&handle) {
+                Some(streamed) => ui.label(format!(
+                    "{} ({} users, {}x{} of {}x{})",
+                    slot.key,
+                    slot.users,
+                    texture::mip_size(streamed.base.width(), streamed.resident),
+                    texture::mip_size(streamed.base.height(), streamed.resident),
+                    streamed.base.width(),
+                    streamed.base.height()
+                )),
+                None => ui.label(format!("{} ({} users)", slot.key, slot.users)),
+            };
+        }
+        for slot in self.meshes.slots.iter().flatten() {
+            ui.label(format!("{} ({} users)", slot.key, slot.users));
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::{self, StorageBuffer};
+use crate::pipeline::{ComputePipelineKey, PipelineCache, Shader};
+use playground_math::{Block, BlockWriter, Layout};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource, BufferAddress,
+    BufferUsage, CommandEncoder, ComputePipeline, Device, Sampler, ShaderStage, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const LUMINANCE_HISTOGRAM_COMP: Shader = Shader {
+    name: "luminance_histogram.comp",
+    source: include_str!("../shaders/luminance_histogram.comp"),
+    stage: ShaderStage::COMPUTE,
+};
+
+const LUMINANCE_AVERAGE_COMP: Shader = Shader {
+    name: "luminance_average.comp",
+    source: include_str!("../shaders/luminance_average.comp"),
+    stage: ShaderStage::COMPUTE,
+};
+
+// Has to match local_size_x and local_size_y in the histogram shader
+const TILE_SIZE: u32 = 16;
+
+const BIN_COUNT: usize = 256;
+
+// Middle grey, what the average luminance gets exposed to
+pub const KEY: f32 = 0.18;
+
+// Matches the storage buffer in the shaders, it never leaves the GPU
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Histogram {
+    bins: [u32; BIN_COUNT],
+    luminance: f32,
+}
+
+unsafe impl bytemuck::Pod for Histogram {}
+
+unsafe impl bytemuck::Zeroable for Histogram {}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct ExposureUniforms {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+    width: u32,
+    height: u32,
+}
+
+impl Block for ExposureUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.min_log_luminance);
+        writer.member(&self.log_luminance_range);
+        writer.member(&self.adaptation);
+        writer.member(&self.width);
+        writer.member(&self.height);
+    }
+}
+
+// Measures how bright the HDR target is, so the tonemapping can expose for it. A compute pass
+// sorts the pixels into a histogram of log luminance and a second one averages it and moves the
+// adapted luminance a bit towards that, like eyes getting used to the dark. All of it stays in a
+// storage buffer the tonemapping reads, so there's no waiting on the GPU.
+pub struct AutoExposure {
+    pub enabled: bool,
+    // Log2 of the darkest and brightest luminance told apart, anything outside gets clamped
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    // How quickly the exposure follows, higher is faster
+    pub adaptation_speed: f32,
+
+    _histogram: StorageBuffer,
+    histogram_pipeline: Arc<ComputePipeline>,
+    average_pipeline: Arc<ComputePipeline>,
+    source_layout: Arc<BindGroupLayout>,
+    source_bind_group: BindGroup,
+    storage_bind_group: Arc<BindGroup>,
+    fragment_bind_group: Arc<BindGroup>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    size: PhysicalSize<u32>,
+}
+
+impl AutoExposure {
+    // Measures `source`, which is `size` pixels and gets read through `sampler`
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        source: &TextureView,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        // Starts out exposed as if the adaptation had already settled on middle grey
+        let histogram = StorageBuffer::new(
+            device,
+            bytemuck::bytes_of(&Histogram {
+                bins: [0; BIN_COUNT],
+                luminance: KEY,
+            }),
+            BufferUsage::empty(),
+        );
+        let storage_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::STORAGE_LAYOUT,
+            "luminance_histogram",
+            &[histogram.binding(0)],
+        );
+        let fragment_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_STORAGE_LAYOUT,
+            "luminance_histogram",
+            &[histogram.binding(0)],
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            ExposureUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::COMPUTE_UNIFORM_LAYOUT,
+            "auto_exposure_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let histogram_pipeline = pipelines.get_compute(
+            device,
+            bind_groups,
+            &ComputePipelineKey {
+                shader: LUMINANCE_HISTOGRAM_COMP,
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::COMPUTE_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::COMPUTE_TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_LAYOUT),
+                ],
+            },
+        );
+        let average_pipeline = pipelines.get_compute(
+            device,
+            bind_groups,
+            &ComputePipelineKey {
+                shader: LUMINANCE_AVERAGE_COMP,
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::COMPUTE_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_LAYOUT),
+                ],
+            },
+        );
+
+        let source_layout = bind_groups.layout(
+            device,
+            "exposure_source",
+            bind_group::COMPUTE_TEXTURE_LAYOUT,
+        );
+        let source_bind_group = create_source_bind_group(device, &source_layout, source, sampler);
+
+        Self {
+            enabled: false,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            adaptation_speed: 1.5,
+            _histogram: histogram,
+            histogram_pipeline,
+            average_pipeline,
+            source_layout,
+            source_bind_group,
+            storage_bind_group,
+            fragment_bind_group,
+            uniform_allocation,
+            uniform_bind_group,
+            size,
+        }
+    }
+
+    // For when the source got replaced, e.g. on resize
+    pub fn set_source(
+        &mut self,
+        device: &Device,
+        source: &TextureView,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) {
+        self.source_bind_group =
+            create_source_bind_group(device, &self.source_layout, source, sampler);
+        self.size = size;
+    }
+
+    // The adapted luminance for a fragment shader, with a `FRAGMENT_STORAGE_LAYOUT`. Only
+    // measured while `enabled`.
+    pub fn luminance_bind_group(&self) -> &BindGroup {
+        &self.fragment_bind_group
+    }
+
+    // `dt` seconds after the last frame
+    pub fn update(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+    ) {
+        let uniforms = ExposureUniforms {
+            min_log_luminance: self.min_log_luminance,
+            log_luminance_range: (self.max_log_luminance - self.min_log_luminance).max(0.01),
+            // Exponential, so it adapts the same however the frames are spread
+            adaptation: 1.0 - (-dt * self.adaptation_speed).exp(),
+            width: self.size.width.max(1),
+            height: self.size.height.max(1),
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Auto exposure");
+        if self.enabled {
+            ui.add(egui::Slider::new(&mut self.adaptation_speed, 0.1..=10.0).text("Adaptation"));
+            ui.add(
+                egui::Slider::new(&mut self.min_log_luminance, -16.0..=0.0).text("Darkest (log2)"),
+            );
+            ui.add(
+                egui::Slider::new(&mut self.max_log_luminance, 0.0..=16.0).text("Brightest (log2)"),
+            );
+        }
+    }
+
+    // Records the two compute passes, once the source has been drawn
+    pub fn measure(&self, encoder: &mut CommandEncoder) {
+        encoder.push_debug_group("Auto exposure");
+        compute::dispatch(
+            encoder,
+            &self.histogram_pipeline,
+            &[
+                &self.uniform_bind_group,
+                &self.source_bind_group,
+                &self.storage_bind_group,
+            ],
+            [
+                compute::workgroups(self.size.width.max(1), TILE_SIZE),
+                compute::workgroups(self.size.height.max(1), TILE_SIZE),
+                1,
+            ],
+        );
+        compute::dispatch(
+            encoder,
+            &self.average_pipeline,
+            &[&self.uniform_bind_group, &self.storage_bind_group],
+            [1, 1, 1],
+        );
+        encoder.pop_debug_group();
+    }
+}
+
+fn create_source_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    source: &TextureView,
+    sampler: &Sampler,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        bindings: &[
+            Binding {
+                binding: 0,
+                resource: BindingResource::TextureView(source),
+            },
+            Binding {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("exposure_source"),
+    })
+}
@@ -0,0 +1,173 @@
+use crate::camera_path::CameraPath;
+use crate::draw_stats::DrawCounts;
+use cgmath::{InnerSpace, Point3, Vector3};
+use log::info;
+use playground_math::Camera;
+use serde::Serialize;
+use std::f32::consts::PI;
+
+// Frames at the start that don't count, while pipelines get created and assets load
+const WARMUP_FRAMES: usize = 30;
+
+#[derive(Serialize)]
+struct Summary {
+    average: f32,
+    min: f32,
+    p50: f32,
+    p95: f32,
+    p99: f32,
+    max: f32,
+}
+
+impl Summary {
+    // Nearest rank percentiles over `values`, which can't be empty
+    fn new(mut values: Vec<f32>) -> Self {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f32| {
+            let rank = (p / 100.0 * values.len() as f32).ceil() as usize;
+            values[rank.clamp(1, values.len()) - 1]
+        };
+        Self {
+            average: values.iter().sum::<f32>() / values.len() as f32,
+            min: values[0],
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: values[values.len() - 1],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    demo: &'a str,
+    adapter: &'a str,
+    backend: String,
+    seconds: f32,
+    frames: usize,
+    fps: f32,
+    frame_time_ms: Summary,
+    draw_calls: Summary,
+    triangles: Summary,
+}
+
+// Where the camera goes while benchmarking
+enum Flight {
+    // The path recorded for the demo with the camera path controls, looping
+    Recorded(CameraPath),
+    // Once around the target, at the distance and height the demo starts the camera at
+    Orbit {
+        center: Point3<f32>,
+        offset: Vector3<f32>,
+        time: f32,
+    },
+}
+
+// Runs one demo for a fixed time along a fixed camera path, collecting each frame's time and
+// draw counts, so changes to the renderer can be compared run against run. The first frames
+// are left out, they mostly measure pipeline creation.
+pub struct Benchmark {
+    demo: &'static str,
+    seconds: f32,
+    flight: Option<Flight>,
+    skipped: usize,
+    elapsed: f32,
+    frame_times: Vec<f32>,
+    draw_counts: Vec<DrawCounts>,
+}
+
+impl Benchmark {
+    pub fn new(demo: &'static str, seconds: f32) -> Self {
+        info!("Benchmarking {} for {} seconds", demo, seconds);
+        Self {
+            demo,
+            seconds,
+            flight: None,
+            skipped: 0,
+            elapsed: 0.0,
+            frame_times: Vec::new(),
+            draw_counts: Vec::new(),
+        }
+    }
+
+    // Moves the camera along the path, picking the path on the first call
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let (demo, seconds) = (self.demo, self.seconds);
+        let flight = self.flight.get_or_insert_with(|| {
+            let mut path = CameraPath::new(demo);
+            if path.is_saved() {
+                path.looping = true;
+                path.toggle_playback(camera);
+            }
+            if path.is_playing() {
+                info!("Following the recorded camera path");
+                return Flight::Recorded(path);
+            }
+            info!("No camera path recorded, orbiting the target");
+            Flight::Orbit {
+                center: camera.target,
+                offset: camera.eye - camera.target,
+                time: 0.0,
+            }
+        });
+
+        match flight {
+            Flight::Recorded(path) => {
+                path.update(camera, dt);
+            }
+            Flight::Orbit {
+                center,
+                offset,
+                time,
+            } => {
+                *time += dt;
+                let angle = *time / seconds * 2.0 * PI;
+                let up = camera.up.normalize();
+                let height = up * offset.dot(up);
+                let flat = *offset - height;
+                let side = up.cross(flat);
+                let rotated = flat * angle.cos() + side * angle.sin();
+                camera.eye = *center + height + rotated;
+                camera.target = *center;
+            }
+        }
+    }
+
+    // Returns true once the time is up
+    pub fn record(&mut self, frame_time: f32, draw_counts: DrawCounts) -> bool {
+        if self.skipped < WARMUP_FRAMES {
+            self.skipped += 1;
+            return false;
+        }
+
+        self.elapsed += frame_time;
+        self.frame_times.push(frame_time);
+        self.draw_counts.push(draw_counts);
+        self.elapsed >= self.seconds
+    }
+
+    pub fn report(&self, adapter: &str, backend: String) -> String {
+        let report = Report {
+            demo: self.demo,
+            adapter,
+            backend,
+            seconds: self.elapsed,
+            frames: self.frame_times.len(),
+            fps: self.frame_times.len() as f32 / self.elapsed,
+            frame_time_ms: Summary::new(self.frame_times.iter().map(|t| t * 1000.0).collect()),
+            draw_calls: Summary::new(
+                self.draw_counts
+                    .iter()
+                    .map(|counts| counts.draw_calls as f32)
+                    .collect(),
+            ),
+            triangles: Summary::new(
+                self.draw_counts
+                    .iter()
+                    .map(|counts| counts.triangles as f32)
+                    .collect(),
+            ),
+        };
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+}
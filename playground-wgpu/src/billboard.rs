@@ -0,0 +1,527 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use crate::sampler::SamplerSettings;
+use crate::texture::{self, Texture};
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector4, Zero};
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use playground_math::{Block, BlockWriter, Camera, Color, Layout};
+use std::f32::consts::PI;
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, Binding, BindingResource, BlendDescriptor, BlendFactor,
+    BlendOperation, Buffer, BufferAddress, BufferDescriptor, BufferUsage, ColorStateDescriptor,
+    ColorWrite, CommandEncoder, CommandEncoderDescriptor, CullMode, Device, IndexFormat,
+    InputStepMode, Origin3d, PrimitiveTopology, Queue, RenderPass, RenderPipeline, ShaderStage,
+    TextureFormat, VertexAttributeDescriptor, VertexFormat,
+};
+
+const BILLBOARD_VERT: Shader = Shader {
+    name: "billboard.vert",
+    source: include_str!("../shaders/billboard.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const BILLBOARD_FRAG: Shader = Shader {
+    name: "billboard.frag",
+    source: include_str!("../shaders/billboard.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+const ADDITIVE_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+// The instance buffer starts out with room for this many billboards and doubles when it runs out
+const INITIAL_CAPACITY: usize = 64;
+
+// Sides of a cell in the generated atlas, in texels
+const CELL_SIZE: u32 = 64;
+// Down to 4x4 cells, any smaller and neighbouring cells bleed into each other
+const ATLAS_MIP_LEVELS: u32 = 5;
+
+// The cells of `Atlas::generated`
+pub const GLOW_CELL: u32 = 0;
+pub const LIGHT_CELL: u32 = 1;
+pub const TREE_CELL: u32 = 2;
+
+// Which way billboards turn to face the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BillboardMode {
+    // All the way, lying in the view plane, for things that look the same from everywhere
+    Spherical,
+    // Only around y, staying upright, for things standing on the ground like trees
+    Cylindrical,
+}
+
+impl BillboardMode {
+    pub const ALL: [BillboardMode; 2] = [BillboardMode::Spherical, BillboardMode::Cylindrical];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BillboardMode::Spherical => "Spherical",
+            BillboardMode::Cylindrical => "Cylindrical",
+        }
+    }
+}
+
+// How billboards go over what's behind them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BillboardBlend {
+    // Either there or not, depth tested and written like any opaque geometry, in passes with a
+    // `depth::DEPTH_FORMAT` depth buffer
+    Cutout,
+    // Only brightening what's behind them, so they don't need sorting. Passes without depth.
+    Additive,
+}
+
+// A camera facing quad centered on `position`, `size` wide and high in world units, showing
+// `cell` of the atlas tinted by `color`
+#[derive(Copy, Clone, Debug)]
+pub struct Billboard {
+    pub position: Point3<f32>,
+    pub size: [f32; 2],
+    pub cell: u32,
+    pub color: Color,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BillboardUniforms {
+    view_proj: Matrix4<f32>,
+    camera_right: Vector4<f32>,
+    camera_up: Vector4<f32>,
+    camera_position: Vector4<f32>,
+    mode: u32,
+    alpha_cutoff: f32,
+}
+
+impl Block for BillboardUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.camera_right);
+        writer.member(&self.camera_up);
+        writer.member(&self.camera_position);
+        writer.member(&self.mode);
+        writer.member(&self.alpha_cutoff);
+    }
+}
+
+impl Default for BillboardUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            camera_right: Vector4::zero(),
+            camera_up: Vector4::zero(),
+            camera_position: Vector4::zero(),
+            mode: 0,
+            alpha_cutoff: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct BillboardInstance {
+    position: [f32; 3],
+    size: [f32; 2],
+    cell: [f32; 4],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for BillboardInstance {}
+
+unsafe impl bytemuck::Zeroable for BillboardInstance {}
+
+fn instance_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<BillboardInstance>() as BufferAddress,
+        step_mode: InputStepMode::Instance,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: 12,
+                shader_location: 1,
+                format: VertexFormat::Float2,
+            },
+            VertexAttributeDescriptor {
+                offset: 20,
+                shader_location: 2,
+                format: VertexFormat::Float4,
+            },
+            VertexAttributeDescriptor {
+                offset: 36,
+                shader_location: 3,
+                format: VertexFormat::Float4,
+            },
+        ],
+    }
+}
+
+// From 1 inside to 0 outside, blended over about a texel at the edge of a cell `CELL_SIZE` wide
+fn coverage(distance_outside: f32) -> f32 {
+    (0.5 - distance_outside * CELL_SIZE as f32 / 2.0).clamp(0.0, 1.0)
+}
+
+// The texel at `u`, `v` of a generated cell, both from -1 to 1 with v going down
+fn cell_texel(cell: u32, u: f32, v: f32) -> [f32; 4] {
+    let radius = (u * u + v * v).sqrt();
+    match cell {
+        // Round and soft, fading out toward the edge
+        GLOW_CELL => {
+            let t = radius.clamp(0.0, 1.0);
+            [1.0, 1.0, 1.0, 1.0 - t * t * (3.0 - 2.0 * t)]
+        }
+        // A disc with eight rays around it, white for tinting
+        LIGHT_CELL => {
+            let disc = coverage(radius - 0.45);
+            let angle = v.atan2(u);
+            let spoke = (angle * 4.0 / PI).round() * PI / 4.0;
+            let across = radius * (angle - spoke).sin().abs();
+            let ray = if radius > 0.6 && radius < 0.95 {
+                coverage(across - 0.06)
+            } else {
+                0.0
+            };
+            [1.0, 1.0, 1.0, disc.max(ray)]
+        }
+        // A conifer, a trunk under three layers of branches, standing on the bottom edge
+        _ => {
+            let trunk = if v > 0.6 {
+                coverage(u.abs() - 0.08)
+            } else {
+                0.0
+            };
+            let crown = (0..3)
+                .map(|layer| {
+                    let top = -0.95 + layer as f32 * 0.35;
+                    let bottom = top + 0.75;
+                    let width = (v - top) / (bottom - top) * (0.55 + layer as f32 * 0.15);
+                    if v > top && v < bottom {
+                        coverage(u.abs() - width)
+                    } else {
+                        0.0
+                    }
+                })
+                .fold(0.0, f32::max);
+            if crown > 0.0 {
+                [0.12, 0.35 + 0.1 * (1.0 - v), 0.15, crown.max(trunk)]
+            } else {
+                [0.35, 0.22, 0.12, trunk]
+            }
+        }
+    }
+}
+
+// A texture of `columns` by `rows` equally sized cells, for billboards to show one each
+pub struct Atlas {
+    _texture: Texture,
+    bind_group: BindGroup,
+    columns: u32,
+    rows: u32,
+}
+
+impl Atlas {
+    // `image` has to be sRGB, with `mip_level_count` levels made by downsampling it
+    pub fn new(
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        image: &RgbaImage,
+        columns: u32,
+        rows: u32,
+        mip_level_count: u32,
+    ) -> (Self, wgpu::CommandBuffer) {
+        let (width, height) = image.dimensions();
+        let texture = Texture::empty(
+            device,
+            width,
+            height,
+            TextureFormat::Rgba8UnormSrgb,
+            mip_level_count,
+        );
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("atlas_upload_encoder"),
+        });
+        let mut level = image.clone();
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                let width = texture::mip_size(width, mip_level);
+                let height = texture::mip_size(height, mip_level);
+                level = imageops::resize(&level, width, height, FilterType::Triangle);
+            }
+            texture::copy_texels_to_texture(
+                device,
+                &mut encoder,
+                &level,
+                level.width(),
+                level.height(),
+                &texture.texture,
+                mip_level,
+                Origin3d::ZERO,
+            );
+        }
+
+        let layout = bind_groups.layout(device, "billboard_atlas", bind_group::TEXTURE_LAYOUT);
+        let sampler = device.create_sampler(&SamplerSettings::SMOOTH.descriptor());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&texture.view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("billboard_atlas"),
+        });
+
+        let atlas = Self {
+            _texture: texture,
+            bind_group,
+            columns,
+            rows,
+        };
+        (atlas, encoder.finish())
+    }
+
+    // A glow for particles, a light icon and a tree, at `GLOW_CELL`, `LIGHT_CELL` and
+    // `TREE_CELL`
+    pub fn generated(device: &Device, queue: &Queue, bind_groups: &mut BindGroupCache) -> Self {
+        let columns = 3;
+        let image = RgbaImage::from_fn(CELL_SIZE * columns, CELL_SIZE, |x, y| {
+            let cell = x / CELL_SIZE;
+            let to_signed =
+                |texel: u32| ((texel % CELL_SIZE) as f32 + 0.5) / CELL_SIZE as f32 * 2.0 - 1.0;
+            let [r, g, b, a] = cell_texel(cell, to_signed(x), to_signed(y));
+            let [r, g, b, a] = Color::new(r, g, b, a).to_srgb8();
+            Rgba([r, g, b, a])
+        });
+        let (atlas, cmd_buffer) =
+            Self::new(device, bind_groups, &image, columns, 1, ATLAS_MIP_LEVELS);
+        queue.submit(&[cmd_buffer]);
+        atlas
+    }
+
+    // The top left and bottom right texture coordinates of `cell`, counting across then down
+    pub fn cell_rect(&self, cell: u32) -> [f32; 4] {
+        let column = cell % self.columns;
+        let row = (cell / self.columns).min(self.rows - 1);
+        let width = 1.0 / self.columns as f32;
+        let height = 1.0 / self.rows as f32;
+        let left = column as f32 * width;
+        let top = row as f32 * height;
+        [left, top, left + width, top + height]
+    }
+
+    // For a `bind_group::TEXTURE_LAYOUT` slot
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
+
+fn create_instance_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("billboard_instances"),
+        size: (capacity * mem::size_of::<BillboardInstance>()) as BufferAddress,
+        usage: BufferUsage::VERTEX | BufferUsage::COPY_DST,
+    })
+}
+
+// Camera facing quads expanded from a single point each in the vertex shader, showing cells of
+// an `Atlas`. `prepare` uploads this frame's billboards and `render` draws them into the demo's
+// render pass.
+pub struct Billboards {
+    pub mode: BillboardMode,
+    blend: BillboardBlend,
+    atlas: Arc<Atlas>,
+
+    instance_buffer: Buffer,
+    // In billboards
+    capacity: usize,
+    instance_count: u32,
+
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: BindGroup,
+}
+
+impl Billboards {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        atlas: Arc<Atlas>,
+        blend: BillboardBlend,
+    ) -> Self {
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            BillboardUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        // Not cached, there can be more than one set of billboards at a time
+        let layout = bind_groups.layout(
+            device,
+            "billboard_uniforms",
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+        );
+        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &layout,
+            bindings: &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+            label: Some("billboard_uniforms"),
+        });
+
+        let (color_blend, depth_stencil_state) = match blend {
+            BillboardBlend::Cutout => (ALPHA_BLEND, Some(depth::depth_stencil_state())),
+            BillboardBlend::Additive => (ADDITIVE_BLEND, None),
+        };
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: BILLBOARD_VERT,
+                fragment_shader: Some(BILLBOARD_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                ],
+                vertex_buffers: vec![instance_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: color_blend.clone(),
+                    alpha_blend: color_blend,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            mode: BillboardMode::Spherical,
+            blend,
+            atlas,
+            instance_buffer: create_instance_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            instance_count: 0,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Billboards")
+            .selected_text(self.mode.name())
+            .show_ui(ui, |ui| {
+                for &mode in &BillboardMode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.name());
+                }
+            });
+    }
+
+    // Uploads `billboards` as seen from `camera`, for the next `render`
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+        billboards: &[Billboard],
+    ) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let uniforms = BillboardUniforms {
+            view_proj: camera.build_view_projection_matrix(),
+            camera_right: right.extend(0.0),
+            camera_up: up.extend(0.0),
+            camera_position: camera.eye.to_homogeneous(),
+            mode: self.mode as u32,
+            alpha_cutoff: match self.blend {
+                BillboardBlend::Cutout => 0.5,
+                BillboardBlend::Additive => 0.0,
+            },
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+
+        let instances: Vec<BillboardInstance> = billboards
+            .iter()
+            .map(|billboard| BillboardInstance {
+                position: billboard.position.into(),
+                size: billboard.size,
+                cell: self.atlas.cell_rect(billboard.cell),
+                color: billboard.color.to_array(),
+            })
+            .collect();
+        self.instance_count = instances.len() as u32;
+        if instances.is_empty() {
+            return;
+        }
+
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.capacity);
+        }
+        let staging =
+            device.create_buffer_with_data(bytemuck::cast_slice(&instances), BufferUsage::COPY_SRC);
+        encoder.copy_buffer_to_buffer(
+            &staging,
+            0,
+            &self.instance_buffer,
+            0,
+            (instances.len() * mem::size_of::<BillboardInstance>()) as BufferAddress,
+        );
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.instance_buffer, 0, 0);
+        draw_stats::record_triangles(6, self.instance_count);
+        render_pass.draw(0..6, 0..self.instance_count);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool) {
+        uniform_pool.free(self.uniform_allocation);
+    }
+}
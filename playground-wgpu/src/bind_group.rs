@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, Binding, BindingType, Device, ShaderStage, TextureComponentType,
+    TextureViewDimension,
+};
+
+// A sampled 2D texture and its sampler, visible to the fragment shader
+pub const TEXTURE_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::SampledTexture {
+            multisampled: false,
+            dimension: TextureViewDimension::D2,
+            component_type: TextureComponentType::Uint,
+        },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// Four layer textures at bindings 0 to 3 for blending by splat weights, and a sampler for all of
+// them. Visible to the fragment shader.
+pub const SPLAT_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 3,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 4,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+const SAMPLED_TEXTURE_2D: BindingType = BindingType::SampledTexture {
+    multisampled: false,
+    dimension: TextureViewDimension::D2,
+    component_type: TextureComponentType::Uint,
+};
+
+// Two textures at bindings 0 and 1 that get read together, and a sampler for both. Visible to the
+// fragment shader.
+pub const TEXTURE_PAIR_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// A shadow map at binding 0, a comparison sampler for it at binding 1, the light's uniform block
+// at binding 2 and a plain sampler for reading its depth at binding 3, for shading with
+// `shadow::ShadowMap`. Visible to the fragment shader.
+pub const SHADOW_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::SampledTexture {
+            multisampled: false,
+            dimension: TextureViewDimension::D2,
+            component_type: TextureComponentType::Float,
+        },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: true },
+    },
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::UniformBuffer { dynamic: false },
+    },
+    BindGroupLayoutEntry {
+        binding: 3,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// A sampled 3D texture and its sampler, visible to the fragment shader
+pub const TEXTURE_3D_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::SampledTexture {
+            multisampled: false,
+            dimension: TextureViewDimension::D3,
+            component_type: TextureComponentType::Float,
+        },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// A scene's color, material and depth targets at bindings 0 to 2 for reading back in a screen
+// space pass, and a sampler for all of them. Visible to the fragment shader.
+pub const SCENE_TARGETS_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 2,
+        visibility: ShaderStage::FRAGMENT,
+        ty: SAMPLED_TEXTURE_2D,
+    },
+    BindGroupLayoutEntry {
+        binding: 3,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// A single uniform block, visible to the vertex shader
+pub const UNIFORM_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::VERTEX,
+    ty: BindingType::UniformBuffer { dynamic: false },
+}];
+
+// A single uniform block, visible to the fragment shader
+pub const FRAGMENT_UNIFORM_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::FRAGMENT,
+    ty: BindingType::UniformBuffer { dynamic: false },
+}];
+
+// A single uniform block, visible to the vertex and fragment shaders
+pub const OBJECT_UNIFORM_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::from_bits_truncate(
+        ShaderStage::VERTEX.bits() | ShaderStage::FRAGMENT.bits(),
+    ),
+    ty: BindingType::UniformBuffer { dynamic: false },
+}];
+
+// A single uniform block, visible to compute shaders
+pub const COMPUTE_UNIFORM_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::COMPUTE,
+    ty: BindingType::UniformBuffer { dynamic: false },
+}];
+
+// A uniform block at binding 0 and a storage buffer at binding 1 the fragment shader reads from,
+// for a list of items and how many of them to use. The buffer needs `BufferUsage::STORAGE_READ`.
+pub const FRAGMENT_LIST_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::UniformBuffer { dynamic: false },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::FRAGMENT,
+        ty: BindingType::StorageBuffer {
+            dynamic: false,
+            readonly: true,
+        },
+    },
+];
+
+// A sampled 2D texture and its sampler, visible to compute shaders
+pub const COMPUTE_TEXTURE_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::SampledTexture {
+            multisampled: false,
+            dimension: TextureViewDimension::D2,
+            component_type: TextureComponentType::Float,
+        },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::Sampler { comparison: false },
+    },
+];
+
+// A single storage buffer that compute shaders read and write
+pub const STORAGE_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::COMPUTE,
+    ty: BindingType::StorageBuffer {
+        dynamic: false,
+        readonly: false,
+    },
+}];
+
+// Storage buffers compute shaders read from at binding 0 and write to at binding 1, for
+// simulations that swap the two every step
+pub const STORAGE_IN_OUT_LAYOUT: &[BindGroupLayoutEntry] = &[
+    BindGroupLayoutEntry {
+        binding: 0,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageBuffer {
+            dynamic: false,
+            readonly: true,
+        },
+    },
+    BindGroupLayoutEntry {
+        binding: 1,
+        visibility: ShaderStage::COMPUTE,
+        ty: BindingType::StorageBuffer {
+            dynamic: false,
+            readonly: false,
+        },
+    },
+];
+
+// A single storage buffer the vertex shader reads from, for pulling vertex data out of what a
+// compute shader wrote. The buffer needs `BufferUsage::STORAGE_READ`.
+pub const VERTEX_STORAGE_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::VERTEX,
+    ty: BindingType::StorageBuffer {
+        dynamic: false,
+        readonly: true,
+    },
+}];
+
+// A single storage buffer the fragment shader reads from, for values a compute shader worked out
+// without a trip through the CPU. The buffer needs `BufferUsage::STORAGE_READ`.
+pub const FRAGMENT_STORAGE_LAYOUT: &[BindGroupLayoutEntry] = &[BindGroupLayoutEntry {
+    binding: 0,
+    visibility: ShaderStage::FRAGMENT,
+    ty: BindingType::StorageBuffer {
+        dynamic: false,
+        readonly: true,
+    },
+}];
+
+// `BindGroupLayoutEntry` is hashable but not comparable, so the entries get flattened into a key
+pub type LayoutKey = Vec<(u32, ShaderStage, BindingType)>;
+
+pub fn layout_key(entries: &[BindGroupLayoutEntry]) -> LayoutKey {
+    entries
+        .iter()
+        .map(|entry| (entry.binding, entry.visibility, entry.ty))
+        .collect()
+}
+
+// Shares bind group layouts between everything that describes them the same way, and bind groups
+// between everything binding the same resources to the same layout.
+//
+// wgpu resources don't expose an identity, so bind groups are keyed on a name chosen by the caller
+// for the bound resources (e.g. the texture path).
+#[derive(Default)]
+pub struct BindGroupCache {
+    layouts: HashMap<LayoutKey, Arc<BindGroupLayout>>,
+    bind_groups: HashMap<(LayoutKey, String), Arc<BindGroup>>,
+}
+
+impl BindGroupCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layout(
+        &mut self,
+        device: &Device,
+        label: &str,
+        entries: &[BindGroupLayoutEntry],
+    ) -> Arc<BindGroupLayout> {
+        self.layout_from_key(device, label, &layout_key(entries))
+    }
+
+    pub fn layout_from_key(
+        &mut self,
+        device: &Device,
+        label: &str,
+        key: &LayoutKey,
+    ) -> Arc<BindGroupLayout> {
+        if let Some(layout) = self.layouts.get(key) {
+            return layout.clone();
+        }
+
+        let entries: Vec<_> = key
+            .iter()
+            .map(|&(binding, visibility, ty)| BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty,
+            })
+            .collect();
+        let layout = Arc::new(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            bindings: &entries,
+            label: Some(label),
+        }));
+
+        self.layouts.insert(key.clone(), layout.clone());
+        layout
+    }
+
+    pub fn bind_group(
+        &mut self,
+        device: &Device,
+        entries: &[BindGroupLayoutEntry],
+        key: &str,
+        bindings: &[Binding],
+    ) -> Arc<BindGroup> {
+        let cache_key = (layout_key(entries), key.to_string());
+        if let Some(bind_group) = self.bind_groups.get(&cache_key) {
+            return bind_group.clone();
+        }
+
+        let layout = self.layout(device, "bind_group_layout", entries);
+        let bind_group = Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            layout: &layout,
+            bindings,
+            label: Some(key),
+        }));
+
+        self.bind_groups.insert(cache_key, bind_group.clone());
+        bind_group
+    }
+
+    // Forgets every bind group created for `key`, e.g. after the resources behind it were replaced
+    pub fn invalidate(&mut self, key: &str) {
+        self.bind_groups.retain(|(_, name), _| name != key);
+    }
+}
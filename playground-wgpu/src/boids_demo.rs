@@ -0,0 +1,446 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::{self, StorageBuffer};
+use crate::demo::{Demo, DemoContext};
+use crate::draw_stats;
+use crate::particles::Rng;
+use crate::pipeline::{ComputePipelineKey, PipelineKey, Shader, VertexLayout};
+use cgmath::{Matrix4, SquareMatrix, Vector3};
+use playground_math::{Block, BlockWriter, Camera, Layout, Projection};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, Buffer, BufferAddress, BufferUsage,
+    Color, ColorStateDescriptor, ColorWrite, CommandEncoder, ComputePipeline, CullMode, Device,
+    IndexFormat, InputStepMode, LoadOp, PrimitiveTopology, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, ShaderStage, StoreOp, TextureView,
+    VertexAttributeDescriptor, VertexFormat,
+};
+use winit::dpi::PhysicalSize;
+
+const BOIDS_COMP: Shader = Shader {
+    name: "boids.comp",
+    source: include_str!("../shaders/boids.comp"),
+    stage: ShaderStage::COMPUTE,
+};
+
+const BOID_VERT: Shader = Shader {
+    name: "boid.vert",
+    source: include_str!("../shaders/boid.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const DEBUG_LINE_FRAG: Shader = Shader {
+    name: "debug_line.frag",
+    source: include_str!("../shaders/debug_line.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Has to match local_size_x in the compute shader
+const WORKGROUP_SIZE: u32 = 64;
+const BOID_COUNT: u32 = 2048;
+// Every boid looks at every other one, so big steps make them jump through each other
+const MAX_STEP: f32 = 1.0 / 30.0;
+
+// Two crossed triangles, so the darts can be seen from any side
+const DART: [[f32; 3]; 6] = [
+    [-0.4, 0.0, -1.0],
+    [0.4, 0.0, -1.0],
+    [0.0, 0.0, 1.0],
+    [0.0, -0.4, -1.0],
+    [0.0, 0.4, -1.0],
+    [0.0, 0.0, 1.0],
+];
+
+// Matches the compute shader's storage buffers, which are also the instance buffers the darts
+// are drawn with
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Boid {
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for Boid {}
+
+unsafe impl bytemuck::Zeroable for Boid {}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct BoidUniforms {
+    dt: f32,
+    separation_distance: f32,
+    alignment_distance: f32,
+    cohesion_distance: f32,
+    separation_weight: f32,
+    alignment_weight: f32,
+    cohesion_weight: f32,
+    max_speed: f32,
+    bounds: f32,
+    count: u32,
+}
+
+impl Block for BoidUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.dt);
+        writer.member(&self.separation_distance);
+        writer.member(&self.alignment_distance);
+        writer.member(&self.cohesion_distance);
+        writer.member(&self.separation_weight);
+        writer.member(&self.alignment_weight);
+        writer.member(&self.cohesion_weight);
+        writer.member(&self.max_speed);
+        writer.member(&self.bounds);
+        writer.member(&self.count);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BoidRenderUniforms {
+    view_proj: Matrix4<f32>,
+    size: f32,
+}
+
+impl Block for BoidRenderUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.size);
+    }
+}
+
+impl Default for BoidRenderUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            size: 0.0,
+        }
+    }
+}
+
+fn dart_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<[f32; 3]>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![VertexAttributeDescriptor {
+            offset: 0,
+            shader_location: 0,
+            format: VertexFormat::Float3,
+        }],
+    }
+}
+
+fn boid_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<Boid>() as BufferAddress,
+        step_mode: InputStepMode::Instance,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 1,
+                format: VertexFormat::Float4,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 4]>() as BufferAddress,
+                shader_location: 2,
+                format: VertexFormat::Float4,
+            },
+        ],
+    }
+}
+
+// A flock steering by separation, alignment and cohesion. Every step a compute pass reads the
+// flock from one storage buffer and writes the next step to the other, which then gets drawn as
+// one instanced dart per boid.
+pub struct BoidsDemo {
+    camera: Camera,
+    paused: bool,
+    separation_distance: f32,
+    alignment_distance: f32,
+    cohesion_distance: f32,
+    separation_weight: f32,
+    alignment_weight: f32,
+    cohesion_weight: f32,
+    max_speed: f32,
+    bounds: f32,
+    size: f32,
+
+    boids: [StorageBuffer; 2],
+    // Which of `boids` holds the latest step
+    current: usize,
+    dart_buffer: Buffer,
+
+    compute_pipeline: Arc<ComputePipeline>,
+    simulation_allocation: Allocation,
+    simulation_bind_group: Arc<BindGroup>,
+    // Reading from `boids[i]` and writing to the other one
+    step_bind_groups: [Arc<BindGroup>; 2],
+
+    render_pipeline: Arc<RenderPipeline>,
+    render_allocation: Allocation,
+    render_bind_group: Arc<BindGroup>,
+}
+
+impl BoidsDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (0.0, 1.0, 7.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+        let bounds = 2.0;
+        let max_speed = 1.5;
+
+        let mut rng = Rng(0x2545_f491);
+        let flock: Vec<Boid> = (0..BOID_COUNT)
+            .map(|_| Boid {
+                position: (rng.in_sphere() * bounds).extend(1.0).into(),
+                velocity: (rng.in_sphere() * max_speed).extend(0.0).into(),
+            })
+            .collect();
+        let boids = [
+            StorageBuffer::new(
+                ctx.device,
+                bytemuck::cast_slice(&flock),
+                BufferUsage::VERTEX,
+            ),
+            StorageBuffer::new(
+                ctx.device,
+                bytemuck::cast_slice(&flock),
+                BufferUsage::VERTEX,
+            ),
+        ];
+        let dart_buffer = ctx
+            .device
+            .create_buffer_with_data(bytemuck::cast_slice(&DART), BufferUsage::VERTEX);
+
+        let simulation_allocation = ctx.uniform_pool.allocate(
+            ctx.device,
+            BoidUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let simulation_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::COMPUTE_UNIFORM_LAYOUT,
+            "boids_simulation",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: ctx.uniform_pool.buffer(&simulation_allocation),
+                    range: simulation_allocation.offset
+                        ..simulation_allocation.offset + simulation_allocation.size,
+                },
+            }],
+        );
+        let step_bind_groups = [
+            ctx.bind_groups.bind_group(
+                ctx.device,
+                bind_group::STORAGE_IN_OUT_LAYOUT,
+                "boids_step_0",
+                &[boids[0].binding(0), boids[1].binding(1)],
+            ),
+            ctx.bind_groups.bind_group(
+                ctx.device,
+                bind_group::STORAGE_IN_OUT_LAYOUT,
+                "boids_step_1",
+                &[boids[1].binding(0), boids[0].binding(1)],
+            ),
+        ];
+
+        let render_allocation = ctx.uniform_pool.allocate(
+            ctx.device,
+            BoidRenderUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let render_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::UNIFORM_LAYOUT,
+            "boids_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: ctx.uniform_pool.buffer(&render_allocation),
+                    range: render_allocation.offset
+                        ..render_allocation.offset + render_allocation.size,
+                },
+            }],
+        );
+
+        let compute_pipeline = ctx.pipelines.get_compute(
+            ctx.device,
+            ctx.bind_groups,
+            &ComputePipelineKey {
+                shader: BOIDS_COMP,
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::COMPUTE_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_IN_OUT_LAYOUT),
+                ],
+            },
+        );
+        let render_pipeline = ctx.pipelines.get(
+            ctx.device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: BOID_VERT,
+                fragment_shader: Some(DEBUG_LINE_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::UNIFORM_LAYOUT)],
+                vertex_buffers: vec![dart_layout(), boid_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format: ctx.format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            camera,
+            paused: false,
+            separation_distance: 0.15,
+            alignment_distance: 0.4,
+            cohesion_distance: 0.5,
+            separation_weight: 8.0,
+            alignment_weight: 1.0,
+            cohesion_weight: 0.8,
+            max_speed,
+            bounds,
+            size: 0.04,
+            boids,
+            current: 0,
+            dart_buffer,
+            compute_pipeline,
+            simulation_allocation,
+            simulation_bind_group,
+            step_bind_groups,
+            render_pipeline,
+            render_allocation,
+            render_bind_group,
+        }
+    }
+}
+
+impl Demo for BoidsDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+
+    fn step(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+    ) {
+        if !self.paused {
+            let uniforms = BoidUniforms {
+                dt: dt.min(MAX_STEP),
+                separation_distance: self.separation_distance,
+                alignment_distance: self.alignment_distance,
+                cohesion_distance: self.cohesion_distance,
+                separation_weight: self.separation_weight,
+                alignment_weight: self.alignment_weight,
+                cohesion_weight: self.cohesion_weight,
+                max_speed: self.max_speed,
+                bounds: self.bounds,
+                count: BOID_COUNT,
+            };
+            uniform_pool.write(
+                device,
+                encoder,
+                &self.simulation_allocation,
+                &uniforms.std140(),
+            );
+            compute::dispatch(
+                encoder,
+                &self.compute_pipeline,
+                &[
+                    &self.simulation_bind_group,
+                    &self.step_bind_groups[self.current],
+                ],
+                [compute::workgroups(BOID_COUNT, WORKGROUP_SIZE), 1, 1],
+            );
+            self.current = 1 - self.current;
+        }
+    }
+
+    // The flock only exists on the GPU, so it's drawn as of the last step
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        let uniforms = BoidRenderUniforms {
+            view_proj: self.camera.build_view_projection_matrix(),
+            size: self.size,
+        };
+        uniform_pool.write(device, encoder, &self.render_allocation, &uniforms.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} boids", BOID_COUNT));
+        ui.checkbox(&mut self.paused, "Paused");
+        ui.add(
+            egui::Slider::new(&mut self.separation_distance, 0.0..=1.0).text("Separation distance"),
+        );
+        ui.add(egui::Slider::new(&mut self.separation_weight, 0.0..=20.0).text("Separation"));
+        ui.add(
+            egui::Slider::new(&mut self.alignment_distance, 0.0..=1.0).text("Alignment distance"),
+        );
+        ui.add(egui::Slider::new(&mut self.alignment_weight, 0.0..=5.0).text("Alignment"));
+        ui.add(egui::Slider::new(&mut self.cohesion_distance, 0.0..=1.0).text("Cohesion distance"));
+        ui.add(egui::Slider::new(&mut self.cohesion_weight, 0.0..=5.0).text("Cohesion"));
+        ui.add(egui::Slider::new(&mut self.max_speed, 0.1..=5.0).text("Max speed"));
+        ui.add(egui::Slider::new(&mut self.bounds, 0.5..=5.0).text("Bounds"));
+        ui.add(egui::Slider::new(&mut self.size, 0.005..=0.2).text("Size"));
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.dart_buffer, 0, 0);
+        render_pass.set_vertex_buffer(1, &self.boids[self.current].buffer, 0, 0);
+        draw_stats::record_triangles(DART.len() as u32, BOID_COUNT);
+        render_pass.draw(0..DART.len() as u32, 0..BOID_COUNT);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        ctx.uniform_pool.free(self.simulation_allocation);
+        ctx.uniform_pool.free(self.render_allocation);
+        ctx.bind_groups.invalidate("boids_simulation");
+        ctx.bind_groups.invalidate("boids_step_0");
+        ctx.bind_groups.invalidate("boids_step_1");
+        ctx.bind_groups.invalidate("boids_uniforms");
+    }
+}
@@ -0,0 +1,291 @@
+use crate::readback::Readback;
+use std::sync::{Arc, Weak};
+use wgpu::{Buffer, BufferAddress, CommandEncoder, Device, Maintain};
+
+// Elements shown at once, large buffers are paged through
+const ELEMENTS_PER_PAGE: usize = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldType {
+    Vec4,
+    Mat4,
+}
+
+impl FieldType {
+    fn size(self) -> usize {
+        match self {
+            FieldType::Vec4 => 16,
+            FieldType::Mat4 => 64,
+        }
+    }
+
+    fn format(self, bytes: &[u8]) -> String {
+        match self {
+            FieldType::Vec4 => {
+                let values: Vec<_> = bytes[..16]
+                    .chunks(4)
+                    .map(|word| {
+                        let word = [word[0], word[1], word[2], word[3]];
+                        format!("{:.4}", f32::from_le_bytes(word))
+                    })
+                    .collect();
+                values.join(" ")
+            }
+            // Column major, one column per line
+            FieldType::Mat4 => {
+                let columns: Vec<_> = bytes[..64]
+                    .chunks(16)
+                    .map(|column| FieldType::Vec4.format(column))
+                    .collect();
+                columns.join("\n")
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: usize,
+    pub ty: FieldType,
+}
+
+// How to interpret the elements of a buffer, offsets follow the shader's (std140/std430) layout
+#[derive(Copy, Clone, Debug)]
+pub struct StructLayout {
+    pub name: &'static str,
+    pub stride: usize,
+    pub fields: &'static [Field],
+}
+
+struct Entry {
+    name: String,
+    // The inspector doesn't keep buffers alive, entries go away with their buffer
+    buffer: Weak<Buffer>,
+    offset: BufferAddress,
+    size: BufferAddress,
+    layout: StructLayout,
+    data: Option<Vec<u8>>,
+}
+
+// Debug window showing the contents of registered buffer ranges, read back from the GPU and
+// interpreted through a struct layout
+pub struct BufferInspector {
+    pub open: bool,
+    entries: Vec<Entry>,
+    selected: Option<usize>,
+    page: usize,
+    // Read the selected buffer every frame instead of only when asked to
+    live: bool,
+    refresh: bool,
+
+    readback: Option<Readback>,
+    readback_entry: Option<String>,
+    copy_recorded: bool,
+}
+
+impl BufferInspector {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            entries: Vec::new(),
+            selected: None,
+            page: 0,
+            live: true,
+            refresh: false,
+            readback: None,
+            readback_entry: None,
+            copy_recorded: false,
+        }
+    }
+
+    // `buffer` needs COPY_SRC usage, `offset` and `size` have to be multiples of 4
+    pub fn register(
+        &mut self,
+        name: &str,
+        buffer: &Arc<Buffer>,
+        offset: BufferAddress,
+        size: BufferAddress,
+        layout: StructLayout,
+    ) {
+        self.entries.push(Entry {
+            name: name.to_string(),
+            buffer: Arc::downgrade(buffer),
+            offset,
+            size,
+            layout,
+            data: None,
+        });
+    }
+
+    pub fn unregister(&mut self, name: &str) {
+        self.entries.retain(|entry| entry.name != name);
+        self.selected = None;
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Buffers")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let mut clicked = None;
+                for (index, entry) in self.entries.iter().enumerate() {
+                    let label = format!(
+                        "{} ({}, {} bytes)",
+                        entry.name, entry.layout.name, entry.size
+                    );
+                    if ui
+                        .selectable_label(self.selected == Some(index), label)
+                        .clicked()
+                    {
+                        clicked = Some(index);
+                    }
+                }
+                if clicked.is_some() {
+                    self.selected = clicked;
+                    self.page = 0;
+                }
+
+                ui.separator();
+                self.selected_ui(ui);
+            });
+        self.open = open;
+    }
+
+    fn selected_ui(&mut self, ui: &mut egui::Ui) {
+        let entry = match self.selected {
+            Some(index) => &self.entries[index],
+            None => {
+                ui.label("Select a buffer");
+                return;
+            }
+        };
+
+        let live = &mut self.live;
+        let refresh = &mut self.refresh;
+        ui.horizontal(|ui| {
+            ui.checkbox(live, "Live");
+            if ui.button("Refresh").clicked() {
+                *refresh = true;
+            }
+        });
+
+        let data = match &entry.data {
+            Some(data) => data,
+            None => {
+                ui.label("Waiting for data...");
+                return;
+            }
+        };
+
+        let layout = entry.layout;
+        let count = data.len() / layout.stride.max(1);
+        let pages = count.div_ceil(ELEMENTS_PER_PAGE);
+        if pages > 1 {
+            ui.add(egui::Slider::new(&mut self.page, 0..=pages - 1).text("Page"));
+        }
+
+        let first = (self.page * ELEMENTS_PER_PAGE).min(count);
+        let last = (first + ELEMENTS_PER_PAGE).min(count);
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("buffer_inspector_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for element in first..last {
+                        let base = element * layout.stride;
+                        for (i, field) in layout.fields.iter().enumerate() {
+                            if i == 0 {
+                                ui.label(format!("[{}]", element));
+                            } else {
+                                ui.label("");
+                            }
+                            ui.label(field.name);
+
+                            let start = base + field.offset;
+                            let end = start + field.ty.size();
+                            if end <= data.len() {
+                                ui.monospace(field.ty.format(&data[start..end]));
+                            } else {
+                                ui.label("out of range");
+                            }
+                            ui.end_row();
+                        }
+                    }
+                });
+        });
+    }
+
+    // Records the copy of the selected buffer range, if a new read is due
+    pub fn render(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        self.entries
+            .retain(|entry| entry.buffer.upgrade().is_some());
+        if self
+            .selected
+            .is_some_and(|index| index >= self.entries.len())
+        {
+            self.selected = None;
+        }
+
+        if !self.open || !(self.live || self.refresh) {
+            return;
+        }
+        if self.readback.as_ref().is_some_and(Readback::is_pending) {
+            return;
+        }
+
+        let entry = match self.selected {
+            Some(index) => &self.entries[index],
+            None => return,
+        };
+        let buffer = match entry.buffer.upgrade() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        if self.readback.as_ref().is_none_or(|r| r.size != entry.size) {
+            self.readback = Some(Readback::new(
+                device,
+                "buffer_inspector_readback",
+                entry.size,
+            ));
+        }
+        let readback = self.readback.as_ref().unwrap();
+
+        encoder.copy_buffer_to_buffer(&buffer, entry.offset, &readback.buffer, 0, entry.size);
+        self.readback_entry = Some(entry.name.clone());
+        self.copy_recorded = true;
+        self.refresh = false;
+    }
+
+    // Call after submitting the encoder passed to `render`
+    pub fn after_submit(&mut self) {
+        if self.copy_recorded {
+            if let Some(readback) = &mut self.readback {
+                readback.map();
+            }
+            self.copy_recorded = false;
+        }
+    }
+
+    // Picks up the buffer contents once the readback finished
+    pub fn poll(&mut self, device: &Device) {
+        let readback = match &mut self.readback {
+            Some(readback) if readback.is_pending() => readback,
+            _ => return,
+        };
+
+        device.poll(Maintain::Poll);
+        if let Some(result) = readback.try_read() {
+            let name = self.readback_entry.take();
+            let entry = self
+                .entries
+                .iter_mut()
+                .find(|entry| Some(&entry.name) == name.as_ref());
+
+            if let (Ok(mapping), Some(entry)) = (result, entry) {
+                entry.data = Some(mapping.as_slice().to_vec());
+            }
+        }
+    }
+}
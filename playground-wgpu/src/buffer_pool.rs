@@ -0,0 +1,228 @@
+use std::sync::Arc;
+use wgpu::{Buffer, BufferAddress, BufferDescriptor, BufferUsage, CommandEncoder, Device};
+
+// Copies between buffers have to be a multiple of 4 bytes
+const COPY_ALIGNMENT: BufferAddress = 4;
+
+fn align_to(value: BufferAddress, alignment: BufferAddress) -> BufferAddress {
+    value.div_ceil(alignment) * alignment
+}
+
+// A range handed out by a `BufferPool`, used to bind or free the data again
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Allocation {
+    block: usize,
+    pub offset: BufferAddress,
+    pub size: BufferAddress,
+}
+
+struct Block {
+    buffer: Arc<Buffer>,
+    size: BufferAddress,
+    // Free ranges as (offset, size), sorted by offset and never adjacent to each other
+    free: Vec<(BufferAddress, BufferAddress)>,
+}
+
+impl Block {
+    fn new(device: &Device, label: &str, usage: BufferUsage, size: BufferAddress) -> Self {
+        let buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+        }));
+
+        Self {
+            buffer,
+            size,
+            free: vec![(0, size)],
+        }
+    }
+
+    // First fit: take the first free range that still fits after aligning its start
+    fn allocate(&mut self, size: BufferAddress, alignment: BufferAddress) -> Option<BufferAddress> {
+        for i in 0..self.free.len() {
+            let (start, len) = self.free[i];
+            let offset = align_to(start, alignment);
+            let end = start + len;
+
+            if offset + size > end {
+                continue;
+            }
+
+            self.free.remove(i);
+            // Keep whatever is left on either side of the allocation
+            if offset + size < end {
+                self.free.insert(i, (offset + size, end - offset - size));
+            }
+            if start < offset {
+                self.free.insert(i, (start, offset - start));
+            }
+
+            return Some(offset);
+        }
+
+        None
+    }
+
+    fn free(&mut self, offset: BufferAddress, size: BufferAddress) {
+        let i = self
+            .free
+            .iter()
+            .position(|&(start, _)| start > offset)
+            .unwrap_or(self.free.len());
+        self.free.insert(i, (offset, size));
+
+        // Merge with the following range, then with the preceding one
+        if i + 1 < self.free.len() && offset + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free == [(0, self.size)]
+    }
+}
+
+// Sub-allocates many small ranges out of a few large buffers sharing the same usage, instead of
+// creating a separate buffer object for every mesh or uniform block.
+pub struct BufferPool {
+    label: String,
+    usage: BufferUsage,
+    block_size: BufferAddress,
+    blocks: Vec<Option<Block>>,
+}
+
+impl BufferPool {
+    pub fn new(label: &str, usage: BufferUsage, block_size: BufferAddress) -> Self {
+        Self {
+            label: label.to_string(),
+            // Data always gets into the pool by copying from a staging buffer, and can be copied
+            // out again for debugging
+            usage: usage | BufferUsage::COPY_DST | BufferUsage::COPY_SRC,
+            block_size,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        device: &Device,
+        size: BufferAddress,
+        alignment: BufferAddress,
+    ) -> Allocation {
+        let size = align_to(size.max(1), COPY_ALIGNMENT);
+        let alignment = alignment.max(COPY_ALIGNMENT);
+
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if let Some(block) = block {
+                if let Some(offset) = block.allocate(size, alignment) {
+                    return Allocation {
+                        block: index,
+                        offset,
+                        size,
+                    };
+                }
+            }
+        }
+
+        // Nothing fits, so start a new block. Oversized requests get a block of their own.
+        let mut block = Block::new(device, &self.label, self.usage, self.block_size.max(size));
+        let offset = block.allocate(size, alignment).unwrap();
+
+        let index = match self.blocks.iter().position(Option::is_none) {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+
+        Allocation {
+            block: index,
+            offset,
+            size,
+        }
+    }
+
+    // Allocates room for `data` and records a copy of it into the pool on `encoder`
+    pub fn upload(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        data: &[u8],
+        alignment: BufferAddress,
+    ) -> Allocation {
+        let allocation = self.allocate(device, data.len() as BufferAddress, alignment);
+        self.write(device, encoder, &allocation, data);
+        allocation
+    }
+
+    // Overwrites (the start of) an existing allocation with `data`
+    pub fn write(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        allocation: &Allocation,
+        data: &[u8],
+    ) {
+        assert!(data.len() as BufferAddress <= allocation.size);
+
+        // Pad the staging data so the copy size stays a multiple of 4
+        let mut padded = data.to_vec();
+        padded.resize(
+            align_to(data.len() as BufferAddress, COPY_ALIGNMENT) as usize,
+            0,
+        );
+
+        let staging = device.create_buffer_with_data(&padded, BufferUsage::COPY_SRC);
+        encoder.insert_debug_marker(&format!(
+            "{} upload, {} bytes at {}",
+            self.label,
+            data.len(),
+            allocation.offset
+        ));
+        encoder.copy_buffer_to_buffer(
+            &staging,
+            0,
+            self.buffer(allocation),
+            allocation.offset,
+            padded.len() as BufferAddress,
+        );
+    }
+
+    pub fn free(&mut self, allocation: Allocation) {
+        let block = self.blocks[allocation.block]
+            .as_mut()
+            .expect("Allocation freed twice");
+        block.free(allocation.offset, allocation.size);
+
+        // Release blocks that no longer hold anything, except the first one
+        if allocation.block > 0 && block.is_empty() {
+            self.blocks[allocation.block] = None;
+        }
+    }
+
+    pub fn buffer(&self, allocation: &Allocation) -> &Buffer {
+        &self.blocks[allocation.block]
+            .as_ref()
+            .expect("Allocation was freed")
+            .buffer
+    }
+
+    // The block behind `allocation`, for holding on to it outside the pool
+    pub fn shared_buffer(&self, allocation: &Allocation) -> Arc<Buffer> {
+        self.blocks[allocation.block]
+            .as_ref()
+            .expect("Allocation was freed")
+            .buffer
+            .clone()
+    }
+}
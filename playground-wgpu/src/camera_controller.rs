@@ -0,0 +1,144 @@
+use crate::gamepad::Gamepads;
+use crate::input::Input;
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation3, Vector2, Vector3, Zero};
+use playground_math::Camera;
+
+// How much zooming changes the distance to the target per second
+const ZOOM_RATE: f32 = 2.0;
+
+// Below this the camera counts as stopped, instead of creeping along forever
+const REST_SPEED: f32 = 1e-4;
+
+// The part of the way to its target a smoothed value covers in `dt` seconds, which compounds to
+// the same amount per second at any frame rate
+fn approach(stiffness: f32, dt: f32) -> f32 {
+    1.0 - (-stiffness * dt).exp()
+}
+
+// Flies the camera around with the camera_* actions or the left stick. Eye and target move
+// together, so only the right stick changes the view direction. Zooming moves the eye toward the
+// target instead, and toggle_projection switches between perspective and orthographic.
+//
+// With smoothing on, the input sets the velocities the camera eases toward instead of moving it
+// directly, so it speeds up and coasts to a stop rather than jumping from frame to frame.
+pub struct CameraController {
+    // Units per second
+    pub speed: f32,
+    // Radians per second at full stick deflection
+    pub look_speed: f32,
+    pub smoothing: bool,
+    // How quickly the velocities catch up with the input, higher is snappier
+    pub stiffness: f32,
+
+    // Along the camera's right, up and forward
+    velocity: Vector3<f32>,
+    // Yaw and pitch, in radians per second
+    look_velocity: Vector2<f32>,
+    // Doublings of the distance to the target per second
+    zoom_velocity: f32,
+}
+
+impl CameraController {
+    pub fn new(speed: f32, look_speed: f32) -> Self {
+        Self {
+            speed,
+            look_speed,
+            smoothing: true,
+            stiffness: 8.0,
+            velocity: Vector3::zero(),
+            look_velocity: Vector2::zero(),
+            zoom_velocity: 0.0,
+        }
+    }
+
+    // Forgets the momentum, for when the camera gets replaced
+    pub fn stop(&mut self) {
+        self.velocity = Vector3::zero();
+        self.look_velocity = Vector2::zero();
+        self.zoom_velocity = 0.0;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.speed, 0.1..=20.0).text("Speed"));
+        ui.add(egui::Slider::new(&mut self.look_speed, 0.1..=5.0).text("Look speed"));
+        ui.checkbox(&mut self.smoothing, "Smoothing");
+        ui.add_enabled(
+            self.smoothing,
+            egui::Slider::new(&mut self.stiffness, 1.0..=30.0).text("Stiffness"),
+        );
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, gamepads: &Gamepads, dt: f32) {
+        if input.pressed("toggle_projection") {
+            camera.projection = camera.projection.toggled();
+        }
+
+        let axis = |positive: &str, negative: &str| {
+            input.held(positive) as i32 as f32 - input.held(negative) as i32 as f32
+        };
+        let stick = gamepads.left_stick();
+        let amount = Vector3::new(
+            axis("camera_right", "camera_left") + stick.x,
+            axis("camera_up", "camera_down"),
+            axis("camera_forward", "camera_back") + stick.y,
+        );
+        let look = gamepads.right_stick();
+        let zoom = axis("camera_zoom_out", "camera_zoom_in");
+
+        let blend = if self.smoothing {
+            approach(self.stiffness, dt)
+        } else {
+            1.0
+        };
+        self.velocity += (amount * self.speed - self.velocity) * blend;
+        self.look_velocity += (look * self.look_speed - self.look_velocity) * blend;
+        self.zoom_velocity += (zoom - self.zoom_velocity) * blend;
+        if self.velocity.magnitude() < REST_SPEED {
+            self.velocity = Vector3::zero();
+        }
+        if self.look_velocity.magnitude() < REST_SPEED {
+            self.look_velocity = Vector2::zero();
+        }
+        if self.zoom_velocity.abs() < REST_SPEED {
+            self.zoom_velocity = 0.0;
+        }
+
+        let view = camera.target - camera.eye;
+        if view.magnitude2() == 0.0 {
+            return;
+        }
+
+        if !self.look_velocity.is_zero() {
+            let up = camera.up.normalize();
+            let yaw = Quaternion::from_axis_angle(up, Rad(-self.look_velocity.x * dt));
+            let right = view.cross(up).normalize();
+            let pitch = Quaternion::from_axis_angle(right, Rad(self.look_velocity.y * dt));
+
+            // Pitching past straight up or down would flip the camera
+            let pitched = pitch * view;
+            let view = if pitched.normalize().dot(up).abs() < 0.99 {
+                yaw * pitched
+            } else {
+                yaw * view
+            };
+            camera.target = camera.eye + view;
+        }
+
+        if self.zoom_velocity != 0.0 {
+            camera.zoom(ZOOM_RATE.powf(self.zoom_velocity * dt));
+        }
+
+        if self.velocity.is_zero() {
+            return;
+        }
+
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+
+        let offset =
+            (right * self.velocity.x + up * self.velocity.y + forward * self.velocity.z) * dt;
+        camera.eye += offset;
+        camera.target += offset;
+    }
+}
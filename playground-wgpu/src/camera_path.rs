@@ -0,0 +1,254 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use log::{error, info};
+use playground_math::{Camera, Keyframes};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Seconds between the keys taken while recording
+const KEY_INTERVAL: f32 = 0.25;
+
+// Each demo's path goes in a file of its own in here
+const DIR: &str = "camera_paths";
+
+// Where the camera was at `time` seconds into the recording
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+struct CameraKey {
+    time: f32,
+    eye: [f32; 3],
+    target: [f32; 3],
+    up: [f32; 3],
+}
+
+impl CameraKey {
+    fn new(time: f32, camera: &Camera) -> Self {
+        Self {
+            time,
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+        }
+    }
+}
+
+// The recorded keys as splines, built when playback starts
+struct Splines {
+    eye: Keyframes<Vector3<f32>>,
+    target: Keyframes<Vector3<f32>>,
+    up: Keyframes<Vector3<f32>>,
+    duration: f32,
+}
+
+impl Splines {
+    fn new(keys: &[CameraKey]) -> Self {
+        let track = |value: fn(&CameraKey) -> [f32; 3]| {
+            Keyframes::new(
+                keys.iter()
+                    .map(|key| (key.time, value(key).into()))
+                    .collect(),
+            )
+        };
+        Self {
+            eye: track(|key| key.eye),
+            target: track(|key| key.target),
+            up: track(|key| key.up),
+            duration: keys.last().map_or(0.0, |key| key.time),
+        }
+    }
+
+    fn apply(&self, time: f32, camera: &mut Camera) {
+        if let (Some(eye), Some(target), Some(up)) = (
+            self.eye.sample_smooth(time),
+            self.target.sample_smooth(time),
+            self.up.sample_smooth(time),
+        ) {
+            camera.eye = Point3::from_vec(eye);
+            camera.target = Point3::from_vec(target);
+            if up.magnitude2() > 1e-6 {
+                camera.up = up.normalize();
+            }
+        }
+    }
+}
+
+enum State {
+    Idle,
+    // Time since the recording started, and since the last key
+    Recording { time: f32, since_key: f32 },
+    Playing { time: f32, splines: Splines },
+}
+
+// Records where the camera goes to a file and flies it along the same path again, through a
+// Catmull-Rom spline over the keys. Every demo has its own path file, named after it, which
+// stays around between runs, so benchmarks and flythroughs can be repeated exactly after
+// changing the code.
+pub struct CameraPath {
+    pub looping: bool,
+    path: PathBuf,
+    keys: Vec<CameraKey>,
+    state: State,
+}
+
+impl CameraPath {
+    // The path for the demo called `demo`, like camera_paths/depth_of_field.json
+    pub fn new(demo: &str) -> Self {
+        let file_name: String = demo
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Self {
+            looping: false,
+            path: Path::new(DIR).join(file_name + ".json"),
+            keys: Vec::new(),
+            state: State::Idle,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.state, State::Recording { .. })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, State::Playing { .. })
+    }
+
+    // Whether anything was ever recorded to the file
+    pub fn is_saved(&self) -> bool {
+        self.path.exists()
+    }
+
+    pub fn toggle_recording(&mut self, camera: &Camera) {
+        if self.is_recording() {
+            self.stop(camera);
+        } else {
+            self.keys = vec![CameraKey::new(0.0, camera)];
+            self.state = State::Recording {
+                time: 0.0,
+                since_key: 0.0,
+            };
+            info!("Recording the camera path");
+        }
+    }
+
+    // Plays what's in the file, or what was just recorded if it can't be read
+    pub fn toggle_playback(&mut self, camera: &Camera) {
+        if self.is_playing() {
+            self.stop(camera);
+            return;
+        }
+
+        self.stop(camera);
+        match self.load() {
+            Ok(keys) => self.keys = keys,
+            Err(err) => error!("Failed to load {}: {}", self.path.display(), err),
+        }
+        if self.keys.is_empty() {
+            return;
+        }
+        self.state = State::Playing {
+            time: 0.0,
+            splines: Splines::new(&self.keys),
+        };
+    }
+
+    // Finishes a recording with a last key where the camera is now and saves it
+    pub fn stop(&mut self, camera: &Camera) {
+        if let State::Recording { time, .. } = self.state {
+            self.keys.push(CameraKey::new(time, camera));
+            match self.save() {
+                Ok(()) => info!(
+                    "Saved {} camera keys to {}",
+                    self.keys.len(),
+                    self.path.display()
+                ),
+                Err(err) => error!("Failed to save {}: {}", self.path.display(), err),
+            }
+        }
+        self.state = State::Idle;
+    }
+
+    fn load(&self) -> Result<Vec<CameraKey>, failure::Error> {
+        Ok(serde_json::from_str(&fs::read_to_string(&self.path)?)?)
+    }
+
+    fn save(&self) -> Result<(), failure::Error> {
+        fs::create_dir_all(DIR)?;
+        fs::write(&self.path, serde_json::to_string_pretty(&self.keys)?)?;
+        Ok(())
+    }
+
+    // Takes a key now and then while recording. While playing, moves the camera along the path
+    // and returns true, so nothing else moves it.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) -> bool {
+        let looping = self.looping;
+        match &mut self.state {
+            State::Idle => false,
+            State::Recording { time, since_key } => {
+                *time += dt;
+                *since_key += dt;
+                if *since_key >= KEY_INTERVAL {
+                    *since_key = 0.0;
+                    self.keys.push(CameraKey::new(*time, camera));
+                }
+                false
+            }
+            State::Playing { time, splines } => {
+                *time += dt;
+                if *time > splines.duration {
+                    if !looping {
+                        splines.apply(splines.duration, camera);
+                        self.state = State::Idle;
+                        return true;
+                    }
+                    *time %= splines.duration.max(f32::EPSILON);
+                }
+                splines.apply(*time, camera);
+                true
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<PathAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            let record = if self.is_recording() {
+                "Stop (F5)"
+            } else {
+                "Record (F5)"
+            };
+            if ui.button(record).clicked() {
+                action = Some(PathAction::ToggleRecording);
+            }
+            let play = if self.is_playing() {
+                "Stop (F6)"
+            } else {
+                "Play (F6)"
+            };
+            if ui.button(play).clicked() {
+                action = Some(PathAction::TogglePlayback);
+            }
+        });
+        ui.checkbox(&mut self.looping, "Loop");
+        let duration = self.keys.last().map_or(0.0, |key| key.time);
+        ui.label(format!(
+            "{} keys, {:.1} s, in {}",
+            self.keys.len(),
+            duration,
+            self.path.display()
+        ));
+        action
+    }
+}
+
+// What the buttons in the UI asked for, done once the camera can be borrowed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathAction {
+    ToggleRecording,
+    TogglePlayback,
+}
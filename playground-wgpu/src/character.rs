@@ -0,0 +1,424 @@
+use crate::skinning::SkinnedVertex;
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use playground_math::{Channel, Clip, Keyframes, Skeleton, Transform};
+use std::f32::consts::PI;
+
+// Sides of every tube, and the height of a ring
+const SIDES: usize = 10;
+const RING_HEIGHT: f32 = 0.05;
+// How far from where two bones meet their weights blend
+const BLEND: f32 = 0.06;
+// Keys per loop of a swinging joint
+const SWING_KEYS: usize = 16;
+
+// Where the skeleton's joints are in the rest pose, standing on the origin facing +z
+fn skeleton() -> Skeleton {
+    let at = |x: f32, y: f32, z: f32| Transform::from_translation(Vector3::new(x, y, z));
+
+    let mut skeleton = Skeleton::new();
+    let hips = skeleton.add_joint("hips", None, at(0.0, 1.0, 0.0));
+    let spine = skeleton.add_joint("spine", Some(hips), at(0.0, 0.1, 0.0));
+    skeleton.add_joint("head", Some(spine), at(0.0, 0.45, 0.0));
+    for &(side, x) in &[("left", 0.1), ("right", -0.1)] {
+        let thigh = skeleton.add_joint(&format!("{} thigh", side), Some(hips), at(x, 0.0, 0.0));
+        skeleton.add_joint(&format!("{} shin", side), Some(thigh), at(0.0, -0.45, 0.0));
+    }
+    for &(side, x) in &[("left", 0.24), ("right", -0.24)] {
+        let upper_arm = skeleton.add_joint(
+            &format!("{} upper arm", side),
+            Some(spine),
+            at(x, 0.35, 0.0),
+        );
+        skeleton.add_joint(
+            &format!("{} forearm", side),
+            Some(upper_arm),
+            at(0.0, -0.3, 0.0),
+        );
+    }
+    skeleton
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<SkinnedVertex>,
+    indices: Vec<u16>,
+}
+
+impl MeshBuilder {
+    // Weights of the two bones around the nearest place two bones meet, or all of it for the bone
+    // `y` is in when that's further than BLEND away. Bones go from the bottom up.
+    fn weights(bones: &[(usize, f32, f32)], y: f32) -> ([u8; 4], [f32; 4]) {
+        for pair in bones.windows(2) {
+            let (lower, upper) = (pair[0], pair[1]);
+            let offset = y - lower.2;
+            if offset.abs() <= BLEND {
+                let upper_weight = 0.5 + 0.5 * offset / BLEND;
+                return (
+                    [lower.0 as u8, upper.0 as u8, 0, 0],
+                    [1.0 - upper_weight, upper_weight, 0.0, 0.0],
+                );
+            }
+        }
+        let bone = bones
+            .iter()
+            .find(|bone| y <= bone.2)
+            .unwrap_or(&bones[bones.len() - 1]);
+        ([bone.0 as u8, 0, 0, 0], [1.0, 0.0, 0.0, 0.0])
+    }
+
+    // An upright tube around (x, z) with its ends capped, running up through `bones`, which are
+    // each a joint and the heights the bone spans
+    fn tube(&mut self, x: f32, z: f32, radius: f32, bones: &[(usize, f32, f32)], color: [u8; 4]) {
+        let bottom = bones[0].1;
+        let top = bones[bones.len() - 1].2;
+        let rings = ((top - bottom) / RING_HEIGHT).ceil().max(1.0) as usize;
+        let first = self.vertices.len() as u16;
+        let mut vertex = |position: [f32; 3], normal: [f32; 3], y: f32| {
+            let (joints, weights) = Self::weights(bones, y);
+            self.vertices.push(SkinnedVertex {
+                position,
+                normal,
+                joints,
+                weights,
+                color,
+            });
+        };
+
+        for ring in 0..=rings {
+            let y = bottom + (top - bottom) * ring as f32 / rings as f32;
+            for side in 0..SIDES {
+                let angle = 2.0 * PI * side as f32 / SIDES as f32;
+                let (sin, cos) = angle.sin_cos();
+                vertex([x + cos * radius, y, z + sin * radius], [cos, 0.0, sin], y);
+            }
+        }
+        vertex([x, bottom, z], [0.0, -1.0, 0.0], bottom);
+        vertex([x, top, z], [0.0, 1.0, 0.0], top);
+
+        let ring_start = |ring: usize| first + (ring * SIDES) as u16;
+        for ring in 0..rings {
+            for side in 0..SIDES {
+                let next = (side + 1) % SIDES;
+                let (a, b) = (
+                    ring_start(ring) + side as u16,
+                    ring_start(ring) + next as u16,
+                );
+                let (c, d) = (
+                    ring_start(ring + 1) + side as u16,
+                    ring_start(ring + 1) + next as u16,
+                );
+                self.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+        let bottom_center = ring_start(rings + 1);
+        let top_center = bottom_center + 1;
+        for side in 0..SIDES {
+            let next = (side + 1) % SIDES;
+            self.indices.extend_from_slice(&[
+                bottom_center,
+                ring_start(0) + side as u16,
+                ring_start(0) + next as u16,
+            ]);
+            self.indices.extend_from_slice(&[
+                top_center,
+                ring_start(rings) + next as u16,
+                ring_start(rings) + side as u16,
+            ]);
+        }
+    }
+}
+
+// A joint rocking back and forth around `axis` every `duration` seconds, `phase` turns into the
+// loop. `bend` keeps only the half of the swing going the way of `amplitude`, the way knees and
+// elbows bend.
+fn swing(
+    joint: usize,
+    axis: Vector3<f32>,
+    amplitude: f32,
+    phase: f32,
+    bend: bool,
+    duration: f32,
+) -> Channel {
+    let keys = (0..=SWING_KEYS)
+        .map(|key| {
+            let t = key as f32 / SWING_KEYS as f32;
+            let mut wave = (2.0 * PI * (t + phase)).sin();
+            if bend {
+                wave = wave.max(0.0);
+            }
+            (
+                t * duration,
+                Quaternion::from_axis_angle(axis, Deg(amplitude * wave)),
+            )
+        })
+        .collect();
+    let mut channel = Channel::new(joint);
+    channel.rotation = Some(Keyframes::new(keys));
+    channel
+}
+
+// The hips bobbing up and down twice a loop, as they do once per step
+fn bob(joint: usize, rest: Vector3<f32>, height: f32, duration: f32) -> Channel {
+    let keys = (0..=SWING_KEYS)
+        .map(|key| {
+            let t = key as f32 / SWING_KEYS as f32;
+            let offset = height * (4.0 * PI * t).cos();
+            (t * duration, rest + Vector3::new(0.0, offset, 0.0))
+        })
+        .collect();
+    let mut channel = Channel::new(joint);
+    channel.translation = Some(Keyframes::new(keys));
+    channel
+}
+
+// A clip holding still in one pose, with the given joints rotated by degrees around an axis
+fn still(name: &str, rotations: &[(usize, Vector3<f32>, f32)]) -> Clip {
+    let channels = rotations
+        .iter()
+        .map(|&(joint, axis, angle)| {
+            let mut channel = Channel::new(joint);
+            channel.rotation = Some(Keyframes::new(vec![(
+                0.0,
+                Quaternion::from_axis_angle(axis, Deg(angle)),
+            )]));
+            channel
+        })
+        .collect();
+    Clip {
+        name: name.to_string(),
+        duration: 0.0,
+        channels,
+    }
+}
+
+// How far a gait swings the joints in degrees, how far the hips bob and how long a stride takes
+struct Gait {
+    name: &'static str,
+    duration: f32,
+    legs: f32,
+    knees: f32,
+    arms: f32,
+    elbows: f32,
+    lean: f32,
+    bob: f32,
+}
+
+fn gait_clip(skeleton: &Skeleton, gait: &Gait) -> Clip {
+    let joint = |name: &str| skeleton.find(name).unwrap();
+    let x = Vector3::unit_x();
+    let d = gait.duration;
+    let hips = joint("hips");
+
+    let mut lean = swing(joint("spine"), x, gait.lean * 0.2, 0.0, false, d);
+    // Leaning forward the whole time, with a little sway on top
+    if let Some(rotation) = &mut lean.rotation {
+        for value in &mut rotation.values {
+            *value = Quaternion::from_axis_angle(x, Deg(gait.lean)) * *value;
+        }
+    }
+
+    Clip {
+        name: gait.name.to_string(),
+        duration: d,
+        channels: vec![
+            bob(hips, skeleton.joints[hips].rest.translation, gait.bob, d),
+            lean,
+            // Forward is a negative angle, so knees bend back and elbows forward
+            swing(joint("left thigh"), x, -gait.legs, 0.0, false, d),
+            swing(joint("right thigh"), x, -gait.legs, 0.5, false, d),
+            swing(joint("left shin"), x, gait.knees, 0.25, true, d),
+            swing(joint("right shin"), x, gait.knees, 0.75, true, d),
+            swing(joint("left upper arm"), x, -gait.arms, 0.5, false, d),
+            swing(joint("right upper arm"), x, -gait.arms, 0.0, false, d),
+            swing(joint("left forearm"), x, -gait.elbows, 0.75, true, d),
+            swing(joint("right forearm"), x, -gait.elbows, 0.25, true, d),
+        ],
+    }
+}
+
+// A mannequin made of tubes with its skeleton and a few clips to play on it
+pub struct Character {
+    pub skeleton: Skeleton,
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u16>,
+    pub clips: Vec<Clip>,
+}
+
+impl Character {
+    pub fn mannequin() -> Self {
+        let skeleton = skeleton();
+        let joint = |name: &str| skeleton.find(name).unwrap();
+
+        let body = [200, 180, 150, 255];
+        let limbs = [90, 120, 170, 255];
+        let mut mesh = MeshBuilder::default();
+        mesh.tube(
+            0.0,
+            0.0,
+            0.16,
+            &[(joint("hips"), 0.9, 1.1), (joint("spine"), 1.1, 1.5)],
+            body,
+        );
+        mesh.tube(0.0, 0.0, 0.11, &[(joint("head"), 1.55, 1.85)], body);
+        for &(side, x) in &[("left", 0.1), ("right", -0.1)] {
+            let thigh = joint(&format!("{} thigh", side));
+            let shin = joint(&format!("{} shin", side));
+            mesh.tube(
+                x,
+                0.0,
+                0.07,
+                &[(shin, 0.0, 0.55), (thigh, 0.55, 1.0)],
+                limbs,
+            );
+        }
+        for &(side, x) in &[("left", 0.24), ("right", -0.24)] {
+            let upper_arm = joint(&format!("{} upper arm", side));
+            let forearm = joint(&format!("{} forearm", side));
+            mesh.tube(
+                x,
+                0.0,
+                0.05,
+                &[(forearm, 0.9, 1.15), (upper_arm, 1.15, 1.45)],
+                limbs,
+            );
+        }
+
+        let y = Vector3::unit_y();
+        let idle = Clip {
+            name: "Idle".to_string(),
+            duration: 3.0,
+            channels: vec![
+                swing(joint("spine"), Vector3::unit_z(), 2.0, 0.0, false, 3.0),
+                swing(joint("head"), y, 8.0, 0.25, false, 3.0),
+                swing(
+                    joint("left upper arm"),
+                    Vector3::unit_x(),
+                    3.0,
+                    0.0,
+                    false,
+                    3.0,
+                ),
+                swing(
+                    joint("right upper arm"),
+                    Vector3::unit_x(),
+                    3.0,
+                    0.5,
+                    false,
+                    3.0,
+                ),
+            ],
+        };
+        let walk = gait_clip(
+            &skeleton,
+            &Gait {
+                name: "Walk",
+                duration: 1.1,
+                legs: 25.0,
+                knees: 40.0,
+                arms: 20.0,
+                elbows: 15.0,
+                lean: 3.0,
+                bob: 0.02,
+            },
+        );
+        let run = gait_clip(
+            &skeleton,
+            &Gait {
+                name: "Run",
+                duration: 0.7,
+                legs: 45.0,
+                knees: 90.0,
+                arms: 40.0,
+                elbows: 70.0,
+                lean: 12.0,
+                bob: 0.05,
+            },
+        );
+
+        // Raised out to the side, which is a negative angle for the right arm
+        let z = Vector3::unit_z();
+        let mut wave = still("Wave", &[(joint("right upper arm"), z, -150.0)]);
+        wave.duration = 1.0;
+        wave.channels
+            .push(swing(joint("right forearm"), z, 30.0, 0.0, false, 1.0));
+        // Meant to be added on top of the others, with the rest pose as the reference
+        let lean = |name: &str, angle: f32| {
+            still(
+                name,
+                &[
+                    (joint("hips"), z, angle * 0.5),
+                    (joint("spine"), z, angle),
+                    (joint("head"), z, -angle * 0.5),
+                ],
+            )
+        };
+        let clips = vec![
+            idle,
+            walk,
+            run,
+            wave,
+            lean("Lean left", -10.0),
+            lean("Lean right", 10.0),
+        ];
+
+        Self {
+            skeleton,
+            vertices: mesh.vertices,
+            indices: mesh.indices,
+            clips,
+        }
+    }
+
+    pub fn clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+}
+
+// The mannequin's locomotion states. Each has a clip of the same name and a speed its feet move
+// at, and the controls decide which state it's in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Locomotion {
+    Idle,
+    Walk,
+    Run,
+}
+
+impl Locomotion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Locomotion::Idle => "Idle",
+            Locomotion::Walk => "Walk",
+            Locomotion::Run => "Run",
+        }
+    }
+
+    // Units per second
+    pub fn speed(self) -> f32 {
+        match self {
+            Locomotion::Idle => 0.0,
+            Locomotion::Walk => 1.2,
+            Locomotion::Run => 3.5,
+        }
+    }
+
+    pub fn from_controls(moving: bool, running: bool) -> Self {
+        match (moving, running) {
+            (false, _) => Locomotion::Idle,
+            (true, false) => Locomotion::Walk,
+            (true, true) => Locomotion::Run,
+        }
+    }
+
+    // Seconds the crossfade from this state into `to` takes, and whether the clips are synced
+    // while it does. Walking and running are both gaits, so their steps line up.
+    pub fn transition(self, to: Locomotion) -> (f32, bool) {
+        match (self, to) {
+            (Locomotion::Walk, Locomotion::Run) | (Locomotion::Run, Locomotion::Walk) => {
+                (0.3, true)
+            }
+            (Locomotion::Idle, _) => (0.25, false),
+            (_, Locomotion::Idle) => (0.4, false),
+            _ => (0.0, false),
+        }
+    }
+}
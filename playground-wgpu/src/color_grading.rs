@@ -0,0 +1,523 @@
+use crate::assets;
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use crate::texture;
+use crate::tonemap::Tonemapper;
+use failure::bail;
+use image::RgbaImage;
+use log::{info, warn};
+use notify::{DebouncedEvent, RecommendedWatcher};
+use playground_math::{Block, BlockWriter, Layout};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BufferAddress, Color, ColorStateDescriptor, ColorWrite, CommandEncoder,
+    CommandEncoderDescriptor, CompareFunction, CullMode, Device, Extent3d, FilterMode, IndexFormat,
+    LoadOp, Origin3d, PrimitiveTopology, Queue, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, StoreOp,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const COLOR_GRADING_FRAG: Shader = Shader {
+    name: "color_grading.frag",
+    source: include_str!("../shaders/color_grading.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// A cube of colors mapping sRGB encoded colors to graded ones, `size` texels along each side.
+// The texels go red fastest, then green, then blue, as RGBA with the alpha unused.
+pub struct Lut {
+    size: u32,
+    texels: Vec<u8>,
+}
+
+impl Lut {
+    // By the file extension, a `.cube` file or a strip image
+    pub fn load(path: &Path) -> Result<Self, failure::Error> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("cube") => Self::from_cube(&fs::read_to_string(path)?),
+            _ => Self::from_strip(&image::open(path)?.to_rgba()),
+        }
+    }
+
+    // The blue slices side by side, each `size` texels square with red going right and green
+    // going down. 32x32x32 LUTs come as 1024x32 strips.
+    pub fn from_strip(strip: &RgbaImage) -> Result<Self, failure::Error> {
+        let size = strip.height();
+        if size < 2 || strip.width() != size * size {
+            bail!(
+                "a strip {} high has to be {} wide, not {}",
+                size,
+                size * size,
+                strip.width()
+            );
+        }
+
+        let mut texels = Vec::with_capacity(4 * (size * size * size) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    texels.extend_from_slice(&strip.get_pixel(blue * size + red, green).0);
+                }
+            }
+        }
+        Ok(Self { size, texels })
+    }
+
+    // The .cube format from Resolve and most other tools: a `LUT_3D_SIZE` line and then one
+    // line of three floats per texel, red fastest. Only the default 0 to 1 domain is supported.
+    pub fn from_cube(text: &str) -> Result<Self, failure::Error> {
+        let mut size = None;
+        let mut values = Vec::new();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let first = words.next().unwrap();
+            match first {
+                "TITLE" => (),
+                "LUT_3D_SIZE" => size = Some(words.next().unwrap_or("").parse::<u32>()?),
+                "LUT_1D_SIZE" => bail!("1D LUTs aren't supported"),
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let expected = if first == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    for word in words {
+                        if word.parse::<f32>()? != expected {
+                            bail!("only the default domain of 0 to 1 is supported");
+                        }
+                    }
+                }
+                _ => {
+                    values.push(first.parse::<f32>()?);
+                    for word in words {
+                        values.push(word.parse::<f32>()?);
+                    }
+                }
+            }
+        }
+
+        let size = match size {
+            Some(size) if size >= 2 => size,
+            Some(size) => bail!("a LUT needs at least 2 texels per side, not {}", size),
+            None => bail!("LUT_3D_SIZE is missing"),
+        };
+        let expected = 3 * (size * size * size) as usize;
+        if values.len() != expected {
+            bail!("expected {} values, found {}", expected, values.len());
+        }
+
+        let texels = values
+            .chunks(3)
+            .flat_map(|rgb| {
+                let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                vec![channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), 255]
+            })
+            .collect();
+        Ok(Self { size, texels })
+    }
+
+    fn upload(&self, device: &Device, queue: &Queue) -> TextureView {
+        let size = Extent3d {
+            width: self.size,
+            height: self.size,
+            depth: self.size,
+        };
+        let lut_texture = device.create_texture(&TextureDescriptor {
+            size,
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            // Sampled colors come out linear
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            label: Some("lut"),
+        });
+
+        // One slice of blue at a time
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("lut_upload_encoder"),
+        });
+        let slice_bytes = 4 * (self.size * self.size) as usize;
+        for (blue, texels) in self.texels.chunks(slice_bytes).enumerate() {
+            texture::copy_texels_to_texture(
+                device,
+                &mut encoder,
+                texels,
+                self.size,
+                self.size,
+                &lut_texture,
+                0,
+                Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: blue as u32,
+                },
+            );
+        }
+        queue.submit(&[encoder.finish()]);
+
+        lut_texture.create_default_view()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct GradingUniforms {
+    strength: f32,
+    lut_size: f32,
+}
+
+impl Block for GradingUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.strength);
+        writer.member(&self.lut_size);
+    }
+}
+
+// What the tonemapper resolves into while grading, and the bind group it gets read through
+struct Input {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Input {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let view = render_target::create_target(device, size, format, "color_grading_input");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("color_grading_input"),
+        });
+
+        Self {
+            view,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// The LUT on the GPU
+struct LutBinding {
+    // Keeps the texture alive
+    _view: TextureView,
+    bind_group: BindGroup,
+    size: u32,
+}
+
+// Grades the tonemapped frame by looking every color up in a 3D LUT. Without a LUT it's skipped
+// and the tonemapper writes into the frame directly.
+pub struct ColorGrading {
+    input: Input,
+    input_layout: Arc<BindGroupLayout>,
+    lut_layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    format: TextureFormat,
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    lut: Option<LutBinding>,
+
+    // How much of the graded color gets mixed in
+    pub strength: f32,
+}
+
+impl ColorGrading {
+    // `format` is the frame's
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let input_layout =
+            bind_groups.layout(device, "color_grading_input", bind_group::TEXTURE_LAYOUT);
+        let lut_layout = bind_groups.layout(device, "lut", bind_group::TEXTURE_3D_LAYOUT);
+        // Blends between the LUT's texels, the input is read texel for texel anyway
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(COLOR_GRADING_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_3D_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            GradingUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "color_grading_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            input: Input::new(device, &input_layout, &sampler, format, size),
+            input_layout,
+            lut_layout,
+            sampler,
+            format,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            lut: None,
+            strength: 1.0,
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if size != self.input.size {
+            self.input = Input::new(device, &self.input_layout, &self.sampler, self.format, size);
+        }
+    }
+
+    // Grades with `lut` from now on, or stops grading
+    pub fn set_lut(&mut self, device: &Device, queue: &Queue, lut: Option<&Lut>) {
+        self.lut = lut.map(|lut| {
+            let view = lut.upload(device, queue);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                layout: &self.lut_layout,
+                bindings: &[
+                    Binding {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&view),
+                    },
+                    Binding {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("lut"),
+            });
+            LutBinding {
+                _view: view,
+                bind_group,
+                size: lut.size,
+            }
+        });
+    }
+
+    pub fn update(&self, device: &Device, encoder: &mut CommandEncoder, uniform_pool: &BufferPool) {
+        let lut_size = match &self.lut {
+            Some(lut) => lut.size,
+            None => return,
+        };
+        let uniforms = GradingUniforms {
+            strength: self.strength,
+            lut_size: lut_size as f32,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // Tonemaps the HDR target into `target`, through the LUT if there is one
+    pub fn resolve(
+        &self,
+        tonemapper: &Tonemapper,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+    ) {
+        let lut = match &self.lut {
+            Some(lut) => lut,
+            None => {
+                tonemapper.resolve(encoder, target);
+                return;
+            }
+        };
+        tonemapper.resolve(encoder, &self.input.view);
+
+        encoder.push_debug_group("Color grading");
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::BLACK,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.input.bind_group, &[]);
+        render_pass.set_bind_group(1, &lut.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        encoder.pop_debug_group();
+    }
+}
+
+// File names of the LUTs in `dir`, sorted
+fn find_luts(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+                    Some(extension) => extension == "cube" || extension == "png",
+                    None => false,
+                })
+                .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// The LUTs in a folder, `.cube` files and strip images, to pick one to grade with from the
+// debug window. LUTs can be added while running, and the picked one gets loaded again whenever
+// its file changes.
+pub struct LutLibrary {
+    dir: PathBuf,
+    names: Vec<String>,
+    pub selected: Option<String>,
+    // What the color grading got last, even if it failed to load
+    loaded: Option<String>,
+    changed: bool,
+    // Only kept alive for `changes`, None if watching failed
+    _watcher: Option<RecommendedWatcher>,
+    changes: Receiver<DebouncedEvent>,
+}
+
+impl LutLibrary {
+    pub fn new(dir: PathBuf) -> Self {
+        let (sender, changes) = mpsc::channel();
+        let watcher = assets::watch(&dir, sender)
+            .map_err(|err| warn!("Not watching {} for LUTs: {}", dir.display(), err))
+            .ok();
+
+        Self {
+            names: find_luts(&dir),
+            dir,
+            selected: None,
+            loaded: None,
+            changed: false,
+            _watcher: watcher,
+            changes,
+        }
+    }
+
+    // Hands the LUT to `color_grading` when a different one got picked, or its file changed
+    pub fn poll(&mut self, device: &Device, queue: &Queue, color_grading: &mut ColorGrading) {
+        while let Ok(event) = self.changes.try_recv() {
+            let path = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+            self.names = find_luts(&self.dir);
+            let name = path.file_name().and_then(|name| name.to_str());
+            if name.is_some() && name == self.selected.as_deref() {
+                self.changed = true;
+            }
+        }
+
+        if self.selected == self.loaded && !self.changed {
+            return;
+        }
+        self.changed = false;
+        self.loaded = self.selected.clone();
+
+        let name = match &self.selected {
+            Some(name) => name,
+            None => {
+                color_grading.set_lut(device, queue, None);
+                return;
+            }
+        };
+        match Lut::load(&self.dir.join(name)) {
+            Ok(lut) => {
+                info!("Color grading with {}", name);
+                color_grading.set_lut(device, queue, Some(&lut));
+            }
+            // Editors can save in several steps, the next change event retries
+            Err(err) => warn!("Failed to load LUT {}: {}", name, err),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let selected = &mut self.selected;
+        let names = &self.names;
+        egui::ComboBox::from_label("LUT")
+            .selected_text(selected.as_deref().unwrap_or("None"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, None, "None");
+                for name in names {
+                    ui.selectable_value(selected, Some(name.clone()), name.as_str());
+                }
+            });
+    }
+}
@@ -0,0 +1,57 @@
+use wgpu::{
+    BindGroup, Binding, BindingResource, Buffer, BufferAddress, BufferUsage, CommandEncoder,
+    ComputePipeline, Device,
+};
+
+// A buffer compute shaders can read and write. It can be bound read-only too, e.g. for a vertex
+// shader pulling out what a compute shader wrote, and `usage` adds anything else it's used for.
+pub struct StorageBuffer {
+    pub buffer: Buffer,
+    pub size: BufferAddress,
+}
+
+impl StorageBuffer {
+    pub fn new(device: &Device, contents: &[u8], usage: BufferUsage) -> Self {
+        let buffer = device.create_buffer_with_data(
+            contents,
+            BufferUsage::STORAGE | BufferUsage::STORAGE_READ | usage,
+        );
+
+        Self {
+            buffer,
+            size: contents.len() as BufferAddress,
+        }
+    }
+
+    // The whole buffer at `binding`
+    pub fn binding(&self, binding: u32) -> Binding<'_> {
+        Binding {
+            binding,
+            resource: BindingResource::Buffer {
+                buffer: &self.buffer,
+                range: 0..self.size,
+            },
+        }
+    }
+}
+
+// Workgroups needed for one invocation per item
+pub fn workgroups(items: u32, workgroup_size: u32) -> u32 {
+    items.div_ceil(workgroup_size)
+}
+
+// Records a compute pass running `pipeline` once over `workgroups`, with `bind_groups` bound to
+// sets 0, 1, ... in order
+pub fn dispatch(
+    encoder: &mut CommandEncoder,
+    pipeline: &ComputePipeline,
+    bind_groups: &[&BindGroup],
+    workgroups: [u32; 3],
+) {
+    let mut compute_pass = encoder.begin_compute_pass();
+    compute_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        compute_pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    compute_pass.dispatch(workgroups[0], workgroups[1], workgroups[2]);
+}
@@ -0,0 +1,30 @@
+use cgmath::Matrix4;
+use playground_math::{Aabb, Frustum, Sphere};
+
+// How many objects the last frame drew and how many it skipped for being outside the frustum
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CullStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+impl CullStats {
+    pub fn count(&mut self, visible: bool) {
+        if visible {
+            self.drawn += 1;
+        } else {
+            self.culled += 1;
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(format!("{} drawn, {} culled", self.drawn, self.culled));
+    }
+}
+
+// Whether a mesh with model space bounds ends up at least partly inside the frustum once `world`
+// places it. The sphere rejects most of what's far outside, the box what's just past a corner.
+pub fn is_visible(frustum: &Frustum, sphere: &Sphere, aabb: &Aabb, world: &Matrix4<f32>) -> bool {
+    frustum.intersects_sphere(&sphere.transform(world))
+        && frustum.intersects_aabb(&aabb.transform(world))
+}
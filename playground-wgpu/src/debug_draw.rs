@@ -0,0 +1,259 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use cgmath::{Matrix4, Point3, Transform as _, Vector3};
+use playground_math::{Aabb, Color};
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, BlendFactor, BlendOperation,
+    BufferAddress, BufferUsage, ColorStateDescriptor, ColorWrite, CommandEncoder, CullMode, Device,
+    IndexFormat, InputStepMode, LoadOp, PrimitiveTopology, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, ShaderStage, StoreOp, TextureFormat, TextureView,
+    VertexAttributeDescriptor, VertexFormat,
+};
+
+const DEBUG_LINE_VERT: Shader = Shader {
+    name: "debug_line.vert",
+    source: include_str!("../shaders/debug_line.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const DEBUG_LINE_FRAG: Shader = Shader {
+    name: "debug_line.frag",
+    source: include_str!("../shaders/debug_line.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Segments per circle of a sphere
+const CIRCLE_SEGMENTS: u32 = 32;
+
+const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for LineVertex {}
+
+unsafe impl bytemuck::Zeroable for LineVertex {}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<LineVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                shader_location: 1,
+                format: VertexFormat::Float4,
+            },
+        ],
+    }
+}
+
+thread_local! {
+    // Pairs of vertices, one pair per line
+    static LINES: RefCell<Vec<LineVertex>> = const { RefCell::new(Vec::new()) };
+}
+
+// Immediate mode drawing of world space lines, for rays, bounds, lights and anything else that
+// needs to be seen. Lines can be added from anywhere on the main thread during the frame, they
+// get drawn on top of the scene by `DebugDrawRenderer::render` and are gone afterwards. Colors
+// are linear.
+pub fn line(a: Point3<f32>, b: Point3<f32>, color: Color) {
+    let color = color.to_array();
+    LINES.with(|lines| {
+        lines.borrow_mut().extend_from_slice(&[
+            LineVertex {
+                position: a.into(),
+                color,
+            },
+            LineVertex {
+                position: b.into(),
+                color,
+            },
+        ]);
+    });
+}
+
+// The twelve edges of the box
+pub fn aabb(aabb: &Aabb, color: Color) {
+    if aabb.is_empty() {
+        return;
+    }
+
+    let corner = |x: bool, y: bool, z: bool| {
+        Point3::new(
+            if x { aabb.max.x } else { aabb.min.x },
+            if y { aabb.max.y } else { aabb.min.y },
+            if z { aabb.max.z } else { aabb.min.z },
+        )
+    };
+    for &a in &[false, true] {
+        for &b in &[false, true] {
+            line(corner(false, a, b), corner(true, a, b), color);
+            line(corner(a, false, b), corner(a, true, b), color);
+            line(corner(a, b, false), corner(a, b, true), color);
+        }
+    }
+}
+
+// One circle around each axis
+pub fn sphere(center: Point3<f32>, radius: f32, color: Color) {
+    let axes = [
+        (Vector3::unit_x(), Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (Vector3::unit_z(), Vector3::unit_x()),
+    ];
+    for &(u, v) in &axes {
+        let point = |segment: u32| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            line(point(segment), point(segment + 1), color);
+        }
+    }
+}
+
+// The X, Y and Z axes of `transform` in red, green and blue, `size` long before scaling
+pub fn axes(transform: &Matrix4<f32>, size: f32) {
+    let origin = transform.transform_point(Point3::new(0.0, 0.0, 0.0));
+    let end = |x: f32, y: f32, z: f32| transform.transform_point(Point3::new(x, y, z) * size);
+    line(origin, end(1.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0, 1.0));
+    line(origin, end(0.0, 1.0, 0.0), Color::new(0.0, 1.0, 0.0, 1.0));
+    line(origin, end(0.0, 0.0, 1.0), Color::new(0.0, 0.0, 1.0, 1.0));
+}
+
+// Forgets everything drawn this frame, for frames where nothing can show it
+pub fn clear() {
+    LINES.with(|lines| lines.borrow_mut().clear());
+}
+
+// The lines added on this thread so far, taken out to be drawn from another thread
+pub struct DebugLines(Vec<LineVertex>);
+
+pub fn take() -> DebugLines {
+    DebugLines(LINES.with(|lines| mem::take(&mut *lines.borrow_mut())))
+}
+
+// Draws the lines from the functions above
+pub struct DebugDrawRenderer {
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+impl DebugDrawRenderer {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            mem::size_of::<Matrix4<f32>>() as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "debug_draw_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: DEBUG_LINE_VERT,
+                fragment_shader: Some(DEBUG_LINE_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::UNIFORM_LAYOUT)],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::LineList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ALPHA_BLEND,
+                    alpha_blend: ALPHA_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+
+    // Draws `lines` on top of `target`, seen through `view_proj`
+    pub fn render(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        uniform_pool: &BufferPool,
+        view_proj: &Matrix4<f32>,
+        lines: DebugLines,
+    ) {
+        let vertices = lines.0;
+        if vertices.is_empty() {
+            return;
+        }
+
+        let view_proj: &[[f32; 4]; 4] = view_proj.as_ref();
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            bytemuck::cast_slice(view_proj),
+        );
+        let vertex_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&vertices), BufferUsage::VERTEX);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &vertex_buffer, 0, 0);
+        render_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::billboard::Atlas;
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use crate::render_target;
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector2, Vector3, Vector4};
+use image::{Rgba, RgbaImage};
+use playground_math::{Block, BlockWriter, Camera, Color, Layout};
+use std::f32::consts::PI;
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BlendFactor, BlendOperation, Buffer, BufferAddress, BufferDescriptor,
+    BufferUsage, ColorStateDescriptor, ColorWrite, CommandEncoder, CompareFunction, CullMode,
+    Device, FilterMode, IndexFormat, InputStepMode, LoadOp, PrimitiveTopology, Queue,
+    RenderPassColorAttachmentDescriptor, RenderPassDepthStencilAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, StoreOp,
+    TextureFormat, TextureView, VertexAttributeDescriptor, VertexFormat,
+};
+use winit::dpi::PhysicalSize;
+
+const DECAL_VERT: Shader = Shader {
+    name: "decal.vert",
+    source: include_str!("../shaders/decal.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const DECAL_FRAG: Shader = Shader {
+    name: "decal.frag",
+    source: include_str!("../shaders/decal.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Over the scene by coverage, leaving its alpha alone
+const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+const KEEP_ALPHA: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::Zero,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+// The instance buffer starts out with room for this many decals and doubles when it runs out
+const INITIAL_CAPACITY: usize = 64;
+
+// Sides of a cell in the generated atlas, in texels
+const CELL_SIZE: u32 = 128;
+const ATLAS_MIP_LEVELS: u32 = 6;
+
+// The cells of `generated_atlas`
+pub const BULLET_HOLE_CELL: u32 = 0;
+pub const SPLAT_CELL: u32 = 1;
+pub const POSTER_CELL: u32 = 2;
+
+// A box projecting `cell` of the atlas onto whatever is inside it, tinted by `color`. The box is
+// the unit cube around the origin moved into the world by `transform`, projecting down its z
+// axis, with the top of the cell along its y axis.
+#[derive(Copy, Clone, Debug)]
+pub struct Decal {
+    pub transform: Matrix4<f32>,
+    pub cell: u32,
+    pub color: Color,
+}
+
+impl Decal {
+    // Lying on a surface through `position` facing `normal`, `size` across and turned by `angle`
+    // radians around the normal. The box reaches a quarter of `size` in front of and behind the
+    // surface, enough for slightly curved or bumpy ones.
+    pub fn on_surface(
+        position: Point3<f32>,
+        normal: Vector3<f32>,
+        size: f32,
+        angle: f32,
+        cell: u32,
+    ) -> Self {
+        let forward = normal.normalize();
+        let helper = if forward.y.abs() < 0.99 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let right = helper.cross(forward).normalize();
+        let up = forward.cross(right);
+        let (sin, cos) = angle.sin_cos();
+        let (right, up) = (right * cos + up * sin, up * cos - right * sin);
+
+        Self {
+            transform: Matrix4::from_cols(
+                (right * size).extend(0.0),
+                (up * size).extend(0.0),
+                (forward * size * 0.5).extend(0.0),
+                position.to_homogeneous(),
+            ),
+            cell,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct DecalUniforms {
+    view_proj: Matrix4<f32>,
+    inverse_view_proj: Matrix4<f32>,
+    camera_position: [f32; 4],
+    viewport_size: [f32; 2],
+    normal_strength: f32,
+    angle_fade: f32,
+}
+
+impl Block for DecalUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.inverse_view_proj);
+        writer.member(&Vector4::from(self.camera_position));
+        writer.member(&Vector2::from(self.viewport_size));
+        writer.member(&self.normal_strength);
+        writer.member(&self.angle_fade);
+    }
+}
+
+impl Default for DecalUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            inverse_view_proj: Matrix4::identity(),
+            camera_position: [0.0; 4],
+            viewport_size: [0.0; 2],
+            normal_strength: 0.0,
+            angle_fade: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DecalInstance {
+    decal_to_world: [[f32; 4]; 4],
+    world_to_decal: [[f32; 4]; 4],
+    cell: [f32; 4],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for DecalInstance {}
+
+unsafe impl bytemuck::Zeroable for DecalInstance {}
+
+// Both matrices a column at a time, then the cell and color, all vec4s
+fn instance_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<DecalInstance>() as BufferAddress,
+        step_mode: InputStepMode::Instance,
+        attributes: (0..10)
+            .map(|location| VertexAttributeDescriptor {
+                offset: location as BufferAddress * 16,
+                shader_location: location,
+                format: VertexFormat::Float4,
+            })
+            .collect(),
+    }
+}
+
+// From 1 inside to 0 outside, blended over about a texel at the edge of a cell `CELL_SIZE` wide
+fn coverage(distance_outside: f32) -> f32 {
+    (0.5 - distance_outside * CELL_SIZE as f32 / 2.0).clamp(0.0, 1.0)
+}
+
+// The texel at `u`, `v` of a generated cell, both from -1 to 1 with v going down
+fn cell_texel(cell: u32, u: f32, v: f32) -> [f32; 4] {
+    let radius = (u * u + v * v).sqrt();
+    let angle = v.atan2(u);
+    match cell {
+        // A dark hole with a chipped rim, cracks running out of it and soot around it all
+        BULLET_HOLE_CELL => {
+            let hole = coverage(radius - 0.16);
+            let rim = coverage(radius - 0.3 - 0.04 * (angle * 7.0).sin());
+            let crack = [0.4, 1.9, 3.1, 4.4, 5.6]
+                .iter()
+                .map(|crack_angle: &f32| {
+                    let across = radius * (angle - crack_angle + PI).sin().abs();
+                    let facing = (angle - crack_angle + PI).cos() > 0.0;
+                    if facing && radius < 0.75 {
+                        coverage(across - 0.02 * (1.0 - radius / 0.75))
+                    } else {
+                        0.0
+                    }
+                })
+                .fold(0.0, f32::max);
+            let soot = 0.45 * (1.0 - (radius / 0.8).clamp(0.0, 1.0)).powi(2);
+            if hole > 0.0 {
+                [0.03, 0.03, 0.03, hole.max(rim)]
+            } else if rim > 0.0 || crack > 0.0 {
+                [0.22, 0.2, 0.18, rim.max(crack * 0.8)]
+            } else {
+                [0.08, 0.07, 0.06, soot]
+            }
+        }
+        // A lumpy blob with drops flung off around it
+        SPLAT_CELL => {
+            let edge =
+                0.5 + 0.1 * (angle * 5.0).sin() + 0.06 * (angle * 13.0 + 1.3).sin() - 0.04 * v;
+            let blob = coverage(radius - edge);
+            let drops = [(0.75, -0.3, 0.08), (-0.7, 0.45, 0.06), (0.2, 0.82, 0.07)]
+                .iter()
+                .map(|&(x, y, size): &(f32, f32, f32)| {
+                    let distance = ((u - x) * (u - x) + (v - y) * (v - y)).sqrt();
+                    coverage(distance - size)
+                })
+                .fold(0.0, f32::max);
+            // Darker where it's thickest
+            let shade = 0.5 - 0.25 * (1.0 - radius / edge.max(0.01)).clamp(0.0, 1.0);
+            [shade, 0.02, 0.03, blob.max(drops)]
+        }
+        // A sunset over a mountain with a couple of lines of text under it, on paper
+        _ => {
+            let paper = coverage((u.abs() - 0.7).max(v.abs() - 0.92));
+            let picture = u.abs() < 0.6 && v > -0.82 && v < 0.45;
+            let text =
+                (v > 0.58 && v < 0.64 && u.abs() < 0.5) || (v > 0.72 && v < 0.78 && u.abs() < 0.35);
+            let [r, g, b] = if picture {
+                let sun = ((u - 0.15) * (u - 0.15) + (v + 0.2) * (v + 0.2)).sqrt() < 0.2;
+                if v > 0.05 + (u + 0.1).abs() * 0.7 {
+                    [0.25, 0.12, 0.3]
+                } else if sun {
+                    [1.0, 0.85, 0.3]
+                } else {
+                    let t = (v + 0.82) / 1.27;
+                    [0.2 + 0.75 * t, 0.3 + 0.2 * t, 0.6 - 0.3 * t]
+                }
+            } else if text {
+                [0.15, 0.15, 0.15]
+            } else {
+                [0.92, 0.9, 0.84]
+            };
+            [r, g, b, paper]
+        }
+    }
+}
+
+// A bullet hole, a splat and a poster, at `BULLET_HOLE_CELL`, `SPLAT_CELL` and `POSTER_CELL`
+pub fn generated_atlas(device: &Device, queue: &Queue, bind_groups: &mut BindGroupCache) -> Atlas {
+    let columns = 3;
+    let image = RgbaImage::from_fn(CELL_SIZE * columns, CELL_SIZE, |x, y| {
+        let cell = x / CELL_SIZE;
+        let to_signed =
+            |texel: u32| ((texel % CELL_SIZE) as f32 + 0.5) / CELL_SIZE as f32 * 2.0 - 1.0;
+        let [r, g, b, a] = cell_texel(cell, to_signed(x), to_signed(y));
+        let [r, g, b, a] = Color::new(r, g, b, a).to_srgb8();
+        Rgba([r, g, b, a])
+    });
+    let (atlas, cmd_buffer) = Atlas::new(device, bind_groups, &image, columns, 1, ATLAS_MIP_LEVELS);
+    queue.submit(&[cmd_buffer]);
+    atlas
+}
+
+fn create_instance_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("decal_instances"),
+        size: (capacity * mem::size_of::<DecalInstance>()) as BufferAddress,
+        usage: BufferUsage::VERTEX | BufferUsage::COPY_DST,
+    })
+}
+
+// The scene's depth and the bind group the decals read it through
+struct Targets {
+    depth: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let depth = render_target::create_target(device, size, depth::DEPTH_FORMAT, "decal_depth");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("decal_depth"),
+        });
+
+        Self {
+            depth,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// Screen-space decals. The scene gets drawn with `depth_attachment`, and `render` draws the
+// back faces of every decal's box over it, finding where each pixel's surface is from the depth
+// and painting the decal's cell wherever that's inside the box. The surface's normal is bent by
+// the edges of the cell, and the cell lit by the `uniform::LightUniforms` the scene is lit by,
+// so decals follow the scene's light instead of being lit a second time by a light of their own.
+pub struct Decals {
+    targets: Targets,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    atlas: Arc<Atlas>,
+
+    instance_buffer: Buffer,
+    // In decals
+    capacity: usize,
+    instance_count: u32,
+
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    // How far the edges of decals bend the surface's normal
+    pub normal_strength: f32,
+    // Surfaces facing further away from decals than this, as the cosine of the angle between
+    // them, don't get any of it
+    pub angle_fade: f32,
+}
+
+impl Decals {
+    // Blends onto `format` targets
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+        atlas: Arc<Atlas>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "decal_depth", bind_group::TEXTURE_LAYOUT);
+        // The depth can't be filtered
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        // Only the back faces, so every pixel gets each decal once, even with the camera inside
+        // its box
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: DECAL_VERT,
+                fragment_shader: Some(DECAL_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![instance_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::Front,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ALPHA_BLEND,
+                    alpha_blend: KEEP_ALPHA,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            DecalUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+            "decal_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            targets: Targets::new(device, &layout, &sampler, size),
+            layout,
+            sampler,
+            atlas,
+            instance_buffer: create_instance_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            instance_count: 0,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            normal_strength: 4.0,
+            angle_fade: 0.3,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.normal_strength, 0.0..=16.0).text("Normal strength"));
+        ui.add(egui::Slider::new(&mut self.angle_fade, 0.0..=0.95).text("Angle fade"));
+    }
+
+    // Has to match the frame, and `camera` the one the scene gets drawn with
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        size: PhysicalSize<u32>,
+        camera: &Camera,
+        decals: &[Decal],
+    ) {
+        if size != self.targets.size {
+            self.targets = Targets::new(device, &self.layout, &self.sampler, size);
+        }
+
+        if decals.len() > self.capacity {
+            self.capacity = decals.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.capacity);
+        }
+        self.instance_count = decals.len() as u32;
+        if !decals.is_empty() {
+            let instances: Vec<DecalInstance> = decals
+                .iter()
+                .map(|decal| DecalInstance {
+                    decal_to_world: decal.transform.into(),
+                    world_to_decal: decal
+                        .transform
+                        .invert()
+                        .unwrap_or_else(Matrix4::identity)
+                        .into(),
+                    cell: self.atlas.cell_rect(decal.cell),
+                    color: decal.color.to_array(),
+                })
+                .collect();
+            let staging = device
+                .create_buffer_with_data(bytemuck::cast_slice(&instances), BufferUsage::COPY_SRC);
+            encoder.copy_buffer_to_buffer(
+                &staging,
+                0,
+                &self.instance_buffer,
+                0,
+                (instances.len() * mem::size_of::<DecalInstance>()) as BufferAddress,
+            );
+        }
+
+        let view_proj = camera.build_view_projection_matrix();
+        let uniforms = DecalUniforms {
+            view_proj,
+            inverse_view_proj: view_proj.invert().unwrap_or_else(Matrix4::identity),
+            camera_position: [camera.eye.x, camera.eye.y, camera.eye.z, 1.0],
+            viewport_size: [size.width.max(1) as f32, size.height.max(1) as f32],
+            normal_strength: self.normal_strength,
+            angle_fade: self.angle_fade,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the scene's pass, which the decals get projected onto
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.targets.depth,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // Paints the decals onto `target`, which already has the scene in it. `light` is the
+    // `bind_group::FRAGMENT_UNIFORM_LAYOUT` bind group of the scene's `uniform::LightUniforms`.
+    pub fn render(&self, encoder: &mut CommandEncoder, target: &TextureView, light: &BindGroup) {
+        if self.instance_count == 0 {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.push_debug_group("Decals");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.targets.bind_group, &[]);
+        render_pass.set_bind_group(2, self.atlas.bind_group(), &[]);
+        render_pass.set_bind_group(3, light, &[]);
+        render_pass.set_vertex_buffer(0, &self.instance_buffer, 0, 0);
+        // The boxes' corners are made up in the vertex shader
+        draw_stats::record_triangles(36, self.instance_count);
+        render_pass.draw(0..36, 0..self.instance_count);
+        render_pass.pop_debug_group();
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("decal_uniforms");
+    }
+}
@@ -0,0 +1,313 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::decal::{self, Decal, Decals};
+use crate::demo::{Demo, DemoContext};
+use crate::input::Input;
+use crate::particles::Rng;
+use crate::picking::{self, PickObject};
+use crate::primitives::{self, CubeInstance, Cubes};
+use crate::sky::Sky;
+use crate::uniform::{self, LightUniforms, Uniforms};
+use cgmath::{Point3, Vector3};
+use playground_math::{sun, Aabb, Block, Camera, Layout, Projection};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, StoreOp, TextureView,
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+// The oldest decals make way for new ones past this many
+const MAX_DECALS: usize = 256;
+
+const UNIFORM_KEYS: [&str; 2] = ["decal_camera", "decal_light"];
+
+// What the user can place, and their cells in `decal::generated_atlas`
+const KINDS: [(&str, u32); 3] = [
+    ("Bullet hole", decal::BULLET_HOLE_CELL),
+    ("Splat", decal::SPLAT_CELL),
+    ("Poster", decal::POSTER_CELL),
+];
+
+// The ring of cubes on a floor in front of a wall, for something to put decals on
+fn scene() -> Vec<CubeInstance> {
+    let mut instances = primitives::ring_of_cubes(6, 2.5);
+    instances.push(CubeInstance {
+        offset_scale: [0.0, -10.0, 0.0, 20.0],
+        color: [0.6, 0.6, 0.6, 1.0],
+    });
+    instances.push(CubeInstance {
+        offset_scale: [0.0, 3.0, -15.0, 20.0],
+        color: [0.75, 0.7, 0.6, 1.0],
+    });
+    instances
+}
+
+// Bullet holes, splats and posters put on the scene by right clicking it. They're drawn after
+// the scene, from its depth, so they follow whatever surface they land on, and lit by the same
+// sun as the scene.
+pub struct DecalDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    // In hours, which puts the sun in the sky and colors its light
+    time_of_day: f32,
+    sun_intensity: f32,
+    ambient: f32,
+    sky: Sky,
+    decals: Decals,
+    placed: Vec<Decal>,
+    // What clicking can put decals on
+    objects: Vec<PickObject>,
+    place_requested: Option<PhysicalPosition<i32>>,
+    rng: Rng,
+
+    // Index into `KINDS`
+    kind: usize,
+    decal_size: f32,
+    random_rotation: bool,
+
+    pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    // The camera and the light
+    uniform_allocations: [Allocation; 2],
+    uniform_bind_groups: [Arc<BindGroup>; 2],
+}
+
+impl DecalDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 4.0, 8.0).into(),
+            target: (0.0, 1.0, -2.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let instances = scene();
+        let objects = instances
+            .iter()
+            .enumerate()
+            .map(|(index, instance)| {
+                let [x, y, z, scale] = instance.offset_scale;
+                let half = Vector3::new(scale, scale, scale) / 2.0;
+                let center = Point3::new(x, y, z);
+                PickObject {
+                    name: format!("cube {}", index),
+                    bounds: Aabb::new(center - half, center + half),
+                    triangles: Vec::new(),
+                }
+            })
+            .collect();
+
+        let atlas = decal::generated_atlas(device, ctx.queue, ctx.bind_groups);
+        let decals = Decals::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            ctx.size,
+            Arc::new(atlas),
+        );
+
+        let sky = Sky::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+        );
+
+        let pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &Cubes::lit_pipeline_key(ctx.format),
+        );
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[0],
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (light_allocation, light_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[1],
+            LightUniforms::size(Layout::Std140),
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            time_of_day: 10.0,
+            sun_intensity: 3.0,
+            ambient: 0.1,
+            sky,
+            decals,
+            placed: Vec::new(),
+            objects,
+            place_requested: None,
+            rng: Rng(0x9e37_79b9),
+            kind: 0,
+            decal_size: 0.6,
+            random_rotation: true,
+            pipeline,
+            cubes: Cubes::new(device, &instances),
+            uniform_allocations: [camera_allocation, light_allocation],
+            uniform_bind_groups: [camera_bind_group, light_bind_group],
+        }
+    }
+
+    // Puts a decal of the chosen kind on whatever is under the cursor, if anything
+    fn place_at(&mut self, cursor: PhysicalPosition<i32>) {
+        let view_proj = self.camera.build_view_projection_matrix();
+        let ray = match picking::cursor_ray(&view_proj, cursor, self.size) {
+            Some(ray) => ray,
+            None => return,
+        };
+        let hit = match picking::pick(&ray, &self.objects) {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        let position = ray.at(hit.distance);
+        let normal = self.objects[hit.object].bounds.face_normal(position);
+        let angle = if self.random_rotation {
+            self.rng.next() * 2.0 * PI
+        } else {
+            0.0
+        };
+        let (_, cell) = KINDS[self.kind];
+        if self.placed.len() >= MAX_DECALS {
+            self.placed.remove(0);
+        }
+        self.placed.push(Decal::on_surface(
+            position,
+            normal,
+            self.decal_size,
+            angle,
+            cell,
+        ));
+    }
+}
+
+impl Demo for DecalDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn handle_input(&mut self, input: &Input) {
+        if input.pressed("place") {
+            self.place_requested = input.cursor();
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        if let Some(cursor) = self.place_requested.take() {
+            self.place_at(cursor);
+        }
+        self.decals.prepare(
+            device,
+            encoder,
+            uniform_pool,
+            self.size,
+            &self.camera,
+            &self.placed,
+        );
+
+        self.sky.prepare(
+            device,
+            encoder,
+            uniform_pool,
+            &self.camera,
+            sun::direction(self.time_of_day),
+        );
+
+        let mut camera = Uniforms::new();
+        camera.update_view_proj(&self.camera);
+        let light = LightUniforms::sun(self.time_of_day, self.sun_intensity, self.ambient);
+        let [camera_allocation, light_allocation] = &self.uniform_allocations;
+        uniform_pool.write(device, encoder, camera_allocation, &camera.std140());
+        uniform_pool.write(device, encoder, light_allocation, &light.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Right click the scene to place a decal");
+        egui::ComboBox::from_label("Decal")
+            .selected_text(KINDS[self.kind].0)
+            .show_ui(ui, |ui| {
+                for (index, (name, _)) in KINDS.iter().enumerate() {
+                    ui.selectable_value(&mut self.kind, index, *name);
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.decal_size, 0.1..=4.0).text("Size"));
+        ui.checkbox(&mut self.random_rotation, "Random rotation");
+        ui.horizontal(|ui| {
+            ui.label(format!("{} decals", self.placed.len()));
+            if ui.button("Clear").clicked() {
+                self.placed.clear();
+            }
+        });
+        ui.separator();
+        self.decals.ui(ui);
+        ui.separator();
+        ui.add(egui::Slider::new(&mut self.time_of_day, 0.0..=24.0).text("Time of day (h)"));
+        ui.add(egui::Slider::new(&mut self.sun_intensity, 0.0..=10.0).text("Sun intensity"));
+        ui.add(egui::Slider::new(&mut self.ambient, 0.0..=0.5).text("Ambient"));
+        self.sky.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.decals.depth_attachment()),
+        });
+        let [camera, light] = &self.uniform_bind_groups;
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        render_pass.set_bind_group(1, light, &[]);
+        self.cubes.draw(&mut render_pass);
+        self.sky.draw(&mut render_pass);
+        drop(render_pass);
+
+        self.decals.render(encoder, target, light);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.decals.release(ctx.uniform_pool, ctx.bind_groups);
+        self.sky.release(ctx.uniform_pool, ctx.bind_groups);
+        for (allocation, key) in self.uniform_allocations.iter().zip(&UNIFORM_KEYS) {
+            ctx.uniform_pool.free(*allocation);
+            ctx.bind_groups.invalidate(key);
+        }
+    }
+}
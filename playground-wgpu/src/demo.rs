@@ -0,0 +1,253 @@
+use crate::assets::Assets;
+use crate::bind_group::BindGroupCache;
+use crate::boids_demo::BoidsDemo;
+use crate::buffer_inspector::BufferInspector;
+use crate::buffer_pool::BufferPool;
+use crate::decal_demo::DecalDemo;
+use crate::dof_demo::DofDemo;
+use crate::imposter_demo::ImposterDemo;
+use crate::indirect_demo::IndirectDemo;
+use crate::input::Input;
+use crate::light_shafts_demo::LightShaftsDemo;
+use crate::lights_demo::LightsDemo;
+use crate::monitor_demo::MonitorDemo;
+use crate::morph_demo::MorphDemo;
+use crate::particle_demo::{GpuParticleDemo, ParticleDemo};
+use crate::passes::Passes;
+use crate::pipeline::PipelineCache;
+use crate::reflection_demo::ReflectionDemo;
+use crate::render_mode::RenderMode;
+use crate::skinning_demo::SkinningDemo;
+use crate::ssr_demo::SsrDemo;
+use crate::terrain_demo::{ProceduralTerrainDemo, TerrainDemo};
+use crate::tree_demo::TreeDemo;
+use crate::video_demo::VideoDemo;
+use cgmath::Matrix4;
+use playground_math::{Camera, Transform};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, LoadOp, Queue, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+// Everything that outlives a demo. Demos create their resources from it and give pool
+// allocations back to it when they get switched out.
+pub struct DemoContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub geometry_pool: &'a mut BufferPool,
+    pub uniform_pool: &'a mut BufferPool,
+    pub passes: &'a mut Passes,
+    pub bind_groups: &'a mut BindGroupCache,
+    pub pipelines: &'a mut PipelineCache,
+    pub assets: &'a mut Assets,
+    pub buffer_inspector: &'a mut BufferInspector,
+    // The `uniform::FrameUniforms`, for shaders that animate
+    pub frame_bind_group: &'a Arc<BindGroup>,
+    pub format: TextureFormat,
+    pub size: PhysicalSize<u32>,
+}
+
+// A scene with its own buffers, textures and pipelines. Switching demos only replaces these,
+// the device, surface and caches stay around.
+pub trait Demo {
+    fn resize(&mut self, _size: PhysicalSize<u32>) {}
+
+    // Reacts to this frame's input, before `update`
+    fn handle_input(&mut self, _input: &Input) {}
+
+    // Advances the simulation by one fixed step of `dt` seconds. Runs as often as it takes to keep
+    // up with the clock, so some frames have none and others several, always before `update`.
+    fn step(
+        &mut self,
+        _device: &Device,
+        _encoder: &mut CommandEncoder,
+        _uniform_pool: &BufferPool,
+        _dt: f32,
+    ) {
+    }
+
+    // Records this frame's buffer updates. The frame is `alpha` of the way from the last step to
+    // the next one, anything that moves gets drawn that far between its last two states.
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        alpha: f32,
+    );
+
+    // Demo specific settings, shown in the debug window
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+
+    // The render mode picked in the debug window, every frame. Demos without the pipeline
+    // permutations keep drawing the way they always do.
+    fn set_render_mode(&mut self, _mode: RenderMode) {}
+
+    // The name of the object selected in the scene, every frame, for highlighting it
+    fn set_selected(&mut self, _name: Option<&str>) {}
+
+    // The camera the orientation gizmo shows and moves, if the demo has one
+    fn camera(&mut self) -> Option<&mut Camera> {
+        None
+    }
+
+    // The name of the object under the cursor, for selecting things in the scene
+    fn pick(
+        &self,
+        _assets: &Assets,
+        _cursor: PhysicalPosition<i32>,
+        _size: PhysicalSize<u32>,
+    ) -> Option<String> {
+        None
+    }
+
+    // The local transform of the object called `name` and the world matrix of its parent, for
+    // moving it around with the transform gizmo
+    fn transform_mut(&mut self, _name: &str) -> Option<(Matrix4<f32>, &mut Transform)> {
+        None
+    }
+
+    // Draws the ID of every object into an `id_buffer::ID_FORMAT` target, for picking on the GPU.
+    // IDs are one past the object's index and the background is cleared to zero.
+    fn render_ids(&self, _assets: &Assets, _encoder: &mut CommandEncoder, _target: &TextureView) {}
+
+    // The name of the object `render_ids` drew as `id`
+    fn object_name(&self, _id: u32) -> Option<String> {
+        None
+    }
+
+    // The demo's settings struct, serialized so it can be saved between runs
+    fn settings(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    // Restores what `settings` returned in an earlier run
+    fn apply_settings(&mut self, _settings: serde_json::Value) -> Result<(), failure::Error> {
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        assets: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    );
+
+    // Gives pool allocations and cached bind groups back, the demo gets dropped afterwards
+    fn release(&mut self, ctx: &mut DemoContext);
+}
+
+pub struct DemoEntry {
+    pub name: &'static str,
+    pub create: fn(&mut DemoContext) -> Box<dyn Demo>,
+}
+
+pub const DEMOS: &[DemoEntry] = &[
+    DemoEntry {
+        name: "Happy tree",
+        create: |ctx| Box::new(TreeDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Particles",
+        create: |ctx| Box::new(ParticleDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "GPU particles",
+        create: |ctx| Box::new(GpuParticleDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Boids",
+        create: |ctx| Box::new(BoidsDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Tree imposters",
+        create: |ctx| Box::new(ImposterDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Terrain",
+        create: |ctx| Box::new(TerrainDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Procedural terrain",
+        create: |ctx| Box::new(ProceduralTerrainDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Indirect draws",
+        create: |ctx| Box::new(IndirectDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Skinning",
+        create: |ctx| Box::new(SkinningDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Morph targets",
+        create: |ctx| Box::new(MorphDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Render to texture",
+        create: |ctx| Box::new(MonitorDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Planar reflection",
+        create: |ctx| Box::new(ReflectionDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Screen-space reflections",
+        create: |ctx| Box::new(SsrDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Depth of field",
+        create: |ctx| Box::new(DofDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Light shafts",
+        create: |ctx| Box::new(LightShaftsDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Decals",
+        create: |ctx| Box::new(DecalDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Dynamic lights",
+        create: |ctx| Box::new(LightsDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Video",
+        create: |ctx| Box::new(VideoDemo::new(ctx)),
+    },
+    DemoEntry {
+        name: "Empty",
+        create: |_| Box::new(EmptyDemo),
+    },
+];
+
+// Only clears the frame, for looking at the cost of everything around the scene
+pub struct EmptyDemo;
+
+impl Demo for EmptyDemo {
+    fn update(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, _: f32) {}
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+    }
+
+    fn release(&mut self, _: &mut DemoContext) {}
+}
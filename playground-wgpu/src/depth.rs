@@ -0,0 +1,135 @@
+use wgpu::{
+    CompareFunction, DepthStencilStateDescriptor, Device, Extent3d, LoadOp,
+    RenderPassDepthStencilAttachmentDescriptor, StencilOperation, StencilStateFaceDescriptor,
+    StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+// For the passes that need a stencil next to the depth
+pub const DEPTH_STENCIL_FORMAT: TextureFormat = TextureFormat::Depth24PlusStencil8;
+
+// Nearer fragments win, and write their depth
+pub fn depth_stencil_state() -> DepthStencilStateDescriptor {
+    DepthStencilStateDescriptor {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: CompareFunction::Less,
+        stencil_front: StencilStateFaceDescriptor::IGNORE,
+        stencil_back: StencilStateFaceDescriptor::IGNORE,
+        stencil_read_mask: 0,
+        stencil_write_mask: 0,
+    }
+}
+
+// Only the fragments that won a depth pre-pass, which already wrote the depth
+pub fn equal_depth_stencil_state() -> DepthStencilStateDescriptor {
+    DepthStencilStateDescriptor {
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Equal,
+        ..depth_stencil_state()
+    }
+}
+
+// Sets the stencil to the pass's stencil reference wherever something gets drawn. Depth isn't
+// tested or written.
+pub fn stencil_write_state() -> DepthStencilStateDescriptor {
+    let face = StencilStateFaceDescriptor {
+        compare: CompareFunction::Always,
+        fail_op: StencilOperation::Keep,
+        depth_fail_op: StencilOperation::Keep,
+        pass_op: StencilOperation::Replace,
+    };
+    DepthStencilStateDescriptor {
+        format: DEPTH_STENCIL_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Always,
+        stencil_front: face.clone(),
+        stencil_back: face,
+        stencil_read_mask: !0,
+        stencil_write_mask: !0,
+    }
+}
+
+// Only draws where the stencil differs from the pass's stencil reference, i.e. outside of what
+// got drawn with `stencil_write_state`
+pub fn stencil_outside_state() -> DepthStencilStateDescriptor {
+    let face = StencilStateFaceDescriptor {
+        compare: CompareFunction::NotEqual,
+        fail_op: StencilOperation::Keep,
+        depth_fail_op: StencilOperation::Keep,
+        pass_op: StencilOperation::Keep,
+    };
+    DepthStencilStateDescriptor {
+        stencil_front: face.clone(),
+        stencil_back: face,
+        stencil_write_mask: 0,
+        ..stencil_write_state()
+    }
+}
+
+// A depth target matching the size of the color target it gets used with
+pub struct DepthBuffer {
+    pub view: TextureView,
+    size: PhysicalSize<u32>,
+    format: TextureFormat,
+}
+
+impl DepthBuffer {
+    pub fn new(device: &Device, size: PhysicalSize<u32>) -> Self {
+        Self::with_format(device, size, DEPTH_FORMAT)
+    }
+
+    // E.g. `DEPTH_STENCIL_FORMAT` for a stencil
+    pub fn with_format(device: &Device, size: PhysicalSize<u32>, format: TextureFormat) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsage::OUTPUT_ATTACHMENT,
+            label: Some("depth_texture"),
+        });
+
+        Self {
+            view: texture.create_default_view(),
+            size,
+            format,
+        }
+    }
+
+    // Demos only hear about the new size without a device, so they catch up on the next update
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if size != self.size {
+            *self = Self::with_format(device, size, self.format);
+        }
+    }
+
+    // Cleared to the far plane at the start of the pass
+    pub fn attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.view,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // Keeps the depth an earlier pass in the frame wrote
+    pub fn load_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            depth_load_op: LoadOp::Load,
+            stencil_load_op: LoadOp::Load,
+            ..self.attachment()
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use cgmath::{Matrix4, SquareMatrix};
+use playground_math::{Block, BlockWriter, Camera, Layout};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BufferAddress, Color, ColorStateDescriptor, ColorWrite, CommandEncoder,
+    CompareFunction, CullMode, Device, FilterMode, IndexFormat, LoadOp, PrimitiveTopology,
+    RenderPassColorAttachmentDescriptor, RenderPassDepthStencilAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, StoreOp,
+    TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const DOF_BLUR_FRAG: Shader = Shader {
+    name: "dof_blur.frag",
+    source: include_str!("../shaders/dof_blur.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const DOF_COMPOSITE_FRAG: Shader = Shader {
+    name: "dof_composite.frag",
+    source: include_str!("../shaders/dof_composite.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+#[derive(Copy, Clone, Debug)]
+struct DepthOfFieldUniforms {
+    inverse_projection: Matrix4<f32>,
+    focus_distance: f32,
+    aperture: f32,
+    max_radius: f32,
+    samples: u32,
+}
+
+impl Block for DepthOfFieldUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.inverse_projection);
+        writer.member(&self.focus_distance);
+        writer.member(&self.aperture);
+        writer.member(&self.max_radius);
+        writer.member(&self.samples);
+    }
+}
+
+impl Default for DepthOfFieldUniforms {
+    fn default() -> Self {
+        Self {
+            inverse_projection: Matrix4::identity(),
+            focus_distance: 0.0,
+            aperture: 0.0,
+            max_radius: 0.0,
+            samples: 0,
+        }
+    }
+}
+
+fn texture_pair(
+    device: &Device,
+    layout: &BindGroupLayout,
+    sampler: &Sampler,
+    first: &TextureView,
+    second: &TextureView,
+    label: &str,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        bindings: &[
+            Binding {
+                binding: 0,
+                resource: BindingResource::TextureView(first),
+            },
+            Binding {
+                binding: 1,
+                resource: BindingResource::TextureView(second),
+            },
+            Binding {
+                binding: 2,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some(label),
+    })
+}
+
+// The scene's targets, the blurred copy of it, and the bind groups the two passes read them
+// through
+struct Targets {
+    color: TextureView,
+    depth: TextureView,
+    blurred: TextureView,
+    blur_bind_group: BindGroup,
+    composite_bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let color = render_target::create_target(device, size, format, "dof_color");
+        let depth = render_target::create_target(device, size, depth::DEPTH_FORMAT, "dof_depth");
+        let blurred = render_target::create_target(device, size, format, "dof_blurred");
+        let blur_bind_group = texture_pair(device, layout, sampler, &color, &depth, "dof_blur");
+        let composite_bind_group =
+            texture_pair(device, layout, sampler, &color, &blurred, "dof_composite");
+
+        Self {
+            color,
+            depth,
+            blurred,
+            blur_bind_group,
+            composite_bind_group,
+            size,
+        }
+    }
+}
+
+// Depth of field. The scene gets drawn into offscreen targets, and `resolve` works out from the
+// depth how far every pixel is out of focus, the radius of its circle of confusion. A blur pass
+// gathers the neighbours whose circles reach each pixel, and a composite pass blends the blur
+// over the sharp scene into the frame.
+pub struct DepthOfField {
+    targets: Targets,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    format: TextureFormat,
+    blur_pipeline: Arc<RenderPipeline>,
+    composite_pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    pub enabled: bool,
+    // Along the view direction, where things are sharp
+    pub focus_distance: f32,
+    // How quickly things blur away from the focus, like a wider lens opening
+    pub aperture: f32,
+    // The largest circle of confusion in pixels, and how many pixels get gathered for each
+    pub max_radius: f32,
+    pub samples: u32,
+}
+
+impl DepthOfField {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "dof_targets", bind_group::TEXTURE_PAIR_LAYOUT);
+        // The depth can't be filtered, and the blur samples a whole disc anyway
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        let key = |fragment_shader, bind_group_layouts| PipelineKey {
+            vertex_shader: FULLSCREEN_VERT,
+            fragment_shader: Some(fragment_shader),
+            bind_group_layouts,
+            vertex_buffers: Vec::new(),
+            index_format: IndexFormat::Uint16,
+            primitive_topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::None,
+            color_states: vec![ColorStateDescriptor {
+                format,
+                color_blend: BlendDescriptor::REPLACE,
+                alpha_blend: BlendDescriptor::REPLACE,
+                write_mask: ColorWrite::ALL,
+            }],
+            depth_stencil_state: None,
+            sample_count: 1,
+        };
+        let blur_pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &key(
+                DOF_BLUR_FRAG,
+                vec![
+                    bind_group::layout_key(bind_group::TEXTURE_PAIR_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+            ),
+        );
+        let composite_pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &key(
+                DOF_COMPOSITE_FRAG,
+                vec![bind_group::layout_key(bind_group::TEXTURE_PAIR_LAYOUT)],
+            ),
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            DepthOfFieldUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "dof_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            targets: Targets::new(device, &layout, &sampler, format, size),
+            layout,
+            sampler,
+            format,
+            blur_pipeline,
+            composite_pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            enabled: true,
+            focus_distance: 8.0,
+            aperture: 0.5,
+            max_radius: 12.0,
+            samples: 48,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Depth of field");
+        ui.add(egui::Slider::new(&mut self.focus_distance, 0.1..=50.0).text("Focus distance"));
+        ui.add(egui::Slider::new(&mut self.aperture, 0.0..=2.0).text("Aperture"));
+        ui.add(egui::Slider::new(&mut self.max_radius, 1.0..=32.0).text("Max blur (px)"));
+        ui.add(egui::Slider::new(&mut self.samples, 4..=128).text("Samples"));
+    }
+
+    // Has to match the frame, and `camera` the one the scene gets drawn with
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        size: PhysicalSize<u32>,
+        camera: &Camera,
+    ) {
+        if size != self.targets.size {
+            self.targets = Targets::new(device, &self.layout, &self.sampler, self.format, size);
+        }
+
+        let uniforms = DepthOfFieldUniforms {
+            inverse_projection: camera
+                .projection_matrix()
+                .invert()
+                .unwrap_or_else(Matrix4::identity),
+            focus_distance: self.focus_distance,
+            // Still copies the scene into the frame, with nothing out of focus
+            aperture: if self.enabled { self.aperture } else { 0.0 },
+            max_radius: self.max_radius,
+            samples: self.samples,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the scene's pass, cleared to `clear_color`
+    pub fn color_attachment(&self, clear_color: Color) -> RenderPassColorAttachmentDescriptor<'_> {
+        RenderPassColorAttachmentDescriptor {
+            attachment: &self.targets.color,
+            resolve_target: None,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            clear_color,
+        }
+    }
+
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.targets.depth,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // Writes every pixel of `target`, so it doesn't need to be cleared
+    pub fn resolve(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: &self.targets.blurred,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.push_debug_group("Depth of field blur");
+        render_pass.set_pipeline(&self.blur_pipeline);
+        render_pass.set_bind_group(0, &self.targets.blur_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::BLACK,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.push_debug_group("Depth of field composite");
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, &self.targets.composite_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("dof_uniforms");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::dof::DepthOfField;
+use crate::input::Input;
+use crate::passes::FilmGrainPass;
+use crate::picking::{self, PickObject};
+use crate::primitives::{self, Cubes};
+use crate::uniform::{self, Uniforms};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use playground_math::{Aabb, Block, Camera, Layout, Projection};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, RenderPassDescriptor, RenderPipeline, TextureView,
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+const CUBE_COUNT: usize = 12;
+const CUBE_SPACING: f32 = 2.5;
+
+const FILM_GRAIN_PASS: &str = "Film grain";
+
+// A row of cubes going off into the distance, with only some of them in focus. Right clicking
+// a cube focuses on it. Adds a film grain pass to the frame for the rest of the filmic look.
+pub struct DofDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth_of_field: DepthOfField,
+    // What clicking can focus on
+    objects: Vec<PickObject>,
+    focus_requested: Option<PhysicalPosition<i32>>,
+
+    pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    camera_allocation: Allocation,
+    camera_bind_group: Arc<BindGroup>,
+}
+
+impl DofDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (3.0, 2.0, 5.0).into(),
+            target: (0.0, 0.5, -10.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let instances = primitives::row_of_cubes(CUBE_COUNT, CUBE_SPACING);
+        let objects = instances
+            .iter()
+            .enumerate()
+            .map(|(index, instance)| {
+                let [x, y, z, scale] = instance.offset_scale;
+                let half = Vector3::new(scale, scale, scale) / 2.0;
+                let center = Point3::new(x, y, z);
+                PickObject {
+                    name: format!("cube {}", index),
+                    bounds: Aabb::new(center - half, center + half),
+                    triangles: Vec::new(),
+                }
+            })
+            .collect();
+
+        let pipeline = ctx
+            .pipelines
+            .get(device, ctx.bind_groups, &Cubes::pipeline_key(ctx.format));
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            "dof_camera",
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let depth_of_field = DepthOfField::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            ctx.size,
+        );
+
+        // Off until it's ticked in the custom passes, and gone again with the demo
+        let film_grain = FilmGrainPass::new(ctx);
+        ctx.passes.add(FILM_GRAIN_PASS, false, Box::new(film_grain));
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth_of_field,
+            objects,
+            focus_requested: None,
+            pipeline,
+            cubes: Cubes::new(device, &instances),
+            camera_allocation,
+            camera_bind_group,
+        }
+    }
+
+    // Focuses on whatever is under the cursor, if anything
+    fn focus_at(&mut self, cursor: PhysicalPosition<i32>) {
+        let view_proj = self.camera.build_view_projection_matrix();
+        let ray = match picking::cursor_ray(&view_proj, cursor, self.size) {
+            Some(ray) => ray,
+            None => return,
+        };
+        if let Some(hit) = picking::pick(&ray, &self.objects) {
+            // The blur goes by depth along the view direction, not by distance
+            let forward = (self.camera.target - self.camera.eye).normalize();
+            let offset = ray.at(hit.distance).to_vec() - self.camera.eye.to_vec();
+            self.depth_of_field.focus_distance = offset.dot(forward);
+        }
+    }
+}
+
+impl Demo for DofDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn handle_input(&mut self, input: &Input) {
+        if input.pressed("focus") {
+            self.focus_requested = input.cursor();
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        if let Some(cursor) = self.focus_requested.take() {
+            self.focus_at(cursor);
+        }
+        self.depth_of_field
+            .prepare(device, encoder, uniform_pool, self.size, &self.camera);
+
+        let mut camera_uniforms = Uniforms::new();
+        camera_uniforms.update_view_proj(&self.camera);
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.camera_allocation,
+            &camera_uniforms.std140(),
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.depth_of_field.ui(ui);
+        ui.label("Right click a cube to focus on it");
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[self.depth_of_field.color_attachment(clear_color)],
+            depth_stencil_attachment: Some(self.depth_of_field.depth_attachment()),
+        });
+        render_pass.push_debug_group("Scene into the depth of field targets");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        self.cubes.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        self.depth_of_field.resolve(encoder, target);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.depth_of_field
+            .release(ctx.uniform_pool, ctx.bind_groups);
+        ctx.uniform_pool.free(self.camera_allocation);
+        ctx.bind_groups.invalidate("dof_camera");
+        ctx.passes.remove(FILM_GRAIN_PASS);
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct DrawCounts {
+    pub draw_calls: u32,
+    pub triangles: u64,
+}
+
+static DRAW_CALLS: AtomicU32 = AtomicU32::new(0);
+static TRIANGLES: AtomicU64 = AtomicU64::new(0);
+
+// Counts what the scene draws, for the benchmark to report how much culling and batching saved.
+// Called next to the draws themselves, from whichever thread records them. Only the scene
+// counts, the debug UI, text and lines on top of it don't.
+pub fn record(draw_calls: u32, triangles: u64) {
+    DRAW_CALLS.fetch_add(draw_calls, Ordering::Relaxed);
+    TRIANGLES.fetch_add(triangles, Ordering::Relaxed);
+}
+
+// For `count` vertices or indices of a triangle list, drawn `instances` times
+pub fn record_triangles(count: u32, instances: u32) {
+    record(1, count as u64 / 3 * instances as u64);
+}
+
+// What was recorded since the last call
+pub fn take() -> DrawCounts {
+    DrawCounts {
+        draw_calls: DRAW_CALLS.swap(0, Ordering::Relaxed),
+        triangles: TRIANGLES.swap(0, Ordering::Relaxed),
+    }
+}
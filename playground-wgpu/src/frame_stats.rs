@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// Height of the graph in milliseconds, frames slower than this get clipped
+const GRAPH_MAX_MS: f32 = 50.0;
+
+// Keeps the CPU frame times of the last `capacity` frames
+pub struct FrameStats {
+    last_frame: Instant,
+    frame_times: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl FrameStats {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            last_frame: Instant::now(),
+            frame_times: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // Call once per frame, returns the time since the previous frame in seconds
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        let frame_time = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        if self.frame_times.len() == self.capacity {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        frame_time
+    }
+
+    // When the current frame's `tick` happened
+    pub fn frame_start(&self) -> Instant {
+        self.last_frame
+    }
+
+    pub fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    pub fn max_frame_time(&self) -> f32 {
+        self.frame_times.iter().cloned().fold(0.0, f32::max)
+    }
+
+    pub fn fps(&self) -> f32 {
+        let average = self.average_frame_time();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    // FPS readout plus a graph of the frame times in the window
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "{:.0} FPS ({:.2} ms avg, {:.2} ms max)",
+            self.fps(),
+            self.average_frame_time() * 1000.0,
+            self.max_frame_time() * 1000.0,
+        ));
+
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(240.0, 60.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+
+        let y_for = |ms: f32| rect.bottom() - (ms / GRAPH_MAX_MS).min(1.0) * rect.height();
+
+        // Reference lines for 60 and 30 FPS
+        for &ms in &[1000.0 / 60.0, 1000.0 / 30.0] {
+            painter.hline(
+                rect.x_range(),
+                y_for(ms),
+                egui::Stroke::new(1.0, egui::Color32::from_gray(90)),
+            );
+        }
+
+        let step = rect.width() / (self.capacity.max(2) - 1) as f32;
+        let points: Vec<_> = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, frame_time)| {
+                egui::pos2(rect.left() + i as f32 * step, y_for(frame_time * 1000.0))
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+        ));
+    }
+}
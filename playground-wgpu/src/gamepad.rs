@@ -0,0 +1,80 @@
+use crate::input::Input;
+use cgmath::{Vector2, Zero};
+use gilrs::{Axis, EventType, GamepadId, Gilrs};
+use log::{info, warn};
+use winit::event::ElementState;
+
+// Polls gilrs once per frame. Buttons go through the action system like keys do, the sticks of
+// whichever gamepad was used last are read directly.
+pub struct Gamepads {
+    // None if the platform's gamepad API couldn't be opened
+    gilrs: Option<Gilrs>,
+    active: Option<GamepadId>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(err) => {
+                warn!("Gamepads are unavailable: {}", err);
+                None
+            }
+        };
+
+        Self {
+            gilrs,
+            active: None,
+        }
+    }
+
+    pub fn poll(&mut self, input: &mut Input) {
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return,
+        };
+
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    info!("Gamepad connected: {}", gilrs.gamepad(event.id).name());
+                }
+                EventType::Disconnected => {
+                    info!("Gamepad disconnected: {}", gilrs.gamepad(event.id).name());
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                        input.release_all();
+                    }
+                }
+                EventType::ButtonPressed(button, _) => {
+                    self.active = Some(event.id);
+                    input.handle_gamepad_button(button, ElementState::Pressed);
+                }
+                EventType::ButtonReleased(button, _) => {
+                    input.handle_gamepad_button(button, ElementState::Released);
+                }
+                EventType::AxisChanged(..) => self.active = Some(event.id),
+                _ => (),
+            }
+        }
+    }
+
+    // Both axes go from -1 to 1 with up and right positive, after gilrs' dead zone
+    fn stick(&self, x: Axis, y: Axis) -> Vector2<f32> {
+        match (&self.gilrs, self.active) {
+            (Some(gilrs), Some(id)) => {
+                let gamepad = gilrs.gamepad(id);
+                Vector2::new(gamepad.value(x), gamepad.value(y))
+            }
+            _ => Vector2::zero(),
+        }
+    }
+
+    pub fn left_stick(&self) -> Vector2<f32> {
+        self.stick(Axis::LeftStickX, Axis::LeftStickY)
+    }
+
+    pub fn right_stick(&self) -> Vector2<f32> {
+        self.stick(Axis::RightStickX, Axis::RightStickY)
+    }
+}
@@ -0,0 +1,128 @@
+use cgmath::{InnerSpace, Vector3};
+use egui::{Align2, Color32, Context, FontId, Id, Pos2, Sense, Stroke};
+use playground_math::Camera;
+
+const SIZE: f32 = 96.0;
+const AXIS_LENGTH: f32 = 32.0;
+const HANDLE_RADIUS: f32 = 8.0;
+
+struct Axis {
+    direction: Vector3<f32>,
+    label: &'static str,
+    color: Color32,
+}
+
+fn axes() -> Vec<Axis> {
+    let red = Color32::from_rgb(230, 70, 70);
+    let green = Color32::from_rgb(110, 200, 60);
+    let blue = Color32::from_rgb(70, 130, 230);
+    vec![
+        Axis {
+            direction: Vector3::unit_x(),
+            label: "X",
+            color: red,
+        },
+        Axis {
+            direction: -Vector3::unit_x(),
+            label: "-X",
+            color: red,
+        },
+        Axis {
+            direction: Vector3::unit_y(),
+            label: "Y",
+            color: green,
+        },
+        Axis {
+            direction: -Vector3::unit_y(),
+            label: "-Y",
+            color: green,
+        },
+        Axis {
+            direction: Vector3::unit_z(),
+            label: "Z",
+            color: blue,
+        },
+        Axis {
+            direction: -Vector3::unit_z(),
+            label: "-Z",
+            color: blue,
+        },
+    ]
+}
+
+// Looks at the target from along `direction`, keeping the distance to it
+fn snap(camera: &mut Camera, direction: Vector3<f32>) {
+    let distance = (camera.eye - camera.target).magnitude();
+    camera.eye = camera.target + direction * distance;
+    // Looking straight up or down needs another up vector, -Z is the top of the screen from above
+    camera.up = if direction.y.abs() > 0.5 {
+        Vector3::new(0.0, 0.0, -direction.y)
+    } else {
+        Vector3::unit_y()
+    };
+}
+
+// An axis triad in the bottom left corner that turns along with the camera. Clicking one of the
+// axis ends moves the camera onto that axis, looking back at its target.
+pub fn show(ctx: &Context, camera: &mut Camera) {
+    // The camera's basis, world axes get projected onto right/up for drawing
+    let forward = (camera.target - camera.eye).normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+
+    egui::Area::new(Id::new("orientation_gizmo"))
+        .anchor(Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+        .show(ctx, |ui| {
+            let (response, painter) = ui.allocate_painter(egui::vec2(SIZE, SIZE), Sense::click());
+            let center = response.rect.center();
+            painter.circle_filled(center, SIZE / 2.0, Color32::from_black_alpha(96));
+
+            let mut handles: Vec<(Pos2, f32, Axis)> = axes()
+                .into_iter()
+                .map(|axis| {
+                    let offset = egui::vec2(axis.direction.dot(right), -axis.direction.dot(up));
+                    let depth = axis.direction.dot(forward);
+                    (center + offset * AXIS_LENGTH, depth, axis)
+                })
+                .collect();
+            // Far ends first, so the ones pointing at the viewer stay on top
+            handles.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+            let hovered = response.hover_pos().and_then(|pointer| {
+                handles
+                    .iter()
+                    .rev()
+                    .position(|(position, _, _)| position.distance(pointer) <= HANDLE_RADIUS)
+                    .map(|index| handles.len() - 1 - index)
+            });
+
+            for (index, (position, _, axis)) in handles.iter().enumerate() {
+                let color = if hovered == Some(index) {
+                    Color32::WHITE
+                } else {
+                    axis.color
+                };
+                if axis.label.starts_with('-') {
+                    painter.circle_stroke(*position, HANDLE_RADIUS - 2.0, Stroke::new(2.0, color));
+                } else {
+                    painter.line_segment([center, *position], Stroke::new(2.0, color));
+                    painter.circle_filled(*position, HANDLE_RADIUS, color);
+                    painter.text(
+                        *position,
+                        Align2::CENTER_CENTER,
+                        axis.label,
+                        FontId::proportional(11.0),
+                        Color32::BLACK,
+                    );
+                }
+            }
+
+            if let Some(index) = hovered {
+                let axis = &handles[index].2;
+                if response.clicked() {
+                    snap(camera, axis.direction);
+                }
+                response.on_hover_text(format!("View from {}", axis.label));
+            }
+        });
+}
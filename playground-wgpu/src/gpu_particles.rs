@@ -0,0 +1,392 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::{self, StorageBuffer};
+use crate::draw_stats;
+use crate::pipeline::{ComputePipelineKey, PipelineCache, PipelineKey, Shader};
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4, Zero};
+use playground_math::{Block, BlockWriter, Camera, Color, Layout};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, BlendFactor, BlendOperation,
+    BufferAddress, BufferUsage, ColorStateDescriptor, ColorWrite, CommandEncoder, ComputePipeline,
+    CullMode, Device, IndexFormat, PrimitiveTopology, RenderPass, RenderPipeline, ShaderStage,
+    TextureFormat,
+};
+
+const GPU_PARTICLES_COMP: Shader = Shader {
+    name: "gpu_particles.comp",
+    source: include_str!("../shaders/gpu_particles.comp"),
+    stage: ShaderStage::COMPUTE,
+};
+
+const GPU_PARTICLE_VERT: Shader = Shader {
+    name: "gpu_particle.vert",
+    source: include_str!("../shaders/gpu_particle.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+// Same soft round quads as the CPU particles
+const PARTICLE_FRAG: Shader = Shader {
+    name: "particle.frag",
+    source: include_str!("../shaders/particle.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const ADDITIVE_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+// Has to match local_size_x in the compute shader
+const WORKGROUP_SIZE: u32 = 256;
+
+// Matches the compute shader's storage buffer, the particles never leave the GPU after the
+// initial upload
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GpuParticle {
+    // w is the age in seconds, negative until the particle is born
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for GpuParticle {}
+
+unsafe impl bytemuck::Zeroable for GpuParticle {}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct SimulationUniforms {
+    emitter: [f32; 4],
+    // w is the random spread
+    velocity: [f32; 4],
+    // w is the time step
+    acceleration: [f32; 4],
+    lifetime: f32,
+    seed: u32,
+    count: u32,
+}
+
+impl Block for SimulationUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector4::from(self.emitter));
+        writer.member(&Vector4::from(self.velocity));
+        writer.member(&Vector4::from(self.acceleration));
+        writer.member(&self.lifetime);
+        writer.member(&self.seed);
+        writer.member(&self.count);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct GpuParticleUniforms {
+    view_proj: Matrix4<f32>,
+    camera_right: Vector4<f32>,
+    camera_up: Vector4<f32>,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    lifetime: f32,
+    size: f32,
+}
+
+impl Block for GpuParticleUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.camera_right);
+        writer.member(&self.camera_up);
+        writer.member(&Vector4::from(self.start_color));
+        writer.member(&Vector4::from(self.end_color));
+        writer.member(&self.lifetime);
+        writer.member(&self.size);
+    }
+}
+
+impl Default for GpuParticleUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            camera_right: Vector4::zero(),
+            camera_up: Vector4::zero(),
+            start_color: [0.0; 4],
+            end_color: [0.0; 4],
+            lifetime: 0.0,
+            size: 0.0,
+        }
+    }
+}
+
+// A fixed number of particles that live in a storage buffer. A compute pass moves them every
+// update and the vertex shader reads them straight from the buffer, so the CPU only ever
+// writes the emitter settings. Particles are reborn at the emitter as soon as they die, which
+// makes the emission rate `count / lifetime`.
+pub struct GpuParticleSystem {
+    pub position: Point3<f32>,
+    pub velocity: Vector3<f32>,
+    // Largest random change to `velocity`, in any direction
+    pub spread: f32,
+    pub acceleration: Vector3<f32>,
+    // In seconds
+    pub lifetime: f32,
+    // Linear, fading from start to end over a particle's life
+    pub start_color: Color,
+    pub end_color: Color,
+    pub size: f32,
+
+    count: u32,
+    steps: u32,
+    _particle_buffer: StorageBuffer,
+
+    compute_pipeline: Arc<ComputePipeline>,
+    simulation_allocation: Allocation,
+    simulation_bind_group: Arc<BindGroup>,
+    storage_bind_group: Arc<BindGroup>,
+
+    render_pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    vertex_storage_bind_group: Arc<BindGroup>,
+}
+
+impl GpuParticleSystem {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        count: u32,
+    ) -> Self {
+        let lifetime = 3.0;
+
+        // Not born yet, with birthdays spread evenly over the first lifetime
+        let particles: Vec<GpuParticle> = (0..count)
+            .map(|index| GpuParticle {
+                position: [0.0, 0.0, 0.0, -lifetime * index as f32 / count as f32],
+                velocity: [0.0; 4],
+            })
+            .collect();
+        let particle_buffer = StorageBuffer::new(
+            device,
+            bytemuck::cast_slice(&particles),
+            BufferUsage::empty(),
+        );
+
+        let simulation_allocation = uniform_pool.allocate(
+            device,
+            SimulationUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let simulation_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::COMPUTE_UNIFORM_LAYOUT,
+            "gpu_particle_simulation",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&simulation_allocation),
+                    range: simulation_allocation.offset
+                        ..simulation_allocation.offset + simulation_allocation.size,
+                },
+            }],
+        );
+        let storage_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::STORAGE_LAYOUT,
+            "gpu_particle_storage",
+            &[particle_buffer.binding(0)],
+        );
+        let vertex_storage_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::VERTEX_STORAGE_LAYOUT,
+            "gpu_particle_vertices",
+            &[particle_buffer.binding(0)],
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            GpuParticleUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "gpu_particle_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let compute_pipeline = pipelines.get_compute(
+            device,
+            bind_groups,
+            &ComputePipelineKey {
+                shader: GPU_PARTICLES_COMP,
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::COMPUTE_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_LAYOUT),
+                ],
+            },
+        );
+        let render_pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: GPU_PARTICLE_VERT,
+                fragment_shader: Some(PARTICLE_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::VERTEX_STORAGE_LAYOUT),
+                ],
+                // Everything comes out of the storage buffer
+                vertex_buffers: vec![],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ADDITIVE_BLEND,
+                    alpha_blend: ADDITIVE_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            position: Point3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 2.5, 0.0),
+            spread: 1.0,
+            acceleration: Vector3::new(0.0, -1.5, 0.0),
+            lifetime,
+            start_color: Color::new(0.2, 0.5, 1.0, 0.5),
+            end_color: Color::new(0.6, 0.1, 0.8, 0.0),
+            size: 0.02,
+            count,
+            steps: 0,
+            _particle_buffer: particle_buffer,
+            compute_pipeline,
+            simulation_allocation,
+            simulation_bind_group,
+            storage_bind_group,
+            render_pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            vertex_storage_bind_group,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            ui.add(egui::DragValue::new(&mut self.position.x).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.position.y).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.position.z).speed(0.05));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Velocity");
+            ui.add(egui::DragValue::new(&mut self.velocity.x).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.velocity.y).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.velocity.z).speed(0.05));
+        });
+        ui.add(egui::Slider::new(&mut self.spread, 0.0..=5.0).text("Spread"));
+        ui.add(egui::Slider::new(&mut self.acceleration.y, -10.0..=10.0).text("Gravity"));
+        ui.add(egui::Slider::new(&mut self.lifetime, 0.1..=10.0).text("Lifetime"));
+        ui.add(egui::Slider::new(&mut self.size, 0.002..=0.2).text("Size"));
+
+        let mut start = self.start_color.to_array();
+        let mut end = self.end_color.to_array();
+        ui.horizontal(|ui| {
+            ui.label("Color over life");
+            ui.color_edit_button_rgba_unmultiplied(&mut start);
+            ui.color_edit_button_rgba_unmultiplied(&mut end);
+        });
+        self.start_color = Color::new(start[0], start[1], start[2], start[3]);
+        self.end_color = Color::new(end[0], end[1], end[2], end[3]);
+    }
+
+    // Records a simulation step of `dt` seconds
+    pub fn step(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+    ) {
+        self.steps = self.steps.wrapping_add(1);
+        let simulation = SimulationUniforms {
+            emitter: self.position.to_homogeneous().into(),
+            velocity: self.velocity.extend(self.spread).into(),
+            acceleration: self.acceleration.extend(dt).into(),
+            lifetime: self.lifetime,
+            seed: self.steps.wrapping_mul(0x9e37_79b9),
+            count: self.count,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.simulation_allocation,
+            &simulation.std140(),
+        );
+        compute::dispatch(
+            encoder,
+            &self.compute_pipeline,
+            &[&self.simulation_bind_group, &self.storage_bind_group],
+            [compute::workgroups(self.count, WORKGROUP_SIZE), 1, 1],
+        );
+    }
+
+    // Records the uniforms for the next `render`
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+    ) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let uniforms = GpuParticleUniforms {
+            view_proj: camera.build_view_projection_matrix(),
+            camera_right: right.extend(0.0),
+            camera_up: up.extend(0.0),
+            start_color: self.start_color.to_array(),
+            end_color: self.end_color.to_array(),
+            lifetime: self.lifetime,
+            size: self.size,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.vertex_storage_bind_group, &[]);
+        draw_stats::record_triangles(6, self.count);
+        render_pass.draw(0..6, 0..self.count);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.simulation_allocation);
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("gpu_particle_simulation");
+        bind_groups.invalidate("gpu_particle_storage");
+        bind_groups.invalidate("gpu_particle_vertices");
+        bind_groups.invalidate("gpu_particle_uniforms");
+    }
+}
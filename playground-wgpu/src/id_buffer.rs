@@ -0,0 +1,118 @@
+use crate::readback::Readback;
+use crate::texture;
+use wgpu::{
+    BufferAddress, BufferCopyView, CommandEncoder, Device, Extent3d, Origin3d, TextureCopyView,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+pub const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+
+// Offscreen target that demos draw object IDs into, for picking on the GPU. Zero means nothing
+// was drawn there. `copy` and `map` read back the texel under the cursor, `try_id` returns it a
+// frame or so later.
+pub struct IdBuffer {
+    texture: wgpu::Texture,
+    pub view: TextureView,
+    size: PhysicalSize<u32>,
+    readback: Readback,
+}
+
+impl IdBuffer {
+    pub fn new(device: &Device, size: PhysicalSize<u32>) -> Self {
+        let (texture, view) = Self::create_texture(device, size);
+
+        // Rows still have to be padded when there's only one texel in them
+        let readback_size = texture::padded_bytes_per_row(1) as BufferAddress;
+        let readback = Readback::new(device, "id_buffer_readback", readback_size);
+
+        Self {
+            texture,
+            view,
+            size,
+            readback,
+        }
+    }
+
+    fn create_texture(device: &Device, size: PhysicalSize<u32>) -> (wgpu::Texture, TextureView) {
+        let texture = device.create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::COPY_SRC,
+            label: Some("id_buffer"),
+        });
+        let view = texture.create_default_view();
+        (texture, view)
+    }
+
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        let (texture, view) = Self::create_texture(device, size);
+        self.texture = texture;
+        self.view = view;
+        self.size = size;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.readback.is_pending()
+    }
+
+    // Copies the ID under the cursor, after the IDs were rendered with the same encoder. Returns
+    // false if the cursor is outside of the buffer.
+    pub fn copy(&self, encoder: &mut CommandEncoder, cursor: PhysicalPosition<i32>) -> bool {
+        if cursor.x < 0
+            || cursor.y < 0
+            || cursor.x as u32 >= self.size.width
+            || cursor.y as u32 >= self.size.height
+        {
+            return false;
+        }
+
+        encoder.copy_texture_to_buffer(
+            TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: Origin3d {
+                    x: cursor.x as u32,
+                    y: cursor.y as u32,
+                    z: 0,
+                },
+            },
+            BufferCopyView {
+                buffer: &self.readback.buffer,
+                offset: 0,
+                bytes_per_row: texture::padded_bytes_per_row(1),
+                rows_per_image: 1,
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        );
+        true
+    }
+
+    // Call after submitting the encoder passed to `copy`
+    pub fn map(&mut self) {
+        self.readback.map();
+    }
+
+    pub fn try_id(&mut self) -> Option<Result<u32, failure::Error>> {
+        Some(match self.readback.try_read()? {
+            Ok(mapping) => {
+                let data = mapping.as_slice();
+                Ok(u32::from_ne_bytes([data[0], data[1], data[2], data[3]]))
+            }
+            Err(_) => Err(failure::err_msg("Failed to map the ID buffer")),
+        })
+    }
+}
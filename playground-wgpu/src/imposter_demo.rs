@@ -0,0 +1,186 @@
+use crate::assets::Assets;
+use crate::billboard::{self, Atlas, Billboard, BillboardBlend, BillboardMode, Billboards};
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::particles::Rng;
+use crate::primitives::{self, CubeInstance, Cubes};
+use crate::uniform::{self, Uniforms};
+use cgmath::{Point3, Vector3};
+use playground_math::{Block, Camera, Layout, Projection};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const TREE_COUNT: usize = 4000;
+
+// Trees grow between these distances from the origin, leaving a clearing for the cubes
+const FOREST_INNER_RADIUS: f32 = 8.0;
+const FOREST_OUTER_RADIUS: f32 = 80.0;
+
+// Sides of the ground, a flat cube with its top at y = 0
+const GROUND_SIZE: f32 = 2.0 * FOREST_OUTER_RADIUS;
+
+const CAMERA_KEY: &str = "imposter_camera";
+
+// Trees standing on the ground, `TREE_COUNT` of them scattered around the clearing
+fn forest() -> Vec<Billboard> {
+    let mut rng = Rng(0x2545_f491);
+    (0..TREE_COUNT)
+        .map(|_| {
+            // Uniform over the ring's area rather than bunching up towards the middle
+            let inner = FOREST_INNER_RADIUS * FOREST_INNER_RADIUS;
+            let outer = FOREST_OUTER_RADIUS * FOREST_OUTER_RADIUS;
+            let distance = (inner + rng.next() * (outer - inner)).sqrt();
+            let angle = rng.next() * 2.0 * PI;
+            let height = 2.0 + rng.next() * 2.5;
+            let shade = 0.75 + rng.next() * 0.25;
+            Billboard {
+                position: Point3::new(angle.cos() * distance, height / 2.0, angle.sin() * distance),
+                size: [height * 0.7, height],
+                cell: billboard::TREE_CELL,
+                color: playground_math::Color::new(shade, shade, shade, 1.0),
+            }
+        })
+        .collect()
+}
+
+// A forest of trees that are only billboards, cheap enough to draw thousands of them. Cylindrical
+// billboards stay upright when looking down on them, spherical ones lean back with the camera.
+pub struct ImposterDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    trees: Vec<Billboard>,
+    imposters: Billboards,
+
+    pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    ground: Cubes,
+    camera_allocation: Allocation,
+    camera_bind_group: Arc<BindGroup>,
+}
+
+impl ImposterDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 4.0, 14.0).into(),
+            target: (0.0, 1.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 250.0,
+            projection: Projection::Perspective,
+        };
+
+        let atlas = Atlas::generated(device, ctx.queue, ctx.bind_groups);
+        let mut imposters = Billboards::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            Arc::new(atlas),
+            BillboardBlend::Cutout,
+        );
+        imposters.mode = BillboardMode::Cylindrical;
+
+        let pipeline = ctx
+            .pipelines
+            .get(device, ctx.bind_groups, &Cubes::pipeline_key(ctx.format));
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            CAMERA_KEY,
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(device, ctx.size),
+            trees: forest(),
+            imposters,
+            pipeline,
+            cubes: Cubes::new(device, &primitives::ring_of_cubes(8, 3.0)),
+            ground: Cubes::new(
+                device,
+                &[CubeInstance {
+                    offset_scale: [0.0, -GROUND_SIZE / 2.0, 0.0, GROUND_SIZE],
+                    color: [0.3, 0.45, 0.25, 1.0],
+                }],
+            ),
+            camera_allocation,
+            camera_bind_group,
+        }
+    }
+}
+
+impl Demo for ImposterDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        self.imposters
+            .prepare(device, encoder, uniform_pool, &self.camera, &self.trees);
+
+        let mut camera = Uniforms::new();
+        camera.update_view_proj(&self.camera);
+        uniform_pool.write(device, encoder, &self.camera_allocation, &camera.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} trees", self.trees.len()));
+        self.imposters.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        self.cubes.draw(&mut render_pass);
+        self.ground.draw(&mut render_pass);
+        self.imposters.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.imposters.release(ctx.uniform_pool);
+        ctx.uniform_pool.free(self.camera_allocation);
+        ctx.bind_groups.invalidate(CAMERA_KEY);
+    }
+}
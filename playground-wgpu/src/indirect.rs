@@ -0,0 +1,127 @@
+use crate::draw_stats;
+use std::mem;
+use wgpu::{
+    Binding, BindingResource, Buffer, BufferAddress, BufferDescriptor, BufferUsage, CommandEncoder,
+    Device, RenderPass,
+};
+
+// The arguments of one `draw_indexed_indirect`, laid out the way the GPU reads them
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+unsafe impl bytemuck::Pod for DrawIndexedIndirect {}
+
+unsafe impl bytemuck::Zeroable for DrawIndexedIndirect {}
+
+pub const COMMAND_SIZE: BufferAddress = mem::size_of::<DrawIndexedIndirect>() as BufferAddress;
+
+fn create_buffer(device: &Device, capacity: usize) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("indirect_arguments"),
+        size: capacity as BufferAddress * COMMAND_SIZE,
+        usage: BufferUsage::INDIRECT | BufferUsage::STORAGE | BufferUsage::COPY_DST,
+    })
+}
+
+// Draw arguments collected on the CPU and uploaded once per frame, so everything sharing a
+// pipeline and its buffers gets drawn straight from the argument buffer. A compute shader can
+// fill in the rest after the upload, e.g. count instances into `instance_count`.
+pub struct IndirectBuffer {
+    commands: Vec<DrawIndexedIndirect>,
+    buffer: Buffer,
+    // In commands
+    capacity: usize,
+    // As of the last `upload`
+    uploaded: usize,
+}
+
+impl IndirectBuffer {
+    pub fn new(device: &Device, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            commands: Vec::with_capacity(capacity),
+            buffer: create_buffer(device, capacity),
+            capacity,
+            uploaded: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn push(&mut self, command: DrawIndexedIndirect) {
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[DrawIndexedIndirect] {
+        &self.commands
+    }
+
+    // Copies the commands pushed since the last `clear` into the argument buffer, growing it
+    // when they don't fit. Growing replaces the buffer, bind groups around `binding` have to be
+    // created again after that.
+    pub fn upload(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        self.uploaded = self.commands.len();
+        if self.commands.is_empty() {
+            return;
+        }
+
+        if self.commands.len() > self.capacity {
+            self.capacity = self.commands.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        let staging = device
+            .create_buffer_with_data(bytemuck::cast_slice(&self.commands), BufferUsage::COPY_SRC);
+        encoder.copy_buffer_to_buffer(
+            &staging,
+            0,
+            &self.buffer,
+            0,
+            self.commands.len() as BufferAddress * COMMAND_SIZE,
+        );
+    }
+
+    // The argument buffer at `binding`, for compute shaders writing to it
+    pub fn binding(&self, binding: u32) -> Binding<'_> {
+        Binding {
+            binding,
+            resource: BindingResource::Buffer {
+                buffer: &self.buffer,
+                range: 0..self.capacity as BufferAddress * COMMAND_SIZE,
+            },
+        }
+    }
+
+    // One indirect draw per uploaded command. The pipeline, bind groups and vertex and index
+    // buffers have to be set already.
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        // Counted from the CPU side copy, so before any culling a compute shader does on them
+        for command in &self.commands[..self.uploaded] {
+            draw_stats::record_triangles(command.index_count, command.instance_count);
+        }
+        for index in 0..self.uploaded {
+            render_pass.draw_indexed_indirect(&self.buffer, index as BufferAddress * COMMAND_SIZE);
+        }
+    }
+
+    // The same draws issued directly from the CPU side copy, for comparing against `draw`. Only
+    // the same when nothing changed the arguments on the GPU.
+    pub fn draw_direct(&self, render_pass: &mut RenderPass) {
+        for command in &self.commands[..self.uploaded] {
+            draw_stats::record_triangles(command.index_count, command.instance_count);
+            render_pass.draw_indexed(
+                command.first_index..command.first_index + command.index_count,
+                command.base_vertex,
+                command.first_instance..command.first_instance + command.instance_count,
+            );
+        }
+    }
+}
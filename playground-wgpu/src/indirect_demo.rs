@@ -0,0 +1,730 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::{self, StorageBuffer};
+use crate::demo::{Demo, DemoContext};
+use crate::depth::{self, DepthBuffer};
+use crate::indirect::{DrawIndexedIndirect, IndirectBuffer};
+use crate::particles::Rng;
+use crate::pipeline::{ComputePipelineKey, PipelineKey, Shader, VertexLayout};
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4, Zero};
+use playground_math::{Block, BlockWriter, Camera, Frustum, Layout, Projection, Sphere};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, Buffer, BufferAddress, BufferUsage,
+    Color, ColorStateDescriptor, ColorWrite, CommandEncoder, ComputePipeline, CullMode, Device,
+    IndexFormat, InputStepMode, LoadOp, PrimitiveTopology, RenderPass,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureView, VertexAttributeDescriptor, VertexFormat,
+};
+use winit::dpi::PhysicalSize;
+
+const SHAPE_VERT: Shader = Shader {
+    name: "shape.vert",
+    source: include_str!("../shaders/shape.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHAPE_FRAG: Shader = Shader {
+    name: "shape.frag",
+    source: include_str!("../shaders/shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const CULL_SHAPES_COMP: Shader = Shader {
+    name: "cull_shapes.comp",
+    source: include_str!("../shaders/cull_shapes.comp"),
+    stage: ShaderStage::COMPUTE,
+};
+
+// Has to match local_size_x in the compute shader
+const WORKGROUP_SIZE: u32 = 64;
+// The compute shader has room for this many shapes
+const MAX_SHAPES: usize = 4;
+
+// Shapes along each side of the field, and the distance between their centers
+const GRID: u32 = 320;
+const SPACING: f32 = 2.0;
+// Every shape fits in a unit cube around its origin
+const SHAPE_RADIUS: f32 = 0.87;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ShapeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+unsafe impl bytemuck::Pod for ShapeVertex {}
+
+unsafe impl bytemuck::Zeroable for ShapeVertex {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ShapeInstance {
+    // Where it sits, and its size in w
+    offset_scale: [f32; 4],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for ShapeInstance {}
+
+unsafe impl bytemuck::Zeroable for ShapeInstance {}
+
+#[derive(Copy, Clone, Debug)]
+struct ShapeUniforms {
+    view_proj: Matrix4<f32>,
+}
+
+impl Block for ShapeUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+    }
+}
+
+impl Default for ShapeUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CullUniforms {
+    // Normal and distance of every frustum plane
+    planes: [Vector4<f32>; 6],
+    // A uvec4 in the shader
+    shape_starts: [u32; MAX_SHAPES],
+    count: u32,
+    shape_count: u32,
+    radius: f32,
+    culling: u32,
+}
+
+impl Block for CullUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.planes);
+        writer.member(&Vector4::from(self.shape_starts));
+        writer.member(&self.count);
+        writer.member(&self.shape_count);
+        writer.member(&self.radius);
+        writer.member(&self.culling);
+    }
+}
+
+impl Default for CullUniforms {
+    fn default() -> Self {
+        Self {
+            planes: [Vector4::zero(); 6],
+            shape_starts: [0; MAX_SHAPES],
+            count: 0,
+            shape_count: 0,
+            radius: 0.0,
+            culling: 0,
+        }
+    }
+}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<ShapeVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                shader_location: 1,
+                format: VertexFormat::Float3,
+            },
+        ],
+    }
+}
+
+fn instance_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<ShapeInstance>() as BufferAddress,
+        step_mode: InputStepMode::Instance,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 2,
+                format: VertexFormat::Float4,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 4]>() as BufferAddress,
+                shader_location: 3,
+                format: VertexFormat::Float4,
+            },
+        ],
+    }
+}
+
+type Triangle = [[f32; 3]; 3];
+
+fn cube() -> Vec<Triangle> {
+    // Each face's normal and two directions across it
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ];
+    let mut triangles = Vec::new();
+    for &(normal, u, v) in &faces {
+        let corner = |su: f32, sv: f32| {
+            let mut corner = [0.0; 3];
+            for axis in 0..3 {
+                corner[axis] = (normal[axis] + u[axis] * su + v[axis] * sv) * 0.5;
+            }
+            corner
+        };
+        let quad = [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ];
+        triangles.push([quad[0], quad[1], quad[2]]);
+        triangles.push([quad[0], quad[2], quad[3]]);
+    }
+    triangles
+}
+
+fn pyramid() -> Vec<Triangle> {
+    let apex = [0.0, 0.5, 0.0];
+    let base = [
+        [-0.5, -0.5, -0.5],
+        [0.5, -0.5, -0.5],
+        [0.5, -0.5, 0.5],
+        [-0.5, -0.5, 0.5],
+    ];
+    let mut triangles = vec![[base[0], base[1], base[2]], [base[0], base[2], base[3]]];
+    for side in 0..4 {
+        triangles.push([base[side], base[(side + 1) % 4], apex]);
+    }
+    triangles
+}
+
+fn octahedron() -> Vec<Triangle> {
+    let mut triangles = Vec::new();
+    for &x in &[-0.5, 0.5] {
+        for &y in &[-0.5, 0.5] {
+            for &z in &[-0.5, 0.5] {
+                triangles.push([[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]]);
+            }
+        }
+    }
+    triangles
+}
+
+// Where a shape sits in the shared vertex and index buffers
+#[derive(Copy, Clone, Debug)]
+struct ShapeRange {
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+}
+
+// Appends a flat shaded shape to the shared buffers. The shapes are convex and around the
+// origin, so every triangle gets turned to face outward whichever way it was wound.
+fn append_shape(
+    triangles: &[Triangle],
+    vertices: &mut Vec<ShapeVertex>,
+    indices: &mut Vec<u16>,
+) -> ShapeRange {
+    let range = ShapeRange {
+        first_index: indices.len() as u32,
+        index_count: (triangles.len() * 3) as u32,
+        base_vertex: vertices.len() as i32,
+    };
+    for (index, triangle) in triangles.iter().enumerate() {
+        let [a, b, c] = triangle.map(Vector3::from);
+        let mut normal = (b - a).cross(c - a).normalize();
+        let corners = if normal.dot(a + b + c) < 0.0 {
+            normal = -normal;
+            [a, c, b]
+        } else {
+            [a, b, c]
+        };
+        for corner in &corners {
+            vertices.push(ShapeVertex {
+                position: (*corner).into(),
+                normal: normal.into(),
+            });
+        }
+        let first = (index * 3) as u16;
+        indices.extend_from_slice(&[first, first + 1, first + 2]);
+    }
+    range
+}
+
+// A field of cubes, pyramids and octahedrons. The shapes share one vertex and one index buffer,
+// and every frame the ones in view get packed per shape into the instance buffer, with one
+// indirect draw per shape.
+//
+// Culling on the GPU, a compute pass tests every instance against the frustum and appends the
+// survivors to their shape's range, counting them into the draw arguments as it goes. The CPU
+// only uploads the arguments with no instances in them. Culling on the CPU, it packs the
+// instances and fills in the arguments itself.
+pub struct IndirectDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    gpu_culling: bool,
+    // Only matters when culling on the CPU, the GPU's counts only exist in the argument buffer
+    use_indirect: bool,
+    culling: bool,
+
+    shapes: Vec<ShapeRange>,
+    // Every shape's instances, whether they're in view or not
+    instances: Vec<Vec<ShapeInstance>>,
+    // Where each shape's instances start in `_all_instances`
+    shape_starts: Vec<u32>,
+    instance_count: usize,
+    // As of the last frame culled on the CPU
+    drawn: usize,
+
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    // `instances` one shape after the other, only kept alive for `instances_bind_group`
+    _all_instances: StorageBuffer,
+    // The instances in view, grouped by shape
+    visible: StorageBuffer,
+    draws: IndirectBuffer,
+
+    cull_pipeline: Arc<ComputePipeline>,
+    cull_allocation: Allocation,
+    cull_bind_group: Arc<BindGroup>,
+    instances_bind_group: Arc<BindGroup>,
+    draws_bind_group: Arc<BindGroup>,
+
+    pipeline: Arc<RenderPipeline>,
+    // With the pre-pass on, the color pass only shades the nearest fragment of every pixel
+    depth_prepass: bool,
+    depth_prepass_pipeline: Arc<RenderPipeline>,
+    after_prepass_pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+impl IndirectDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (0.0, 30.0, 60.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 1000.0,
+            projection: Projection::Perspective,
+        };
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let shapes: Vec<ShapeRange> = [cube(), pyramid(), octahedron()]
+            .iter()
+            .map(|triangles| append_shape(triangles, &mut vertices, &mut indices))
+            .collect();
+        let vertex_buffer = ctx
+            .device
+            .create_buffer_with_data(bytemuck::cast_slice(&vertices), BufferUsage::VERTEX);
+        // Padded to keep the buffer size a multiple of 4
+        if indices.len() % 2 == 1 {
+            indices.push(0);
+        }
+        let index_buffer = ctx
+            .device
+            .create_buffer_with_data(bytemuck::cast_slice(&indices), BufferUsage::INDEX);
+
+        let mut rng = Rng(0x1234_5678);
+        let mut instances = vec![Vec::new(); shapes.len()];
+        let half = (GRID - 1) as f32 * SPACING * 0.5;
+        for z in 0..GRID {
+            for x in 0..GRID {
+                let shape = ((rng.next() * shapes.len() as f32) as usize).min(shapes.len() - 1);
+                let scale = 0.5 + rng.next();
+                instances[shape].push(ShapeInstance {
+                    offset_scale: [
+                        x as f32 * SPACING - half,
+                        scale * 0.5,
+                        z as f32 * SPACING - half,
+                        scale,
+                    ],
+                    color: [
+                        0.2 + 0.8 * rng.next(),
+                        0.2 + 0.8 * rng.next(),
+                        0.2 + 0.8 * rng.next(),
+                        1.0,
+                    ],
+                });
+            }
+        }
+        let instance_count = (GRID * GRID) as usize;
+        let mut shape_starts = Vec::new();
+        let mut flattened = Vec::with_capacity(instance_count);
+        for shape_instances in &instances {
+            shape_starts.push(flattened.len() as u32);
+            flattened.extend_from_slice(shape_instances);
+        }
+        let all_instances = StorageBuffer::new(
+            ctx.device,
+            bytemuck::cast_slice(&flattened),
+            BufferUsage::empty(),
+        );
+        let visible = StorageBuffer::new(
+            ctx.device,
+            bytemuck::cast_slice(&flattened),
+            BufferUsage::VERTEX | BufferUsage::COPY_DST,
+        );
+        let draws = IndirectBuffer::new(ctx.device, shapes.len());
+
+        let cull_allocation = ctx.uniform_pool.allocate(
+            ctx.device,
+            CullUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let cull_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::COMPUTE_UNIFORM_LAYOUT,
+            "shape_culling",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: ctx.uniform_pool.buffer(&cull_allocation),
+                    range: cull_allocation.offset..cull_allocation.offset + cull_allocation.size,
+                },
+            }],
+        );
+        let instances_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::STORAGE_IN_OUT_LAYOUT,
+            "shape_instances",
+            &[all_instances.binding(0), visible.binding(1)],
+        );
+        let draws_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::STORAGE_LAYOUT,
+            "shape_draws",
+            &[draws.binding(0)],
+        );
+        let cull_pipeline = ctx.pipelines.get_compute(
+            ctx.device,
+            ctx.bind_groups,
+            &ComputePipelineKey {
+                shader: CULL_SHAPES_COMP,
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::COMPUTE_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_IN_OUT_LAYOUT),
+                    bind_group::layout_key(bind_group::STORAGE_LAYOUT),
+                ],
+            },
+        );
+
+        let uniform_allocation = ctx.uniform_pool.allocate(
+            ctx.device,
+            ShapeUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = ctx.bind_groups.bind_group(
+            ctx.device,
+            bind_group::UNIFORM_LAYOUT,
+            "shape_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: ctx.uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let key = PipelineKey {
+            vertex_shader: SHAPE_VERT,
+            fragment_shader: Some(SHAPE_FRAG),
+            bind_group_layouts: vec![bind_group::layout_key(bind_group::UNIFORM_LAYOUT)],
+            vertex_buffers: vec![vertex_layout(), instance_layout()],
+            index_format: IndexFormat::Uint16,
+            primitive_topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::Back,
+            color_states: vec![ColorStateDescriptor {
+                format: ctx.format,
+                color_blend: BlendDescriptor::REPLACE,
+                alpha_blend: BlendDescriptor::REPLACE,
+                write_mask: ColorWrite::ALL,
+            }],
+            depth_stencil_state: Some(depth::depth_stencil_state()),
+            sample_count: 1,
+        };
+        let pipeline = ctx.pipelines.get(ctx.device, ctx.bind_groups, &key);
+        // Only writes depth, nothing gets shaded
+        let depth_prepass_pipeline = ctx.pipelines.get(
+            ctx.device,
+            ctx.bind_groups,
+            &PipelineKey {
+                fragment_shader: None,
+                color_states: vec![],
+                ..key.clone()
+            },
+        );
+        let after_prepass_pipeline = ctx.pipelines.get(
+            ctx.device,
+            ctx.bind_groups,
+            &PipelineKey {
+                depth_stencil_state: Some(depth::equal_depth_stencil_state()),
+                ..key
+            },
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(ctx.device, ctx.size),
+            gpu_culling: true,
+            use_indirect: true,
+            culling: true,
+            shapes,
+            instances,
+            shape_starts,
+            instance_count,
+            drawn: 0,
+            vertex_buffer,
+            index_buffer,
+            _all_instances: all_instances,
+            visible,
+            draws,
+            cull_pipeline,
+            cull_allocation,
+            cull_bind_group,
+            instances_bind_group,
+            draws_bind_group,
+            pipeline,
+            depth_prepass: false,
+            depth_prepass_pipeline,
+            after_prepass_pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+
+    // Every shape in view with whichever pipeline is set
+    fn draw_shapes<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_vertex_buffer(1, &self.visible.buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        if self.gpu_culling || self.use_indirect {
+            self.draws.draw(render_pass);
+        } else {
+            self.draws.draw_direct(render_pass);
+        }
+    }
+
+    // Packs the instances in view shape by shape, so each shape's instances end up next to each
+    // other, and fills in the draw arguments to match
+    fn cull_on_cpu(&mut self, device: &Device, encoder: &mut CommandEncoder, frustum: &Frustum) {
+        let culling = self.culling;
+        let mut visible: Vec<ShapeInstance> = Vec::with_capacity(self.instance_count);
+        self.draws.clear();
+        for (shape, instances) in self.shapes.iter().zip(&self.instances) {
+            let first_instance = visible.len() as u32;
+            visible.extend(instances.iter().filter(|instance| {
+                let [x, y, z, scale] = instance.offset_scale;
+                let bounds = Sphere::new(Point3::new(x, y, z), SHAPE_RADIUS * scale);
+                !culling || frustum.intersects_sphere(&bounds)
+            }));
+            self.draws.push(DrawIndexedIndirect {
+                index_count: shape.index_count,
+                instance_count: visible.len() as u32 - first_instance,
+                first_index: shape.first_index,
+                base_vertex: shape.base_vertex,
+                first_instance,
+            });
+        }
+        self.drawn = visible.len();
+
+        if !visible.is_empty() {
+            let staging = device
+                .create_buffer_with_data(bytemuck::cast_slice(&visible), BufferUsage::COPY_SRC);
+            encoder.copy_buffer_to_buffer(
+                &staging,
+                0,
+                &self.visible.buffer,
+                0,
+                (visible.len() * mem::size_of::<ShapeInstance>()) as BufferAddress,
+            );
+        }
+        self.draws.upload(device, encoder);
+    }
+
+    // Uploads the draw arguments without any instances and has the compute pass count the ones
+    // in view into them. Each shape's survivors go where its instances start in `_all_instances`,
+    // since that's as many as could survive.
+    fn cull_on_gpu(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        frustum: &Frustum,
+    ) {
+        self.draws.clear();
+        for (shape, &first_instance) in self.shapes.iter().zip(&self.shape_starts) {
+            self.draws.push(DrawIndexedIndirect {
+                index_count: shape.index_count,
+                instance_count: 0,
+                first_index: shape.first_index,
+                base_vertex: shape.base_vertex,
+                first_instance,
+            });
+        }
+        self.draws.upload(device, encoder);
+
+        let mut planes = [Vector4::zero(); 6];
+        for (plane, frustum_plane) in planes.iter_mut().zip(&frustum.planes) {
+            *plane = frustum_plane.normal.extend(frustum_plane.distance);
+        }
+        let mut shape_starts = [0; MAX_SHAPES];
+        shape_starts[..self.shape_starts.len()].copy_from_slice(&self.shape_starts);
+        let uniforms = CullUniforms {
+            planes,
+            shape_starts,
+            count: self.instance_count as u32,
+            shape_count: self.shapes.len() as u32,
+            radius: SHAPE_RADIUS,
+            culling: self.culling as u32,
+        };
+        uniform_pool.write(device, encoder, &self.cull_allocation, &uniforms.std140());
+        compute::dispatch(
+            encoder,
+            &self.cull_pipeline,
+            &[
+                &self.cull_bind_group,
+                &self.instances_bind_group,
+                &self.draws_bind_group,
+            ],
+            [
+                compute::workgroups(self.instance_count as u32, WORKGROUP_SIZE),
+                1,
+                1,
+            ],
+        );
+    }
+}
+
+impl Demo for IndirectDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+
+        let view_proj = self.camera.build_view_projection_matrix();
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &ShapeUniforms { view_proj }.std140(),
+        );
+
+        let frustum = Frustum::from_view_proj(&view_proj);
+        if self.gpu_culling {
+            self.cull_on_gpu(device, encoder, uniform_pool, &frustum);
+        } else {
+            self.cull_on_cpu(device, encoder, &frustum);
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.gpu_culling {
+            ui.label(format!(
+                "{} shapes in {} draws, counted on the GPU",
+                self.instance_count,
+                self.draws.commands().len()
+            ));
+        } else {
+            ui.label(format!(
+                "{} of {} shapes in {} draws",
+                self.drawn,
+                self.instance_count,
+                self.draws.commands().len()
+            ));
+        }
+        ui.checkbox(&mut self.gpu_culling, "Cull on the GPU");
+        ui.add_enabled(
+            !self.gpu_culling,
+            egui::Checkbox::new(&mut self.use_indirect, "Indirect draws"),
+        );
+        ui.checkbox(&mut self.culling, "Frustum culling");
+        ui.checkbox(&mut self.depth_prepass, "Depth pre-pass")
+            .on_hover_text("Compare the frame times with it on and off");
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let depth_attachment = if self.depth_prepass {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[],
+                depth_stencil_attachment: Some(self.depth.attachment()),
+            });
+            render_pass.push_debug_group("Depth prepass");
+            render_pass.set_pipeline(&self.depth_prepass_pipeline);
+            self.draw_shapes(&mut render_pass);
+            render_pass.pop_debug_group();
+            self.depth.load_attachment()
+        } else {
+            self.depth.attachment()
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(depth_attachment),
+        });
+        if self.depth_prepass {
+            render_pass.set_pipeline(&self.after_prepass_pipeline);
+        } else {
+            render_pass.set_pipeline(&self.pipeline);
+        }
+        self.draw_shapes(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        ctx.uniform_pool.free(self.uniform_allocation);
+        ctx.uniform_pool.free(self.cull_allocation);
+        ctx.bind_groups.invalidate("shape_uniforms");
+        ctx.bind_groups.invalidate("shape_culling");
+        ctx.bind_groups.invalidate("shape_instances");
+        ctx.bind_groups.invalidate("shape_draws");
+    }
+}
@@ -0,0 +1,235 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(gilrs::Button),
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::Mouse(button) => write!(f, "Mouse {:?}", button),
+            Binding::Gamepad(button) => write!(f, "Pad {:?}", button),
+        }
+    }
+}
+
+fn default_bindings() -> BTreeMap<String, Vec<Binding>> {
+    use gilrs::Button::*;
+    use Binding::{Gamepad, Key, Mouse};
+    use VirtualKeyCode::*;
+
+    let bindings = vec![
+        ("quit", vec![Key(Escape)]),
+        ("select", vec![Mouse(MouseButton::Left)]),
+        ("focus", vec![Mouse(MouseButton::Right)]),
+        ("place", vec![Mouse(MouseButton::Right)]),
+        ("screenshot", vec![Key(F12), Gamepad(Select)]),
+        ("toggle_recording", vec![Key(F10)]),
+        ("toggle_camera_recording", vec![Key(F5)]),
+        ("toggle_camera_playback", vec![Key(F6)]),
+        ("cycle_present_mode", vec![Key(F9)]),
+        ("toggle_debug_window", vec![Key(F8), Gamepad(Start)]),
+        ("toggle_debug_text", vec![Key(F7)]),
+        ("cycle_window_mode", vec![Key(F11)]),
+        // Only with Alt held
+        ("toggle_fullscreen", vec![Key(Return)]),
+        ("renderdoc_capture", vec![Key(F4)]),
+        ("gizmo_translate", vec![Key(Key1)]),
+        ("gizmo_rotate", vec![Key(Key2)]),
+        ("gizmo_scale", vec![Key(Key3)]),
+        ("render_mode_solid", vec![Key(Key4)]),
+        ("render_mode_wireframe", vec![Key(Key5)]),
+        ("render_mode_normals", vec![Key(Key6)]),
+        ("render_mode_uv_checker", vec![Key(Key7)]),
+        ("render_mode_flat_white", vec![Key(Key8)]),
+        ("camera_forward", vec![Key(W), Key(Up), Gamepad(DPadUp)]),
+        ("camera_back", vec![Key(S), Key(Down), Gamepad(DPadDown)]),
+        ("camera_left", vec![Key(A), Key(Left), Gamepad(DPadLeft)]),
+        ("camera_right", vec![Key(D), Key(Right), Gamepad(DPadRight)]),
+        ("camera_up", vec![Key(E), Gamepad(RightTrigger)]),
+        ("camera_down", vec![Key(Q), Gamepad(LeftTrigger)]),
+        ("camera_zoom_in", vec![Key(Equals), Key(Add)]),
+        ("camera_zoom_out", vec![Key(Minus), Key(Subtract)]),
+        ("toggle_projection", vec![Key(P)]),
+        ("toggle_pause", vec![Key(Space)]),
+        ("step_simulation", vec![Key(Period)]),
+        ("slow_down", vec![Key(LBracket)]),
+        ("speed_up", vec![Key(RBracket)]),
+        ("character_forward", vec![Key(I)]),
+        ("character_turn_left", vec![Key(J)]),
+        ("character_turn_right", vec![Key(L)]),
+        ("character_run", vec![Key(LShift)]),
+        ("character_wave", vec![Key(U)]),
+    ];
+
+    bindings
+        .into_iter()
+        .map(|(action, keys)| (action.to_string(), keys))
+        .collect()
+}
+
+fn binding_state(event: &WindowEvent) -> Option<(Binding, ElementState)> {
+    match event {
+        WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+            ..
+        } => Some((Binding::Key(*keycode), *state)),
+        WindowEvent::MouseInput { state, button, .. } => Some((Binding::Mouse(*button), *state)),
+        _ => None,
+    }
+}
+
+// Maps keys, mouse and gamepad buttons to named actions. Events come in through `handle_event`, the
+// action state gets queried during the frame and `end_frame` forgets this frame's presses and
+// releases.
+pub struct Input {
+    bindings: BTreeMap<String, Vec<Binding>>,
+    held: HashSet<Binding>,
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    // None while it's outside of the window
+    cursor: Option<PhysicalPosition<i32>>,
+}
+
+impl Input {
+    // `config` replaces the default bindings of the actions it mentions
+    pub fn new(config: Option<&Value>) -> Self {
+        let mut bindings = default_bindings();
+        if let Some(config) = config {
+            match serde_json::from_value::<BTreeMap<String, Vec<Binding>>>(config.clone()) {
+                Ok(overrides) => bindings.extend(overrides),
+                Err(err) => warn!("Ignoring input bindings: {}", err),
+            }
+        }
+
+        Self {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            cursor: None,
+        }
+    }
+
+    // For writing back to the config file, so the bindings can be edited there
+    pub fn bindings(&self) -> Value {
+        serde_json::to_value(&self.bindings).unwrap()
+    }
+
+    // One line per action, with everything it's bound to
+    pub fn help(&self) -> String {
+        self.bindings
+            .iter()
+            .map(|(action, bindings)| {
+                let bindings: Vec<_> = bindings.iter().map(|b| b.to_string()).collect();
+                format!("{}: {}", action, bindings.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Returns true if the event was bound to an action
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Focused(false) => {
+                // We won't hear about releases while unfocused
+                self.release_all();
+                return false;
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(*position);
+                return false;
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                return false;
+            }
+            _ => (),
+        }
+
+        let (binding, state) = match binding_state(event) {
+            Some(binding_state) => binding_state,
+            None => return false,
+        };
+        self.set_state(binding, state);
+
+        self.bindings.values().any(|keys| keys.contains(&binding))
+    }
+
+    pub fn handle_gamepad_button(&mut self, button: gilrs::Button, state: ElementState) {
+        self.set_state(Binding::Gamepad(button), state);
+    }
+
+    // For events the UI took, presses belong to it but a release still has to let go of the key
+    pub fn handle_release(&mut self, event: &WindowEvent) {
+        if let Some((binding, ElementState::Released)) = binding_state(event) {
+            self.release(binding);
+        }
+    }
+
+    fn set_state(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            // Key repeats don't count as new presses
+            ElementState::Pressed => {
+                if self.held.insert(binding) {
+                    self.pressed.insert(binding);
+                }
+            }
+            ElementState::Released => self.release(binding),
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    fn release(&mut self, binding: Binding) {
+        if self.held.remove(&binding) {
+            self.released.insert(binding);
+        }
+    }
+
+    fn any(&self, action: &str, set: &HashSet<Binding>) -> bool {
+        self.bindings
+            .get(action)
+            .is_some_and(|keys| keys.iter().any(|key| set.contains(key)))
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.any(action, &self.held)
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.any(action, &self.pressed)
+    }
+
+    // Nothing built in acts on releases yet
+    #[allow(dead_code)]
+    pub fn released(&self, action: &str) -> bool {
+        self.any(action, &self.released)
+    }
+
+    pub fn cursor(&self) -> Option<PhysicalPosition<i32>> {
+        self.cursor
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
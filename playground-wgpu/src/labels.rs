@@ -0,0 +1,454 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use crate::texture::Texture;
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector4, Zero};
+use image::{Rgba, RgbaImage};
+use playground_math::{Block, BlockWriter, Camera, Color, Layout};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, Binding, BindingResource, BlendDescriptor, BlendFactor, BlendOperation,
+    Buffer, BufferAddress, BufferUsage, ColorStateDescriptor, ColorWrite, CommandEncoder,
+    CompareFunction, CullMode, Device, FilterMode, IndexFormat, InputStepMode, PrimitiveTopology,
+    Queue, RenderPass, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, TextureFormat,
+    VertexAttributeDescriptor, VertexFormat,
+};
+
+const LABEL_VERT: Shader = Shader {
+    name: "label.vert",
+    source: include_str!("../shaders/label.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const LABEL_FRAG: Shader = Shader {
+    name: "label.frag",
+    source: include_str!("../shaders/label.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// The atlas holds a distance field of the 128 ASCII glyphs of font8x8, 16 per row. Every glyph
+// gets padding around it for outlines and shadows, the field covers `SPREAD` font pixels on
+// either side of the edge.
+const GLYPH_SIZE: u32 = 8;
+const PADDING: u32 = 2;
+const SPREAD: f32 = 2.0;
+const TEXELS_PER_PIXEL: u32 = 4;
+const CELL_SIZE: u32 = (GLYPH_SIZE + 2 * PADDING) * TEXELS_PER_PIXEL;
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 8;
+
+const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+fn glyph_pixel(glyph: &[u8; 8], x: i32, y: i32) -> bool {
+    // The lowest bit is the leftmost pixel
+    (0..8).contains(&x) && (0..8).contains(&y) && glyph[y as usize] & (1 << x) != 0
+}
+
+// From a point to the closest point of a pixel's square, in font pixels
+fn pixel_distance(px: f32, py: f32, x: i32, y: i32) -> f32 {
+    let dx = (x as f32 - px).max(px - (x + 1) as f32).max(0.0);
+    let dy = (y as f32 - py).max(py - (y + 1) as f32).max(0.0);
+    (dx * dx + dy * dy).sqrt()
+}
+
+// Distance to the glyph's outline, negative inside and clamped to `SPREAD`. The glyph is made
+// of square pixels, so this is exact instead of an estimate from a supersampled bitmap.
+fn signed_distance(glyph: &[u8; 8], px: f32, py: f32) -> f32 {
+    let (cx, cy) = (px.floor() as i32, py.floor() as i32);
+    let inside = glyph_pixel(glyph, cx, cy);
+
+    let reach = SPREAD.ceil() as i32 + 1;
+    let mut closest = SPREAD;
+    for y in cy - reach..=cy + reach {
+        for x in cx - reach..=cx + reach {
+            if glyph_pixel(glyph, x, y) != inside {
+                closest = closest.min(pixel_distance(px, py, x, y));
+            }
+        }
+    }
+
+    if inside {
+        -closest
+    } else {
+        closest
+    }
+}
+
+// White texels with the distance in alpha, 0.5 on the edge and higher inside
+fn distance_atlas() -> RgbaImage {
+    RgbaImage::from_fn(ATLAS_COLUMNS * CELL_SIZE, ATLAS_ROWS * CELL_SIZE, |x, y| {
+        let glyph = &font8x8::legacy::BASIC_LEGACY
+            [(y / CELL_SIZE * ATLAS_COLUMNS + x / CELL_SIZE) as usize];
+        if glyph.iter().all(|&row| row == 0) {
+            return Rgba([255, 255, 255, 0]);
+        }
+
+        // Texel centers in font pixels, relative to the glyph's top left corner
+        let px = ((x % CELL_SIZE) as f32 + 0.5) / TEXELS_PER_PIXEL as f32 - PADDING as f32;
+        let py = ((y % CELL_SIZE) as f32 + 0.5) / TEXELS_PER_PIXEL as f32 - PADDING as f32;
+        let value = 0.5 - signed_distance(glyph, px, py) / (2.0 * SPREAD);
+        Rgba([255, 255, 255, (value.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+// How a label looks. Colors are linear.
+#[derive(Clone, Debug)]
+pub struct LabelStyle {
+    // Height of a line of text in world units
+    pub size: f32,
+    pub color: Color,
+    pub outline_color: Color,
+    // In font pixels (a glyph is 8 of them high), up to 2
+    pub outline_width: f32,
+    pub shadow_color: Color,
+    // Right and down, as a fraction of `size`
+    pub shadow_offset: [f32; 2],
+    // In font pixels
+    pub shadow_softness: f32,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        Self {
+            size: 0.1,
+            color: Color::WHITE,
+            outline_color: Color::BLACK,
+            outline_width: 0.75,
+            shadow_color: Color::new(0.0, 0.0, 0.0, 0.5),
+            shadow_offset: [0.1, 0.1],
+            shadow_softness: 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct LabelUniforms {
+    view_proj: Matrix4<f32>,
+    camera_right: Vector4<f32>,
+    camera_up: Vector4<f32>,
+}
+
+impl Block for LabelUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.camera_right);
+        writer.member(&self.camera_up);
+    }
+}
+
+impl Default for LabelUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            camera_right: Vector4::zero(),
+            camera_up: Vector4::zero(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct LabelVertex {
+    anchor: [f32; 3],
+    // In the plane facing the camera, in world units
+    offset: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+    outline_color: [f32; 4],
+    // Outline width and softness, in distance field units
+    style: [f32; 2],
+}
+
+unsafe impl bytemuck::Pod for LabelVertex {}
+
+unsafe impl bytemuck::Zeroable for LabelVertex {}
+
+fn vertex_layout() -> VertexLayout {
+    let attribute = |offset: usize, shader_location, format| VertexAttributeDescriptor {
+        offset: offset as BufferAddress,
+        shader_location,
+        format,
+    };
+
+    VertexLayout {
+        stride: mem::size_of::<LabelVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            attribute(0, 0, VertexFormat::Float3),
+            attribute(12, 1, VertexFormat::Float2),
+            attribute(20, 2, VertexFormat::Float2),
+            attribute(28, 3, VertexFormat::Float4),
+            attribute(44, 4, VertexFormat::Float4),
+            attribute(60, 5, VertexFormat::Float2),
+        ],
+    }
+}
+
+// Everything a single run of glyph quads shares
+struct GlyphRun {
+    shift: [f32; 2],
+    color: Color,
+    outline_color: Color,
+    outline_width: f32,
+    softness: f32,
+}
+
+// Text labels in the world, billboarded toward the camera. Drawn from a signed distance field
+// so they stay crisp at any distance and can have outlines and soft shadows. Labels get queued
+// every frame, `prepare` uploads them and `render` draws them into the demo's render pass.
+pub struct LabelRenderer {
+    quads: Vec<[LabelVertex; 4]>,
+    // Vertex buffer, index buffer and index count of the last `prepare`
+    batch: Option<(Buffer, Buffer, u32)>,
+
+    pipeline: Arc<RenderPipeline>,
+    // Only kept alive for `atlas_bind_group`
+    _atlas: Texture,
+    _sampler: Sampler,
+    atlas_bind_group: Arc<BindGroup>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+impl LabelRenderer {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let (atlas, cmd_buffer) =
+            Texture::from_rgba(device, &distance_atlas(), TextureFormat::Rgba8Unorm, 1);
+        queue.submit(&[cmd_buffer]);
+
+        // Distance fields need linear filtering in both directions
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let atlas_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::TEXTURE_LAYOUT,
+            "label_atlas",
+            &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&atlas.view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            LabelUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "label_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: LABEL_VERT,
+                fragment_shader: Some(LABEL_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                ],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint32,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ALPHA_BLEND,
+                    alpha_blend: ALPHA_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            quads: Vec::new(),
+            batch: None,
+            pipeline,
+            _atlas: atlas,
+            _sampler: sampler,
+            atlas_bind_group,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+
+    // Queues `text` centered on `anchor`. Only ASCII is supported, anything else shows up as a
+    // question mark.
+    pub fn queue(&mut self, anchor: Point3<f32>, text: &str, style: &LabelStyle) {
+        // Both are fractions of the field's range of 2 * SPREAD font pixels
+        let outline_width = style.outline_width.clamp(0.0, SPREAD) / (2.0 * SPREAD);
+        let shadow_softness = style.shadow_softness.max(0.0) / (2.0 * SPREAD);
+
+        if style.shadow_color.a > 0.0 {
+            let shadow = GlyphRun {
+                shift: [
+                    style.shadow_offset[0] * style.size,
+                    -style.shadow_offset[1] * style.size,
+                ],
+                color: style.shadow_color,
+                outline_color: style.shadow_color,
+                outline_width,
+                softness: shadow_softness,
+            };
+            self.queue_glyphs(anchor, text, style.size, &shadow);
+        }
+
+        let glyphs = GlyphRun {
+            shift: [0.0, 0.0],
+            color: style.color,
+            outline_color: style.outline_color,
+            outline_width,
+            softness: 0.0,
+        };
+        self.queue_glyphs(anchor, text, style.size, &glyphs);
+    }
+
+    fn queue_glyphs(&mut self, anchor: Point3<f32>, text: &str, size: f32, run: &GlyphRun) {
+        let pixel = size / GLYPH_SIZE as f32;
+        let cell = CELL_SIZE as f32 / TEXELS_PER_PIXEL as f32 * pixel;
+        let line_height = size * 1.25;
+        let height = text.lines().count() as f32 * line_height;
+        let du = 1.0 / ATLAS_COLUMNS as f32;
+        let dv = 1.0 / ATLAS_ROWS as f32;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let width = line.chars().count() as f32 * size;
+            let left = -width / 2.0 + run.shift[0] - PADDING as f32 * pixel;
+            let top = height / 2.0 - line_index as f32 * line_height
+                + run.shift[1]
+                + PADDING as f32 * pixel;
+
+            for (column, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let glyph = if c.is_ascii() { c as u32 } else { '?' as u32 };
+                let u = (glyph % ATLAS_COLUMNS) as f32 * du;
+                let v = (glyph / ATLAS_COLUMNS) as f32 * dv;
+                let cell_left = left + column as f32 * size;
+
+                let vertex = |dx: f32, dy: f32| LabelVertex {
+                    anchor: anchor.into(),
+                    offset: [cell_left + dx * cell, top - dy * cell],
+                    tex_coords: [u + dx * du, v + dy * dv],
+                    color: run.color.to_array(),
+                    outline_color: run.outline_color.to_array(),
+                    style: [run.outline_width, run.softness],
+                };
+                self.quads.push([
+                    vertex(0.0, 0.0),
+                    vertex(1.0, 0.0),
+                    vertex(1.0, 1.0),
+                    vertex(0.0, 1.0),
+                ]);
+            }
+        }
+    }
+
+    // Uploads everything queued since the last call, for the next `render`
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+    ) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let uniforms = LabelUniforms {
+            view_proj: camera.build_view_projection_matrix(),
+            camera_right: right.extend(0.0),
+            camera_up: up.extend(0.0),
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+
+        let quads = mem::take(&mut self.quads);
+        if quads.is_empty() {
+            self.batch = None;
+            return;
+        }
+
+        let indices: Vec<u32> = (0..quads.len() as u32)
+            .flat_map(|quad| {
+                let first = quad * 4;
+                vec![first, first + 1, first + 2, first, first + 2, first + 3]
+            })
+            .collect();
+        let vertex_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&quads), BufferUsage::VERTEX);
+        let index_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&indices), BufferUsage::INDEX);
+        self.batch = Some((vertex_buffer, index_buffer, indices.len() as u32));
+    }
+
+    // Draws what was prepared, on top of whatever the pass drew before
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        let (vertex_buffer, index_buffer, num_indices) = match &self.batch {
+            Some(batch) => batch,
+            None => return,
+        };
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(index_buffer, 0, 0);
+        draw_stats::record_triangles(*num_indices, 1);
+        render_pass.draw_indexed(0..*num_indices, 0, 0..1);
+    }
+
+    // Gives the uniform block back and drops the cached bind groups, which point at it and at
+    // this renderer's atlas
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("label_atlas");
+        bind_groups.invalidate("label_uniforms");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::primitives::{self, CubeInstance, Cubes};
+use crate::shadow::{self, ShadowMap};
+use crate::sky::Sky;
+use crate::uniform::{self, LightUniforms, Uniforms};
+use crate::volumetric::VolumetricLight;
+use cgmath::{Point3, Vector3};
+use playground_math::{sun, Block, Camera, Layout, Projection, Sphere};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+// Half the room's width and depth, and its height
+const ROOM_HALF_SIZE: i32 = 5;
+const ROOM_HEIGHT: i32 = 4;
+
+// A closed room out of unit cubes on a big floor cube, with two windows in the wall at -x
+fn room() -> Vec<CubeInstance> {
+    let wall = [0.75, 0.72, 0.68, 1.0];
+    let mut instances = vec![CubeInstance {
+        offset_scale: [0.0, -6.0, 0.0, 12.0],
+        color: [0.6, 0.55, 0.5, 1.0],
+    }];
+    let mut block = |x: f32, y: f32, z: f32| {
+        instances.push(CubeInstance {
+            offset_scale: [x, y, z, 1.0],
+            color: wall,
+        })
+    };
+    // Two units wide and high, either side of the middle
+    let is_window =
+        |row: i32, offset: f32| (1..=2).contains(&row) && (1.0..3.0).contains(&offset.abs());
+
+    // Cubes centered half a unit off the grid, the walls just outside the room's floor
+    let half = ROOM_HALF_SIZE as f32 + 0.5;
+    for row in 0..ROOM_HEIGHT {
+        let y = row as f32 + 0.5;
+        for along in -ROOM_HALF_SIZE - 1..=ROOM_HALF_SIZE {
+            let offset = along as f32 + 0.5;
+            if !is_window(row, offset) {
+                block(-half, y, offset);
+            }
+            block(half, y, offset);
+            block(offset, y, -half);
+            block(offset, y, half);
+        }
+    }
+    for x in -ROOM_HALF_SIZE..ROOM_HALF_SIZE {
+        for z in -ROOM_HALF_SIZE..ROOM_HALF_SIZE {
+            block(x as f32 + 0.5, ROOM_HEIGHT as f32 + 0.5, z as f32 + 0.5);
+        }
+    }
+    instances
+}
+
+// Sunlight falling into a dusty room through its windows, with the sky outside. The sun's shadow
+// map shades the room, and gets marched through for the light shafts.
+pub struct LightShaftsDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    // In hours, which puts the sun in the sky and colors its light
+    time_of_day: f32,
+    animate: bool,
+    hours_per_second: f32,
+    sun_intensity: f32,
+    ambient: f32,
+    shadow_map: ShadowMap,
+    volumetric: VolumetricLight,
+    sky: Sky,
+
+    caster_pipeline: Arc<RenderPipeline>,
+    pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    camera_allocation: Allocation,
+    camera_bind_group: Arc<BindGroup>,
+    light_allocation: Allocation,
+    light_bind_group: Arc<BindGroup>,
+}
+
+impl LightShaftsDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (4.0, 2.0, 4.0).into(),
+            target: (-3.0, 1.2, -1.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 60.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let mut instances = room();
+        instances.extend(primitives::ring_of_cubes(6, 2.5));
+
+        let caster_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &shadow::casting(&Cubes::pipeline_key(ctx.format)),
+        );
+        let pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &Cubes::shadowed_pipeline_key(ctx.format),
+        );
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            "light_shafts_camera",
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (light_allocation, light_bind_group) = uniform::allocate(
+            ctx,
+            "light_shafts_light",
+            LightUniforms::size(Layout::Std140),
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+        );
+        let shadow_map = ShadowMap::new(device, ctx.uniform_pool, ctx.bind_groups, SHADOW_MAP_SIZE);
+        let volumetric = VolumetricLight::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            ctx.size,
+        );
+        let sky = Sky::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            // The afternoon sun shines in through the windows
+            time_of_day: 16.0,
+            animate: false,
+            hours_per_second: 0.5,
+            sun_intensity: 3.0,
+            ambient: 0.05,
+            shadow_map,
+            volumetric,
+            sky,
+            caster_pipeline,
+            pipeline,
+            cubes: Cubes::new(device, &instances),
+            camera_allocation,
+            camera_bind_group,
+            light_allocation,
+            light_bind_group,
+        }
+    }
+}
+
+impl Demo for LightShaftsDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        if self.animate {
+            self.time_of_day = (self.time_of_day + dt * self.hours_per_second) % 24.0;
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        // The sky looks towards the sun, the light travels away from it
+        let towards_sun = sun::direction(self.time_of_day);
+        let direction = -towards_sun;
+        let transmittance = sun::transmittance(towards_sun);
+        let color = [transmittance.r, transmittance.g, transmittance.b];
+        // The room and a bit of the ground around it
+        let bounds = Sphere::new(Point3::new(0.0, 2.0, 0.0), 9.0);
+        self.shadow_map
+            .prepare(device, encoder, uniform_pool, direction, &bounds);
+        self.volumetric.prepare(
+            device,
+            encoder,
+            uniform_pool,
+            self.size,
+            &self.camera,
+            direction,
+            color,
+        );
+        self.sky
+            .prepare(device, encoder, uniform_pool, &self.camera, towards_sun);
+
+        let mut camera_uniforms = Uniforms::new();
+        camera_uniforms.update_view_proj(&self.camera);
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.camera_allocation,
+            &camera_uniforms.std140(),
+        );
+
+        let light = LightUniforms::sun(self.time_of_day, self.sun_intensity, self.ambient);
+        uniform_pool.write(device, encoder, &self.light_allocation, &light.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.time_of_day, 0.0..=24.0).text("Time of day (h)"));
+        ui.checkbox(&mut self.animate, "Animate");
+        if self.animate {
+            ui.add(
+                egui::Slider::new(&mut self.hours_per_second, 0.05..=4.0).text("Hours per second"),
+            );
+        }
+        ui.add(egui::Slider::new(&mut self.sun_intensity, 0.0..=10.0).text("Sun intensity"));
+        ui.add(egui::Slider::new(&mut self.ambient, 0.0..=0.5).text("Ambient"));
+        self.sky.ui(ui);
+        ui.separator();
+        self.shadow_map.ui(ui);
+        ui.separator();
+        self.volumetric.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[],
+            depth_stencil_attachment: Some(self.shadow_map.attachment()),
+        });
+        render_pass.push_debug_group("Sun's shadow map");
+        render_pass.set_pipeline(&self.caster_pipeline);
+        render_pass.set_bind_group(0, self.shadow_map.caster_bind_group(), &[]);
+        self.cubes.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.volumetric.depth_attachment()),
+        });
+        render_pass.push_debug_group("Shadowed room");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, self.shadow_map.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+        self.cubes.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        render_pass.push_debug_group("Sky");
+        self.sky.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        self.volumetric.render(encoder, target, &self.shadow_map);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.shadow_map.release(ctx.uniform_pool, ctx.bind_groups);
+        self.volumetric.release(ctx.uniform_pool, ctx.bind_groups);
+        self.sky.release(ctx.uniform_pool, ctx.bind_groups);
+        ctx.uniform_pool.free(self.camera_allocation);
+        ctx.uniform_pool.free(self.light_allocation);
+        ctx.bind_groups.invalidate("light_shafts_camera");
+        ctx.bind_groups.invalidate("light_shafts_light");
+    }
+}
@@ -0,0 +1,363 @@
+use crate::billboard::{self, Billboard};
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::StorageBuffer;
+use crate::debug_draw;
+use cgmath::{Angle, Deg, InnerSpace, Point3, Vector3};
+use playground_math::{Block, BlockWriter, Color, Layout};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource, BufferAddress,
+    BufferUsage, CommandEncoder, Device,
+};
+
+// Space for this many lights at first, the buffer grows when more get added
+const INITIAL_CAPACITY: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    // Lights everything from `direction`, like the sun
+    Directional,
+    // Lights everything around `position` up to `range`
+    Point,
+    // A point light only lighting a cone around `direction`
+    Spot,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 3] = [LightKind::Directional, LightKind::Point, LightKind::Spot];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LightKind::Directional => "Directional",
+            LightKind::Point => "Point",
+            LightKind::Spot => "Spot",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    // Full brightness inside the inner angle, fading out toward the outer one
+    pub inner_angle: Deg<f32>,
+    pub outer_angle: Deg<f32>,
+}
+
+impl Light {
+    // A white light of `kind` above the origin, pointing down
+    pub fn new(kind: LightKind) -> Self {
+        Self {
+            kind,
+            position: Point3::new(0.0, 3.0, 0.0),
+            direction: -Vector3::unit_y(),
+            color: [1.0; 3],
+            intensity: if kind == LightKind::Directional {
+                1.0
+            } else {
+                10.0
+            },
+            range: 10.0,
+            inner_angle: Deg(20.0),
+            outer_angle: Deg(30.0),
+        }
+    }
+
+    // Straight down while it's dragged through zero in the UI
+    fn direction(&self) -> Vector3<f32> {
+        if self.direction.magnitude2() > 1e-6 {
+            self.direction.normalize()
+        } else {
+            -Vector3::unit_y()
+        }
+    }
+
+    fn to_gpu(&self) -> GpuLight {
+        let direction = self.direction();
+        let [r, g, b] = self.color;
+        let outer_angle = Deg(self.outer_angle.0.max(self.inner_angle.0));
+        GpuLight {
+            position_kind: [
+                self.position.x,
+                self.position.y,
+                self.position.z,
+                self.kind as u32 as f32,
+            ],
+            direction_range: [direction.x, direction.y, direction.z, self.range.max(1e-3)],
+            color_intensity: [r, g, b, self.intensity],
+            cone: [self.inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Kind")
+            .selected_text(self.kind.name())
+            .show_ui(ui, |ui| {
+                for &kind in &LightKind::ALL {
+                    ui.selectable_value(&mut self.kind, kind, kind.name());
+                }
+            });
+        if self.kind != LightKind::Directional {
+            ui.horizontal(|ui| {
+                ui.label("Position");
+                ui.add(egui::DragValue::new(&mut self.position.x).speed(0.05));
+                ui.add(egui::DragValue::new(&mut self.position.y).speed(0.05));
+                ui.add(egui::DragValue::new(&mut self.position.z).speed(0.05));
+            });
+        }
+        if self.kind != LightKind::Point {
+            ui.horizontal(|ui| {
+                ui.label("Direction");
+                ui.add(egui::DragValue::new(&mut self.direction.x).speed(0.02));
+                ui.add(egui::DragValue::new(&mut self.direction.y).speed(0.02));
+                ui.add(egui::DragValue::new(&mut self.direction.z).speed(0.02));
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_rgb(&mut self.color);
+        });
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=50.0).text("Intensity"));
+        if self.kind != LightKind::Directional {
+            ui.add(egui::Slider::new(&mut self.range, 0.5..=50.0).text("Range"));
+        }
+        if self.kind == LightKind::Spot {
+            ui.add(egui::Slider::new(&mut self.inner_angle.0, 0.0..=90.0).text("Inner angle"));
+            ui.add(egui::Slider::new(&mut self.outer_angle.0, 0.0..=90.0).text("Outer angle"));
+        }
+    }
+
+    // An icon where the light is, in its color, to go with the lines `draw_gizmo` draws. Needs
+    // `billboard::LIGHT_CELL` from `Atlas::generated`.
+    pub fn icon(&self) -> Billboard {
+        let [r, g, b] = self.color;
+        Billboard {
+            position: self.gizmo_position(),
+            size: [0.5, 0.5],
+            cell: billboard::LIGHT_CELL,
+            color: Color::new(r, g, b, 1.0),
+        }
+    }
+
+    // Directional lights have no position, so they get shown somewhere above the scene
+    fn gizmo_position(&self) -> Point3<f32> {
+        match self.kind {
+            LightKind::Directional => Point3::new(0.0, 5.0, 0.0),
+            LightKind::Point | LightKind::Spot => self.position,
+        }
+    }
+
+    // Where the light is and where it points, in its color
+    fn draw_gizmo(&self) {
+        let [r, g, b] = self.color;
+        let color = Color::new(r, g, b, 1.0);
+        let direction = self.direction();
+        match self.kind {
+            LightKind::Directional => {
+                let from = self.gizmo_position();
+                debug_draw::line(from, from + direction, color);
+            }
+            LightKind::Point => debug_draw::sphere(self.position, 0.15, color),
+            LightKind::Spot => {
+                debug_draw::sphere(self.position, 0.15, color);
+                debug_draw::line(self.position, self.position + direction, color);
+            }
+        }
+    }
+}
+
+// One light as `lit.frag` reads it
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GpuLight {
+    position_kind: [f32; 4],
+    direction_range: [f32; 4],
+    color_intensity: [f32; 4],
+    cone: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for GpuLight {}
+
+unsafe impl bytemuck::Zeroable for GpuLight {}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct LightUniforms {
+    count: u32,
+    ambient: f32,
+    pulse: f32,
+}
+
+impl Block for LightUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.count);
+        writer.member(&self.ambient);
+        writer.member(&self.pulse);
+    }
+}
+
+fn create_storage(device: &Device, capacity: usize) -> StorageBuffer {
+    StorageBuffer::new(
+        device,
+        &vec![0; capacity * mem::size_of::<GpuLight>()],
+        BufferUsage::COPY_DST,
+    )
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    uniform_pool: &BufferPool,
+    uniform_allocation: &Allocation,
+    storage: &StorageBuffer,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        layout,
+        bindings: &[
+            Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            },
+            storage.binding(1),
+        ],
+        label: Some("lights"),
+    })
+}
+
+// Any number of directional, point and spot lights, in a storage buffer next to a uniform with
+// their count. Shaders loop over them, like `lit.frag`, with `bind_group` at the set laid out as a
+// `bind_group::FRAGMENT_LIST_LAYOUT`. Lights can be added, removed and changed at any time, they
+// get uploaded in `update`.
+pub struct Lights {
+    pub lights: Vec<Light>,
+    // Added to every light, so nothing is completely dark
+    pub ambient: f32,
+    // How far the ambient light swells and fades with the frame clock
+    pub pulse: f32,
+    storage: StorageBuffer,
+    capacity: usize,
+    uniform_allocation: Allocation,
+    layout: Arc<BindGroupLayout>,
+    bind_group: BindGroup,
+}
+
+impl Lights {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        lights: Vec<Light>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "lights", bind_group::FRAGMENT_LIST_LAYOUT);
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            LightUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let capacity = lights.len().max(INITIAL_CAPACITY).next_power_of_two();
+        let storage = create_storage(device, capacity);
+        let bind_group =
+            create_bind_group(device, &layout, uniform_pool, &uniform_allocation, &storage);
+
+        Self {
+            lights,
+            ambient: 0.1,
+            pulse: 0.0,
+            storage,
+            capacity,
+            uniform_allocation,
+            layout,
+            bind_group,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.ambient, 0.0..=1.0).text("Ambient"));
+        ui.add(egui::Slider::new(&mut self.pulse, 0.0..=1.0).text("Ambient pulse"));
+
+        let mut removed = None;
+        for (index, light) in self.lights.iter_mut().enumerate() {
+            let title = format!("Light {} ({})", index, light.kind.name());
+            egui::CollapsingHeader::new(title)
+                .id_salt(("light", index))
+                .show(ui, |ui| {
+                    light.ui(ui);
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+        }
+        if let Some(index) = removed {
+            self.lights.remove(index);
+        }
+
+        if ui.button("Add light").clicked() {
+            self.lights.push(Light::new(LightKind::Point));
+        }
+    }
+
+    // Uploads the lights, growing the buffer if they don't fit anymore
+    pub fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+    ) {
+        if self.lights.len() > self.capacity {
+            self.capacity = self.lights.len().next_power_of_two();
+            self.storage = create_storage(device, self.capacity);
+            self.bind_group = create_bind_group(
+                device,
+                &self.layout,
+                uniform_pool,
+                &self.uniform_allocation,
+                &self.storage,
+            );
+        }
+
+        if !self.lights.is_empty() {
+            let lights: Vec<GpuLight> = self.lights.iter().map(Light::to_gpu).collect();
+            let staging = device
+                .create_buffer_with_data(bytemuck::cast_slice(&lights), BufferUsage::COPY_SRC);
+            encoder.copy_buffer_to_buffer(
+                &staging,
+                0,
+                &self.storage.buffer,
+                0,
+                (lights.len() * mem::size_of::<GpuLight>()) as BufferAddress,
+            );
+        }
+        let uniforms = LightUniforms {
+            count: self.lights.len() as u32,
+            ambient: self.ambient,
+            pulse: self.pulse,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+
+        for light in &self.lights {
+            light.draw_gizmo();
+        }
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool) {
+        uniform_pool.free(self.uniform_allocation);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::assets::Assets;
+use crate::billboard::{Atlas, BillboardBlend, Billboards};
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::lights::{Light, LightKind, Lights};
+use crate::pipeline::{PipelineKey, Shader};
+use crate::primitives::{self, CubeInstance, Cubes};
+use crate::uniform::{self, Uniforms};
+use cgmath::{Deg, Point3, Vector3};
+use playground_math::{Block, Camera, Layout, Projection};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, ShaderStage, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const LIT_FRAG: Shader = Shader {
+    name: "lit.frag",
+    source: include_str!("../shaders/lit.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Sides of the floor, a flat cube with its top at y = 0
+const FLOOR_SIZE: f32 = 14.0;
+
+const CAMERA_KEY: &str = "lights_camera";
+
+// A ring of cubes lit by a list of lights that can be added, removed and moved around in the
+// debug window
+pub struct LightsDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    lights: Lights,
+    // Where the lights are
+    icons: Billboards,
+
+    pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    floor: Cubes,
+
+    camera_allocation: Allocation,
+    camera_bind_group: Arc<BindGroup>,
+    frame_bind_group: Arc<BindGroup>,
+}
+
+impl LightsDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 6.0, 11.0).into(),
+            target: (0.0, 0.5, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let mut key = Cubes::pipeline_key(ctx.format);
+        key.bind_group_layouts
+            .push(bind_group::layout_key(bind_group::FRAGMENT_LIST_LAYOUT));
+        key.bind_group_layouts
+            .push(bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT));
+        let pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                fragment_shader: Some(LIT_FRAG),
+                ..key
+            },
+        );
+
+        let lights = vec![
+            Light {
+                direction: Vector3::new(-0.4, -0.8, -0.3),
+                intensity: 0.3,
+                ..Light::new(LightKind::Directional)
+            },
+            Light {
+                position: Point3::new(2.0, 1.5, 2.0),
+                color: [1.0, 0.4, 0.3],
+                ..Light::new(LightKind::Point)
+            },
+            Light {
+                position: Point3::new(-3.0, 4.0, 0.0),
+                direction: Vector3::new(0.6, -1.0, 0.0),
+                color: [0.4, 0.6, 1.0],
+                intensity: 20.0,
+                outer_angle: Deg(35.0),
+                ..Light::new(LightKind::Spot)
+            },
+        ];
+
+        let mut lights = Lights::new(device, ctx.uniform_pool, ctx.bind_groups, lights);
+        lights.pulse = 0.1;
+
+        let atlas = Atlas::generated(device, ctx.queue, ctx.bind_groups);
+        let icons = Billboards::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            Arc::new(atlas),
+            BillboardBlend::Cutout,
+        );
+
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            CAMERA_KEY,
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(device, ctx.size),
+            lights,
+            icons,
+            pipeline,
+            cubes: Cubes::new(device, &primitives::ring_of_cubes(8, 3.0)),
+            floor: Cubes::new(
+                device,
+                &[CubeInstance {
+                    offset_scale: [0.0, -FLOOR_SIZE / 2.0, 0.0, FLOOR_SIZE],
+                    color: [0.6, 0.6, 0.6, 1.0],
+                }],
+            ),
+            camera_allocation,
+            camera_bind_group,
+            frame_bind_group: ctx.frame_bind_group.clone(),
+        }
+    }
+}
+
+impl Demo for LightsDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        self.lights.update(device, encoder, uniform_pool);
+        let icons: Vec<_> = self.lights.lights.iter().map(Light::icon).collect();
+        self.icons
+            .prepare(device, encoder, uniform_pool, &self.camera, &icons);
+
+        let mut camera = Uniforms::new();
+        camera.update_view_proj(&self.camera);
+        uniform_pool.write(device, encoder, &self.camera_allocation, &camera.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.lights.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, self.lights.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.frame_bind_group, &[]);
+        self.cubes.draw(&mut render_pass);
+        self.floor.draw(&mut render_pass);
+        self.icons.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.lights.release(ctx.uniform_pool);
+        self.icons.release(ctx.uniform_pool);
+        ctx.uniform_pool.free(self.camera_allocation);
+        ctx.bind_groups.invalidate(CAMERA_KEY);
+    }
+}
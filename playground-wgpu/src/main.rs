@@ -1,142 +1,250 @@
-mod camera;
+mod assets;
+mod auto_exposure;
+mod bench;
+mod billboard;
+mod bind_group;
+mod boids_demo;
+mod buffer_inspector;
+mod buffer_pool;
+mod camera_controller;
+mod camera_path;
+mod character;
+mod color_grading;
+mod compute;
+mod culling;
+mod debug_draw;
+mod decal;
+mod decal_demo;
+mod demo;
+mod depth;
+mod dof;
+mod dof_demo;
+mod draw_stats;
+mod frame_stats;
+mod gamepad;
+mod gizmo;
+mod gpu_particles;
+mod id_buffer;
+mod imposter_demo;
+mod indirect;
+mod indirect_demo;
+mod input;
+mod labels;
+mod light_shafts_demo;
+mod lights;
+mod lights_demo;
+mod monitor_demo;
+mod morph;
+mod morph_demo;
+mod overlay;
+mod particle_demo;
+mod particles;
+mod passes;
+mod picking;
+mod pipeline;
+mod primitives;
+mod procedural_terrain;
+mod readback;
+mod options;
+mod recorder;
+mod reflection_demo;
+mod render_mode;
+mod render_target;
+mod renderdoc;
+mod sampler;
+mod scene;
+mod scheduler;
+mod screenshot;
+mod selection_outline;
+mod settings;
+mod shadow;
+mod skinning;
+mod skinning_demo;
+mod sky;
+mod ssr;
+mod ssr_demo;
+mod surface;
+mod terrain;
+mod terrain_demo;
 mod texture;
+mod text;
+mod texture_inspector;
+mod timestep;
+mod tonemap;
+mod transform_gizmo;
+mod transparency;
+mod tree_demo;
 mod uniform;
+mod video;
+mod video_demo;
+mod volumetric;
+mod window_mode;
 
 use futures::executor;
 use image::GenericImageView;
-use std::mem;
+use log::{error, info, warn};
+use std::path::PathBuf;
+use std::time::Duration;
 use wgpu::{
-    Adapter, AddressMode, BackendBit, BindGroup, BindGroupDescriptor, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, Binding, BindingResource, BindingType, BlendDescriptor, Buffer,
-    BufferAddress, BufferCopyView, BufferUsage, Color, ColorStateDescriptor, ColorWrite,
-    CommandEncoderDescriptor, CompareFunction, CullMode, Device, DeviceDescriptor, Extent3d,
-    FilterMode, FrontFace, IndexFormat, InputStepMode, LoadOp, Origin3d, PipelineLayoutDescriptor,
-    PresentMode, PrimitiveTopology, ProgrammableStageDescriptor, Queue,
-    RasterizationStateDescriptor, RenderPassColorAttachmentDescriptor, RenderPassDescriptor,
-    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerDescriptor, ShaderStage, StoreOp,
-    Surface, SwapChain, SwapChainDescriptor, Texture, TextureComponentType, TextureCopyView,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
-    TextureViewDimension, VertexAttributeDescriptor, VertexBufferDescriptor, VertexFormat,
-    VertexStateDescriptor,
+    Adapter, AddressMode, BufferCopyView, BufferUsage, Color, CommandBuffer, CommandEncoder,
+    CommandEncoderDescriptor, CompareFunction, Device, DeviceDescriptor, Extent3d, FilterMode,
+    LoadOp, Maintain, Origin3d, PresentMode, Queue, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, Sampler, SamplerDescriptor, StoreOp, Texture, TextureCopyView,
+    TextureDescriptor, TextureDimension, TextureView,
 };
-use winit::dpi::PhysicalSize;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::{Window, WindowBuilder};
-use cgmath::Vector3;
-use camera::Camera;
-use crate::uniform::Uniforms;
-
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-0.0868241, 0.49240386, 0.0],
-        tex_coords: [0.4131759, 0.00759614],
-    },
-    Vertex {
-        position: [-0.49513406, 0.06958647, 0.0],
-        tex_coords: [0.0048659444, 0.43041354],
-    },
-    Vertex {
-        position: [-0.21918549, -0.44939706, 0.0],
-        tex_coords: [0.28081453, 0.949397057],
-    },
-    Vertex {
-        position: [0.35966998, -0.3473291, 0.0],
-        tex_coords: [0.85967, 0.84732911],
-    },
-    Vertex {
-        position: [0.44147372, 0.2347359, 0.0],
-        tex_coords: [0.9414737, 0.2652641],
-    },
-];
-
-const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
-}
-
-impl Vertex {
-    fn descriptor<'a>() -> VertexBufferDescriptor<'a> {
-        VertexBufferDescriptor {
-            // How wide is the Vertex
-            stride: mem::size_of::<Vertex>() as BufferAddress,
-            // How often should it move to the next vertex
-            step_mode: InputStepMode::Vertex,
-            // Attributes of our vertex data
-            attributes: &[
-                VertexAttributeDescriptor {
-                    // Where does the attribute start?
-                    offset: 0,
-                    // Where to store the attribute, ex: layout(location=0) in vec3 x would be position
-                    shader_location: 0,
-                    // Shape of the attribute, corresponds to vec3 in shader
-                    format: VertexFormat::Float3,
-                },
-                VertexAttributeDescriptor {
-                    // Where does the attribute start?
-                    offset: mem::size_of::<[f32; 3]>() as BufferAddress,
-                    // Where to store the attribute, ex: layout(location=1) in vec3 x would be color
-                    shader_location: 1,
-                    // Shape of the attribute, corresponds to vec3 in shader
-                    format: VertexFormat::Float2,
-                },
-            ],
-        }
-    }
-}
-
-// Plain old data: Can be interpreted as &[u8]
-unsafe impl bytemuck::Pod for Vertex {}
-
-// We can use std::mem::zeroed()
-unsafe impl bytemuck::Zeroable for Vertex {}
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceEvent, Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use winit::window::{Window, WindowBuilder, WindowId};
+use assets::Assets;
+use bench::Benchmark;
+use bind_group::BindGroupCache;
+use buffer_inspector::BufferInspector;
+use buffer_pool::BufferPool;
+use camera_controller::CameraController;
+use camera_path::{CameraPath, PathAction};
+use color_grading::{ColorGrading, LutLibrary};
+use debug_draw::DebugDrawRenderer;
+use demo::{Demo, DemoContext};
+use draw_stats::DrawCounts;
+use frame_stats::FrameStats;
+use gamepad::Gamepads;
+use id_buffer::IdBuffer;
+use input::Input;
+use options::Options;
+use overlay::Overlay;
+use passes::{Passes, VignettePass};
+use pipeline::PipelineCache;
+use recorder::{Recorder, RecordingOutput};
+use render_mode::RenderMode;
+use renderdoc::RenderDoc;
+use scheduler::{Scheduler, TaskContext};
+use screenshot::Screenshot;
+use settings::SettingsStore;
+use surface::WindowSurface;
+use text::TextRenderer;
+use texture_inspector::TextureInspector;
+use timestep::FixedTimestep;
+use tonemap::Tonemapper;
+use transform_gizmo::{GizmoMode, TransformGizmo};
+use uniform::FrameClock;
+use window_mode::WindowModes;
 
 struct State {
-    surface: Surface,
     adapter: Adapter,
     device: Device,
     queue: Queue,
-    sc_desc: SwapChainDescriptor,
-    swap_chain: SwapChain,
-    size: PhysicalSize<u32>,
-    render_pipeline: RenderPipeline,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
-    num_indices: u32,
-
-    // Texture
-    diffuse_texture: texture::Texture,
-    diffuse_bind_group: BindGroup,
-
-    // Camera
-    camera: Camera,
-
-    // Uniforms
-    uniforms: Uniforms,
-    uniform_buffer: Buffer,
-    uniform_bind_group: BindGroup,
+
+    // The debug UI is drawn on top of the main window, unless it has a window of its own
+    main_window: WindowSurface,
+    debug_window: Option<WindowSurface>,
+
+    // Shared between demos
+    pipelines: PipelineCache,
+    assets: Assets,
+    bind_groups: BindGroupCache,
+    geometry_pool: BufferPool,
+    uniform_pool: BufferPool,
+    passes: Passes,
+    tonemapper: Tonemapper,
+    color_grading: ColorGrading,
+    // The LUTs in resources/luts, the picked one goes to `color_grading`
+    luts: LutLibrary,
+    // Deferred work like mip generation, run with the time left over after a frame
+    scheduler: Scheduler,
+
+    demo: Box<dyn Demo>,
+    demo_index: usize,
+    // Name of the object last clicked on
+    selected: Option<String>,
+    // Handles for moving the selected object around
+    transform_gizmo: TransformGizmo,
+    // How demos draw their meshes, picked in the debug window
+    render_mode: RenderMode,
+    // Picking reads the object ID under the cursor back from the GPU instead of casting rays
+    gpu_picking: bool,
+    id_buffer: IdBuffer,
+    pick_requested: Option<PhysicalPosition<i32>>,
+    settings: SettingsStore,
+    input: Input,
+    gamepads: Gamepads,
+    camera_controller: CameraController,
+    camera_path: CameraPath,
+    timestep: FixedTimestep,
+    frame_clock: FrameClock,
+    // Set with --bench, flies the camera instead of the controls and ends the program
+    benchmark: Option<Benchmark>,
+
+    // What the scene drew last frame
+    draw_counts: DrawCounts,
+
+    // Debug UI
+    overlay: Overlay,
+    frame_stats: FrameStats,
+    texture_inspector: TextureInspector,
+    buffer_inspector: BufferInspector,
+    clear_color: Color,
+
+    // Screenshots
+    screenshot_requested: bool,
+    screenshot: Option<Screenshot>,
+    recorder: Recorder,
+    // Only there when the program was started from RenderDoc
+    renderdoc: Option<RenderDoc>,
+    text: TextRenderer,
+    show_debug_text: bool,
+    debug_draw: DebugDrawRenderer,
 }
 
 impl State {
-    async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
-        let surface = wgpu::Surface::create(window);
-        let num_indices = INDICES.len() as u32;
-
-        let adapter = Adapter::request(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            },
-            // Vulkan + Metal + DX12 + Browser WebGPU
-            BackendBit::PRIMARY,
-        )
-        .await
-        .unwrap();
+    async fn new(window: Window, options: &Options) -> Self {
+        let surface = wgpu::Surface::create(&window);
+
+        let mut adapters = Adapter::enumerate(options.backends);
+        for (index, adapter) in adapters.iter().enumerate() {
+            let info = adapter.get_info();
+            info!(
+                "Adapter {}: {} ({:?}, {:?})",
+                index, info.name, info.device_type, info.backend
+            );
+        }
+
+        let adapter = match &options.adapter {
+            // Either an index into the list above or part of the adapter's name
+            Some(selector) => {
+                let lowercase = selector.to_lowercase();
+                let position = match selector.parse::<usize>() {
+                    Ok(index) if index < adapters.len() => Some(index),
+                    _ => adapters.iter().position(|adapter| {
+                        adapter.get_info().name.to_lowercase().contains(&lowercase)
+                    }),
+                };
+                match position {
+                    Some(index) => adapters.swap_remove(index),
+                    // A typo on the command line, reported like the other option errors
+                    None => {
+                        eprintln!("No adapter matches '{}'\n\nAvailable adapters:", selector);
+                        for (index, adapter) in adapters.iter().enumerate() {
+                            let info = adapter.get_info();
+                            eprintln!("    {}: {} ({:?})", index, info.name, info.backend);
+                        }
+                        std::process::exit(2);
+                    }
+                }
+            }
+            None => Adapter::request(
+                &wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::Default,
+                    compatible_surface: Some(&surface),
+                },
+                // Vulkan + Metal + DX12 + Browser WebGPU, unless picked on the command line
+                options.backends,
+            )
+            .await
+            .expect("No adapter found for the selected backend(s)"),
+        };
+        let info = adapter.get_info();
+        info!("Using {} ({:?})", info.name, info.backend);
 
         let (device, mut queue) = adapter
             .request_device(&DeviceDescriptor {
@@ -147,296 +255,1024 @@ impl State {
             })
             .await;
 
-        let sc_desc = SwapChainDescriptor {
-            usage: TextureUsage::OUTPUT_ATTACHMENT,
-            format: TextureFormat::Bgra8UnormSrgb,
-            width: size.width,
-            height: size.height,
-            present_mode: PresentMode::Fifo,
-        };
+        let main_window = WindowSurface::new(&device, window, surface);
+        let format = main_window.sc_desc.format;
+        let size = main_window.size;
 
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-
-        // Load the tree picture
-        let diffuse_bytes = include_bytes!("../resources/happy-tree.png");
-        let (diffuse_texture, cmd_buffer) =
-            texture::Texture::from_bytes(&device, diffuse_bytes).unwrap();
-
-        queue.submit(&[cmd_buffer]);
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                bindings: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStage::FRAGMENT,
-                        ty: BindingType::SampledTexture {
-                            multisampled: false,
-                            dimension: TextureViewDimension::D2,
-                            component_type: TextureComponentType::Uint,
-                        },
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStage::FRAGMENT,
-                        ty: BindingType::Sampler { comparison: false },
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
+        let mut bind_groups = BindGroupCache::new();
+        let mut pipelines = PipelineCache::new();
 
-        let diffuse_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            bindings: &[
-                Binding {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&diffuse_texture.view),
-                },
-                Binding {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
+        // Vertices and indices share one pool, uniform blocks get their own
+        let mut geometry_pool =
+            BufferPool::new("geometry_pool", BufferUsage::VERTEX | BufferUsage::INDEX, 1 << 20);
+        let mut uniform_pool = BufferPool::new("uniform_pool", BufferUsage::UNIFORM, 1 << 16);
+
+        let mut overlay = Overlay::new(
+            &device,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+            format,
+            size,
+            main_window.window.scale_factor() as f32,
+        );
+
+        let mut texture_inspector = TextureInspector::new(
+            &device,
+            &mut overlay,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+        );
+        let mut buffer_inspector = BufferInspector::new();
+
+        let mut passes = Passes::new();
+        // Custom passes draw into the scene before it's tonemapped
+        let vignette =
+            VignettePass::new(&device, &mut bind_groups, &mut pipelines, tonemap::HDR_FORMAT);
+        passes.add("Vignette", false, Box::new(vignette));
+
+        let resources = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        let mut assets = Assets::new(
+            &device,
+            &queue,
+            &mut geometry_pool,
+            &mut bind_groups,
+            resources,
+        );
+        for (name, texture) in assets.placeholders.named().iter() {
+            texture_inspector.register(&device, &mut overlay, name, &texture.texture, texture.info);
+        }
+
+        let scheduler = Scheduler::new(Duration::from_millis(12));
+        let frame_clock = FrameClock::new(&device, &mut uniform_pool, &mut bind_groups);
+
+        let demo_index = options.bench.as_ref().map_or(0, |bench| bench.demo);
+        let demo = (demo::DEMOS[demo_index].create)(&mut DemoContext {
+            device: &device,
+            queue: &queue,
+            geometry_pool: &mut geometry_pool,
+            uniform_pool: &mut uniform_pool,
+            passes: &mut passes,
+            bind_groups: &mut bind_groups,
+            pipelines: &mut pipelines,
+            assets: &mut assets,
+            buffer_inspector: &mut buffer_inspector,
+            frame_bind_group: frame_clock.bind_group(),
+            format: tonemap::HDR_FORMAT,
+            size,
         });
 
-        let camera = Camera {
-            eye: (0.0, 1.0, 2.0).into(),
-            target: (0.0, 0.0, 0.0).into(),
-            up: Vector3::unit_y(),
-            aspect: sc_desc.width as f32 / sc_desc.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
+        let tonemapper = Tonemapper::new(
+            &device,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+            format,
+            size,
+        );
+        let color_grading = ColorGrading::new(
+            &device,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+            format,
+            size,
+        );
+        let luts = LutLibrary::new(assets.path("luts"));
+
+        let text = TextRenderer::new(
+            &device,
+            &queue,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+            format,
+            size,
+            main_window.window.scale_factor() as f32,
+        );
+
+        let debug_draw = DebugDrawRenderer::new(
+            &device,
+            &mut uniform_pool,
+            &mut bind_groups,
+            &mut pipelines,
+            format,
+        );
+
+        let id_buffer = IdBuffer::new(&device, size);
+        let settings = SettingsStore::load();
+        let input = Input::new(settings.get("input"));
+
+        let mut state = Self {
+            adapter,
+            device,
+            queue,
+            main_window,
+            debug_window: None,
+            pipelines,
+            assets,
+            bind_groups,
+            geometry_pool,
+            uniform_pool,
+            passes,
+            tonemapper,
+            color_grading,
+            luts,
+            scheduler,
+            demo,
+            demo_index,
+            selected: None,
+            transform_gizmo: TransformGizmo::new(),
+            render_mode: RenderMode::Solid,
+            gpu_picking: false,
+            id_buffer,
+            pick_requested: None,
+            settings,
+            input,
+            gamepads: Gamepads::new(),
+            camera_controller: CameraController::new(2.0, 1.5),
+            camera_path: CameraPath::new(demo::DEMOS[demo_index].name),
+            // Demos simulate at 60 Hz, whatever the frame rate
+            timestep: FixedTimestep::new(60.0),
+            frame_clock,
+            benchmark: options.bench.as_ref().map(|bench| {
+                Benchmark::new(demo::DEMOS[bench.demo].name, bench.seconds)
+            }),
+            draw_counts: DrawCounts::default(),
+            overlay,
+            frame_stats: FrameStats::new(120),
+            texture_inspector,
+            buffer_inspector,
+            clear_color: Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            screenshot_requested: false,
+            screenshot: None,
+            recorder: Recorder::new(),
+            renderdoc: RenderDoc::attach(),
+            text,
+            show_debug_text: true,
+            debug_draw,
+        };
+        // Benchmarks run the demo's defaults without vsync, so runs compare against each other
+        if state.benchmark.is_some() {
+            state.set_present_mode(PresentMode::Immediate);
+        } else {
+            state.restore_settings();
+        }
+        state
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.main_window.resize(&self.device, new_size);
+
+        // Demos keep their old size while the window is minimized
+        if self.main_window.is_minimized() {
+            return;
+        }
+
+        self.demo.resize(new_size);
+        self.passes.resize(new_size);
+        self.tonemapper.resize(&self.device, new_size);
+        self.color_grading.resize(&self.device, new_size);
+        self.text.resize(new_size);
+        self.id_buffer.resize(&self.device, new_size);
+    }
+
+    // Moves the debug UI into a window of its own, or back onto the main window
+    fn toggle_debug_window(&mut self, target: &EventLoopWindowTarget<()>) {
+        let window = match self.debug_window.take() {
+            Some(_) => &self.main_window.window,
+            None => {
+                let window = WindowBuilder::new()
+                    .with_title("Debug")
+                    .build(target)
+                    .unwrap();
+                let surface = wgpu::Surface::create(&window);
+                let debug_window = WindowSurface::new(&self.device, window, surface);
+                &self.debug_window.get_or_insert(debug_window).window
+            }
         };
+        self.overlay
+            .set_window(window.inner_size(), window.scale_factor() as f32);
+    }
 
-        let mut uniforms = Uniforms::new();
-        uniforms.update_view_proj(&camera);
+    fn is_debug_window(&self, window_id: WindowId) -> bool {
+        self.debug_window
+            .as_ref()
+            .is_some_and(|debug_window| debug_window.window.id() == window_id)
+    }
+
+    // Returns true if the debug window should be closed
+    fn debug_window_input(&mut self, event: &WindowEvent) -> bool {
+        let debug_window = self.debug_window.as_mut().unwrap();
+        match event {
+            WindowEvent::Resized(size) => debug_window.resize(&self.device, *size),
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                debug_window.resize(&self.device, **new_inner_size)
+            }
+            WindowEvent::CloseRequested => return true,
+            _ => (),
+        }
+
+        self.overlay.handle_event(event);
+        false
+    }
+
+    // Replaces only the scene, everything else stays alive
+    fn switch_demo(&mut self, index: usize) {
+        self.store_settings();
+        self.recreate_demo(index);
+        self.restore_settings();
+    }
+
+    // Goes back to the demo's defaults, and forgets what was saved for it
+    fn reset_settings(&mut self) {
+        self.settings.remove(demo::DEMOS[self.demo_index].name);
+        self.recreate_demo(self.demo_index);
+    }
+
+    fn store_settings(&mut self) {
+        if let Some(settings) = self.demo.settings() {
+            self.settings.set(demo::DEMOS[self.demo_index].name, settings);
+        }
+    }
+
+    fn restore_settings(&mut self) {
+        let name = demo::DEMOS[self.demo_index].name;
+        if let Some(settings) = self.settings.get(name).cloned() {
+            if let Err(err) = self.demo.apply_settings(settings) {
+                warn!("Ignoring saved settings for {}: {}", name, err);
+            }
+        }
+    }
+
+    fn save_settings(&mut self) {
+        self.store_settings();
+        self.settings.set("input", self.input.bindings());
+        if let Err(err) = self.settings.save() {
+            error!("Failed to save settings: {}", err);
+        }
+    }
 
-        let uniform_buffer = device.create_buffer_with_data(
-            bytemuck::cast_slice(&[uniforms]),
-            BufferUsage::UNIFORM | BufferUsage::COPY_DST,
+    fn recreate_demo(&mut self, index: usize) {
+        // A recording in progress gets saved for the demo it was recorded in
+        if let Some(camera) = self.demo.camera() {
+            self.camera_path.stop(camera);
+        }
+        self.camera_path = CameraPath::new(demo::DEMOS[index].name);
+
+        let mut ctx = DemoContext {
+            device: &self.device,
+            queue: &self.queue,
+            geometry_pool: &mut self.geometry_pool,
+            uniform_pool: &mut self.uniform_pool,
+            passes: &mut self.passes,
+            bind_groups: &mut self.bind_groups,
+            pipelines: &mut self.pipelines,
+            assets: &mut self.assets,
+            buffer_inspector: &mut self.buffer_inspector,
+            frame_bind_group: self.frame_clock.bind_group(),
+            format: tonemap::HDR_FORMAT,
+            size: self.main_window.size,
+        };
+
+        self.demo.release(&mut ctx);
+        self.demo = (demo::DEMOS[index].create)(&mut ctx);
+        self.demo_index = index;
+        self.selected = None;
+        self.transform_gizmo.cancel();
+        self.camera_controller.stop();
+    }
+
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.debug_window.is_none() && self.overlay.handle_event(event) {
+            self.input.handle_release(event);
+            return true;
+        }
+
+        self.input.handle_event(event)
+    }
+
+    // wgpu falls back to Fifo (and logs a warning) when the surface doesn't support the mode
+    fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if present_mode == self.main_window.sc_desc.present_mode {
+            return;
+        }
+
+        info!("Switching to {:?} presentation", present_mode);
+        self.main_window.set_present_mode(&self.device, present_mode);
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.recorder.is_recording() {
+            self.recorder.stop();
+        } else if self.main_window.is_minimized() {
+            warn!("Not recording while the window is minimized");
+        } else {
+            let sc_desc = &self.main_window.sc_desc;
+            self.recorder
+                .start(&self.device, sc_desc.width, sc_desc.height, sc_desc.format);
+        }
+    }
+
+    // `dt` seconds after the last frame, `time` seconds after the first one
+    fn update(&mut self, dt: f32, time: f32) {
+        self.texture_inspector.poll(&self.device);
+        let loaded = self.assets.poll(
+            &self.device,
+            &self.queue,
+            &mut self.geometry_pool,
+            &mut self.scheduler,
         );
+        for (path, texture) in loaded {
+            self.texture_inspector.register(
+                &self.device,
+                &mut self.overlay,
+                &path,
+                &texture.texture,
+                texture.info,
+            );
+        }
+        self.buffer_inspector.poll(&self.device);
+        self.luts
+            .poll(&self.device, &self.queue, &mut self.color_grading);
 
-        let uniform_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            bindings: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStage::VERTEX,
-                    ty: BindingType::UniformBuffer {
-                        dynamic: false,
-                    },
+        if let Some(screenshot) = &mut self.screenshot {
+            self.device.poll(Maintain::Poll);
+            if let Some(result) = screenshot.try_save() {
+                match result {
+                    Ok(path) => info!("Saved screenshot to {}", path.display()),
+                    Err(err) => error!("Failed to save screenshot: {}", err),
                 }
-            ],
-            label: Some("uniform_bind_layout_layout"),
-        });
+                self.screenshot = None;
+            }
+        }
+        self.recorder.poll(&self.device);
+        if let Some(renderdoc) = &mut self.renderdoc {
+            renderdoc.poll();
+        }
 
-        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            bindings: &[
-                Binding {
-                    binding: 0,
-                    resource: BindingResource::Buffer {
-                        buffer: &uniform_buffer,
-                        range: 0..std::mem::size_of_val(&uniforms) as BufferAddress,
-                    },
-                },
-            ],
-            label: Some("uniform_bind_group"),
-        });
+        self.gamepads.poll(&mut self.input);
+        if self.input.pressed("screenshot") {
+            self.screenshot_requested = true;
+        }
+        if self.input.pressed("toggle_recording") {
+            self.toggle_recording();
+        }
+        if self.input.pressed("renderdoc_capture") {
+            match &self.renderdoc {
+                Some(renderdoc) => renderdoc.trigger_capture(),
+                None => warn!("Frames can only be captured when RenderDoc starts the program"),
+            }
+        }
+        if self.input.pressed("toggle_debug_text") {
+            self.show_debug_text = !self.show_debug_text;
+        }
+        if self.input.pressed("cycle_present_mode") {
+            let present_mode = match self.main_window.sc_desc.present_mode {
+                PresentMode::Fifo => PresentMode::Mailbox,
+                PresentMode::Mailbox => PresentMode::Immediate,
+                PresentMode::Immediate => PresentMode::Fifo,
+            };
+            self.set_present_mode(present_mode);
+        }
+        if self.input.pressed("toggle_pause") {
+            self.timestep.paused = !self.timestep.paused;
+        }
+        if self.input.pressed("step_simulation") {
+            self.timestep.request_step();
+        }
+        if self.input.pressed("slow_down") {
+            self.timestep.change_scale(-1);
+        }
+        if self.input.pressed("speed_up") {
+            self.timestep.change_scale(1);
+        }
+        if let Some(camera) = self.demo.camera() {
+            if self.input.pressed("toggle_camera_recording") {
+                self.camera_path.toggle_recording(camera);
+            }
+            if self.input.pressed("toggle_camera_playback") {
+                self.camera_path.toggle_playback(camera);
+            }
+            if let Some(benchmark) = &mut self.benchmark {
+                benchmark.update_camera(camera, dt);
+            } else if !self.camera_path.update(camera, dt) {
+                self.camera_controller
+                    .update(camera, &self.input, &self.gamepads, dt);
+            }
+        }
+        self.demo.handle_input(&self.input);
+        if self.show_debug_text {
+            self.queue_debug_text();
+        }
+        if self.input.pressed("gizmo_translate") {
+            self.transform_gizmo.mode = GizmoMode::Translate;
+        }
+        if self.input.pressed("gizmo_rotate") {
+            self.transform_gizmo.mode = GizmoMode::Rotate;
+        }
+        if self.input.pressed("gizmo_scale") {
+            self.transform_gizmo.mode = GizmoMode::Scale;
+        }
+        for &mode in &RenderMode::ALL {
+            if self.input.pressed(mode.action()) {
+                self.render_mode = mode;
+            }
+        }
+        let grabbed_gizmo = self.update_transform_gizmo();
+        if self.id_buffer.is_pending() {
+            self.device.poll(Maintain::Poll);
+            if let Some(result) = self.id_buffer.try_id() {
+                match result {
+                    Ok(id) => self.select(self.demo.object_name(id)),
+                    Err(err) => error!("GPU picking failed: {}", err),
+                }
+            }
+        }
+        if self.input.pressed("select") && !grabbed_gizmo {
+            if let Some(cursor) = self.input.cursor() {
+                if self.gpu_picking {
+                    // The IDs get drawn along with this frame's updates
+                    self.pick_requested = Some(cursor);
+                } else {
+                    // Picks against what was drawn last frame, which is what the user clicked on
+                    self.select(self.demo.pick(&self.assets, cursor, self.main_window.size));
+                }
+            }
+        }
 
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            bind_group_layouts: &[
-                &texture_bind_group_layout,
-                &uniform_bind_group_layout,
-            ]
-        });
+        let demo = &mut self.demo;
+        let demo_index = self.demo_index;
+        let selected = &self.selected;
+        let gpu_picking = &mut self.gpu_picking;
+        let gizmo_mode = &mut self.transform_gizmo.mode;
+        let render_mode = &mut self.render_mode;
+        let mut switch_to = None;
+        let mut reset_settings = false;
+        let clear_color = &mut self.clear_color;
+        let frame_stats = &self.frame_stats;
+        let texture_inspector = &mut self.texture_inspector;
+        let buffer_inspector = &mut self.buffer_inspector;
+        let passes = &mut self.passes;
+        let tonemapper = &mut self.tonemapper;
+        let color_grading = &mut self.color_grading;
+        let luts = &mut self.luts;
+        let scheduler = &mut self.scheduler;
+        let assets = &mut self.assets;
+        let recorder = &mut self.recorder;
+        let camera_controller = &mut self.camera_controller;
+        let camera_path = &mut self.camera_path;
+        let timestep = &mut self.timestep;
+        let mut path_action = None;
+        let mut toggle_recording = false;
+        let mut present_mode = self.main_window.sc_desc.present_mode;
 
-        // Include GLSL shaders
-        let vs_src = include_str!("../shaders/shader.vert");
-        let fs_src = include_str!("../shaders/shader.frag");
+        self.overlay.frame(|ctx| {
+            egui::Window::new("Frame stats")
+                .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+                .resizable(false)
+                .show(ctx, |ui| frame_stats.ui(ui));
 
-        // Compile the shaders
-        let vs_spirv = glsl_to_spirv::compile(vs_src, glsl_to_spirv::ShaderType::Vertex).unwrap();
-        let fs_spirv = glsl_to_spirv::compile(fs_src, glsl_to_spirv::ShaderType::Fragment).unwrap();
+            egui::Window::new("Debug").show(ctx, |ui| {
+                egui::ComboBox::from_label("Demo")
+                    .selected_text(demo::DEMOS[demo_index].name)
+                    .show_ui(ui, |ui| {
+                        for (index, entry) in demo::DEMOS.iter().enumerate() {
+                            if ui.selectable_label(index == demo_index, entry.name).clicked() {
+                                switch_to = Some(index);
+                            }
+                        }
+                    });
+                ui.label(format!(
+                    "Selected: {}",
+                    selected.as_deref().unwrap_or("nothing")
+                ));
+                ui.horizontal(|ui| {
+                    ui.label("Gizmo");
+                    ui.radio_value(gizmo_mode, GizmoMode::Translate, "Move (1)");
+                    ui.radio_value(gizmo_mode, GizmoMode::Rotate, "Rotate (2)");
+                    ui.radio_value(gizmo_mode, GizmoMode::Scale, "Scale (3)");
+                });
+                egui::ComboBox::from_label("Render mode")
+                    .selected_text(render_mode.name())
+                    .show_ui(ui, |ui| {
+                        for (key, &mode) in (4..).zip(&RenderMode::ALL) {
+                            let label = format!("{} ({})", mode.name(), key);
+                            ui.selectable_value(render_mode, mode, label);
+                        }
+                    });
+                ui.checkbox(gpu_picking, "Pick on the GPU");
+                demo.ui(ui);
+                if ui.button("Reset settings").clicked() {
+                    reset_settings = true;
+                }
+                passes.ui(ui);
 
-        // Load the SPIR-V data
-        let vs_data = wgpu::read_spirv(vs_spirv).unwrap();
-        let fs_data = wgpu::read_spirv(fs_spirv).unwrap();
+                ui.collapsing("Camera controls", |ui| camera_controller.ui(ui));
+                ui.collapsing("Camera path", |ui| path_action = camera_path.ui(ui));
+                ui.collapsing("Time", |ui| timestep.ui(ui));
+                ui.collapsing("Deferred tasks", |ui| scheduler.ui(ui));
+                ui.collapsing("Assets", |ui| assets.ui(ui));
 
-        // Create shader modules
-        let vs_module = device.create_shader_module(&vs_data);
-        let fs_module = device.create_shader_module(&fs_data);
+                ui.collapsing("Tonemapping", |ui| tonemapper.ui(ui));
+                ui.collapsing("Color grading", |ui| {
+                    luts.ui(ui);
+                    let strength = &mut color_grading.strength;
+                    ui.add(egui::Slider::new(strength, 0.0..=1.0).text("Strength"));
+                });
 
-        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            bind_group_layouts: &[&texture_bind_group_layout],
-        });
+                ui.collapsing("Clear color", |ui| {
+                    // The swap chain is sRGB, so the clear color is linear
+                    let mut rgb = [
+                        clear_color.r as f32,
+                        clear_color.g as f32,
+                        clear_color.b as f32,
+                    ];
+                    ui.color_edit_button_rgb(&mut rgb);
+                    clear_color.r = rgb[0] as f64;
+                    clear_color.g = rgb[1] as f64;
+                    clear_color.b = rgb[2] as f64;
+                });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            layout: &render_pipeline_layout,
-            vertex_stage: ProgrammableStageDescriptor {
-                module: &vs_module,
-                entry_point: "main",
-            },
-            fragment_stage: Some(ProgrammableStageDescriptor {
-                module: &fs_module,
-                entry_point: "main",
-            }),
-            // describes how to process primitives before they are sent to the fragment shader
-            rasterization_state: Some(RasterizationStateDescriptor {
-                front_face: FrontFace::Ccw,
-                cull_mode: CullMode::Back,
-                depth_bias: 0,
-                depth_bias_slope_scale: 0.0,
-                depth_bias_clamp: 0.0,
-            }),
-            // Describes how colors are stored and processed throughout the pipeline
-            color_states: &[ColorStateDescriptor {
-                format: sc_desc.format,
-                alpha_blend: BlendDescriptor::REPLACE,
-                color_blend: BlendDescriptor::REPLACE,
-                write_mask: ColorWrite::ALL,
-            }],
-            // We're drawing a list of triangles
-            primitive_topology: PrimitiveTopology::TriangleList,
-            depth_stencil_state: None,
-            vertex_state: VertexStateDescriptor {
-                // Use 16-bit integers for indexing
-                index_format: IndexFormat::Uint16,
-                vertex_buffers: &[Vertex::descriptor()],
-            },
-            sample_count: 1,
-            // Specifies which samples should be active, !0 is all of them
-            sample_mask: !0,
-            // No anti-aliasing
-            alpha_to_coverage_enabled: false,
+                ui.collapsing("Present mode (F9)", |ui| {
+                    ui.radio_value(&mut present_mode, PresentMode::Fifo, "Fifo (vsync)");
+                    ui.radio_value(&mut present_mode, PresentMode::Mailbox, "Mailbox");
+                    ui.radio_value(&mut present_mode, PresentMode::Immediate, "Immediate");
+                    ui.label("Unsupported modes fall back to Fifo");
+                });
+
+                ui.collapsing("Recording", |ui| {
+                    let recording = recorder.is_recording();
+                    ui.add_enabled_ui(!recording, |ui| {
+                        ui.radio_value(
+                            &mut recorder.output,
+                            RecordingOutput::PngSequence,
+                            "PNG sequence",
+                        );
+                        ui.radio_value(&mut recorder.output, RecordingOutput::Ffmpeg, "ffmpeg");
+                    });
+                    let label = if recording { "Stop (F10)" } else { "Record (F10)" };
+                    if ui.button(label).clicked() {
+                        toggle_recording = true;
+                    }
+                });
+
+                ui.checkbox(&mut texture_inspector.open, "Texture inspector");
+                ui.checkbox(&mut buffer_inspector.open, "Buffer inspector");
+            });
+
+            texture_inspector.ui(ctx);
+            buffer_inspector.ui(ctx);
+
+            if let Some(camera) = demo.camera() {
+                gizmo::show(ctx, camera);
+            }
         });
 
-        let vertex_buffer =
-            device.create_buffer_with_data(bytemuck::cast_slice(VERTICES), BufferUsage::VERTEX);
+        if toggle_recording {
+            self.toggle_recording();
+        }
+        if let (Some(action), Some(camera)) = (path_action, self.demo.camera()) {
+            match action {
+                PathAction::ToggleRecording => self.camera_path.toggle_recording(camera),
+                PathAction::TogglePlayback => self.camera_path.toggle_playback(camera),
+            }
+        }
+        self.set_present_mode(present_mode);
+        if reset_settings {
+            self.reset_settings();
+        }
+        if let Some(index) = switch_to {
+            self.switch_demo(index);
+        }
 
-        let index_buffer =
-            device.create_buffer_with_data(bytemuck::cast_slice(INDICES), BufferUsage::INDEX);
+        // Nothing gets drawn while minimized, and demos still have their old size
+        if self.main_window.is_minimized() {
+            return;
+        }
 
-        Self {
-            surface,
-            adapter,
-            device,
-            queue,
-            sc_desc,
-            swap_chain,
-            size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
-            diffuse_texture,
-            diffuse_bind_group,
-            camera,
-            uniforms,
-            uniform_buffer,
-            uniform_bind_group
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("update_encoder"),
+            });
+        self.demo.set_render_mode(self.render_mode);
+        self.demo.set_selected(self.selected.as_deref());
+        // Debug groups make the sections of the frame easy to find in RenderDoc or Nsight
+        encoder.push_debug_group("Frame uniforms");
+        self.frame_clock
+            .update(&self.device, &mut encoder, &self.uniform_pool, dt, time);
+        encoder.pop_debug_group();
+        let steps = self.timestep.advance(dt);
+        encoder.push_debug_group(&format!("Simulation, {} steps", steps));
+        for step in 0..steps {
+            encoder.insert_debug_marker(&format!("Step {}", step));
+            self.demo.step(
+                &self.device,
+                &mut encoder,
+                &self.uniform_pool,
+                self.timestep.step(),
+            );
+        }
+        encoder.pop_debug_group();
+        encoder.push_debug_group(&format!("Update {}", demo::DEMOS[self.demo_index].name));
+        self.demo.update(
+            &self.device,
+            &mut encoder,
+            &self.uniform_pool,
+            self.timestep.alpha(),
+        );
+        encoder.pop_debug_group();
+        encoder.push_debug_group("Custom passes");
+        self.passes
+            .update(&self.device, &mut encoder, &self.uniform_pool);
+        encoder.pop_debug_group();
+        self.tonemapper
+            .update(&self.device, &mut encoder, &self.uniform_pool, dt);
+        self.color_grading
+            .update(&self.device, &mut encoder, &self.uniform_pool);
+
+        let mut id_copied = false;
+        if let Some(cursor) = self.pick_requested.take() {
+            if !self.id_buffer.is_pending() {
+                encoder.push_debug_group("Picking IDs");
+                self.demo
+                    .render_ids(&self.assets, &mut encoder, &self.id_buffer.view);
+                id_copied = self.id_buffer.copy(&mut encoder, cursor);
+                encoder.pop_debug_group();
+            }
+        }
+
+        self.queue.submit(&[encoder.finish()]);
+        if id_copied {
+            self.id_buffer.map();
         }
     }
 
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        self.size = new_size;
-        self.sc_desc.width = new_size.width;
-        self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+    fn select(&mut self, selected: Option<String>) {
+        if let Some(name) = &selected {
+            info!("Selected {}", name);
+        }
+        self.selected = selected;
+        self.transform_gizmo.cancel();
     }
 
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+    // Draws the handles of the selected object and drags them around. Returns true if this
+    // frame's select press grabbed a handle instead of picking.
+    fn update_transform_gizmo(&mut self) -> bool {
+        let name = match &self.selected {
+            Some(name) => name.clone(),
+            None => return false,
+        };
+        let (eye, view_proj) = match self.demo.camera() {
+            Some(camera) => (camera.eye, camera.build_view_projection_matrix()),
+            None => return false,
+        };
+        let size = self.main_window.size;
+        let ray = self
+            .input
+            .cursor()
+            .and_then(|cursor| picking::cursor_ray(&view_proj, cursor, size));
+
+        match self.demo.transform_mut(&name) {
+            Some((parent_world, local)) => self.transform_gizmo.update(
+                eye,
+                ray.as_ref(),
+                self.input.pressed("select"),
+                self.input.held("select"),
+                &parent_world,
+                local,
+            ),
+            None => {
+                self.transform_gizmo.cancel();
+                false
+            }
+        }
+    }
+
+    // Frame rate, camera position and the controls, in the top left corner
+    fn queue_debug_text(&mut self) {
+        let mut text = format!(
+            "{:.0} FPS ({:.2} ms)\n",
+            self.frame_stats.fps(),
+            self.frame_stats.average_frame_time() * 1000.0
+        );
+        text += &format!(
+            "{} draws, {} triangles\n",
+            self.draw_counts.draw_calls, self.draw_counts.triangles
+        );
+        if let Some(camera) = self.demo.camera() {
+            text += &format!(
+                "Eye {:.2} {:.2} {:.2}\nTarget {:.2} {:.2} {:.2}\n{}\n",
+                camera.eye.x,
+                camera.eye.y,
+                camera.eye.z,
+                camera.target.x,
+                camera.target.y,
+                camera.target.z,
+                camera.projection.name()
+            );
+        }
+        if let Some(status) = self.timestep.status() {
+            text += &status;
+            text += "\n";
+        }
+        if let Some((done, started)) = self.assets.progress() {
+            text += &format!("Loading assets {}/{}\n", done, started);
+        }
+        text += "\n";
+        text += &self.input.help();
+
+        let margin = self.text.line_height();
+        self.text.queue(margin, margin, &text, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    // Whatever is left of the frame budget goes to deferred tasks
+    fn run_tasks(&mut self) {
+        let ctx = TaskContext {
+            device: &self.device,
+            queue: &self.queue,
+        };
+        self.scheduler.run(&ctx, self.frame_stats.frame_start());
+    }
+
+    // Everything that ends up in screenshots and recordings, the debug UI goes on top later
+    fn render_scene(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let hdr = self.tonemapper.target();
+        encoder.push_debug_group(demo::DEMOS[self.demo_index].name);
+        self.demo.render(&self.assets, encoder, hdr, self.clear_color);
+        encoder.pop_debug_group();
+        encoder.push_debug_group("Custom passes");
+        self.passes.render(encoder, hdr);
+        encoder.pop_debug_group();
+        encoder.push_debug_group("Tonemapping");
+        self.color_grading
+            .resolve(&self.tonemapper, encoder, target);
+        encoder.pop_debug_group();
     }
 
-    fn update(&mut self) {}
+    // Records the frame's layers into command buffers of their own, the demo on this thread and
+    // the custom passes with the tonemapping and grading, debug lines and text on worker threads
+    // meanwhile. The layers only meet in their targets, so recording them in parallel is fine as
+    // long as they're submitted in order.
+    fn record_layers(&mut self, target: &TextureView) -> Vec<CommandBuffer> {
+        // Lines can only be added on the main thread, where they're kept
+        let lines = match self.demo.camera() {
+            Some(camera) => Some((debug_draw::take(), camera.build_view_projection_matrix())),
+            // The lines are in world space, there's nothing to see them through
+            None => {
+                debug_draw::clear();
+                None
+            }
+        };
+
+        let device = &self.device;
+        let uniform_pool = &self.uniform_pool;
+        let demo = &self.demo;
+        let demo_name = demo::DEMOS[self.demo_index].name;
+        let assets = &mut self.assets;
+        let clear_color = self.clear_color;
+        let passes = &self.passes;
+        let tonemapper = &self.tonemapper;
+        let color_grading = &self.color_grading;
+        let hdr = tonemapper.target();
+        let debug_draw = &self.debug_draw;
+        let text = &mut self.text;
+        let create_encoder = |label| {
+            device.create_command_encoder(&CommandEncoderDescriptor { label: Some(label) })
+        };
+
+        let mut post = None;
+        let mut overlays = None;
+        let scene = rayon::in_place_scope(|scope| {
+            scope.spawn(|_| {
+                let mut encoder = create_encoder("post_encoder");
+                encoder.push_debug_group("Custom passes");
+                passes.render(&mut encoder, hdr);
+                encoder.pop_debug_group();
+                // Once a frame, screenshots and recordings use what this measured
+                if tonemapper.auto_exposure.enabled {
+                    tonemapper.auto_exposure.measure(&mut encoder);
+                }
+                encoder.push_debug_group("Tonemapping");
+                color_grading.resolve(tonemapper, &mut encoder, target);
+                encoder.pop_debug_group();
+                post = Some(encoder.finish());
+            });
+            scope.spawn(|_| {
+                let mut encoder = create_encoder("debug_overlay_encoder");
+                if let Some((lines, view_proj)) = lines {
+                    encoder.push_debug_group("Debug lines");
+                    debug_draw.render(
+                        device,
+                        &mut encoder,
+                        target,
+                        uniform_pool,
+                        &view_proj,
+                        lines,
+                    );
+                    encoder.pop_debug_group();
+                }
+                encoder.push_debug_group("Debug text");
+                text.render(device, &mut encoder, target, uniform_pool);
+                encoder.pop_debug_group();
+                overlays = Some(encoder.finish());
+            });
+
+            let mut encoder = create_encoder("scene_encoder");
+            encoder.push_debug_group(demo_name);
+            demo.render(assets, &mut encoder, hdr, clear_color);
+            encoder.pop_debug_group();
+            encoder.finish()
+        });
+        vec![scene, post.unwrap(), overlays.unwrap()]
+    }
 
     fn render(&mut self) {
-        let frame = self
-            .swap_chain
-            .get_next_texture()
-            .expect("Timeout getting texture");
+        let frame = match self.main_window.next_frame(&self.device) {
+            Some(frame) => frame,
+            None => {
+                debug_draw::clear();
+                return;
+            }
+        };
+        let debug_frame = match &mut self.debug_window {
+            Some(debug_window) => debug_window.next_frame(&self.device),
+            None => None,
+        };
 
+        let mut command_buffers = self.record_layers(&frame.view);
         let mut encoder = self
             .device
             .create_command_encoder(&CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        // The swap chain can't be copied from, so the scene gets drawn a second time into a
+        // texture that can
+        let capture = self.screenshot_requested && self.screenshot.is_none();
+        if capture {
+            let sc_desc = &self.main_window.sc_desc;
+            let screenshot =
+                Screenshot::new(&self.device, sc_desc.width, sc_desc.height, sc_desc.format);
+            encoder.push_debug_group("Screenshot");
+            self.render_scene(&mut encoder, &screenshot.view);
+            screenshot.copy(&mut encoder);
+            encoder.pop_debug_group();
+            self.screenshot = Some(screenshot);
+            self.screenshot_requested = false;
+        }
+
+        let sc_desc = &self.main_window.sc_desc;
+        if self.recorder.begin_frame(sc_desc.width, sc_desc.height) {
+            encoder.push_debug_group("Recording");
+            self.render_scene(&mut encoder, self.recorder.view().unwrap());
+            self.recorder.copy(&mut encoder);
+            encoder.pop_debug_group();
+        }
+
+        encoder.push_debug_group("Inspectors");
+        self.texture_inspector.render(
+            &self.device,
+            &mut encoder,
+            &mut self.overlay,
+            &self.uniform_pool,
+        );
+        self.buffer_inspector.render(&self.device, &mut encoder);
+        encoder.pop_debug_group();
+
+        encoder.push_debug_group("Debug UI");
+        if self.debug_window.is_none() {
+            self.overlay
+                .render(&self.device, &mut encoder, &frame.view, &self.uniform_pool);
+        } else if let Some(debug_frame) = &debug_frame {
+            encoder.begin_render_pass(&RenderPassDescriptor {
                 color_attachments: &[RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: &debug_frame.view,
                     resolve_target: None,
                     load_op: LoadOp::Clear,
                     store_op: StoreOp::Store,
-                    clear_color: Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    },
+                    clear_color: Color::BLACK,
                 }],
                 depth_stencil_attachment: None,
             });
+            self.overlay.render(
+                &self.device,
+                &mut encoder,
+                &debug_frame.view,
+                &self.uniform_pool,
+            );
+        }
+        encoder.pop_debug_group();
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
-            render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        command_buffers.push(encoder.finish());
+        self.queue.submit(&command_buffers);
+        self.texture_inspector.after_submit();
+        self.buffer_inspector.after_submit();
+        if capture {
+            self.screenshot.as_mut().unwrap().map();
+        }
+        self.recorder.after_submit();
+        self.draw_counts = draw_stats::take();
+    }
 
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    // Returns true once the benchmark is over, after printing what it measured
+    fn finish_frame_benchmark(&mut self, dt: f32) -> bool {
+        let benchmark = match &mut self.benchmark {
+            Some(benchmark) => benchmark,
+            None => return false,
+        };
+        if !benchmark.record(dt, self.draw_counts) {
+            return false;
         }
 
-        self.queue.submit(&[encoder.finish()]);
+        let info = self.adapter.get_info();
+        println!("{}", benchmark.report(&info.name, format!("{:?}", info.backend)));
+        true
     }
 }
 
 fn main() {
+    let options = Options::from_env();
+
+    // wgpu reports validation errors through the log crate, they go nowhere without a logger
+    let mut logger = env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("warn,playground_wgpu=info"),
+    );
+    if let Some(filter) = &options.log {
+        logger.parse_filters(filter);
+    }
+    logger.init();
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
     // Since main can't be async, we're going to need to block
-    let mut state = executor::block_on(State::new(&window));
+    let mut state = executor::block_on(State::new(window, &options));
+    if options.debug_window {
+        state.toggle_debug_window(&event_loop);
+    }
+    let mut window_modes = WindowModes::new();
+    // Summed up in f64, as an f32 would soon be too coarse to add a frame's time to
+    let mut time = 0.0f64;
 
-    event_loop.run(move |event, _, control_flow| {
+    event_loop.run(move |event, target, control_flow| {
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::KeyboardInput { input, .. } => match input {
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            } => *control_flow = ControlFlow::Exit,
-                            _ => (),
-                        },
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &mut so w have to dereference it twice
-                            state.resize(**new_inner_size)
-                        }
-                        _ => (),
-                    }
+            } if window_id == state.main_window.window.id() && !state.input(event) => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(physical_size) => {
+                    state.resize(*physical_size);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    // new_inner_size is &mut so w have to dereference it twice
+                    state.resize(**new_inner_size)
                 }
+                _ => (),
+            },
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if state.is_debug_window(window_id) && state.debug_window_input(event) => {
+                state.toggle_debug_window(target);
             }
-            Event::RedrawRequested(_) => {
-                state.update();
+            Event::DeviceEvent {
+                event: DeviceEvent::ModifiersChanged(modifiers),
+                ..
+            } => window_modes.set_modifiers(modifiers),
+            // The debug window gets drawn along with the main window
+            Event::RedrawRequested(window_id) if window_id == state.main_window.window.id() => {
+                let dt = state.frame_stats.tick();
+                time += dt as f64;
+                state.update(dt, time as f32);
+                if state.input.pressed("quit") {
+                    *control_flow = ControlFlow::Exit;
+                }
+                if state.input.pressed("toggle_debug_window") {
+                    state.toggle_debug_window(target);
+                }
+                window_modes.update(&state.main_window.window, &state.input);
                 state.render();
+                if state.finish_frame_benchmark(dt) {
+                    *control_flow = ControlFlow::Exit;
+                }
+                state.run_tasks();
+                state.input.end_frame();
             }
             Event::MainEventsCleared => {
                 // RedrawRequested will only trigger once, unless we manually request it
-                window.request_redraw();
+                state.main_window.window.request_redraw();
             }
+            // Benchmarks leave the saved settings alone
+            Event::LoopDestroyed if state.benchmark.is_none() => state.save_settings(),
             _ => (),
         }
     });
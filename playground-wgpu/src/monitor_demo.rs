@@ -0,0 +1,284 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::debug_draw;
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::pipeline::{PipelineKey, Shader};
+use crate::primitives::{self, Cubes, Quad};
+use crate::render_target::RenderTarget;
+use crate::uniform::{self, ObjectUniforms, Uniforms};
+use cgmath::{Matrix4, Point3, Vector3};
+use playground_math::{Block, Camera, Layout, Projection};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, CullMode, Device, LoadOp, RenderPass,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SHADER_VERT: Shader = Shader {
+    name: "shader.vert",
+    source: include_str!("../shaders/shader.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHADER_FRAG: Shader = Shader {
+    name: "shader.frag",
+    source: include_str!("../shaders/shader.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Cubes in the ring, and how far the ring is from the middle
+const RING: usize = 8;
+const RING_RADIUS: f32 = 3.0;
+// Where the monitor hangs, and its height. The width follows the monitor camera's aspect.
+const MONITOR_CENTER: Vector3<f32> = Vector3::new(0.0, 2.5, -5.5);
+const MONITOR_HEIGHT: f32 = 3.0;
+
+const UNIFORM_KEYS: [&str; 3] = ["monitor_main_camera", "monitor_camera", "monitor_quad"];
+
+// A ring of cubes seen through a second camera circling it. That camera renders into an
+// offscreen target every frame, which then shows up on a monitor hanging behind the ring.
+pub struct MonitorDemo {
+    camera: Camera,
+    monitor_camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    monitor: RenderTarget,
+    // Of the window, for the size of the monitor's target
+    resolution: f32,
+    orbit_speed: f32,
+    // Along the orbit, at the last step and the one before
+    time: f32,
+    previous_time: f32,
+
+    cube_pipeline: Arc<RenderPipeline>,
+    quad_pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    quad: Quad,
+
+    // The main camera, the monitor camera and the quad's model matrix
+    uniform_allocations: [Allocation; 3],
+    uniform_bind_groups: [Arc<BindGroup>; 3],
+}
+
+impl MonitorDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 5.0, 11.0).into(),
+            target: (0.0, 1.5, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+        let resolution = 0.5;
+        let monitor_size = PhysicalSize::new(
+            (ctx.size.width as f32 * resolution) as u32,
+            (ctx.size.height as f32 * resolution) as u32,
+        );
+        let monitor = RenderTarget::new(device, ctx.bind_groups, ctx.format, monitor_size);
+
+        let cube_pipeline =
+            ctx.pipelines
+                .get(device, ctx.bind_groups, &Cubes::pipeline_key(ctx.format));
+        let quad_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: SHADER_VERT,
+                fragment_shader: Some(SHADER_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![Quad::vertex_layout()],
+                // Seen from behind, the picture is mirrored
+                cull_mode: CullMode::None,
+                ..Cubes::pipeline_key(ctx.format)
+            },
+        );
+
+        let (main_allocation, main_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[0],
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (monitor_allocation, monitor_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[1],
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (quad_allocation, quad_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[2],
+            ObjectUniforms::size(Layout::Std140),
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+        );
+
+        Self {
+            camera,
+            // Moved around the ring in `update`
+            monitor_camera: Camera {
+                eye: (0.0, 2.5, 6.0).into(),
+                target: (0.0, 0.5, 0.0).into(),
+                up: Vector3::unit_y(),
+                aspect: monitor.aspect(),
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: 100.0,
+                projection: Projection::Perspective,
+            },
+            size: ctx.size,
+            depth: DepthBuffer::new(device, ctx.size),
+            monitor,
+            resolution,
+            orbit_speed: 0.4,
+            time: 0.0,
+            previous_time: 0.0,
+            cube_pipeline,
+            quad_pipeline,
+            cubes: Cubes::new(device, &primitives::ring_of_cubes(RING, RING_RADIUS)),
+            quad: Quad::new(device),
+            uniform_allocations: [main_allocation, monitor_allocation, quad_allocation],
+            uniform_bind_groups: [main_bind_group, monitor_bind_group, quad_bind_group],
+        }
+    }
+
+    fn monitor_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            (self.size.width as f32 * self.resolution) as u32,
+            (self.size.height as f32 * self.resolution) as u32,
+        )
+    }
+
+    // The cubes as seen by the camera bound at set 0
+    fn draw_cubes<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera: &'a BindGroup) {
+        render_pass.set_pipeline(&self.cube_pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        self.cubes.draw(render_pass);
+    }
+}
+
+impl Demo for MonitorDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        self.previous_time = self.time;
+        self.time += dt * self.orbit_speed;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        self.monitor.resize(device, self.monitor_size());
+
+        let time = self.previous_time + (self.time - self.previous_time) * alpha;
+        let (sin, cos) = time.sin_cos();
+        self.monitor_camera.eye = Point3::new(sin * 6.0, 2.5, cos * 6.0);
+        self.monitor_camera.target = Point3::new(0.0, 0.5, 0.0);
+        self.monitor_camera.aspect = self.monitor.aspect();
+        debug_draw::sphere(
+            self.monitor_camera.eye,
+            0.15,
+            playground_math::Color::new(1.0, 0.3, 0.3, 1.0),
+        );
+        debug_draw::line(
+            self.monitor_camera.eye,
+            self.monitor_camera.target,
+            playground_math::Color::new(1.0, 0.3, 0.3, 1.0),
+        );
+
+        let mut main = Uniforms::new();
+        main.update_view_proj(&self.camera);
+        let mut monitor = Uniforms::new();
+        monitor.update_view_proj(&self.monitor_camera);
+        let quad = Matrix4::from_translation(MONITOR_CENTER)
+            * Matrix4::from_nonuniform_scale(
+                MONITOR_HEIGHT * self.monitor.aspect(),
+                MONITOR_HEIGHT,
+                1.0,
+            );
+        let [main_allocation, monitor_allocation, quad_allocation] = &self.uniform_allocations;
+        uniform_pool.write(device, encoder, main_allocation, &main.std140());
+        uniform_pool.write(device, encoder, monitor_allocation, &monitor.std140());
+        uniform_pool.write(
+            device,
+            encoder,
+            quad_allocation,
+            &ObjectUniforms::new(quad, 1.0).std140(),
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.orbit_speed, -2.0..=2.0).text("Orbit speed"));
+        ui.add(egui::Slider::new(&mut self.resolution, 0.1..=1.0).text("Resolution"));
+        let size = self.monitor.size();
+        ui.label(format!("Monitor target: {}x{}", size.width, size.height));
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let [main_camera, monitor_camera, quad] = &self.uniform_bind_groups;
+
+        // The monitor isn't in its own picture, so its texture is never read while it's drawn to
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[self.monitor.color_attachment(clear_color)],
+            depth_stencil_attachment: Some(self.monitor.depth_attachment()),
+        });
+        render_pass.push_debug_group("Monitor view");
+        self.draw_cubes(&mut render_pass, monitor_camera);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        self.draw_cubes(&mut render_pass, main_camera);
+
+        render_pass.set_pipeline(&self.quad_pipeline);
+        render_pass.set_bind_group(0, self.monitor.bind_group(), &[]);
+        render_pass.set_bind_group(1, main_camera, &[]);
+        render_pass.set_bind_group(2, quad, &[]);
+        self.quad.draw(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        for (allocation, key) in self.uniform_allocations.iter().zip(&UNIFORM_KEYS) {
+            ctx.uniform_pool.free(*allocation);
+            ctx.bind_groups.invalidate(key);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::compute::StorageBuffer;
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4, Zero};
+use playground_math::{Block, BlockWriter, Layout};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, Buffer, BufferAddress, BufferUsage,
+    ColorStateDescriptor, ColorWrite, CommandEncoder, CullMode, Device, IndexFormat, InputStepMode,
+    PrimitiveTopology, RenderPass, RenderPipeline, ShaderStage, TextureFormat,
+    VertexAttributeDescriptor, VertexFormat,
+};
+
+const MORPH_VERT: Shader = Shader {
+    name: "morph.vert",
+    source: include_str!("../shaders/morph.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHAPE_FRAG: Shader = Shader {
+    name: "shape.frag",
+    source: include_str!("../shaders/shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Has to match the size of u_weights in the vertex shader
+pub const MAX_TARGETS: usize = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MorphVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [u8; 4],
+}
+
+unsafe impl bytemuck::Pod for MorphVertex {}
+
+unsafe impl bytemuck::Zeroable for MorphVertex {}
+
+#[derive(Copy, Clone, Debug)]
+struct MorphUniforms {
+    view_proj: Matrix4<f32>,
+    model: Matrix4<f32>,
+    counts: Vector4<u32>,
+    // Four to a vec4
+    weights: [Vector4<f32>; MAX_TARGETS / 4],
+}
+
+impl Block for MorphUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.model);
+        writer.member(&self.counts);
+        writer.member(&self.weights);
+    }
+}
+
+impl Default for MorphUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            model: Matrix4::identity(),
+            counts: Vector4::zero(),
+            weights: [Vector4::zero(); MAX_TARGETS / 4],
+        }
+    }
+}
+
+// How far one target moves every vertex of the mesh, and how it turns their normals
+pub struct MorphTarget {
+    pub name: String,
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+}
+
+impl MorphTarget {
+    // The target that moves the mesh to `positions`, with the normals worked out from the moved
+    // triangles
+    pub fn from_positions(
+        name: &str,
+        vertices: &[MorphVertex],
+        indices: &[u16],
+        positions: &[Vector3<f32>],
+    ) -> Self {
+        let normals = vertex_normals(positions, indices);
+        Self {
+            name: name.to_string(),
+            positions: vertices
+                .iter()
+                .zip(positions)
+                .map(|(vertex, position)| position - Vector3::from(vertex.position))
+                .collect(),
+            normals: vertices
+                .iter()
+                .zip(normals)
+                .map(|(vertex, normal)| normal - Vector3::from(vertex.normal))
+                .collect(),
+        }
+    }
+}
+
+// Smooth normals, averaged over the triangles around each vertex weighted by their area
+pub fn vertex_normals(positions: &[Vector3<f32>], indices: &[u16]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+    for triangle in indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize);
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for &corner in &[a, b, c] {
+            normals[corner] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.is_zero() {
+                normal
+            } else {
+                normal.normalize()
+            }
+        })
+        .collect()
+}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<MorphVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: 12,
+                shader_location: 1,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: 24,
+                shader_location: 2,
+                format: VertexFormat::Uchar4Norm,
+            },
+        ],
+    }
+}
+
+// A mesh with morph targets blended in the vertex shader. The deltas of all targets sit in one
+// storage buffer the shader indexes with the vertex index, so only the weights change per frame.
+pub struct MorphMesh {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+    vertex_count: u32,
+    target_count: u32,
+
+    // Only kept alive for `deltas_bind_group`
+    _deltas: StorageBuffer,
+    deltas_bind_group: Arc<BindGroup>,
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    // Prefixes the bind group keys in the cache, so several meshes can be around at once
+    key: String,
+}
+
+impl MorphMesh {
+    // Only the first MAX_TARGETS targets are used
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        key: &str,
+        vertices: &[MorphVertex],
+        indices: &[u16],
+        targets: &[MorphTarget],
+    ) -> Self {
+        let vertex_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(vertices), BufferUsage::VERTEX);
+        // Padded to keep the buffer size a multiple of 4
+        let mut padded = indices.to_vec();
+        if padded.len() % 2 == 1 {
+            padded.push(0);
+        }
+        let index_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&padded), BufferUsage::INDEX);
+
+        let targets = &targets[..targets.len().min(MAX_TARGETS)];
+        let mut deltas: Vec<[f32; 4]> = Vec::with_capacity(targets.len() * vertices.len() * 2);
+        for target in targets {
+            for (position, normal) in target.positions.iter().zip(&target.normals) {
+                deltas.push(position.extend(0.0).into());
+                deltas.push(normal.extend(0.0).into());
+            }
+        }
+        // Storage buffers can't be empty
+        if deltas.is_empty() {
+            deltas.push([0.0; 4]);
+        }
+        let deltas =
+            StorageBuffer::new(device, bytemuck::cast_slice(&deltas), BufferUsage::empty());
+        let deltas_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::VERTEX_STORAGE_LAYOUT,
+            &format!("{}_deltas", key),
+            &[deltas.binding(0)],
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            MorphUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            &format!("{}_uniforms", key),
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: MORPH_VERT,
+                fragment_shader: Some(SHAPE_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::VERTEX_STORAGE_LAYOUT),
+                ],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::Back,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: Some(depth::depth_stencil_state()),
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            vertex_count: vertices.len() as u32,
+            target_count: targets.len() as u32,
+            _deltas: deltas,
+            deltas_bind_group,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            key: key.to_string(),
+        }
+    }
+
+    // One weight per target, in the order they were given to `new`
+    pub fn prepare(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        view_proj: Matrix4<f32>,
+        model: Matrix4<f32>,
+        weights: &[f32],
+    ) {
+        let mut uniforms = MorphUniforms {
+            view_proj,
+            model,
+            counts: Vector4::new(self.vertex_count, self.target_count, 0, 0),
+            weights: [Vector4::zero(); MAX_TARGETS / 4],
+        };
+        for (index, weight) in weights.iter().enumerate().take(MAX_TARGETS) {
+            uniforms.weights[index / 4][index % 4] = *weight;
+        }
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.deltas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        draw_stats::record_triangles(self.index_count, 1);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate(&format!("{}_deltas", self.key));
+        bind_groups.invalidate(&format!("{}_uniforms", self.key));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::assets::Assets;
+use crate::buffer_pool::BufferPool;
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::morph::{self, MorphMesh, MorphTarget, MorphVertex};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use playground_math::{Camera, Projection};
+use std::f32::consts::PI;
+use wgpu::{
+    Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+// Rings from pole to pole, and vertices around each ring
+const RINGS: usize = 32;
+const SEGMENTS: usize = 48;
+
+const SKIN: [u8; 4] = [225, 185, 150, 255];
+const EYES: [u8; 4] = [40, 40, 50, 255];
+const LIPS: [u8; 4] = [180, 70, 70, 255];
+
+const MOUTH: Vector3<f32> = Vector3::new(0.0, -0.35, 0.93);
+
+// 1 at `center` down to 0 at `radius` away from it
+fn falloff(p: Vector3<f32>, center: Vector3<f32>, radius: f32) -> f32 {
+    (1.0 - (p - center).magnitude() / radius).max(0.0).powi(2)
+}
+
+// Like `falloff`, with `center` mirrored onto the side of the face `p` is on
+fn mirrored(p: Vector3<f32>, center: Vector3<f32>, radius: f32) -> f32 {
+    let center = Vector3::new(center.x.abs() * p.x.signum(), center.y, center.z);
+    falloff(p, center, radius)
+}
+
+// A unit sphere facing +z with a pole at the top and bottom. Returns the vertices and indices.
+fn head() -> (Vec<MorphVertex>, Vec<u16>) {
+    let mut positions = vec![Vector3::unit_y()];
+    for ring in 1..RINGS {
+        let (sin_theta, cos_theta) = (PI * ring as f32 / RINGS as f32).sin_cos();
+        for segment in 0..SEGMENTS {
+            let (sin_phi, cos_phi) = (2.0 * PI * segment as f32 / SEGMENTS as f32).sin_cos();
+            positions.push(Vector3::new(
+                sin_theta * sin_phi,
+                cos_theta,
+                sin_theta * cos_phi,
+            ));
+        }
+    }
+    positions.push(-Vector3::unit_y());
+
+    let ring_vertex =
+        |ring: usize, segment: usize| (1 + ring * SEGMENTS + segment % SEGMENTS) as u16;
+    let bottom = (positions.len() - 1) as u16;
+    let mut indices = Vec::new();
+    for segment in 0..SEGMENTS {
+        indices.extend_from_slice(&[0, ring_vertex(0, segment), ring_vertex(0, segment + 1)]);
+        indices.extend_from_slice(&[
+            bottom,
+            ring_vertex(RINGS - 2, segment + 1),
+            ring_vertex(RINGS - 2, segment),
+        ]);
+    }
+    for ring in 0..RINGS - 2 {
+        for segment in 0..SEGMENTS {
+            let (a, b) = (ring_vertex(ring, segment), ring_vertex(ring, segment + 1));
+            let (c, d) = (
+                ring_vertex(ring + 1, segment),
+                ring_vertex(ring + 1, segment + 1),
+            );
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+
+    let normals = morph::vertex_normals(&positions, &indices);
+    let vertices = positions
+        .iter()
+        .zip(normals)
+        .map(|(&p, normal)| {
+            let color = if mirrored(p, Vector3::new(0.35, 0.3, 0.88), 0.15) > 0.0 {
+                EYES
+            } else if (p.y - MOUTH.y).abs() < 0.04 && p.x.abs() < 0.25 && p.z > 0.0 {
+                LIPS
+            } else {
+                SKIN
+            };
+            MorphVertex {
+                position: p.into(),
+                normal: normal.into(),
+                color,
+            }
+        })
+        .collect();
+    (vertices, indices)
+}
+
+// Moves a point of the unit sphere
+type Shape = fn(Vector3<f32>) -> Vector3<f32>;
+
+fn targets(vertices: &[MorphVertex], indices: &[u16]) -> Vec<MorphTarget> {
+    let shapes: [(&str, Shape); 5] = [
+        ("Open mouth", |p| {
+            let jaw = ((MOUTH.y - p.y) / 0.3).clamp(0.0, 1.0) * p.z.max(0.0);
+            Vector3::new(0.0, -0.25, -0.05) * jaw
+        }),
+        ("Smile", |p| {
+            let corners = falloff(p, MOUTH, 0.5) * (p.x.abs() / 0.3).min(1.0);
+            Vector3::new(p.x * 0.15, 0.15, 0.0) * corners
+        }),
+        ("Raise brows", |p| {
+            Vector3::new(0.0, 0.12, 0.0) * mirrored(p, Vector3::new(0.35, 0.55, 0.75), 0.35)
+        }),
+        ("Puff cheeks", |p| {
+            p * 0.2 * mirrored(p, Vector3::new(0.5, -0.15, 0.75), 0.45)
+        }),
+        ("Squash", |p| Vector3::new(p.x * 0.2, -p.y * 0.3, p.z * 0.2)),
+    ];
+
+    shapes
+        .iter()
+        .map(|(name, shape)| {
+            let positions: Vec<_> = vertices
+                .iter()
+                .map(|vertex| {
+                    let p = Vector3::from(vertex.position);
+                    p + shape(p)
+                })
+                .collect();
+            MorphTarget::from_positions(name, vertices, indices, &positions)
+        })
+        .collect()
+}
+
+// A head with a few facial expressions and shape changes as morph targets, blended on the GPU
+// with the weights from the UI
+pub struct MorphDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    mesh: MorphMesh,
+
+    names: Vec<String>,
+    weights: Vec<f32>,
+    // Sweeps every weight up and down at its own pace
+    animate: bool,
+    // At the last step and the one before
+    time: f32,
+    previous_time: f32,
+}
+
+impl MorphDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (0.8, 0.3, 3.5).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let (vertices, indices) = head();
+        let targets = targets(&vertices, &indices);
+        let mesh = MorphMesh::new(
+            ctx.device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            "morph_head",
+            &vertices,
+            &indices,
+            &targets,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(ctx.device, ctx.size),
+            mesh,
+            names: targets.iter().map(|target| target.name.clone()).collect(),
+            weights: vec![0.0; targets.len()],
+            animate: false,
+            time: 0.0,
+            previous_time: 0.0,
+        }
+    }
+}
+
+impl Demo for MorphDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        if self.animate {
+            self.previous_time = self.time;
+            self.time += dt;
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        if self.animate {
+            let time = self.previous_time + (self.time - self.previous_time) * alpha;
+            for (index, weight) in self.weights.iter_mut().enumerate() {
+                let speed = 0.7 + 0.35 * index as f32;
+                *weight = 0.5 - 0.5 * (time * speed).cos();
+            }
+        }
+
+        self.mesh.prepare(
+            device,
+            encoder,
+            uniform_pool,
+            self.camera.build_view_projection_matrix(),
+            Matrix4::identity(),
+            &self.weights,
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        for (name, weight) in self.names.iter().zip(&mut self.weights) {
+            ui.add_enabled(
+                !self.animate,
+                egui::Slider::new(weight, 0.0..=1.0).text(name.as_str()),
+            );
+        }
+        if ui.checkbox(&mut self.animate, "Animate").changed() {
+            self.time = 0.0;
+            self.previous_time = 0.0;
+        }
+        if ui.button("Reset").clicked() {
+            self.animate = false;
+            self.weights.iter_mut().for_each(|weight| *weight = 0.0);
+        }
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        self.mesh.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.mesh.release(ctx.uniform_pool, ctx.bind_groups);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::demo;
+use failure::bail;
+use std::env;
+use wgpu::BackendBit;
+
+const USAGE: &str = "Usage: playground-wgpu [options]
+
+Options:
+    --backend <vulkan|dx12|dx11|metal|gl|primary|all>
+                          Graphics backend(s) to pick an adapter from (default: primary)
+    --adapter <index|name>
+                          Adapter to use, by its index in the list printed on startup or
+                          part of its name (default: picked by wgpu)
+    --log <filter>        Log levels, per module if needed, e.g. 'info,wgpu_core=warn'
+                          (default: $RUST_LOG, or warnings plus this program's info)
+    --debug-window        Show the debug UI in a window of its own (toggle with F8)
+    --bench <demo> <seconds>
+                          Fly a fixed camera path through a demo, by its index or part of its
+                          name, without vsync for that many seconds. Prints the frame times,
+                          draw calls and triangles as JSON and exits.
+    -h, --help            Print this message";
+
+// Command line options
+pub struct Options {
+    pub backends: BackendBit,
+    // Index or name substring of the adapter to use
+    pub adapter: Option<String>,
+    // env_logger filter, overrides RUST_LOG
+    pub log: Option<String>,
+    pub debug_window: bool,
+    pub bench: Option<BenchOptions>,
+}
+
+pub struct BenchOptions {
+    // Index into `demo::DEMOS`
+    pub demo: usize,
+    pub seconds: f32,
+}
+
+fn parse_backend(name: &str) -> Result<BackendBit, failure::Error> {
+    Ok(match name.to_lowercase().as_str() {
+        "vulkan" | "vk" => BackendBit::VULKAN,
+        "dx12" | "d3d12" => BackendBit::DX12,
+        "dx11" | "d3d11" => BackendBit::DX11,
+        "metal" | "mtl" => BackendBit::METAL,
+        "gl" | "opengl" => BackendBit::GL,
+        "primary" => BackendBit::PRIMARY,
+        "all" => BackendBit::PRIMARY | BackendBit::SECONDARY,
+        _ => bail!("Unknown backend '{}'", name),
+    })
+}
+
+fn parse_demo(selector: &str) -> Result<usize, failure::Error> {
+    let lowercase = selector.to_lowercase();
+    match selector.parse::<usize>() {
+        Ok(index) if index < demo::DEMOS.len() => Ok(index),
+        _ => match demo::DEMOS
+            .iter()
+            .position(|entry| entry.name.to_lowercase().contains(&lowercase))
+        {
+            Some(index) => Ok(index),
+            None => bail!("No demo matches '{}'", selector),
+        },
+    }
+}
+
+fn parse_seconds(value: Option<String>) -> Result<f32, failure::Error> {
+    match value.as_deref().map(str::parse::<f32>) {
+        Some(Ok(seconds)) if seconds > 0.0 => Ok(seconds),
+        _ => bail!("--bench needs a positive number of seconds after the demo"),
+    }
+}
+
+impl Options {
+    // Parses the process arguments, printing the usage and exiting on errors or `--help`
+    pub fn from_env() -> Self {
+        match Self::parse(env::args().skip(1)) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("{}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // Returns None when only the usage was asked for
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, failure::Error> {
+        let mut options = Options {
+            backends: BackendBit::PRIMARY,
+            adapter: None,
+            log: None,
+            debug_window: false,
+            bench: None,
+        };
+
+        while let Some(arg) = args.next() {
+            // Both `--flag value` and `--flag=value` work
+            let (flag, inline_value) = match arg.find('=') {
+                Some(index) if arg.starts_with("--") => {
+                    (arg[..index].to_string(), Some(arg[index + 1..].to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || match inline_value.clone().or_else(|| args.next()) {
+                Some(value) => Ok(value),
+                None => Err(failure::format_err!("{} needs a value", flag)),
+            };
+
+            match flag.as_str() {
+                "--backend" => options.backends = parse_backend(&value()?)?,
+                "--adapter" => options.adapter = Some(value()?),
+                "--log" => options.log = Some(value()?),
+                "--debug-window" => options.debug_window = true,
+                "--bench" => {
+                    let demo = parse_demo(&value()?)?;
+                    options.bench = Some(BenchOptions {
+                        demo,
+                        seconds: parse_seconds(args.next())?,
+                    });
+                }
+                "-h" | "--help" => return Ok(None),
+                _ => bail!("Unknown option '{}'", arg),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
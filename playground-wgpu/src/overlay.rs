@@ -0,0 +1,692 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout, FULLSCREEN_VERT};
+use crate::texture;
+use cgmath::Vector2;
+use egui::epaint::{ImageData, Primitive};
+use egui::{
+    ClippedPrimitive, Context, Event, Key, Modifiers, MouseWheelUnit, PointerButton, Pos2,
+    RawInput, Rect, TextureFilter, TextureId, TexturesDelta, ViewportId,
+};
+use playground_math::{Block, BlockWriter, Layout};
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use std::time::Instant;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BlendFactor, BlendOperation, BufferAddress, BufferUsage, ColorStateDescriptor,
+    ColorWrite, CommandEncoder, CompareFunction, CullMode, Device, Extent3d, FilterMode,
+    IndexFormat, InputStepMode, LoadOp, Origin3d, PrimitiveTopology,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderStage, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage, TextureView, VertexAttributeDescriptor, VertexFormat,
+};
+use winit::dpi::PhysicalSize;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+const OVERLAY_VERT: Shader = Shader {
+    name: "overlay.vert",
+    source: include_str!("../shaders/overlay.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const OVERLAY_FRAG: Shader = Shader {
+    name: "overlay.frag",
+    source: include_str!("../shaders/overlay.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const OVERLAY_USER_FRAG: Shader = Shader {
+    name: "overlay_user.frag",
+    source: include_str!("../shaders/overlay_user.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const COMPOSITE_FRAG: Shader = Shader {
+    name: "composite.frag",
+    source: include_str!("../shaders/composite.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// egui blends in sRGB space, so the UI gets drawn into a non-sRGB target first and composited
+// onto the frame afterwards
+const UI_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+// Premultiplied alpha "over" blending
+const PREMULTIPLIED_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::One,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+#[derive(Copy, Clone, Debug, Default)]
+struct OverlayUniforms {
+    screen_size: [f32; 2],
+}
+
+impl Block for OverlayUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector2::from(self.screen_size));
+    }
+}
+
+fn vertex_layout() -> VertexLayout {
+    // Matches the layout of `egui::epaint::Vertex`
+    VertexLayout {
+        stride: mem::size_of::<egui::epaint::Vertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float2,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 2]>() as BufferAddress,
+                shader_location: 1,
+                format: VertexFormat::Float2,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 4]>() as BufferAddress,
+                shader_location: 2,
+                format: VertexFormat::Uchar4Norm,
+            },
+        ],
+    }
+}
+
+fn ui_pipeline_key(fragment_shader: Shader) -> PipelineKey {
+    PipelineKey {
+        vertex_shader: OVERLAY_VERT,
+        fragment_shader: Some(fragment_shader),
+        bind_group_layouts: vec![
+            bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+            bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+        ],
+        vertex_buffers: vec![vertex_layout()],
+        index_format: IndexFormat::Uint32,
+        primitive_topology: PrimitiveTopology::TriangleList,
+        cull_mode: CullMode::None,
+        color_states: vec![ColorStateDescriptor {
+            format: UI_FORMAT,
+            color_blend: PREMULTIPLIED_BLEND,
+            alpha_blend: PREMULTIPLIED_BLEND,
+            write_mask: ColorWrite::ALL,
+        }],
+        depth_stencil_state: None,
+        sample_count: 1,
+    }
+}
+
+fn filter_mode(filter: TextureFilter) -> FilterMode {
+    match filter {
+        TextureFilter::Nearest => FilterMode::Nearest,
+        TextureFilter::Linear => FilterMode::Linear,
+    }
+}
+
+fn key_from_keycode(keycode: VirtualKeyCode) -> Option<Key> {
+    Some(match keycode {
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+// Offscreen target the UI gets drawn into, matching the window size
+struct UiTarget {
+    size: PhysicalSize<u32>,
+    _texture: wgpu::Texture,
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+// Immediate mode debug UI (egui), drawn into its own target and then composited on top of the
+// finished frame
+pub struct Overlay {
+    pub context: Context,
+    start: Instant,
+    events: Vec<Event>,
+    pointer_pos: Pos2,
+    modifiers: Modifiers,
+    size: PhysicalSize<u32>,
+    scale_factor: f32,
+
+    pipeline: Arc<RenderPipeline>,
+    user_pipeline: Arc<RenderPipeline>,
+    composite_pipeline: Arc<RenderPipeline>,
+    ui_target: Option<UiTarget>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    texture_bind_group_layout: Arc<BindGroupLayout>,
+    // Managed textures are owned by the overlay, user textures only get a bind group
+    textures: HashMap<TextureId, (Option<wgpu::Texture>, BindGroup)>,
+    user_sampler: Sampler,
+    next_user_texture: u64,
+
+    // Output of the last `frame`, waiting to be rendered
+    primitives: Vec<ClippedPrimitive>,
+    textures_delta: TexturesDelta,
+}
+
+impl Overlay {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+        scale_factor: f32,
+    ) -> Self {
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            OverlayUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "overlay_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let texture_bind_group_layout = bind_groups.layout(
+            device,
+            "texture_bind_group_layout",
+            bind_group::TEXTURE_LAYOUT,
+        );
+
+        // Managed textures hold sRGB values, user textures get converted to sRGB when sampled
+        let pipeline = pipelines.get(device, bind_groups, &ui_pipeline_key(OVERLAY_FRAG));
+        let user_pipeline = pipelines.get(device, bind_groups, &ui_pipeline_key(OVERLAY_USER_FRAG));
+
+        let composite_pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(COMPOSITE_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::TEXTURE_LAYOUT)],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: PREMULTIPLIED_BLEND,
+                    alpha_blend: PREMULTIPLIED_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            context: Context::default(),
+            start: Instant::now(),
+            events: Vec::new(),
+            pointer_pos: Pos2::ZERO,
+            modifiers: Modifiers::default(),
+            size,
+            scale_factor,
+            pipeline,
+            user_pipeline,
+            composite_pipeline,
+            ui_target: None,
+            uniform_allocation,
+            uniform_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+            // Nearest filtering so individual texels stay visible when zooming in
+            user_sampler: device.create_sampler(&SamplerDescriptor {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Nearest,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Nearest,
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare: CompareFunction::Always,
+            }),
+            next_user_texture: 0,
+            primitives: Vec::new(),
+            textures_delta: TexturesDelta::default(),
+        }
+    }
+
+    // For moving the UI to another window
+    pub fn set_window(&mut self, size: PhysicalSize<u32>, scale_factor: f32) {
+        self.size = size;
+        self.scale_factor = scale_factor;
+        self.events.push(Event::PointerGone);
+    }
+
+    // Feeds a window event to egui, returns true if the UI consumed it
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Resized(size) => self.size = *size,
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size,
+            } => {
+                self.scale_factor = *scale_factor as f32;
+                self.size = **new_inner_size;
+            }
+            WindowEvent::Focused(focused) => self.events.push(Event::WindowFocused(*focused)),
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_pos = Pos2::new(
+                    position.x as f32 / self.scale_factor,
+                    position.y as f32 / self.scale_factor,
+                );
+                self.events.push(Event::PointerMoved(self.pointer_pos));
+            }
+            WindowEvent::CursorLeft { .. } => self.events.push(Event::PointerGone),
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                self.events.push(Event::PointerButton {
+                    pos: self.pointer_pos,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (unit, delta) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (MouseWheelUnit::Line, egui::vec2(*x, *y)),
+                    MouseScrollDelta::PixelDelta(delta) => (
+                        MouseWheelUnit::Point,
+                        egui::vec2(delta.x as f32, delta.y as f32),
+                    ),
+                };
+                self.events.push(Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers: self.modifiers,
+                });
+            }
+            WindowEvent::ReceivedCharacter(ch) if !ch.is_control() => {
+                self.events.push(Event::Text(ch.to_string()));
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let pressed = *state == ElementState::Pressed;
+                match keycode {
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => {
+                        self.modifiers.shift = pressed
+                    }
+                    VirtualKeyCode::LAlt | VirtualKeyCode::RAlt => self.modifiers.alt = pressed,
+                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => {
+                        self.modifiers.ctrl = pressed;
+                        self.modifiers.command = pressed;
+                    }
+                    _ => (),
+                }
+
+                if let Some(key) = key_from_keycode(*keycode) {
+                    self.events.push(Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed,
+                        repeat: false,
+                        modifiers: self.modifiers,
+                    });
+                }
+            }
+            _ => (),
+        }
+
+        match event {
+            WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. } => self.context.wants_pointer_input(),
+            WindowEvent::KeyboardInput { .. } | WindowEvent::ReceivedCharacter(_) => {
+                self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    // Runs the UI code for this frame; the result gets drawn by the next `render`
+    pub fn frame(&mut self, run_ui: impl FnMut(&Context)) {
+        let mut raw_input = RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                egui::vec2(
+                    self.size.width as f32 / self.scale_factor,
+                    self.size.height as f32 / self.scale_factor,
+                ),
+            )),
+            max_texture_side: Some(8192),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: mem::take(&mut self.events),
+            focused: true,
+            ..RawInput::default()
+        };
+        raw_input
+            .viewports
+            .entry(ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(self.scale_factor);
+
+        let output = self.context.run(raw_input, run_ui);
+
+        self.textures_delta.append(output.textures_delta);
+        self.primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        view: &TextureView,
+        sampler: &Sampler,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("overlay_texture_bind_group"),
+        })
+    }
+
+    // Makes a texture created elsewhere available to `egui::Image`
+    pub fn register_user_texture(&mut self, device: &Device, view: &TextureView) -> TextureId {
+        let id = TextureId::User(self.next_user_texture);
+        self.next_user_texture += 1;
+
+        self.set_user_texture(device, id, view);
+        id
+    }
+
+    pub fn set_user_texture(&mut self, device: &Device, id: TextureId, view: &TextureView) {
+        let bind_group = self.create_bind_group(device, view, &self.user_sampler);
+        self.textures.insert(id, (None, bind_group));
+    }
+
+    pub fn free_user_texture(&mut self, id: TextureId) {
+        self.textures.remove(&id);
+    }
+
+    fn update_texture(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        id: TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let [width, height] = delta.image.size();
+        let texels: Vec<u8> = match &delta.image {
+            ImageData::Color(image) => image.pixels.iter().flat_map(|c| c.to_array()).collect(),
+            ImageData::Font(image) => image
+                .srgba_pixels(None)
+                .flat_map(|c| c.to_array())
+                .collect(),
+        };
+
+        // Partial updates go into the existing texture, everything else replaces it
+        if delta.pos.is_none() {
+            let texture = device.create_texture(&TextureDescriptor {
+                size: Extent3d {
+                    width: width as u32,
+                    height: height as u32,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                // Sampled without decoding, the UI is drawn in sRGB space
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+                label: Some("overlay_texture"),
+            });
+
+            let sampler = device.create_sampler(&SamplerDescriptor {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: filter_mode(delta.options.magnification),
+                min_filter: filter_mode(delta.options.minification),
+                mipmap_filter: FilterMode::Nearest,
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare: CompareFunction::Always,
+            });
+
+            let bind_group =
+                self.create_bind_group(device, &texture.create_default_view(), &sampler);
+
+            self.textures.insert(id, (Some(texture), bind_group));
+        }
+
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        if let Some((Some(texture), _)) = self.textures.get(&id) {
+            texture::copy_texels_to_texture(
+                device,
+                encoder,
+                &texels,
+                width as u32,
+                height as u32,
+                texture,
+                0,
+                Origin3d {
+                    x: x as u32,
+                    y: y as u32,
+                    z: 0,
+                },
+            );
+        }
+    }
+
+    fn update_ui_target(&mut self, device: &Device) {
+        if self.ui_target.as_ref().is_some_and(|t| t.size == self.size) {
+            return;
+        }
+
+        let texture = device.create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width: self.size.width.max(1),
+                height: self.size.height.max(1),
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: UI_FORMAT,
+            usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED,
+            label: Some("overlay_ui_target"),
+        });
+        let view = texture.create_default_view();
+        let bind_group = self.create_bind_group(device, &view, &self.user_sampler);
+
+        self.ui_target = Some(UiTarget {
+            size: self.size,
+            _texture: texture,
+            view,
+            bind_group,
+        });
+    }
+
+    pub fn render(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        uniform_pool: &BufferPool,
+    ) {
+        let textures_delta = mem::take(&mut self.textures_delta);
+        for (id, delta) in &textures_delta.set {
+            self.update_texture(device, encoder, *id, delta);
+        }
+
+        let uniforms = OverlayUniforms {
+            screen_size: [
+                self.size.width as f32 / self.scale_factor,
+                self.size.height as f32 / self.scale_factor,
+            ],
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+
+        // Gather all meshes into one vertex and one index buffer
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut draws = Vec::new();
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &self.primitives
+        {
+            let mesh = match primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue,
+            };
+
+            let first_index = indices.len() as u32;
+            draws.push((
+                *clip_rect,
+                mesh.texture_id,
+                first_index..first_index + mesh.indices.len() as u32,
+                vertices.len() as i32,
+            ));
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        if !draws.is_empty() {
+            self.update_ui_target(device);
+            let ui_target = self.ui_target.as_ref().unwrap();
+
+            let vertex_buffer = device
+                .create_buffer_with_data(bytemuck::cast_slice(&vertices), BufferUsage::VERTEX);
+            let index_buffer =
+                device.create_buffer_with_data(bytemuck::cast_slice(&indices), BufferUsage::INDEX);
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: &ui_target.view,
+                    resolve_target: None,
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    clear_color: wgpu::Color::TRANSPARENT,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_vertex_buffer(0, &vertex_buffer, 0, 0);
+            render_pass.set_index_buffer(&index_buffer, 0, 0);
+
+            let mut current_pipeline = None;
+            for (clip_rect, texture_id, index_range, base_vertex) in draws {
+                // Clip rectangles are in points, scissor rectangles in pixels
+                let min_x = (clip_rect.min.x * self.scale_factor).round().max(0.0) as u32;
+                let min_y = (clip_rect.min.y * self.scale_factor).round().max(0.0) as u32;
+                let max_x =
+                    ((clip_rect.max.x * self.scale_factor).round() as u32).min(self.size.width);
+                let max_y =
+                    ((clip_rect.max.y * self.scale_factor).round() as u32).min(self.size.height);
+                if max_x <= min_x || max_y <= min_y {
+                    continue;
+                }
+
+                let bind_group = match self.textures.get(&texture_id) {
+                    Some((_, bind_group)) => bind_group,
+                    None => continue,
+                };
+
+                let pipeline = match texture_id {
+                    TextureId::Managed(_) => &self.pipeline,
+                    TextureId::User(_) => &self.user_pipeline,
+                };
+                if !current_pipeline.is_some_and(|current| Arc::ptr_eq(current, pipeline)) {
+                    render_pass.set_pipeline(pipeline);
+                    render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                    current_pipeline = Some(pipeline);
+                }
+
+                render_pass.set_scissor_rect(min_x, min_y, max_x - min_x, max_y - min_y);
+                render_pass.set_bind_group(1, bind_group, &[]);
+                render_pass.draw_indexed(index_range, base_vertex, 0..1);
+            }
+            drop(render_pass);
+
+            // Composite the finished UI on top of whatever was rendered before
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: target,
+                    resolve_target: None,
+                    load_op: LoadOp::Load,
+                    store_op: StoreOp::Store,
+                    clear_color: wgpu::Color::TRANSPARENT,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.composite_pipeline);
+            render_pass.set_bind_group(0, &ui_target.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::assets::Assets;
+use crate::billboard::Atlas;
+use crate::buffer_pool::BufferPool;
+use crate::demo::{Demo, DemoContext};
+use crate::gpu_particles::GpuParticleSystem;
+use crate::particles::{Emitter, ParticleSystem};
+use cgmath::{Point3, Vector3};
+use playground_math::{Camera, Projection};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use wgpu::{
+    Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+fn particle_camera(size: PhysicalSize<u32>) -> Camera {
+    Camera {
+        eye: (0.0, 1.5, 5.0).into(),
+        target: (0.0, 1.0, 0.0).into(),
+        up: Vector3::unit_y(),
+        aspect: size.width.max(1) as f32 / size.height.max(1) as f32,
+        fovy: 45.0,
+        znear: 0.1,
+        zfar: 100.0,
+        projection: Projection::Perspective,
+    }
+}
+
+// An emitter's tunables, as the UI changes them
+#[derive(Serialize, Deserialize)]
+struct EmitterSettings {
+    position: [f32; 3],
+    spawn_rate: f32,
+    lifetime: f32,
+    velocity: [f32; 3],
+    spread: f32,
+    acceleration: [f32; 3],
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    size: f32,
+}
+
+impl EmitterSettings {
+    fn new(emitter: &Emitter) -> Self {
+        Self {
+            position: emitter.position.into(),
+            spawn_rate: emitter.spawn_rate,
+            lifetime: emitter.lifetime,
+            velocity: emitter.velocity.into(),
+            spread: emitter.spread,
+            acceleration: emitter.acceleration.into(),
+            start_color: emitter.start_color.to_array(),
+            end_color: emitter.end_color.to_array(),
+            size: emitter.size,
+        }
+    }
+
+    fn apply(&self, emitter: &mut Emitter) {
+        let [r, g, b, a] = self.start_color;
+        let [end_r, end_g, end_b, end_a] = self.end_color;
+        emitter.position = self.position.into();
+        emitter.spawn_rate = self.spawn_rate;
+        emitter.lifetime = self.lifetime;
+        emitter.velocity = self.velocity.into();
+        emitter.spread = self.spread;
+        emitter.acceleration = self.acceleration.into();
+        emitter.start_color = playground_math::Color::new(r, g, b, a);
+        emitter.end_color = playground_math::Color::new(end_r, end_g, end_b, end_a);
+        emitter.size = self.size;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParticleSettings {
+    paused: bool,
+    max_particles: usize,
+    // In the order of the demo's emitters
+    emitters: Vec<EmitterSettings>,
+}
+
+// A fountain of sparks and a column of smoke, simulated on the CPU
+pub struct ParticleDemo {
+    camera: Camera,
+    particles: ParticleSystem,
+    paused: bool,
+}
+
+impl ParticleDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = particle_camera(ctx.size);
+        let atlas = Atlas::generated(ctx.device, ctx.queue, ctx.bind_groups);
+        let mut particles = ParticleSystem::new(
+            ctx.device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            Arc::new(atlas),
+        );
+        particles.emitters.push(Emitter {
+            spawn_rate: 10000.0,
+            lifetime: 2.5,
+            velocity: Vector3::new(0.0, 3.0, 0.0),
+            spread: 0.8,
+            acceleration: Vector3::new(0.0, -2.5, 0.0),
+            start_color: playground_math::Color::new(1.0, 0.6, 0.1, 1.0),
+            end_color: playground_math::Color::new(0.8, 0.05, 0.0, 0.0),
+            size: 0.03,
+            ..Emitter::new(Point3::new(-1.0, 0.0, 0.0))
+        });
+        particles.emitters.push(Emitter {
+            spawn_rate: 400.0,
+            lifetime: 5.0,
+            velocity: Vector3::new(0.0, 0.6, 0.0),
+            spread: 0.15,
+            acceleration: Vector3::new(0.1, 0.05, 0.0),
+            start_color: playground_math::Color::new(0.3, 0.3, 0.35, 0.3),
+            end_color: playground_math::Color::new(0.1, 0.1, 0.1, 0.0),
+            size: 0.4,
+            ..Emitter::new(Point3::new(1.0, 0.0, 0.0))
+        });
+
+        Self {
+            camera,
+            particles,
+            paused: false,
+        }
+    }
+}
+
+impl Demo for ParticleDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        if !self.paused {
+            self.particles.update(dt);
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.particles
+            .prepare(device, encoder, uniform_pool, &self.camera);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} particles", self.particles.len()));
+        ui.checkbox(&mut self.paused, "Paused");
+        ui.add(
+            egui::Slider::new(&mut self.particles.max_particles, 0..=200_000).text("Max particles"),
+        );
+        for (index, emitter) in self.particles.emitters.iter_mut().enumerate() {
+            ui.collapsing(format!("Emitter {}", index + 1), |ui| emitter.ui(ui));
+        }
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn settings(&self) -> Option<serde_json::Value> {
+        let settings = ParticleSettings {
+            paused: self.paused,
+            max_particles: self.particles.max_particles,
+            emitters: self
+                .particles
+                .emitters
+                .iter()
+                .map(EmitterSettings::new)
+                .collect(),
+        };
+        serde_json::to_value(settings).ok()
+    }
+
+    fn apply_settings(&mut self, settings: serde_json::Value) -> Result<(), failure::Error> {
+        let settings: ParticleSettings = serde_json::from_value(settings)?;
+        self.paused = settings.paused;
+        self.particles.max_particles = settings.max_particles;
+        for (emitter, saved) in self.particles.emitters.iter_mut().zip(&settings.emitters) {
+            saved.apply(emitter);
+        }
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+        self.particles.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.particles.release(ctx.uniform_pool);
+    }
+}
+
+// What `GpuParticleSystem::ui` changes
+#[derive(Serialize, Deserialize)]
+struct GpuParticleSettings {
+    paused: bool,
+    position: [f32; 3],
+    velocity: [f32; 3],
+    spread: f32,
+    acceleration: [f32; 3],
+    lifetime: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    size: f32,
+}
+
+// Half a million particles that are simulated and drawn without leaving the GPU
+pub struct GpuParticleDemo {
+    camera: Camera,
+    particles: GpuParticleSystem,
+    paused: bool,
+}
+
+impl GpuParticleDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let particles = GpuParticleSystem::new(
+            ctx.device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            1 << 19,
+        );
+
+        Self {
+            camera: particle_camera(ctx.size),
+            particles,
+            paused: false,
+        }
+    }
+}
+
+impl Demo for GpuParticleDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+    }
+
+    fn step(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+    ) {
+        if !self.paused {
+            self.particles.step(device, encoder, uniform_pool, dt);
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.particles
+            .prepare(device, encoder, uniform_pool, &self.camera);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("{} particles", self.particles.count()));
+        ui.checkbox(&mut self.paused, "Paused");
+        self.particles.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn settings(&self) -> Option<serde_json::Value> {
+        let particles = &self.particles;
+        let settings = GpuParticleSettings {
+            paused: self.paused,
+            position: particles.position.into(),
+            velocity: particles.velocity.into(),
+            spread: particles.spread,
+            acceleration: particles.acceleration.into(),
+            lifetime: particles.lifetime,
+            start_color: particles.start_color.to_array(),
+            end_color: particles.end_color.to_array(),
+            size: particles.size,
+        };
+        serde_json::to_value(settings).ok()
+    }
+
+    fn apply_settings(&mut self, settings: serde_json::Value) -> Result<(), failure::Error> {
+        let settings: GpuParticleSettings = serde_json::from_value(settings)?;
+        let [r, g, b, a] = settings.start_color;
+        let [end_r, end_g, end_b, end_a] = settings.end_color;
+        let particles = &mut self.particles;
+        self.paused = settings.paused;
+        particles.position = settings.position.into();
+        particles.velocity = settings.velocity.into();
+        particles.spread = settings.spread;
+        particles.acceleration = settings.acceleration.into();
+        particles.lifetime = settings.lifetime;
+        particles.start_color = playground_math::Color::new(r, g, b, a);
+        particles.end_color = playground_math::Color::new(end_r, end_g, end_b, end_a);
+        particles.size = settings.size;
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+        self.particles.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.particles.release(ctx.uniform_pool, ctx.bind_groups);
+    }
+}
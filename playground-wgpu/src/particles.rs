@@ -0,0 +1,226 @@
+use crate::billboard::{self, Atlas, Billboard, BillboardBlend, Billboards};
+use crate::bind_group::BindGroupCache;
+use crate::buffer_pool::BufferPool;
+use crate::pipeline::PipelineCache;
+use cgmath::{InnerSpace, Point3, Vector3};
+use playground_math::{Camera, Color};
+use std::sync::Arc;
+use wgpu::{CommandEncoder, Device, RenderPass, TextureFormat};
+
+// Xorshift, random enough for scattering particles
+pub struct Rng(pub u32);
+
+impl Rng {
+    pub fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    // Somewhere in the unit ball
+    pub fn in_sphere(&mut self) -> Vector3<f32> {
+        loop {
+            let point = Vector3::new(self.next(), self.next(), self.next()) * 2.0
+                - Vector3::new(1.0, 1.0, 1.0);
+            if point.magnitude2() <= 1.0 {
+                return point;
+            }
+        }
+    }
+}
+
+// Spawns particles at a point. Colors are linear and fade from start to end over a particle's
+// life.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub position: Point3<f32>,
+    // Particles per second
+    pub spawn_rate: f32,
+    // In seconds
+    pub lifetime: f32,
+    pub velocity: Vector3<f32>,
+    // Largest random change to `velocity`, in any direction
+    pub spread: f32,
+    pub acceleration: Vector3<f32>,
+    pub start_color: Color,
+    pub end_color: Color,
+    // Width of the particle's quad in world units
+    pub size: f32,
+}
+
+impl Emitter {
+    pub fn new(position: Point3<f32>) -> Self {
+        Self {
+            position,
+            spawn_rate: 1000.0,
+            lifetime: 2.0,
+            velocity: Vector3::new(0.0, 2.0, 0.0),
+            spread: 0.5,
+            acceleration: Vector3::new(0.0, -1.0, 0.0),
+            start_color: Color::WHITE,
+            end_color: Color::TRANSPARENT,
+            size: 0.05,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            ui.add(egui::DragValue::new(&mut self.position.x).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.position.y).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.position.z).speed(0.05));
+        });
+        ui.add(
+            egui::Slider::new(&mut self.spawn_rate, 0.0..=50000.0)
+                .logarithmic(true)
+                .text("Spawn rate"),
+        );
+        ui.add(egui::Slider::new(&mut self.lifetime, 0.1..=10.0).text("Lifetime"));
+        ui.horizontal(|ui| {
+            ui.label("Velocity");
+            ui.add(egui::DragValue::new(&mut self.velocity.x).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.velocity.y).speed(0.05));
+            ui.add(egui::DragValue::new(&mut self.velocity.z).speed(0.05));
+        });
+        ui.add(egui::Slider::new(&mut self.spread, 0.0..=5.0).text("Spread"));
+        ui.add(egui::Slider::new(&mut self.acceleration.y, -10.0..=10.0).text("Gravity"));
+        ui.add(egui::Slider::new(&mut self.size, 0.005..=0.5).text("Size"));
+
+        let mut start = self.start_color.to_array();
+        let mut end = self.end_color.to_array();
+        ui.horizontal(|ui| {
+            ui.label("Color over life");
+            ui.color_edit_button_rgba_unmultiplied(&mut start);
+            ui.color_edit_button_rgba_unmultiplied(&mut end);
+        });
+        self.start_color = Color::new(start[0], start[1], start[2], start[3]);
+        self.end_color = Color::new(end[0], end[1], end[2], end[3]);
+    }
+}
+
+struct Particle {
+    position: Point3<f32>,
+    velocity: Vector3<f32>,
+    age: f32,
+    emitter: usize,
+}
+
+// Particles simulated on the CPU and drawn as glowing billboards. `update` moves them along,
+// `prepare` uploads them and `render` draws them into the demo's render pass.
+pub struct ParticleSystem {
+    pub emitters: Vec<Emitter>,
+    // Spawning stops while this many particles are alive
+    pub max_particles: usize,
+    particles: Vec<Particle>,
+    // Particles each emitter owes from earlier frames, spawn rates rarely divide evenly into them
+    pending: Vec<f32>,
+    rng: Rng,
+    billboards: Billboards,
+}
+
+impl ParticleSystem {
+    // `atlas` needs the glow of `Atlas::generated` at `billboard::GLOW_CELL`
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        atlas: Arc<Atlas>,
+    ) -> Self {
+        // Particles only ever brighten what's behind them, so they don't need sorting
+        let billboards = Billboards::new(
+            device,
+            uniform_pool,
+            bind_groups,
+            pipelines,
+            format,
+            atlas,
+            BillboardBlend::Additive,
+        );
+
+        Self {
+            emitters: Vec::new(),
+            max_particles: 100_000,
+            particles: Vec::new(),
+            pending: Vec::new(),
+            rng: Rng(0x9e37_79b9),
+            billboards,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    // Ages and moves every particle by `dt` seconds, then spawns new ones
+    pub fn update(&mut self, dt: f32) {
+        let emitters = &self.emitters;
+        self.particles.retain_mut(|particle| {
+            let emitter = match emitters.get(particle.emitter) {
+                Some(emitter) => emitter,
+                None => return false,
+            };
+            particle.age += dt;
+            particle.velocity += emitter.acceleration * dt;
+            particle.position += particle.velocity * dt;
+            particle.age < emitter.lifetime
+        });
+
+        self.pending.resize(self.emitters.len(), 0.0);
+        for (index, emitter) in self.emitters.iter().enumerate() {
+            let pending = &mut self.pending[index];
+            *pending += emitter.spawn_rate * dt;
+            let count = pending.floor();
+            *pending -= count;
+
+            let room = self.max_particles.saturating_sub(self.particles.len());
+            for _ in 0..(count as usize).min(room) {
+                let velocity = emitter.velocity + self.rng.in_sphere() * emitter.spread;
+                // Spread out over the frame, so they don't leave in clumps
+                let age = self.rng.next() * dt;
+                self.particles.push(Particle {
+                    position: emitter.position + velocity * age,
+                    velocity,
+                    age,
+                    emitter: index,
+                });
+            }
+        }
+    }
+
+    // Uploads the particles as they are now, for the next `render`
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+    ) {
+        let billboards: Vec<Billboard> = self
+            .particles
+            .iter()
+            .map(|particle| {
+                let emitter = &self.emitters[particle.emitter];
+                let life = particle.age / emitter.lifetime;
+                Billboard {
+                    position: particle.position,
+                    size: [emitter.size, emitter.size],
+                    cell: billboard::GLOW_CELL,
+                    color: emitter.start_color.lerp(&emitter.end_color, life),
+                }
+            })
+            .collect();
+        self.billboards
+            .prepare(device, encoder, uniform_pool, camera, &billboards);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        self.billboards.render(render_pass);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool) {
+        self.billboards.release(uniform_pool);
+    }
+}
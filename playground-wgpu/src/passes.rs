@@ -0,0 +1,253 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::BufferPool;
+use crate::demo::DemoContext;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BlendDescriptor, BlendFactor, BlendOperation, ColorStateDescriptor, ColorWrite,
+    CommandEncoder, CullMode, Device, IndexFormat, LoadOp, PrimitiveTopology,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+// A pass added to the frame from outside the renderer. Passes get created with whatever they
+// need from a `DemoContext` (device, queue, pools and caches) and keep it around. They get
+// recorded on a worker thread, while the demo records on the main thread.
+pub trait CustomPass: Send + Sync {
+    fn resize(&mut self, _size: PhysicalSize<u32>) {}
+
+    // Records this frame's buffer updates
+    fn update(
+        &mut self,
+        _device: &Device,
+        _encoder: &mut CommandEncoder,
+        _uniform_pool: &BufferPool,
+    ) {
+    }
+
+    // Settings, shown in the debug window
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+
+    // Draws on top of the demo's output in `target`, before the overlay
+    fn render(&self, encoder: &mut CommandEncoder, target: &TextureView);
+}
+
+struct Entry {
+    name: String,
+    enabled: bool,
+    pass: Box<dyn CustomPass>,
+}
+
+// The custom passes, run in the order they were added
+pub struct Passes {
+    entries: Vec<Entry>,
+}
+
+impl Passes {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    // Replaces a pass that was added with the same name
+    pub fn add(&mut self, name: &str, enabled: bool, pass: Box<dyn CustomPass>) {
+        self.remove(name);
+        self.entries.push(Entry {
+            name: name.to_string(),
+            enabled,
+            pass,
+        });
+    }
+
+    // Demos remove the passes they added when they get released
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn CustomPass>> {
+        let index = self.entries.iter().position(|entry| entry.name == name)?;
+        Some(self.entries.remove(index).pass)
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        for entry in &mut self.entries {
+            entry.pass.resize(size);
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+    ) {
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled) {
+            encoder.push_debug_group(&entry.name);
+            entry.pass.update(device, encoder, uniform_pool);
+            encoder.pop_debug_group();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        ui.collapsing("Custom passes", |ui| {
+            for entry in &mut self.entries {
+                ui.checkbox(&mut entry.enabled, entry.name.as_str());
+                if entry.enabled {
+                    let pass = &mut entry.pass;
+                    ui.indent(entry.name.as_str(), |ui| pass.ui(ui));
+                }
+            }
+        });
+    }
+
+    // Each pass gets a debug group by its name, for finding it in RenderDoc captures
+    pub fn render(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        for entry in self.entries.iter().filter(|entry| entry.enabled) {
+            encoder.push_debug_group(&entry.name);
+            entry.pass.render(encoder, target);
+            encoder.pop_debug_group();
+        }
+    }
+}
+
+const FILM_GRAIN_FRAG: Shader = Shader {
+    name: "film_grain.frag",
+    source: include_str!("../shaders/film_grain.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const VIGNETTE_FRAG: Shader = Shader {
+    name: "vignette.frag",
+    source: include_str!("../shaders/vignette.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Darkens the corners of the frame. Mostly here as an example of a custom pass.
+pub struct VignettePass {
+    pipeline: Arc<RenderPipeline>,
+}
+
+impl VignettePass {
+    pub fn new(
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        // The shader outputs a factor the frame gets multiplied with
+        let multiply = BlendDescriptor {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::SrcColor,
+            operation: BlendOperation::Add,
+        };
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(VIGNETTE_FRAG),
+                bind_group_layouts: Vec::new(),
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: multiply.clone(),
+                    alpha_blend: multiply,
+                    write_mask: ColorWrite::COLOR,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self { pipeline }
+    }
+}
+
+impl CustomPass for VignettePass {
+    fn render(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Noise over the whole frame, different every frame, multiplied in like the vignette. Demos add
+// it through `DemoContext::passes` for a filmic look.
+pub struct FilmGrainPass {
+    pipeline: Arc<RenderPipeline>,
+    // The `uniform::FrameUniforms`
+    frame_bind_group: Arc<BindGroup>,
+}
+
+impl FilmGrainPass {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let multiply = BlendDescriptor {
+            src_factor: BlendFactor::Zero,
+            dst_factor: BlendFactor::SrcColor,
+            operation: BlendOperation::Add,
+        };
+        let pipeline = ctx.pipelines.get(
+            ctx.device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(FILM_GRAIN_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT)],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format: ctx.format,
+                    color_blend: multiply.clone(),
+                    alpha_blend: multiply,
+                    write_mask: ColorWrite::COLOR,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            pipeline,
+            frame_bind_group: ctx.frame_bind_group.clone(),
+        }
+    }
+}
+
+impl CustomPass for FilmGrainPass {
+    fn render(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.frame_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,74 @@
+use cgmath::{Matrix4, Point3};
+use playground_math::{Aabb, Ray};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+// Something a demo lets the user click on, in world space
+pub struct PickObject {
+    pub name: String,
+    pub bounds: Aabb,
+    // Tested after the bounding box if there are any, otherwise hitting the box is enough
+    pub triangles: Vec<[Point3<f32>; 3]>,
+}
+
+pub struct Hit {
+    // Index into the objects that were tested
+    pub object: usize,
+    pub distance: f32,
+}
+
+// The ray under a cursor position in the window. `view_proj` is the matrix the frame under the
+// cursor was drawn with.
+pub fn cursor_ray(
+    view_proj: &Matrix4<f32>,
+    cursor: PhysicalPosition<i32>,
+    size: PhysicalSize<u32>,
+) -> Option<Ray> {
+    if size.width == 0 || size.height == 0 {
+        return None;
+    }
+
+    let x = 2.0 * cursor.x as f32 / size.width as f32 - 1.0;
+    let y = 1.0 - 2.0 * cursor.y as f32 / size.height as f32;
+    Ray::from_ndc(view_proj, x, y)
+}
+
+// The closest object along the ray
+pub fn pick(ray: &Ray, objects: &[PickObject]) -> Option<Hit> {
+    let mut closest: Option<Hit> = None;
+
+    for (index, object) in objects.iter().enumerate() {
+        let box_distance = match ray.intersect_aabb(&object.bounds) {
+            Some(distance) => distance,
+            None => continue,
+        };
+        if closest
+            .as_ref()
+            .is_some_and(|hit| hit.distance < box_distance)
+        {
+            continue;
+        }
+
+        let distance = if object.triangles.is_empty() {
+            Some(box_distance)
+        } else {
+            object
+                .triangles
+                .iter()
+                .filter_map(|[a, b, c]| ray.intersect_triangle(*a, *b, *c))
+                .fold(None, |closest: Option<f32>, distance| {
+                    Some(closest.map_or(distance, |closest| closest.min(distance)))
+                })
+        };
+
+        if let Some(distance) = distance {
+            if closest.as_ref().is_none_or(|hit| distance < hit.distance) {
+                closest = Some(Hit {
+                    object: index,
+                    distance,
+                });
+            }
+        }
+    }
+
+    closest
+}
@@ -0,0 +1,214 @@
+use crate::bind_group::{BindGroupCache, LayoutKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::{
+    BufferAddress, ColorStateDescriptor, ComputePipeline, ComputePipelineDescriptor, CullMode,
+    DepthStencilStateDescriptor, Device, FrontFace, IndexFormat, InputStepMode, PipelineLayout,
+    PipelineLayoutDescriptor, PrimitiveTopology, ProgrammableStageDescriptor,
+    RasterizationStateDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModule,
+    ShaderStage, VertexAttributeDescriptor, VertexBufferDescriptor, VertexStateDescriptor,
+};
+
+// GLSL source of a single shader stage. The name identifies the shader in the caches.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Shader {
+    pub name: &'static str,
+    pub source: &'static str,
+    pub stage: ShaderStage,
+}
+
+impl Shader {
+    fn compile(&self, device: &Device) -> ShaderModule {
+        let ty = if self.stage == ShaderStage::VERTEX {
+            glsl_to_spirv::ShaderType::Vertex
+        } else if self.stage == ShaderStage::FRAGMENT {
+            glsl_to_spirv::ShaderType::Fragment
+        } else {
+            glsl_to_spirv::ShaderType::Compute
+        };
+
+        let spirv = glsl_to_spirv::compile(self.source, ty)
+            .unwrap_or_else(|err| panic!("Failed to compile {}: {}", self.name, err));
+        let data = wgpu::read_spirv(spirv).unwrap();
+
+        device.create_shader_module(&data)
+    }
+}
+
+// A single triangle covering the whole target, for passes that only run a fragment shader
+pub const FULLSCREEN_VERT: Shader = Shader {
+    name: "fullscreen.vert",
+    source: include_str!("../shaders/fullscreen.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+// Owned, hashable counterpart of `VertexBufferDescriptor`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    pub stride: BufferAddress,
+    pub step_mode: InputStepMode,
+    pub attributes: Vec<VertexAttributeDescriptor>,
+}
+
+impl From<VertexBufferDescriptor<'_>> for VertexLayout {
+    fn from(descriptor: VertexBufferDescriptor) -> Self {
+        Self {
+            stride: descriptor.stride,
+            step_mode: descriptor.step_mode,
+            attributes: descriptor.attributes.to_vec(),
+        }
+    }
+}
+
+// Everything that makes two render pipelines different from each other
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub vertex_shader: Shader,
+    pub fragment_shader: Option<Shader>,
+    pub bind_group_layouts: Vec<LayoutKey>,
+    pub vertex_buffers: Vec<VertexLayout>,
+    pub index_format: IndexFormat,
+    pub primitive_topology: PrimitiveTopology,
+    pub cull_mode: CullMode,
+    pub color_states: Vec<ColorStateDescriptor>,
+    pub depth_stencil_state: Option<DepthStencilStateDescriptor>,
+    pub sample_count: u32,
+}
+
+// Everything that makes two compute pipelines different from each other
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComputePipelineKey {
+    pub shader: Shader,
+    pub bind_group_layouts: Vec<LayoutKey>,
+}
+
+// Creates every render and compute pipeline (and the shader modules and layouts it needs) only
+// once
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: HashMap<Shader, ShaderModule>,
+    layouts: HashMap<Vec<LayoutKey>, PipelineLayout>,
+    pipelines: HashMap<PipelineKey, Arc<RenderPipeline>>,
+    compute_pipelines: HashMap<ComputePipelineKey, Arc<ComputePipeline>>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(
+        &mut self,
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        key: &PipelineKey,
+    ) -> Arc<RenderPipeline> {
+        if let Some(pipeline) = self.pipelines.get(key) {
+            return pipeline.clone();
+        }
+
+        for shader in std::iter::once(&key.vertex_shader).chain(&key.fragment_shader) {
+            self.compile(device, shader);
+        }
+        self.create_layout(device, bind_groups, &key.bind_group_layouts);
+
+        let vertex_buffers: Vec<_> = key
+            .vertex_buffers
+            .iter()
+            .map(|layout| VertexBufferDescriptor {
+                stride: layout.stride,
+                step_mode: layout.step_mode,
+                attributes: &layout.attributes,
+            })
+            .collect();
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            layout: &self.layouts[&key.bind_group_layouts],
+            vertex_stage: ProgrammableStageDescriptor {
+                module: &self.shaders[&key.vertex_shader],
+                entry_point: "main",
+            },
+            fragment_stage: key.fragment_shader.as_ref().map(|shader| {
+                ProgrammableStageDescriptor {
+                    module: &self.shaders[shader],
+                    entry_point: "main",
+                }
+            }),
+            // describes how to process primitives before they are sent to the fragment shader
+            rasterization_state: Some(RasterizationStateDescriptor {
+                front_face: FrontFace::Ccw,
+                cull_mode: key.cull_mode,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &key.color_states,
+            primitive_topology: key.primitive_topology,
+            depth_stencil_state: key.depth_stencil_state.clone(),
+            vertex_state: VertexStateDescriptor {
+                index_format: key.index_format,
+                vertex_buffers: &vertex_buffers,
+            },
+            sample_count: key.sample_count,
+            // Specifies which samples should be active, !0 is all of them
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+
+        let pipeline = Arc::new(pipeline);
+        self.pipelines.insert(key.clone(), pipeline.clone());
+        pipeline
+    }
+
+    pub fn get_compute(
+        &mut self,
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        key: &ComputePipelineKey,
+    ) -> Arc<ComputePipeline> {
+        if let Some(pipeline) = self.compute_pipelines.get(key) {
+            return pipeline.clone();
+        }
+
+        self.compile(device, &key.shader);
+        self.create_layout(device, bind_groups, &key.bind_group_layouts);
+
+        let pipeline = Arc::new(device.create_compute_pipeline(&ComputePipelineDescriptor {
+            layout: &self.layouts[&key.bind_group_layouts],
+            compute_stage: ProgrammableStageDescriptor {
+                module: &self.shaders[&key.shader],
+                entry_point: "main",
+            },
+        }));
+        self.compute_pipelines.insert(key.clone(), pipeline.clone());
+        pipeline
+    }
+
+    fn compile(&mut self, device: &Device, shader: &Shader) {
+        if !self.shaders.contains_key(shader) {
+            self.shaders.insert(*shader, shader.compile(device));
+        }
+    }
+
+    fn create_layout(
+        &mut self,
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        layouts: &[LayoutKey],
+    ) {
+        if self.layouts.contains_key(layouts) {
+            return;
+        }
+
+        let bind_group_layouts: Vec<_> = layouts
+            .iter()
+            .map(|layout| bind_groups.layout_from_key(device, "bind_group_layout", layout))
+            .collect();
+        let bind_group_layouts: Vec<_> = bind_group_layouts.iter().map(|l| &**l).collect();
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            bind_group_layouts: &bind_group_layouts,
+        });
+        self.layouts.insert(layouts.to_vec(), layout);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::bind_group;
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineKey, Shader, VertexLayout};
+use cgmath::Vector3;
+use std::f32::consts::PI;
+use std::mem;
+use wgpu::{
+    BlendDescriptor, Buffer, BufferAddress, BufferUsage, ColorStateDescriptor, ColorWrite,
+    CullMode, Device, IndexFormat, InputStepMode, PrimitiveTopology, RenderPass, ShaderStage,
+    TextureFormat, VertexAttributeDescriptor, VertexFormat,
+};
+
+const SHAPE_VERT: Shader = Shader {
+    name: "shape.vert",
+    source: include_str!("../shaders/shape.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHAPE_FRAG: Shader = Shader {
+    name: "shape.frag",
+    source: include_str!("../shaders/shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const LIT_SHAPE_FRAG: Shader = Shader {
+    name: "lit_shape.frag",
+    source: include_str!("../shaders/lit_shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const SHADOWED_SHAPE_FRAG: Shader = Shader {
+    name: "shadowed_shape.frag",
+    source: include_str!("../shaders/shadowed_shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CubeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+unsafe impl bytemuck::Pod for CubeVertex {}
+
+unsafe impl bytemuck::Zeroable for CubeVertex {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CubeInstance {
+    // Where it sits, and its size in w
+    pub offset_scale: [f32; 4],
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for CubeInstance {}
+
+unsafe impl bytemuck::Zeroable for CubeInstance {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct QuadVertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+unsafe impl bytemuck::Pod for QuadVertex {}
+
+unsafe impl bytemuck::Zeroable for QuadVertex {}
+
+// A unit quad facing +z, the top of the texture at the top
+const QUAD_VERTICES: &[QuadVertex] = &[
+    QuadVertex {
+        position: [-0.5, -0.5, 0.0],
+        tex_coords: [0.0, 1.0],
+    },
+    QuadVertex {
+        position: [0.5, -0.5, 0.0],
+        tex_coords: [1.0, 1.0],
+    },
+    QuadVertex {
+        position: [0.5, 0.5, 0.0],
+        tex_coords: [1.0, 0.0],
+    },
+    QuadVertex {
+        position: [-0.5, 0.5, 0.0],
+        tex_coords: [0.0, 0.0],
+    },
+];
+
+const QUAD_INDICES: &[u16] = &[0, 1, 2, 0, 2, 3];
+
+// A cube around the origin with sides of 1, with flat normals. Returns the vertices and indices.
+fn cube() -> (Vec<CubeVertex>, Vec<u16>) {
+    // The normal of every face and two directions along it, crossing to the normal
+    let faces = [
+        (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()),
+        (Vector3::unit_y(), Vector3::unit_z(), Vector3::unit_x()),
+        (Vector3::unit_z(), Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_z(), Vector3::unit_y()),
+        (-Vector3::unit_y(), Vector3::unit_x(), Vector3::unit_z()),
+        (-Vector3::unit_z(), Vector3::unit_y(), Vector3::unit_x()),
+    ];
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for &(normal, u, v) in &faces {
+        let first = vertices.len() as u16;
+        for &(a, b) in &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+            let position: Vector3<f32> = normal * 0.5 + u * a + v * b;
+            vertices.push(CubeVertex {
+                position: position.into(),
+                normal: normal.into(),
+            });
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    (vertices, indices)
+}
+
+// `count` colored cubes resting on y = 0, `radius` around a larger white one
+pub fn ring_of_cubes(count: usize, radius: f32) -> Vec<CubeInstance> {
+    let mut instances = vec![CubeInstance {
+        offset_scale: [0.0, 0.75, 0.0, 1.5],
+        color: [0.9, 0.9, 0.9, 1.0],
+    }];
+    for index in 0..count {
+        let angle = 2.0 * PI * index as f32 / count as f32;
+        let (sin, cos) = angle.sin_cos();
+        let hue = index as f32 / count as f32;
+        let channel = |offset: f32| 0.5 + 0.5 * (2.0 * PI * (hue + offset)).cos();
+        instances.push(CubeInstance {
+            offset_scale: [sin * radius, 0.4, cos * radius, 0.8],
+            color: [channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0), 1.0],
+        });
+    }
+    instances
+}
+
+// `count` colored cubes resting on y = 0, zigzagging away from the origin down -z with `spacing`
+// between them
+pub fn row_of_cubes(count: usize, spacing: f32) -> Vec<CubeInstance> {
+    (0..count)
+        .map(|index| {
+            let side = if index % 2 == 0 { -1.0 } else { 1.0 };
+            let hue = index as f32 / count as f32;
+            let channel = |offset: f32| 0.5 + 0.5 * (2.0 * PI * (hue + offset)).cos();
+            CubeInstance {
+                offset_scale: [side * 0.75, 0.5, -spacing * index as f32, 1.0],
+                color: [channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0), 1.0],
+            }
+        })
+        .collect()
+}
+
+// Instanced cubes drawn with shape.vert, lit by a fixed sun. The camera uniforms go at set 0.
+pub struct Cubes {
+    vertices: Buffer,
+    indices: Buffer,
+    index_count: u32,
+    instances: Buffer,
+    instance_count: u32,
+}
+
+impl Cubes {
+    pub fn new(device: &Device, instances: &[CubeInstance]) -> Self {
+        let (vertices, indices) = cube();
+        Self {
+            vertices: device
+                .create_buffer_with_data(bytemuck::cast_slice(&vertices), BufferUsage::VERTEX),
+            indices: device
+                .create_buffer_with_data(bytemuck::cast_slice(&indices), BufferUsage::INDEX),
+            index_count: indices.len() as u32,
+            instances: device
+                .create_buffer_with_data(bytemuck::cast_slice(instances), BufferUsage::VERTEX),
+            instance_count: instances.len() as u32,
+        }
+    }
+
+    // Depth tested, into a single `format` target
+    pub fn pipeline_key(format: TextureFormat) -> PipelineKey {
+        PipelineKey {
+            vertex_shader: SHAPE_VERT,
+            fragment_shader: Some(SHAPE_FRAG),
+            bind_group_layouts: vec![bind_group::layout_key(bind_group::UNIFORM_LAYOUT)],
+            vertex_buffers: vec![
+                VertexLayout {
+                    stride: mem::size_of::<CubeVertex>() as BufferAddress,
+                    step_mode: InputStepMode::Vertex,
+                    attributes: vec![
+                        VertexAttributeDescriptor {
+                            offset: 0,
+                            shader_location: 0,
+                            format: VertexFormat::Float3,
+                        },
+                        VertexAttributeDescriptor {
+                            offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                            shader_location: 1,
+                            format: VertexFormat::Float3,
+                        },
+                    ],
+                },
+                VertexLayout {
+                    stride: mem::size_of::<CubeInstance>() as BufferAddress,
+                    step_mode: InputStepMode::Instance,
+                    attributes: vec![
+                        VertexAttributeDescriptor {
+                            offset: 0,
+                            shader_location: 2,
+                            format: VertexFormat::Float4,
+                        },
+                        VertexAttributeDescriptor {
+                            offset: mem::size_of::<[f32; 4]>() as BufferAddress,
+                            shader_location: 3,
+                            format: VertexFormat::Float4,
+                        },
+                    ],
+                },
+            ],
+            index_format: IndexFormat::Uint16,
+            primitive_topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::Back,
+            color_states: vec![ColorStateDescriptor {
+                format,
+                color_blend: BlendDescriptor::REPLACE,
+                alpha_blend: BlendDescriptor::REPLACE,
+                write_mask: ColorWrite::ALL,
+            }],
+            depth_stencil_state: Some(depth::depth_stencil_state()),
+            sample_count: 1,
+        }
+    }
+
+    // Lit by a directional light instead of the fixed sun, with `uniform::LightUniforms` at set 1
+    pub fn lit_pipeline_key(format: TextureFormat) -> PipelineKey {
+        let mut key = Self::pipeline_key(format);
+        key.fragment_shader = Some(LIT_SHAPE_FRAG);
+        key.bind_group_layouts
+            .push(bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT));
+        key
+    }
+
+    // Lit by a directional light instead of the fixed sun, and shadowed by a
+    // `shadow::ShadowMap`. Its bind group goes at set 1 and the light's uniforms at set 2.
+    pub fn shadowed_pipeline_key(format: TextureFormat) -> PipelineKey {
+        let mut key = Self::pipeline_key(format);
+        key.fragment_shader = Some(SHADOWED_SHAPE_FRAG);
+        key.bind_group_layouts.extend(vec![
+            bind_group::layout_key(bind_group::SHADOW_LAYOUT),
+            bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+        ]);
+        key
+    }
+
+    // The pipeline and the camera have to be set already
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, &self.vertices, 0, 0);
+        render_pass.set_vertex_buffer(1, &self.instances, 0, 0);
+        render_pass.set_index_buffer(&self.indices, 0, 0);
+        draw_stats::record_triangles(self.index_count, self.instance_count);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..self.instance_count);
+    }
+}
+
+// A unit quad facing +z with texture coordinates, laid out for shader.vert
+pub struct Quad {
+    vertices: Buffer,
+    indices: Buffer,
+}
+
+impl Quad {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            vertices: device
+                .create_buffer_with_data(bytemuck::cast_slice(QUAD_VERTICES), BufferUsage::VERTEX),
+            indices: device
+                .create_buffer_with_data(bytemuck::cast_slice(QUAD_INDICES), BufferUsage::INDEX),
+        }
+    }
+
+    pub fn vertex_layout() -> VertexLayout {
+        VertexLayout {
+            stride: mem::size_of::<QuadVertex>() as BufferAddress,
+            step_mode: InputStepMode::Vertex,
+            attributes: vec![
+                VertexAttributeDescriptor {
+                    offset: 0,
+                    shader_location: 0,
+                    format: VertexFormat::Float3,
+                },
+                VertexAttributeDescriptor {
+                    offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                    shader_location: 1,
+                    format: VertexFormat::Float2,
+                },
+            ],
+        }
+    }
+
+    // The pipeline and its bind groups have to be set already
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, &self.vertices, 0, 0);
+        render_pass.set_index_buffer(&self.indices, 0, 0);
+        draw_stats::record_triangles(QUAD_INDICES.len() as u32, 1);
+        render_pass.draw_indexed(0..QUAD_INDICES.len() as u32, 0, 0..1);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::bind_group::BindGroupCache;
+use crate::buffer_pool::BufferPool;
+use crate::terrain::{Heightfield, TerrainMaterial, Tile, TileMesh};
+use cgmath::Point2;
+use playground_math::{Camera, Frustum, Perlin};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use wgpu::{BufferAddress, BufferUsage, CommandEncoder, Device, RenderPass};
+
+// Quads along each side of a chunk
+const CHUNK_QUADS: u32 = 64;
+// Distance between neighbouring samples
+const SPACING: f32 = 0.5;
+const CHUNK_SIZE: f32 = CHUNK_QUADS as f32 * SPACING;
+// Room for a few dozen chunks per block
+const BLOCK_SIZE: BufferAddress = 8 << 20;
+
+// Heights from fractal noise, the same for the same settings
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TerrainGenerator {
+    pub seed: u32,
+    pub octaves: u32,
+    // Of the first octave, per world unit
+    pub frequency: f32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    // Height of the highest peaks
+    pub height: f32,
+}
+
+impl Default for TerrainGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            octaves: 6,
+            frequency: 0.01,
+            lacunarity: 2.0,
+            gain: 0.5,
+            height: 40.0,
+        }
+    }
+}
+
+impl TerrainGenerator {
+    pub fn height(&self, perlin: &Perlin, x: f32, z: f32) -> f32 {
+        let noise = perlin.fbm(
+            x * self.frequency,
+            z * self.frequency,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+        );
+        (noise + 0.5) * self.height
+    }
+
+    // One extra sample on every side, so the normals along the edges match the neighbours'
+    fn chunk_mesh(&self, (chunk_x, chunk_z): (i32, i32)) -> TileMesh {
+        let perlin = Perlin::new(self.seed);
+        let origin = Point2::new(
+            chunk_x as f32 * CHUNK_SIZE - SPACING,
+            chunk_z as f32 * CHUNK_SIZE - SPACING,
+        );
+        let heightfield =
+            Heightfield::from_fn(CHUNK_QUADS + 3, CHUNK_QUADS + 3, SPACING, origin, |x, z| {
+                self.height(&perlin, x, z)
+            });
+        TileMesh::new(
+            &heightfield,
+            (1, CHUNK_QUADS + 1),
+            (1, CHUNK_QUADS + 1),
+            self.height,
+        )
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.add(egui::DragValue::new(&mut self.seed));
+        });
+        ui.add(egui::Slider::new(&mut self.octaves, 1..=10).text("Octaves"));
+        ui.add(egui::Slider::new(&mut self.frequency, 0.001..=0.1).text("Frequency"));
+        ui.add(egui::Slider::new(&mut self.lacunarity, 1.0..=4.0).text("Lacunarity"));
+        ui.add(egui::Slider::new(&mut self.gain, 0.1..=0.9).text("Gain"));
+        ui.add(egui::Slider::new(&mut self.height, 1.0..=100.0).text("Height"));
+    }
+}
+
+enum Chunk {
+    // Being built on the thread pool
+    Loading,
+    Loaded(Tile),
+}
+
+// Built chunks coming back from the thread pool, with the generation they were built for
+type Built = (u32, (i32, i32), TileMesh);
+
+// Endless terrain from a `TerrainGenerator`. Chunks around the camera get built on a thread pool
+// and uploaded as they finish, nearest first, and chunks that fall behind get unloaded again so
+// only the area around the camera takes up memory.
+pub struct StreamingTerrain {
+    pub generator: TerrainGenerator,
+    // In chunks around the one the camera is above
+    pub view_distance: u32,
+    // What the current chunks were built with
+    built_with: TerrainGenerator,
+    // Bumped when the generator changes, chunks still being built for older ones get dropped
+    generation: u32,
+
+    chunks: HashMap<(i32, i32), Chunk>,
+    visible: Vec<(i32, i32)>,
+    geometry_pool: BufferPool,
+    material: TerrainMaterial,
+
+    pool: ThreadPool,
+    sender: Sender<Built>,
+    receiver: Receiver<Built>,
+}
+
+impl StreamingTerrain {
+    pub fn new(material: TerrainMaterial, generator: TerrainGenerator) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            generator,
+            view_distance: 6,
+            built_with: generator,
+            generation: 0,
+            chunks: HashMap::new(),
+            visible: Vec::new(),
+            geometry_pool: BufferPool::new(
+                "terrain_chunks",
+                BufferUsage::VERTEX | BufferUsage::INDEX,
+                BLOCK_SIZE,
+            ),
+            material,
+            pool: ThreadPoolBuilder::new()
+                .thread_name(|index| format!("terrain builder {}", index))
+                .build()
+                .unwrap(),
+            sender,
+            receiver,
+        }
+    }
+
+    // Loaded and still loading chunks
+    pub fn chunk_counts(&self) -> (usize, usize) {
+        let loading = self
+            .chunks
+            .values()
+            .filter(|chunk| matches!(chunk, Chunk::Loading))
+            .count();
+        (self.chunks.len() - loading, loading)
+    }
+
+    pub fn visible_chunk_count(&self) -> usize {
+        self.visible.len()
+    }
+
+    fn unload(&mut self, key: (i32, i32)) {
+        if let Some(Chunk::Loaded(tile)) = self.chunks.remove(&key) {
+            tile.free(&mut self.geometry_pool);
+        }
+    }
+
+    fn unload_all(&mut self) {
+        let keys: Vec<_> = self.chunks.keys().cloned().collect();
+        for key in keys {
+            self.unload(key);
+        }
+    }
+
+    // Uploads the chunks that finished building, starts building the ones that came into view,
+    // unloads the ones out of view and records the uniforms for the next `render`
+    pub fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+    ) {
+        if self.generator != self.built_with {
+            self.unload_all();
+            self.built_with = self.generator;
+            self.generation += 1;
+        }
+
+        for (generation, key, mesh) in self.receiver.try_iter() {
+            // The chunk could have been unloaded while it was being built
+            if generation == self.generation {
+                if let Some(chunk @ Chunk::Loading) = self.chunks.get_mut(&key) {
+                    *chunk = Chunk::Loaded(Tile::upload(
+                        device,
+                        encoder,
+                        &mut self.geometry_pool,
+                        &mesh,
+                    ));
+                }
+            }
+        }
+
+        let center = (
+            (camera.eye.x / CHUNK_SIZE).floor() as i32,
+            (camera.eye.z / CHUNK_SIZE).floor() as i32,
+        );
+        let distance2 = |(x, z): (i32, i32)| {
+            let (dx, dz) = ((x - center.0) as i64, (z - center.1) as i64);
+            dx * dx + dz * dz
+        };
+
+        // A chunk more than the view distance before unloading, so chunks at the edge don't
+        // load and unload over and over
+        let radius = self.view_distance as i64;
+        let unload_radius = radius + 1;
+        let far: Vec<_> = self
+            .chunks
+            .keys()
+            .cloned()
+            .filter(|&key| distance2(key) > unload_radius * unload_radius)
+            .collect();
+        for key in far {
+            self.unload(key);
+        }
+
+        let mut missing = Vec::new();
+        for z in center.1 - radius as i32..=center.1 + radius as i32 {
+            for x in center.0 - radius as i32..=center.0 + radius as i32 {
+                if distance2((x, z)) <= radius * radius && !self.chunks.contains_key(&(x, z)) {
+                    missing.push((x, z));
+                }
+            }
+        }
+        missing.sort_by_key(|&key| distance2(key));
+        for key in missing {
+            self.chunks.insert(key, Chunk::Loading);
+            let generator = self.generator;
+            let generation = self.generation;
+            let sender = self.sender.clone();
+            self.pool.spawn(move || {
+                // Only fails if the terrain was dropped, and then nobody is interested anymore
+                sender
+                    .send((generation, key, generator.chunk_mesh(key)))
+                    .ok();
+            });
+        }
+
+        let view_proj = camera.build_view_projection_matrix();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        self.visible = self
+            .chunks
+            .iter()
+            .filter_map(|(&key, chunk)| match chunk {
+                Chunk::Loaded(tile) if frustum.intersects_aabb(&tile.bounds) => Some(key),
+                _ => None,
+            })
+            .collect();
+        self.material
+            .prepare(device, encoder, uniform_pool, view_proj);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        self.material.bind(render_pass);
+        for key in &self.visible {
+            if let Some(Chunk::Loaded(tile)) = self.chunks.get(key) {
+                tile.draw(render_pass);
+            }
+        }
+    }
+
+    pub fn release(&mut self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        self.unload_all();
+        self.visible.clear();
+        self.material.release(uniform_pool, bind_groups);
+    }
+}
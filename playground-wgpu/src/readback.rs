@@ -0,0 +1,58 @@
+use futures::task::noop_waker;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wgpu::{
+    Buffer, BufferAddress, BufferAsyncErr, BufferDescriptor, BufferReadMapping, BufferUsage, Device,
+};
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<BufferReadMapping, BufferAsyncErr>> + Send>>;
+
+// A buffer that GPU data gets copied into, mapped for reading once the copy was submitted.
+//
+// Mapping only completes while the device is polled, so keep calling `device.poll` and `try_read`
+// every frame until the data shows up.
+pub struct Readback {
+    pub buffer: Buffer,
+    pub size: BufferAddress,
+    pending: Option<MapFuture>,
+}
+
+impl Readback {
+    pub fn new(device: &Device, label: &str, size: BufferAddress) -> Self {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsage::MAP_READ | BufferUsage::COPY_DST,
+        });
+
+        Self {
+            buffer,
+            size,
+            pending: None,
+        }
+    }
+
+    // Call after submitting the commands that copy into `buffer`
+    pub fn map(&mut self) {
+        self.pending = Some(Box::pin(self.buffer.map_read(0, self.size)));
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    // The mapped data once it's available. Dropping the mapping unmaps the buffer again.
+    pub fn try_read(&mut self) -> Option<Result<BufferReadMapping, BufferAsyncErr>> {
+        let future = self.pending.as_mut()?;
+
+        let waker = noop_waker();
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(result) => {
+                self.pending = None;
+                Some(result)
+            }
+            Poll::Pending => None,
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::screenshot::Screenshot;
+use log::{error, info};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wgpu::{CommandEncoder, Device, Maintain, TextureFormat, TextureView};
+
+// Frames that can be in flight at once. When all of them are still waiting for their readback
+// the frame gets dropped instead of stalling the renderer.
+const RING_SIZE: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordingOutput {
+    PngSequence,
+    // Raw frames piped into an `ffmpeg` process found on the PATH
+    Ffmpeg,
+}
+
+struct Frame {
+    index: u64,
+    pixels: Vec<u8>,
+}
+
+fn write_frames(
+    frames: Receiver<Frame>,
+    output: RecordingOutput,
+    directory: PathBuf,
+    width: u32,
+    height: u32,
+) -> Result<(), failure::Error> {
+    let mut ffmpeg: Option<Child> = match output {
+        RecordingOutput::PngSequence => None,
+        RecordingOutput::Ffmpeg => Some(
+            Command::new("ffmpeg")
+                .args([
+                    "-y",
+                    "-f",
+                    "rawvideo",
+                    "-pixel_format",
+                    "rgba",
+                    "-video_size",
+                ])
+                .arg(format!("{}x{}", width, height))
+                .args(["-framerate", "60", "-i", "-", "-pix_fmt", "yuv420p"])
+                .arg(directory.join("recording.mp4"))
+                .stdin(Stdio::piped())
+                .spawn()?,
+        ),
+    };
+
+    for frame in frames {
+        match &mut ffmpeg {
+            Some(ffmpeg) => ffmpeg.stdin.as_mut().unwrap().write_all(&frame.pixels)?,
+            None => image::save_buffer(
+                directory.join(format!("frame-{:05}.png", frame.index)),
+                &frame.pixels,
+                width,
+                height,
+                image::ColorType::RGBA(8),
+            )?,
+        }
+    }
+
+    // Closing stdin lets ffmpeg finish the file
+    if let Some(mut ffmpeg) = ffmpeg {
+        drop(ffmpeg.stdin.take());
+        ffmpeg.wait()?;
+    }
+
+    Ok(())
+}
+
+// A single recording: a ring of offscreen frames being read back, and a worker thread encoding
+// whatever comes out of them
+struct Recording {
+    directory: PathBuf,
+    width: u32,
+    height: u32,
+    slots: Vec<(Screenshot, Option<u64>)>,
+    next_slot: usize,
+    // Slot that got a copy recorded this frame, to be mapped after submitting
+    copied_slot: Option<usize>,
+    frame_count: u64,
+    dropped_frames: u64,
+    sender: Sender<Frame>,
+}
+
+pub struct Recorder {
+    pub output: RecordingOutput,
+    recording: Option<Recording>,
+    // Recordings that were stopped but still have frames in flight
+    finishing: Vec<Recording>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            output: RecordingOutput::PngSequence,
+            recording: None,
+            finishing: Vec::new(),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start(&mut self, device: &Device, width: u32, height: u32, format: TextureFormat) {
+        if self.recording.is_some() {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let directory = PathBuf::from(format!("recording-{}", timestamp));
+        if let Err(err) = fs::create_dir_all(&directory) {
+            error!("Failed to create {}: {}", directory.display(), err);
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let output = self.output;
+        let worker_directory = directory.clone();
+        thread::spawn(move || {
+            if let Err(err) = write_frames(receiver, output, worker_directory, width, height) {
+                error!("Failed to write recording: {}", err);
+            }
+        });
+
+        let slots = (0..RING_SIZE)
+            .map(|_| (Screenshot::new(device, width, height, format), None))
+            .collect();
+
+        info!("Recording to {}", directory.display());
+        self.recording = Some(Recording {
+            directory,
+            width,
+            height,
+            slots,
+            next_slot: 0,
+            copied_slot: None,
+            frame_count: 0,
+            dropped_frames: 0,
+            sender,
+        });
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            info!(
+                "Recorded {} frames to {} ({} dropped)",
+                recording.frame_count,
+                recording.directory.display(),
+                recording.dropped_frames
+            );
+            self.finishing.push(recording);
+        }
+    }
+
+    // Picks the slot this frame gets copied into, returns false if there is nothing to record.
+    // Recording stops when the frame size changed.
+    pub fn begin_frame(&mut self, width: u32, height: u32) -> bool {
+        if self
+            .recording
+            .as_ref()
+            .is_some_and(|r| r.width != width || r.height != height)
+        {
+            self.stop();
+        }
+
+        let recording = match &mut self.recording {
+            Some(recording) => recording,
+            None => return false,
+        };
+
+        let slot = recording.next_slot;
+        if recording.slots[slot].0.is_pending() {
+            recording.dropped_frames += 1;
+            return false;
+        }
+
+        recording.next_slot = (slot + 1) % recording.slots.len();
+        recording.copied_slot = Some(slot);
+        recording.slots[slot].1 = Some(recording.frame_count);
+        recording.frame_count += 1;
+        true
+    }
+
+    // Where to draw the frame picked by `begin_frame`
+    pub fn view(&self) -> Option<&TextureView> {
+        let recording = self.recording.as_ref()?;
+        let slot = recording.copied_slot?;
+        Some(&recording.slots[slot].0.view)
+    }
+
+    // Records the copy of the frame drawn into `view`
+    pub fn copy(&self, encoder: &mut CommandEncoder) {
+        if let Some(recording) = &self.recording {
+            if let Some(slot) = recording.copied_slot {
+                recording.slots[slot].0.copy(encoder);
+            }
+        }
+    }
+
+    // Call after submitting the encoder passed to `copy`
+    pub fn after_submit(&mut self) {
+        if let Some(recording) = &mut self.recording {
+            if let Some(slot) = recording.copied_slot.take() {
+                recording.slots[slot].0.map();
+            }
+        }
+    }
+
+    // Hands finished readbacks to the worker thread
+    pub fn poll(&mut self, device: &Device) {
+        if self.recording.is_none() && self.finishing.is_empty() {
+            return;
+        }
+
+        device.poll(Maintain::Poll);
+        for recording in self.recording.iter_mut().chain(&mut self.finishing) {
+            for (screenshot, index) in &mut recording.slots {
+                match screenshot.try_pixels() {
+                    Some(Ok(pixels)) => {
+                        let frame = Frame {
+                            index: index.take().unwrap(),
+                            pixels,
+                        };
+                        // The worker only goes away after failing, and it reported that already
+                        let _ = recording.sender.send(frame);
+                    }
+                    Some(Err(err)) => error!("Failed to read back frame: {}", err),
+                    None => (),
+                }
+            }
+        }
+
+        // Dropping a finished recording closes the channel, which ends its worker
+        self.finishing
+            .retain(|recording| recording.slots.iter().any(|(s, _)| s.is_pending()));
+    }
+}
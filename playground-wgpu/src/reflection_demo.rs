@@ -0,0 +1,284 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::pipeline::{PipelineKey, Shader};
+use crate::primitives::{self, Cubes, Quad};
+use crate::render_target::RenderTarget;
+use crate::uniform::{self, ObjectUniforms, Uniforms};
+use cgmath::{Deg, Matrix4, Point3, Vector3, Vector4};
+use playground_math::{Block, BlockWriter, Camera, Layout, Plane, Projection};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, CullMode, Device, LoadOp,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SHADER_VERT: Shader = Shader {
+    name: "shader.vert",
+    source: include_str!("../shaders/shader.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const MIRROR_FRAG: Shader = Shader {
+    name: "mirror.frag",
+    source: include_str!("../shaders/mirror.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Sides of the mirror floor
+const FLOOR_SIZE: f32 = 14.0;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct MirrorUniforms {
+    tint: [f32; 4],
+    reflectivity: f32,
+}
+
+impl Block for MirrorUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector4::from(self.tint));
+        writer.member(&self.reflectivity);
+    }
+}
+
+const UNIFORM_KEYS: [&str; 4] = [
+    "reflection_camera",
+    "reflection_mirrored_camera",
+    "reflection_floor",
+    "reflection_mirror",
+];
+
+// A ring of cubes on a mirror floor. Every frame the cubes get rendered a second time from the
+// camera mirrored in the floor, into a target the floor then samples at each fragment's spot on
+// the screen.
+pub struct ReflectionDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    reflection: RenderTarget,
+    mirror: Plane,
+    // Moves the mirrored camera's near plane onto the mirror, off to compare
+    clip: bool,
+    tint: [f32; 3],
+    reflectivity: f32,
+
+    cube_pipeline: Arc<RenderPipeline>,
+    // Mirroring turns the cubes inside out, so this one culls the other side
+    mirrored_cube_pipeline: Arc<RenderPipeline>,
+    floor_pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    floor: Quad,
+
+    // The camera, the mirrored camera, the floor's model matrix and the mirror material
+    uniform_allocations: [Allocation; 4],
+    uniform_bind_groups: [Arc<BindGroup>; 4],
+}
+
+impl ReflectionDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 4.0, 10.0).into(),
+            target: (0.0, 0.5, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let cube_key = Cubes::pipeline_key(ctx.format);
+        let cube_pipeline = ctx.pipelines.get(device, ctx.bind_groups, &cube_key);
+        let mirrored_cube_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                cull_mode: CullMode::Front,
+                ..cube_key.clone()
+            },
+        );
+        let floor_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: SHADER_VERT,
+                fragment_shader: Some(MIRROR_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![Quad::vertex_layout()],
+                cull_mode: CullMode::None,
+                ..cube_key
+            },
+        );
+
+        // The one in the middle sinks into the floor, which the clipping keeps out of the
+        // reflection
+        let mut instances = primitives::ring_of_cubes(8, 3.0);
+        instances[0].offset_scale[1] = 0.3;
+
+        let mut allocate = |key, size, layout| uniform::allocate(ctx, key, size, layout);
+        let camera_size = Uniforms::size(Layout::Std140);
+        let (camera_allocation, camera_bind_group) =
+            allocate(UNIFORM_KEYS[0], camera_size, bind_group::UNIFORM_LAYOUT);
+        let (mirrored_allocation, mirrored_bind_group) =
+            allocate(UNIFORM_KEYS[1], camera_size, bind_group::UNIFORM_LAYOUT);
+        let (floor_allocation, floor_bind_group) = allocate(
+            UNIFORM_KEYS[2],
+            ObjectUniforms::size(Layout::Std140),
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+        );
+        let (mirror_allocation, mirror_bind_group) = allocate(
+            UNIFORM_KEYS[3],
+            MirrorUniforms::size(Layout::Std140),
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(device, ctx.size),
+            reflection: RenderTarget::new(device, ctx.bind_groups, ctx.format, ctx.size),
+            mirror: Plane::from_point_normal(Point3::new(0.0, 0.0, 0.0), Vector3::unit_y()),
+            clip: true,
+            tint: [0.35, 0.38, 0.45],
+            reflectivity: 0.6,
+            cube_pipeline,
+            mirrored_cube_pipeline,
+            floor_pipeline,
+            cubes: Cubes::new(device, &instances),
+            floor: Quad::new(device),
+            uniform_allocations: [
+                camera_allocation,
+                mirrored_allocation,
+                floor_allocation,
+                mirror_allocation,
+            ],
+            uniform_bind_groups: [
+                camera_bind_group,
+                mirrored_bind_group,
+                floor_bind_group,
+                mirror_bind_group,
+            ],
+        }
+    }
+}
+
+impl Demo for ReflectionDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        // Sampled pixel for pixel, so it has to match the frame
+        self.reflection.resize(device, self.size);
+
+        let mut camera = Uniforms::new();
+        camera.update_view_proj(&self.camera);
+        let mut mirrored = Uniforms::new();
+        mirrored.set_view_proj(
+            self.camera
+                .mirrored_view_projection(&self.mirror, self.clip),
+        );
+        // The quad lies down on the mirror, facing up
+        let floor = Matrix4::from_angle_x(Deg(-90.0)) * Matrix4::from_scale(FLOOR_SIZE);
+        let [r, g, b] = self.tint;
+        let mirror = MirrorUniforms {
+            tint: [r, g, b, 1.0],
+            reflectivity: self.reflectivity,
+        };
+
+        let [camera_allocation, mirrored_allocation, floor_allocation, mirror_allocation] =
+            &self.uniform_allocations;
+        uniform_pool.write(device, encoder, camera_allocation, &camera.std140());
+        uniform_pool.write(device, encoder, mirrored_allocation, &mirrored.std140());
+        uniform_pool.write(
+            device,
+            encoder,
+            floor_allocation,
+            &ObjectUniforms::new(floor, 1.0).std140(),
+        );
+        uniform_pool.write(device, encoder, mirror_allocation, &mirror.std140());
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.reflectivity, 0.0..=1.0).text("Reflectivity"));
+        ui.horizontal(|ui| {
+            ui.label("Floor");
+            ui.color_edit_button_rgb(&mut self.tint);
+        });
+        ui.checkbox(&mut self.clip, "Clip at the mirror")
+            .on_hover_text(
+                "Oblique near plane, keeps what's below the floor out of the reflection",
+            );
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let [camera, mirrored, floor, mirror] = &self.uniform_bind_groups;
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[self.reflection.color_attachment(clear_color)],
+            depth_stencil_attachment: Some(self.reflection.depth_attachment()),
+        });
+        render_pass.push_debug_group("Mirrored cubes");
+        render_pass.set_pipeline(&self.mirrored_cube_pipeline);
+        render_pass.set_bind_group(0, mirrored, &[]);
+        self.cubes.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        render_pass.set_pipeline(&self.cube_pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        self.cubes.draw(&mut render_pass);
+
+        render_pass.set_pipeline(&self.floor_pipeline);
+        render_pass.set_bind_group(0, self.reflection.bind_group(), &[]);
+        render_pass.set_bind_group(1, camera, &[]);
+        render_pass.set_bind_group(2, floor, &[]);
+        render_pass.set_bind_group(3, mirror, &[]);
+        self.floor.draw(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        for (allocation, key) in self.uniform_allocations.iter().zip(&UNIFORM_KEYS) {
+            ctx.uniform_pool.free(*allocation);
+            ctx.bind_groups.invalidate(key);
+        }
+    }
+}
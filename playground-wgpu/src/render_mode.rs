@@ -0,0 +1,95 @@
+use crate::pipeline::{PipelineKey, Shader};
+use wgpu::{CullMode, PrimitiveTopology, ShaderStage};
+
+const DEBUG_WIREFRAME_FRAG: Shader = Shader {
+    name: "debug_wireframe.frag",
+    source: include_str!("../shaders/debug_wireframe.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const DEBUG_NORMALS_FRAG: Shader = Shader {
+    name: "debug_normals.frag",
+    source: include_str!("../shaders/debug_normals.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const DEBUG_UV_FRAG: Shader = Shader {
+    name: "debug_uv.frag",
+    source: include_str!("../shaders/debug_uv.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const DEBUG_LIT_FRAG: Shader = Shader {
+    name: "debug_lit.frag",
+    source: include_str!("../shaders/debug_lit.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// How demos draw their meshes, switched with the render_mode_* actions. Every mode is a
+// permutation of the demo's own pipeline, so the pipeline cache only builds each one once.
+//
+// wgpu 0.5 has no polygon modes, so the wireframe is a line list permutation drawn with the
+// edge indices of the meshes instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RenderMode {
+    Solid,
+    Wireframe,
+    Normals,
+    UvChecker,
+    FlatWhite,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 5] = [
+        RenderMode::Solid,
+        RenderMode::Wireframe,
+        RenderMode::Normals,
+        RenderMode::UvChecker,
+        RenderMode::FlatWhite,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderMode::Solid => "Solid",
+            RenderMode::Wireframe => "Wireframe",
+            RenderMode::Normals => "Normals",
+            RenderMode::UvChecker => "UV checker",
+            RenderMode::FlatWhite => "Flat white",
+        }
+    }
+
+    // The input action switching to this mode
+    pub fn action(self) -> &'static str {
+        match self {
+            RenderMode::Solid => "render_mode_solid",
+            RenderMode::Wireframe => "render_mode_wireframe",
+            RenderMode::Normals => "render_mode_normals",
+            RenderMode::UvChecker => "render_mode_uv_checker",
+            RenderMode::FlatWhite => "render_mode_flat_white",
+        }
+    }
+
+    // Whether meshes have to be drawn with their edge indices
+    pub fn draws_lines(self) -> bool {
+        self == RenderMode::Wireframe
+    }
+
+    // `solid` with this mode's fragment shader and topology. The fragment shaders read the
+    // texture coordinates from location 0 and the world space position from location 1.
+    pub fn pipeline_key(self, solid: &PipelineKey) -> PipelineKey {
+        let fragment_shader = match self {
+            RenderMode::Solid => return solid.clone(),
+            RenderMode::Wireframe => DEBUG_WIREFRAME_FRAG,
+            RenderMode::Normals => DEBUG_NORMALS_FRAG,
+            RenderMode::UvChecker => DEBUG_UV_FRAG,
+            RenderMode::FlatWhite => DEBUG_LIT_FRAG,
+        };
+        let mut key = solid.clone();
+        key.fragment_shader = Some(fragment_shader);
+        if self.draws_lines() {
+            key.primitive_topology = PrimitiveTopology::LineList;
+            key.cull_mode = CullMode::None;
+        }
+        key
+    }
+}
@@ -0,0 +1,148 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::depth::DepthBuffer;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource, Color,
+    CompareFunction, Device, Extent3d, FilterMode, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDepthStencilAttachmentDescriptor, Sampler, SamplerDescriptor, StoreOp,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+// A texture that gets rendered into and then sampled, at least 1x1
+pub fn create_target(
+    device: &Device,
+    size: PhysicalSize<u32>,
+    format: TextureFormat,
+    label: &str,
+) -> TextureView {
+    device
+        .create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED,
+            label: Some(label),
+        })
+        .create_default_view()
+}
+
+// The color texture and the bind group sampling it, replaced together on resize
+struct ColorTexture {
+    view: TextureView,
+    bind_group: BindGroup,
+}
+
+impl ColorTexture {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+        format: TextureFormat,
+    ) -> Self {
+        let view = create_target(device, size, format, "render_target");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("render_target"),
+        });
+
+        Self { view, bind_group }
+    }
+}
+
+// An offscreen color target with its own depth buffer, for rendering into a texture that gets
+// sampled later in the frame. The texture is bound like any other through `bind_group`, with a
+// `bind_group::TEXTURE_LAYOUT`.
+pub struct RenderTarget {
+    color: ColorTexture,
+    depth: DepthBuffer,
+    format: TextureFormat,
+    size: PhysicalSize<u32>,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "render_target", bind_group::TEXTURE_LAYOUT);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        Self {
+            color: ColorTexture::new(device, &layout, &sampler, size, format),
+            depth: DepthBuffer::new(device, size),
+            format,
+            size,
+            layout,
+            sampler,
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.size.width.max(1) as f32 / self.size.height.max(1) as f32
+    }
+
+    // Replaces the textures when the size changed, which also replaces `bind_group`
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if size != self.size {
+            self.color = ColorTexture::new(device, &self.layout, &self.sampler, size, self.format);
+            self.depth.resize(device, size);
+            self.size = size;
+        }
+    }
+
+    // Cleared to `clear_color` at the start of the pass
+    pub fn color_attachment(&self, clear_color: Color) -> RenderPassColorAttachmentDescriptor<'_> {
+        RenderPassColorAttachmentDescriptor {
+            attachment: &self.color.view,
+            resolve_target: None,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            clear_color,
+        }
+    }
+
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        self.depth.attachment()
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.color.bind_group
+    }
+}
@@ -0,0 +1,131 @@
+use libloading::Library;
+use log::info;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+// eRENDERDOC_API_Version_1_1_2, the first with everything used here
+const API_VERSION: c_int = 10102;
+
+type GetApi = unsafe extern "C" fn(version: c_int, api: *mut *mut c_void) -> c_int;
+
+// The start of RENDERDOC_API_1_1_2 from renderdoc_app.h, a table of function pointers. Only the
+// functions used here are spelled out.
+#[repr(C)]
+struct Api {
+    get_api_version: unsafe extern "C" fn(major: *mut c_int, minor: *mut c_int, patch: *mut c_int),
+    // Capture options, keys, overlay, shutdown, crash handler and file path template
+    _unused: [*const c_void; 12],
+    get_num_captures: unsafe extern "C" fn() -> u32,
+    get_capture: unsafe extern "C" fn(
+        index: u32,
+        filename: *mut c_char,
+        path_length: *mut u32,
+        timestamp: *mut u64,
+    ) -> u32,
+    trigger_capture: unsafe extern "C" fn(),
+}
+
+// RenderDoc only hooks into the graphics API when it starts the program, or gets injected right
+// after. Loading the library any later wouldn't capture anything, so this only looks for it.
+#[cfg(unix)]
+fn find_library() -> Option<Library> {
+    use libloading::os::unix;
+    unix::Library::open(Some("librenderdoc.so"), libc::RTLD_NOW | libc::RTLD_NOLOAD)
+        .ok()
+        .map(Library::from)
+}
+
+#[cfg(windows)]
+fn find_library() -> Option<Library> {
+    use libloading::os::windows;
+    windows::Library::open_already_loaded("renderdoc.dll")
+        .ok()
+        .map(Library::from)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn find_library() -> Option<Library> {
+    None
+}
+
+// Frame captures triggered from inside the program through RenderDoc's in-application API, for
+// glitches that are gone before there's time to press RenderDoc's own capture key
+pub struct RenderDoc {
+    api: *const Api,
+    // Keeps `api` valid
+    _library: Library,
+    // Captures RenderDoc had the last time they were looked at
+    captures: u32,
+}
+
+impl RenderDoc {
+    // None unless the program was started from RenderDoc
+    pub fn attach() -> Option<Self> {
+        let library = find_library()?;
+        let mut api = ptr::null_mut();
+        unsafe {
+            let get_api = library.get::<GetApi>(b"RENDERDOC_GetAPI\0").ok()?;
+            if get_api(API_VERSION, &mut api) != 1 || api.is_null() {
+                return None;
+            }
+        }
+
+        let api = api as *const Api;
+        let (mut major, mut minor, mut patch) = (0, 0, 0);
+        let captures = unsafe {
+            ((*api).get_api_version)(&mut major, &mut minor, &mut patch);
+            ((*api).get_num_captures)()
+        };
+        info!("Attached to RenderDoc {}.{}.{}", major, minor, patch);
+
+        Some(Self {
+            api,
+            _library: library,
+            captures,
+        })
+    }
+
+    // Captures the next frame that gets presented
+    pub fn trigger_capture(&self) {
+        info!("Capturing the next frame with RenderDoc");
+        unsafe { ((*self.api).trigger_capture)() }
+    }
+
+    // Logs where the captures finished since the last call were saved
+    pub fn poll(&mut self) {
+        let count = unsafe { ((*self.api).get_num_captures)() };
+        for index in self.captures..count {
+            if let Some(path) = self.capture_path(index) {
+                info!("Saved RenderDoc capture to {}", path);
+            }
+        }
+        self.captures = count;
+    }
+
+    fn capture_path(&self, index: u32) -> Option<String> {
+        let get_capture = unsafe { (*self.api).get_capture };
+
+        // Asked for the length first, which includes the terminating null
+        let mut length = 0;
+        let found = unsafe { get_capture(index, ptr::null_mut(), &mut length, ptr::null_mut()) };
+        if found == 0 || length == 0 {
+            return None;
+        }
+
+        let mut path = vec![0u8; length as usize];
+        unsafe {
+            get_capture(
+                index,
+                path.as_mut_ptr() as *mut c_char,
+                &mut length,
+                ptr::null_mut(),
+            )
+        };
+        path.truncate(
+            path.iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(path.len()),
+        );
+        Some(String::from_utf8_lossy(&path).into_owned())
+    }
+}
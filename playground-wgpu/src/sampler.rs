@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu::{AddressMode, CompareFunction, Device, FilterMode, Sampler, SamplerDescriptor};
+
+// How a material samples its texture. The parts of a `SamplerDescriptor` materials get to pick,
+// which unlike the descriptor can be compared and hashed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub mag_filter: FilterMode,
+    pub min_filter: FilterMode,
+    pub mipmap_filter: FilterMode,
+}
+
+impl SamplerSettings {
+    // Blended between texels and mip levels, clamped at the edges
+    pub const SMOOTH: Self = Self {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Linear,
+    };
+
+    // The nearest texel of the nearest mip level, so pixel art stays blocky up close
+    pub const PIXEL_ART: Self = Self {
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        mipmap_filter: FilterMode::Nearest,
+        ..Self::SMOOTH
+    };
+
+    // The same filtering, wrapping around the edges with `address_mode`
+    pub fn wrapped(self, address_mode: AddressMode) -> Self {
+        Self {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            ..self
+        }
+    }
+
+    pub fn descriptor(&self) -> SamplerDescriptor {
+        SamplerDescriptor {
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        }
+    }
+
+    // Filtering and wrapping, for a material's inspector
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let mut pixel_art = self.mag_filter == FilterMode::Nearest;
+        if ui.checkbox(&mut pixel_art, "Pixel art filtering").changed() {
+            let filters = if pixel_art {
+                Self::PIXEL_ART
+            } else {
+                Self::SMOOTH
+            };
+            *self = filters.wrapped(self.address_mode_u);
+        }
+
+        let mut address_mode = self.address_mode_u;
+        egui::ComboBox::from_label("Wrap")
+            .selected_text(format!("{:?}", address_mode))
+            .show_ui(ui, |ui| {
+                for &mode in &[
+                    AddressMode::ClampToEdge,
+                    AddressMode::Repeat,
+                    AddressMode::MirrorRepeat,
+                ] {
+                    ui.selectable_value(&mut address_mode, mode, format!("{:?}", mode));
+                }
+            });
+        *self = self.wrapped(address_mode);
+    }
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self::SMOOTH
+    }
+}
+
+// Creates one sampler for every distinct `SamplerSettings`, so all the textures sampled the same
+// way share it
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerSettings, Arc<Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&mut self, device: &Device, settings: SamplerSettings) -> Arc<Sampler> {
+        self.samplers
+            .entry(settings)
+            .or_insert_with(|| Arc::new(device.create_sampler(&settings.descriptor())))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+}
@@ -0,0 +1,149 @@
+use crate::assets::{self, Handle};
+use crate::labels::LabelStyle;
+use crate::sampler::SamplerSettings;
+use crate::texture::Texture;
+use cgmath::{EuclideanSpace, Matrix4, MetricSpace, Point3, SquareMatrix, Transform as _, Vector3};
+use hecs::{Entity, World};
+use playground_math::{Color, LodDistances, Transform};
+
+// Components. Every entity spawned through `Scene::spawn` has a Name, a Transform relative to its
+// parent and a WorldTransform, the rest gets inserted by whoever fills the scene.
+pub struct Name(pub String);
+
+pub struct Parent(pub Entity);
+
+pub struct Children(pub Vec<Entity>);
+
+// As of the last `update_world_matrices`
+pub struct WorldTransform(pub Matrix4<f32>);
+
+pub struct Mesh(pub Handle<assets::Mesh>);
+
+// Meshes of decreasing detail for the same model. `Scene::update_lods` picks one by distance and
+// puts it in the entity's `Mesh`.
+pub struct Lod {
+    pub meshes: Vec<Handle<assets::Mesh>>,
+    pub distances: LodDistances,
+    // Index into `meshes` as of the last `update_lods`
+    pub level: usize,
+}
+
+// Its bind group gets bound at set 0 when drawing the entity's mesh
+pub struct Material {
+    pub texture: Handle<Texture>,
+    pub sampler: SamplerSettings,
+    // Below 1 the object gets blended over what's behind it
+    pub opacity: f32,
+}
+
+// Text shown above the entity, facing the camera
+pub struct Label {
+    pub text: String,
+    pub style: LabelStyle,
+    // Where the label sits in the entity's own space
+    pub offset: Vector3<f32>,
+}
+
+pub struct Light {
+    // Linear
+    pub color: Color,
+    pub intensity: f32,
+}
+
+// Entities and their components in a hecs world, plus the parent/child links between them
+#[derive(Default)]
+pub struct Scene {
+    pub world: World,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self, name: &str, parent: Option<Entity>, local: Transform) -> Entity {
+        let entity = self.world.spawn((
+            Name(name.to_string()),
+            local,
+            WorldTransform(Matrix4::identity()),
+            Children(Vec::new()),
+        ));
+
+        if let Some(parent) = parent {
+            self.world.insert_one(entity, Parent(parent)).unwrap();
+            self.world
+                .get::<&mut Children>(parent)
+                .unwrap()
+                .0
+                .push(entity);
+        }
+        entity
+    }
+
+    pub fn roots(&self) -> Vec<Entity> {
+        self.world
+            .query::<Entity>()
+            .with::<&Name>()
+            .without::<&Parent>()
+            .iter()
+            .collect()
+    }
+
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        self.world
+            .get::<&Children>(entity)
+            .map_or_else(|_| Vec::new(), |children| children.0.clone())
+    }
+
+    pub fn name(&self, entity: Entity) -> String {
+        self.world
+            .get::<&Name>(entity)
+            .map_or_else(|_| String::new(), |name| name.0.clone())
+    }
+
+    // The first entity called `name`
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.world
+            .query::<(Entity, &Name)>()
+            .iter()
+            .find(|(_, entity_name)| entity_name.0 == name)
+            .map(|(entity, _)| entity)
+    }
+
+    // Walks down from the roots, so parents are always done before their children
+    pub fn update_world_matrices(&mut self) {
+        let mut stack = self.roots();
+
+        while let Some(entity) = stack.pop() {
+            let parent_world = match self.world.get::<&Parent>(entity) {
+                Ok(parent) => self.world.get::<&WorldTransform>(parent.0).unwrap().0,
+                Err(_) => Matrix4::identity(),
+            };
+            let world = match self.world.get::<&Transform>(entity) {
+                Ok(local) => parent_world * local.matrix(),
+                Err(_) => parent_world,
+            };
+            if let Ok(mut world_transform) = self.world.get::<&mut WorldTransform>(entity) {
+                world_transform.0 = world;
+            }
+
+            stack.extend(self.children(entity));
+        }
+    }
+
+    // Switches every entity with a `Lod` to the level for its distance from `eye`, measured to its
+    // origin as of the last `update_world_matrices`
+    pub fn update_lods(&mut self, eye: Point3<f32>) {
+        for (world, lod, mesh) in self
+            .world
+            .query_mut::<(&WorldTransform, &mut Lod, &mut Mesh)>()
+        {
+            let distance = world.0.transform_point(Point3::origin()).distance(eye);
+            lod.level = lod
+                .distances
+                .select(distance, lod.level)
+                .min(lod.meshes.len() - 1);
+            mesh.0 = lod.meshes[lod.level];
+        }
+    }
+}
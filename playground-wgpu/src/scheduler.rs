@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use wgpu::{Device, Queue};
+
+pub struct TaskContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+}
+
+pub enum TaskStatus {
+    Done,
+    // Wants to run again, after the other tasks had their turn
+    Pending,
+}
+
+type Task = Box<dyn FnMut(&TaskContext) -> TaskStatus>;
+
+// Runs deferred work with whatever time is left of a frame's budget, and leaves the rest for
+// later frames. Tasks are expected to do a small step per call, a single long step still
+// causes a hitch.
+pub struct Scheduler {
+    pub budget: Duration,
+    tasks: VecDeque<(String, Task)>,
+    // For the UI
+    steps_last_frame: usize,
+    deferred_frames: u64,
+}
+
+impl Scheduler {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            tasks: VecDeque::new(),
+            steps_last_frame: 0,
+            deferred_frames: 0,
+        }
+    }
+
+    pub fn add(&mut self, name: &str, task: impl FnMut(&TaskContext) -> TaskStatus + 'static) {
+        self.tasks.push_back((name.to_string(), Box::new(task)));
+    }
+
+    // Call once the frame's own work is done. Runs tasks round robin until the time since
+    // `frame_start` exceeds the budget.
+    pub fn run(&mut self, ctx: &TaskContext, frame_start: Instant) {
+        self.steps_last_frame = 0;
+        while let Some((name, mut task)) = self.tasks.pop_front() {
+            if frame_start.elapsed() >= self.budget {
+                self.tasks.push_front((name, task));
+                self.deferred_frames += 1;
+                break;
+            }
+
+            self.steps_last_frame += 1;
+            if let TaskStatus::Pending = task(ctx) {
+                self.tasks.push_back((name, task));
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let mut budget_ms = self.budget.as_secs_f32() * 1000.0;
+        ui.add(egui::Slider::new(&mut budget_ms, 1.0..=33.0).text("Frame budget (ms)"));
+        self.budget = Duration::from_secs_f32(budget_ms / 1000.0);
+
+        ui.label(format!(
+            "{} pending, {} steps last frame, {} frames over budget",
+            self.tasks.len(),
+            self.steps_last_frame,
+            self.deferred_frames
+        ));
+        for (name, _) in &self.tasks {
+            ui.label(name.as_str());
+        }
+    }
+}
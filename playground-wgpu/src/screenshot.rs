@@ -0,0 +1,143 @@
+use crate::readback::Readback;
+use crate::texture;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wgpu::{
+    BufferAddress, BufferCopyView, CommandEncoder, Device, Extent3d, Origin3d, TextureCopyView,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsage, TextureView,
+};
+
+// Turns rows of a texture copied with padded rows into tightly packed RGBA pixels
+fn rgba_pixels(data: &[u8], width: u32, height: u32, format: TextureFormat) -> Vec<u8> {
+    let unpadded = 4 * width as usize;
+    let padded = texture::padded_bytes_per_row(width) as usize;
+    let bgra = matches!(
+        format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    );
+
+    let mut pixels = Vec::with_capacity(unpadded * height as usize);
+    for row in data.chunks(padded).take(height as usize) {
+        pixels.extend_from_slice(&row[..unpadded]);
+    }
+    if bgra {
+        for pixel in pixels.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    pixels
+}
+
+// An offscreen copy of a frame on its way to a PNG file. The frame gets rendered into `view`,
+// `copy` and `map` start the readback, `try_save` writes the file once the data arrived.
+pub struct Screenshot {
+    texture: wgpu::Texture,
+    pub view: TextureView,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    readback: Readback,
+}
+
+impl Screenshot {
+    pub fn new(device: &Device, width: u32, height: u32, format: TextureFormat) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::COPY_SRC,
+            label: Some("screenshot"),
+        });
+        let view = texture.create_default_view();
+
+        let size = (texture::padded_bytes_per_row(width) * height) as BufferAddress;
+        let readback = Readback::new(device, "screenshot_readback", size);
+
+        Self {
+            texture,
+            view,
+            format,
+            width,
+            height,
+            readback,
+        }
+    }
+
+    pub fn copy(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: Origin3d::ZERO,
+            },
+            BufferCopyView {
+                buffer: &self.readback.buffer,
+                offset: 0,
+                bytes_per_row: texture::padded_bytes_per_row(self.width),
+                rows_per_image: self.height,
+            },
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            },
+        );
+    }
+
+    // Call after submitting the encoder passed to `copy`
+    pub fn map(&mut self) {
+        self.readback.map();
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.readback.is_pending()
+    }
+
+    // Tightly packed RGBA pixels, once the readback finished
+    pub fn try_pixels(&mut self) -> Option<Result<Vec<u8>, failure::Error>> {
+        Some(match self.readback.try_read()? {
+            Ok(mapping) => Ok(rgba_pixels(
+                mapping.as_slice(),
+                self.width,
+                self.height,
+                self.format,
+            )),
+            Err(_) => Err(failure::err_msg("Failed to map the screenshot buffer")),
+        })
+    }
+
+    // Writes the PNG once the readback finished, returns where it ended up
+    pub fn try_save(&mut self) -> Option<Result<PathBuf, failure::Error>> {
+        let pixels = match self.try_pixels()? {
+            Ok(pixels) => pixels,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let path = PathBuf::from(format!("screenshot-{}.png", timestamp));
+
+        Some(
+            image::save_buffer(
+                &path,
+                &pixels,
+                self.width,
+                self.height,
+                image::ColorType::RGBA(8),
+            )
+            .map(|_| path)
+            .map_err(failure::Error::from),
+        )
+    }
+}
@@ -0,0 +1,190 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth::{self, DepthBuffer};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use cgmath::Vector4;
+use playground_math::{Block, BlockWriter, Layout};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, BufferAddress, ColorStateDescriptor,
+    ColorWrite, CommandEncoder, CullMode, Device, IndexFormat, PrimitiveTopology, RenderPass,
+    RenderPassDepthStencilAttachmentDescriptor, RenderPipeline, ShaderStage, TextureFormat,
+};
+use winit::dpi::PhysicalSize;
+
+const ID_VERT: Shader = Shader {
+    name: "id.vert",
+    source: include_str!("../shaders/id.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const OUTLINE_VERT: Shader = Shader {
+    name: "outline.vert",
+    source: include_str!("../shaders/outline.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const OUTLINE_FRAG: Shader = Shader {
+    name: "outline.frag",
+    source: include_str!("../shaders/outline.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// What `mark` leaves in the stencil
+const MARKED: u32 = 1;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct OutlineUniforms {
+    color: [f32; 4],
+    scale: f32,
+}
+
+impl Block for OutlineUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector4::from(self.color));
+        writer.member(&self.scale);
+    }
+}
+
+// Outlines the selected object in two steps in the same pass. `mark` writes the object's shape
+// into the stencil, `outline` draws it again a little larger in a flat color, but only outside
+// the marked shape.
+//
+// Both pipelines take the camera uniforms at set 0 and the object's uniforms at set 1, laid out
+// like `uniform::ObjectUniforms`, and the object's positions at location 0. After each call the
+// demo binds those and draws the object.
+pub struct SelectionOutline {
+    stencil: DepthBuffer,
+    mark_pipeline: Arc<RenderPipeline>,
+    outline_pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    pub color: [f32; 3],
+    // How much larger than the object the outline gets drawn
+    pub width: f32,
+}
+
+impl SelectionOutline {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+        vertex_layout: VertexLayout,
+    ) -> Self {
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            OutlineUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+            "outline_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let outline = PipelineKey {
+            vertex_shader: OUTLINE_VERT,
+            fragment_shader: Some(OUTLINE_FRAG),
+            bind_group_layouts: vec![
+                bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+            ],
+            vertex_buffers: vec![vertex_layout],
+            index_format: IndexFormat::Uint16,
+            primitive_topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::None,
+            color_states: vec![ColorStateDescriptor {
+                format,
+                color_blend: BlendDescriptor::REPLACE,
+                alpha_blend: BlendDescriptor::REPLACE,
+                write_mask: ColorWrite::ALL,
+            }],
+            depth_stencil_state: Some(depth::stencil_outside_state()),
+            sample_count: 1,
+        };
+        let mark = PipelineKey {
+            vertex_shader: ID_VERT,
+            // Only the stencil gets written
+            fragment_shader: None,
+            bind_group_layouts: outline.bind_group_layouts[..2].to_vec(),
+            color_states: vec![ColorStateDescriptor {
+                write_mask: ColorWrite::empty(),
+                ..outline.color_states[0].clone()
+            }],
+            depth_stencil_state: Some(depth::stencil_write_state()),
+            ..outline.clone()
+        };
+
+        Self {
+            stencil: DepthBuffer::with_format(device, size, depth::DEPTH_STENCIL_FORMAT),
+            mark_pipeline: pipelines.get(device, bind_groups, &mark),
+            outline_pipeline: pipelines.get(device, bind_groups, &outline),
+            uniform_allocation,
+            uniform_bind_group,
+            color: [1.0, 0.6, 0.1],
+            width: 0.05,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Selection outline");
+            ui.color_edit_button_rgb(&mut self.color);
+        });
+        ui.add(egui::Slider::new(&mut self.width, 0.0..=0.3).text("Outline width"));
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        size: PhysicalSize<u32>,
+    ) {
+        self.stencil.resize(device, size);
+        let [r, g, b] = self.color;
+        let uniforms = OutlineUniforms {
+            color: [r, g, b, 1.0],
+            scale: 1.0 + self.width,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the pass the outline gets drawn in, with the stencil cleared
+    pub fn attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        self.stencil.attachment()
+    }
+
+    pub fn mark<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.mark_pipeline);
+        render_pass.set_stencil_reference(MARKED);
+    }
+
+    pub fn outline<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.outline_pipeline);
+        render_pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("outline_uniforms");
+    }
+}
@@ -0,0 +1,48 @@
+use log::warn;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+// Settings that survive restarts, kept as one JSON object per demo (and one for the input
+// bindings) in the working directory. Owners serialize their own settings structs, this only
+// stores them by name.
+pub struct SettingsStore {
+    path: PathBuf,
+    demos: Map<String, Value>,
+}
+
+impl SettingsStore {
+    // A missing file is fine, a broken one gets reported and overwritten on the next save
+    pub fn load() -> Self {
+        let path = PathBuf::from("settings.json");
+        let demos = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(demos) => demos,
+                Err(err) => {
+                    warn!("Ignoring {}: {}", path.display(), err);
+                    Map::new()
+                }
+            },
+            Err(_) => Map::new(),
+        };
+
+        Self { path, demos }
+    }
+
+    pub fn get(&self, demo: &str) -> Option<&Value> {
+        self.demos.get(demo)
+    }
+
+    pub fn set(&mut self, demo: &str, settings: Value) {
+        self.demos.insert(demo.to_string(), settings);
+    }
+
+    pub fn remove(&mut self, demo: &str) {
+        self.demos.remove(demo);
+    }
+
+    pub fn save(&self) -> Result<(), failure::Error> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.demos)?)?;
+        Ok(())
+    }
+}
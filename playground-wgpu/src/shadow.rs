@@ -0,0 +1,316 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::pipeline::PipelineKey;
+use cgmath::{Angle, Deg, Matrix4, SquareMatrix, Vector3};
+use playground_math::Sphere;
+use playground_math::{camera, Block, BlockWriter, Layout};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, Binding, BindingResource, BufferAddress,
+    CommandEncoder, CompareFunction, Device, Extent3d, FilterMode, LoadOp,
+    RenderPassDepthStencilAttachmentDescriptor, SamplerDescriptor, StoreOp, TextureDescriptor,
+    TextureDimension, TextureUsage, TextureView,
+};
+
+// How receivers soften the shadow's edges
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadowFilter {
+    // A single lookup, blending the nearest four texels
+    Hard,
+    // Percentage-closer filtering, averaging a square of lookups around the receiver
+    Pcf,
+    // Percentage-closer soft shadows, widening the square the further the receiver is behind
+    // what blocks the light, so shadows are sharp where things touch
+    Pcss,
+}
+
+impl ShadowFilter {
+    pub const ALL: [ShadowFilter; 3] = [ShadowFilter::Hard, ShadowFilter::Pcf, ShadowFilter::Pcss];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShadowFilter::Hard => "Hard",
+            ShadowFilter::Pcf => "PCF",
+            ShadowFilter::Pcss => "PCSS",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ShadowUniforms {
+    // First, so the casters' vertex shaders can read it as their camera
+    view_proj: Matrix4<f32>,
+    filter: u32,
+    kernel_radius: u32,
+    depth_bias: f32,
+    slope_bias: f32,
+    // In world units
+    normal_offset: f32,
+    // How much wider the penumbra gets per unit of depth between blocker and receiver
+    penumbra_scale: f32,
+    texel_size: f32,
+}
+
+impl Block for ShadowUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.filter);
+        writer.member(&self.kernel_radius);
+        writer.member(&self.depth_bias);
+        writer.member(&self.slope_bias);
+        writer.member(&self.normal_offset);
+        writer.member(&self.penumbra_scale);
+        writer.member(&self.texel_size);
+    }
+}
+
+impl Default for ShadowUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            filter: 0,
+            kernel_radius: 0,
+            depth_bias: 0.0,
+            slope_bias: 0.0,
+            normal_offset: 0.0,
+            penumbra_scale: 0.0,
+            texel_size: 0.0,
+        }
+    }
+}
+
+// `key` drawing only the depth, for casting shadows into a `ShadowMap`. The vertex shader's
+// camera at set 0 gets the light's.
+pub fn casting(key: &PipelineKey) -> PipelineKey {
+    let mut key = key.clone();
+    key.fragment_shader = None;
+    key.color_states = Vec::new();
+    key.bind_group_layouts.truncate(1);
+    key.depth_stencil_state = Some(depth::depth_stencil_state());
+    key
+}
+
+// The depth seen from a directional light, for telling what it reaches. Casters get drawn into
+// it with a `casting` pipeline and `caster_bind_group` as their camera, and receivers look it up
+// through `bind_group` with a `bind_group::SHADOW_LAYOUT`, where it comes with the light's view
+// projection and filtering settings at binding 2.
+pub struct ShadowMap {
+    view: TextureView,
+    bind_group: BindGroup,
+    caster_bind_group: Arc<BindGroup>,
+    uniform_allocation: Allocation,
+    size: u32,
+
+    pub filter: ShadowFilter,
+    // Lookups either side of the receiver, in texels, so 1 is 3x3
+    pub kernel_radius: u32,
+    // Pushes receivers towards the light in depth, more for surfaces at a grazing angle. Too
+    // little and they shadow themselves in stripes, too much and shadows come loose from the
+    // casters.
+    pub depth_bias: f32,
+    pub slope_bias: f32,
+    // Moves receivers out along their normals before the lookup, in shadow map texels
+    pub normal_offset: f32,
+    // How wide the light looks in degrees, which sets how soft PCSS gets
+    pub light_angle: f32,
+}
+
+impl ShadowMap {
+    // `size` texels square
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        size: u32,
+    ) -> Self {
+        let view = device
+            .create_texture(&TextureDescriptor {
+                size: Extent3d {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                },
+                array_layer_count: 1,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: depth::DEPTH_FORMAT,
+                usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED,
+                label: Some("shadow_map"),
+            })
+            .create_default_view();
+        // Compares the depth, blending the results of the nearest four texels
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::LessEqual,
+        });
+        // Reads the depth itself, for PCSS to find what's blocking the light
+        let depth_sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            ShadowUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniforms = BindingResource::Buffer {
+            buffer: uniform_pool.buffer(&uniform_allocation),
+            range: uniform_allocation.offset..uniform_allocation.offset + uniform_allocation.size,
+        };
+        let caster_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "shadow_casters",
+            &[Binding {
+                binding: 0,
+                resource: uniforms.clone(),
+            }],
+        );
+        let layout = bind_groups.layout(device, "shadow_map", bind_group::SHADOW_LAYOUT);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+                Binding {
+                    binding: 2,
+                    resource: uniforms,
+                },
+                Binding {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&depth_sampler),
+                },
+            ],
+            label: Some("shadow_map"),
+        });
+
+        Self {
+            view,
+            bind_group,
+            caster_bind_group,
+            uniform_allocation,
+            size,
+            filter: ShadowFilter::Pcf,
+            kernel_radius: 1,
+            depth_bias: 0.0005,
+            slope_bias: 0.002,
+            normal_offset: 0.5,
+            light_angle: 2.0,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Shadow filter")
+            .selected_text(self.filter.name())
+            .show_ui(ui, |ui| {
+                for &filter in &ShadowFilter::ALL {
+                    ui.selectable_value(&mut self.filter, filter, filter.name());
+                }
+            });
+        match self.filter {
+            ShadowFilter::Hard => {}
+            ShadowFilter::Pcf => {
+                ui.add(egui::Slider::new(&mut self.kernel_radius, 1..=4).text("Kernel radius"));
+            }
+            ShadowFilter::Pcss => {
+                ui.add(
+                    egui::Slider::new(&mut self.light_angle, 0.1..=10.0).text("Light size (deg)"),
+                );
+            }
+        }
+        ui.add(
+            egui::Slider::new(&mut self.depth_bias, 0.0..=0.01)
+                .text("Depth bias")
+                .logarithmic(true),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.slope_bias, 0.0..=0.02)
+                .text("Slope bias")
+                .logarithmic(true),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.normal_offset, 0.0..=4.0).text("Normal offset (texels)"),
+        );
+    }
+
+    // For a light shining along `direction`, covering everything in `bounds`
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        direction: Vector3<f32>,
+        bounds: &Sphere,
+    ) {
+        // The map covers the bounds' diameter
+        let texel_world_size = 2.0 * bounds.radius / self.size as f32;
+        let uniforms = ShadowUniforms {
+            view_proj: camera::directional_light_view_projection(direction, bounds),
+            filter: self.filter as u32,
+            kernel_radius: self.kernel_radius,
+            depth_bias: self.depth_bias,
+            slope_bias: self.slope_bias,
+            normal_offset: self.normal_offset * texel_world_size,
+            // The depth and the map's width both span the bounds' diameter, so the penumbra
+            // grows by the light's angle in either
+            penumbra_scale: Deg(self.light_angle / 2.0).tan(),
+            texel_size: 1.0 / self.size as f32,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the casters' pass, cleared to nothing in the way
+    pub fn attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.view,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // The light's view projection where casters expect their camera
+    pub fn caster_bind_group(&self) -> &BindGroup {
+        &self.caster_bind_group
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("shadow_casters");
+    }
+}
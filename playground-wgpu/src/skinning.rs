@@ -0,0 +1,235 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use cgmath::{Matrix4, SquareMatrix};
+use playground_math::{Block, BlockWriter, Layout};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, Buffer, BufferAddress, BufferUsage,
+    ColorStateDescriptor, ColorWrite, CommandEncoder, CullMode, Device, IndexFormat, InputStepMode,
+    PrimitiveTopology, RenderPass, RenderPipeline, ShaderStage, TextureFormat,
+    VertexAttributeDescriptor, VertexFormat,
+};
+
+const SKINNED_VERT: Shader = Shader {
+    name: "skinned.vert",
+    source: include_str!("../shaders/skinned.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHAPE_FRAG: Shader = Shader {
+    name: "shape.frag",
+    source: include_str!("../shaders/shape.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Has to match the size of u_joints in the vertex shader
+pub const MAX_JOINTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    // Indices into the skeleton's joints
+    pub joints: [u8; 4],
+    // Adding up to 1
+    pub weights: [f32; 4],
+    pub color: [u8; 4],
+}
+
+unsafe impl bytemuck::Pod for SkinnedVertex {}
+
+unsafe impl bytemuck::Zeroable for SkinnedVertex {}
+
+#[derive(Copy, Clone, Debug)]
+struct SkinUniforms {
+    view_proj: Matrix4<f32>,
+    model: Matrix4<f32>,
+    joints: [Matrix4<f32>; MAX_JOINTS],
+}
+
+impl Block for SkinUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.model);
+        writer.member(&self.joints);
+    }
+}
+
+impl Default for SkinUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            model: Matrix4::identity(),
+            joints: [Matrix4::identity(); MAX_JOINTS],
+        }
+    }
+}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<SkinnedVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: 12,
+                shader_location: 1,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: 24,
+                shader_location: 2,
+                format: VertexFormat::Uchar4,
+            },
+            VertexAttributeDescriptor {
+                offset: 28,
+                shader_location: 3,
+                format: VertexFormat::Float4,
+            },
+            VertexAttributeDescriptor {
+                offset: 44,
+                shader_location: 4,
+                format: VertexFormat::Uchar4Norm,
+            },
+        ],
+    }
+}
+
+// A mesh bent by a skeleton in the vertex shader. `prepare` uploads the joint matrices of the
+// current pose, `render` draws the mesh with them into the demo's render pass.
+pub struct SkinnedMesh {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    index_count: u32,
+
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    // Names the uniform bind group in the cache, so several meshes can be around at once
+    key: String,
+}
+
+impl SkinnedMesh {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        key: &str,
+        vertices: &[SkinnedVertex],
+        indices: &[u16],
+    ) -> Self {
+        let vertex_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(vertices), BufferUsage::VERTEX);
+        // Padded to keep the buffer size a multiple of 4
+        let mut padded = indices.to_vec();
+        if padded.len() % 2 == 1 {
+            padded.push(0);
+        }
+        let index_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&padded), BufferUsage::INDEX);
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            SkinUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            key,
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: SKINNED_VERT,
+                fragment_shader: Some(SHAPE_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::UNIFORM_LAYOUT)],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                // The tubes are open where the limbs meet the body
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: Some(depth::depth_stencil_state()),
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            key: key.to_string(),
+        }
+    }
+
+    // `joints` are the skeleton's skinning matrices, joints past MAX_JOINTS are ignored
+    pub fn prepare(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        view_proj: Matrix4<f32>,
+        model: Matrix4<f32>,
+        joints: &[Matrix4<f32>],
+    ) {
+        let mut uniforms = SkinUniforms {
+            view_proj,
+            model,
+            joints: [Matrix4::identity(); MAX_JOINTS],
+        };
+        for (uniform, joint) in uniforms.joints.iter_mut().zip(joints) {
+            *uniform = *joint;
+        }
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        draw_stats::record_triangles(self.index_count, 1);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate(&self.key);
+    }
+}
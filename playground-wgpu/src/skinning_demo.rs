@@ -0,0 +1,322 @@
+use crate::assets::Assets;
+use crate::buffer_pool::BufferPool;
+use crate::character::{Character, Locomotion};
+use crate::debug_draw;
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::input::Input;
+use crate::skinning::SkinnedMesh;
+use cgmath::{EuclideanSpace, Matrix4, Point3, Rad, Vector3};
+use playground_math::{animation, Camera, MathBackend, Player, Projection};
+use wgpu::{
+    Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+// Radians per second
+const TURN_SPEED: f32 = 2.5;
+// How quickly the speed, the lean and the wave layer catch up with the controls, per second
+const RESPONSE: f32 = 5.0;
+
+fn approach(value: f32, target: f32, dt: f32) -> f32 {
+    value + (target - value) * (RESPONSE * dt).min(1.0)
+}
+
+// What the character_* actions ask for this frame
+#[derive(Default)]
+struct Controls {
+    forward: bool,
+    running: bool,
+    // -1 for right, 1 for left
+    turn: f32,
+    waving: bool,
+}
+
+// A mannequin playing its clips, skinned in the vertex shader. Either a clip picked in the UI
+// plays, or the controls walk the mannequin around and pick its clips.
+pub struct SkinningDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    character: Character,
+    mesh: SkinnedMesh,
+
+    player: Player,
+    paused: bool,
+    // Seconds a crossfade picked in the UI takes
+    fade: f32,
+    show_skeleton: bool,
+    // Which library works out the skinning matrices
+    math: MathBackend,
+
+    controlled: bool,
+    controls: Controls,
+    locomotion: Locomotion,
+    position: Vector3<f32>,
+    // Around y, facing +z at 0
+    heading: f32,
+    // As of the step before, for drawing in between
+    previous_position: Vector3<f32>,
+    previous_heading: f32,
+    speed: f32,
+    // -1 to 1, leaning into the turn
+    lean: f32,
+    wave: usize,
+    wave_time: f32,
+    wave_weight: f32,
+    // The right arm, which the wave gets layered onto
+    wave_mask: Vec<f32>,
+}
+
+impl SkinningDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (2.0, 1.5, 3.0).into(),
+            target: (0.0, 1.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let character = Character::mannequin();
+        let mesh = SkinnedMesh::new(
+            ctx.device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            "mannequin_skin",
+            &character.vertices,
+            &character.indices,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(ctx.device, ctx.size),
+            player: Player::new(character.clip("Walk").unwrap()),
+            paused: false,
+            fade: 0.3,
+            show_skeleton: false,
+            math: MathBackend::default(),
+            controlled: false,
+            controls: Controls::default(),
+            locomotion: Locomotion::Idle,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            heading: 0.0,
+            previous_position: Vector3::new(0.0, 0.0, 0.0),
+            previous_heading: 0.0,
+            speed: 0.0,
+            lean: 0.0,
+            wave: character.clip("Wave").unwrap(),
+            wave_time: 0.0,
+            wave_weight: 0.0,
+            wave_mask: character.skeleton.mask(&["right upper arm"]),
+            character,
+            mesh,
+        }
+    }
+
+    fn clip(&self, name: &str) -> usize {
+        self.character.clip(name).unwrap()
+    }
+
+    // Runs the locomotion state machine and moves the mannequin along
+    fn steer(&mut self, dt: f32) {
+        let controls = &self.controls;
+        let locomotion = Locomotion::from_controls(controls.forward, controls.running);
+        if locomotion != self.locomotion {
+            let (fade, sync) = self.locomotion.transition(locomotion);
+            let clip = self.clip(locomotion.name());
+            self.player.play(&self.character.clips, clip, fade, sync);
+            self.locomotion = locomotion;
+        }
+
+        // Turning on the spot is fine, leaning into it only once moving
+        self.heading += controls.turn * TURN_SPEED * dt;
+        self.speed = approach(self.speed, locomotion.speed(), dt);
+        let moving = (self.speed / Locomotion::Walk.speed()).min(1.0);
+        self.lean = approach(self.lean, controls.turn * moving, dt);
+
+        let (sin, cos) = self.heading.sin_cos();
+        let offset = Vector3::new(sin, 0.0, cos) * self.speed * dt;
+        self.position += offset;
+        // The camera keeps following from the same place relative to the mannequin
+        self.camera.eye += offset;
+        self.camera.target += offset;
+    }
+
+    // Where the mannequin is `alpha` of the way from the step before to the last one
+    fn model(&self, alpha: f32) -> Matrix4<f32> {
+        let position = self.previous_position + (self.position - self.previous_position) * alpha;
+        let heading = self.previous_heading + (self.heading - self.previous_heading) * alpha;
+        Matrix4::from_translation(position) * Matrix4::from_angle_y(Rad(heading))
+    }
+}
+
+impl Demo for SkinningDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn handle_input(&mut self, input: &Input) {
+        let held = |action: &str| input.held(action) as i32 as f32;
+        self.controls.forward = input.held("character_forward");
+        self.controls.running = input.held("character_run");
+        self.controls.turn = held("character_turn_left") - held("character_turn_right");
+        if input.pressed("character_wave") {
+            self.controls.waving = !self.controls.waving;
+        }
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        self.previous_position = self.position;
+        self.previous_heading = self.heading;
+        let dt = if self.paused { 0.0 } else { dt };
+        if self.controlled {
+            self.steer(dt);
+        }
+        self.player.advance(&self.character.clips, dt);
+        let waving = self.controls.waving as i32 as f32;
+        self.wave_weight = approach(self.wave_weight, waving, dt);
+        let wave_duration = self.character.clips[self.wave].duration;
+        self.wave_time = (self.wave_time + dt * self.player.speed).rem_euclid(wave_duration);
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+
+        let clips = &self.character.clips;
+        let skeleton = &self.character.skeleton;
+        let rest = skeleton.rest_pose();
+        let mut pose = rest.clone();
+        self.player.sample(clips, &mut pose);
+        if self.wave_weight > 0.0 {
+            let mut wave = rest.clone();
+            clips[self.wave].sample(self.wave_time, &mut wave);
+            animation::blend(&mut pose, &wave, self.wave_weight, Some(&self.wave_mask));
+        }
+        if self.lean != 0.0 {
+            let lean = if self.lean > 0.0 {
+                "Lean left"
+            } else {
+                "Lean right"
+            };
+            let mut leaning = rest.clone();
+            clips[self.clip(lean)].sample(0.0, &mut leaning);
+            animation::add(&mut pose, &leaning, &rest, self.lean.abs(), None);
+        }
+
+        // The camera follows the mannequin, so it lags behind the last step just as far
+        let model = self.model(alpha);
+        let lag = model.w.truncate() - self.position;
+        self.mesh.prepare(
+            device,
+            encoder,
+            uniform_pool,
+            self.camera.build_view_projection_matrix() * Matrix4::from_translation(-lag),
+            model,
+            &skeleton.skinning_matrices_in(self.math, &pose),
+        );
+
+        if self.show_skeleton {
+            let world = skeleton.world_matrices(&pose);
+            let position =
+                |joint: usize| Point3::from_vec((model * world[joint].w).truncate() - lag);
+            for (index, joint) in skeleton.joints.iter().enumerate() {
+                if let Some(parent) = joint.parent {
+                    debug_draw::line(
+                        position(parent),
+                        position(index),
+                        playground_math::Color::new(1.0, 1.0, 0.0, 1.0),
+                    );
+                }
+            }
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(&mut self.controlled, "Controlled")
+            .on_hover_text("Moved with the character_* actions")
+            .changed()
+            && self.controlled
+        {
+            let clip = self.clip(self.locomotion.name());
+            self.player
+                .play(&self.character.clips, clip, self.fade, false);
+        }
+
+        let clips = &self.character.clips;
+        if self.controlled {
+            ui.label(format!("State: {}", self.locomotion.name()));
+            ui.label(format!("Speed: {:.1}", self.speed));
+        } else {
+            let mut clip = self.player.current.clip;
+            egui::ComboBox::from_label("Clip")
+                .selected_text(clips[clip].name.as_str())
+                .show_ui(ui, |ui| {
+                    for (index, entry) in clips.iter().enumerate() {
+                        ui.selectable_value(&mut clip, index, entry.name.as_str());
+                    }
+                });
+            if clip != self.player.current.clip {
+                self.player.play(clips, clip, self.fade, false);
+            }
+            ui.add(egui::Slider::new(&mut self.fade, 0.0..=1.0).text("Crossfade"));
+            let duration = clips[clip].duration;
+            ui.add(egui::Slider::new(&mut self.player.current.time, 0.0..=duration).text("Time"));
+        }
+        ui.label(format!("Blend: {:.2}", self.player.weight()));
+        ui.checkbox(&mut self.controls.waving, "Wave");
+        ui.checkbox(&mut self.paused, "Paused");
+        ui.add(egui::Slider::new(&mut self.player.speed, 0.0..=3.0).text("Speed"));
+        ui.checkbox(&mut self.show_skeleton, "Skeleton");
+        egui::ComboBox::from_label("Math")
+            .selected_text(self.math.name())
+            .show_ui(ui, |ui| {
+                for backend in MathBackend::ALL {
+                    ui.selectable_value(&mut self.math, *backend, backend.name());
+                }
+            });
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        self.mesh.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.mesh.release(ctx.uniform_pool, ctx.bind_groups);
+    }
+}
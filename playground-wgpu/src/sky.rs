@@ -0,0 +1,187 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader};
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+use playground_math::{sun, Block, BlockWriter, Camera, Layout};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, BufferAddress, ColorStateDescriptor,
+    ColorWrite, CommandEncoder, CompareFunction, CullMode, DepthStencilStateDescriptor, Device,
+    IndexFormat, PrimitiveTopology, RenderPass, RenderPipeline, ShaderStage, TextureFormat,
+};
+
+const SKY_VERT: Shader = Shader {
+    name: "sky.vert",
+    source: include_str!("../shaders/sky.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SKY_FRAG: Shader = Shader {
+    name: "sky.frag",
+    source: include_str!("../shaders/sky.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+#[derive(Copy, Clone, Debug)]
+struct SkyUniforms {
+    inverse_view_proj: Matrix4<f32>,
+    camera_position: [f32; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    turbidity: f32,
+    brightness: f32,
+}
+
+impl Block for SkyUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.inverse_view_proj);
+        writer.member(&Vector4::from(self.camera_position));
+        writer.member(&Vector4::from(self.sun_direction));
+        writer.member(&Vector4::from(self.sun_color));
+        writer.member(&self.turbidity);
+        writer.member(&self.brightness);
+    }
+}
+
+impl Default for SkyUniforms {
+    fn default() -> Self {
+        Self {
+            inverse_view_proj: Matrix4::identity(),
+            camera_position: [0.0; 4],
+            sun_direction: [0.0; 4],
+            sun_color: [0.0; 4],
+            turbidity: 0.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+// A procedural sky, with Preetham's analytic model of the daylight scattered by the atmosphere.
+// It's drawn last in the scene's pass on the far plane, so it only shows where nothing else got
+// drawn, and follows the same sun direction as the scene's light.
+pub struct Sky {
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    // How hazy the air is, from 2 for a clear day to about 10
+    pub turbidity: f32,
+    // The sky's luminance comes out in thousands of cd/m^2, this scales it for the HDR target
+    pub brightness: f32,
+}
+
+impl Sky {
+    // Into `format` targets, in a pass with a `depth::DEPTH_FORMAT` depth buffer
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: SKY_VERT,
+                fragment_shader: Some(SKY_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(
+                    bind_group::FRAGMENT_UNIFORM_LAYOUT,
+                )],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                // Passes where the depth was left cleared
+                depth_stencil_state: Some(DepthStencilStateDescriptor {
+                    depth_write_enabled: false,
+                    depth_compare: CompareFunction::LessEqual,
+                    ..depth::depth_stencil_state()
+                }),
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            SkyUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "sky_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            turbidity: 3.0,
+            brightness: 0.1,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.turbidity, 2.0..=10.0).text("Turbidity"));
+        ui.add(egui::Slider::new(&mut self.brightness, 0.01..=1.0).text("Sky brightness"));
+    }
+
+    // `camera` has to be the one the scene gets drawn with, and `sun_direction` point towards
+    // the sun
+    pub fn prepare(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+        sun_direction: Vector3<f32>,
+    ) {
+        let uniforms = SkyUniforms {
+            inverse_view_proj: camera
+                .build_view_projection_matrix()
+                .invert()
+                .unwrap_or_else(Matrix4::identity),
+            camera_position: [camera.eye.x, camera.eye.y, camera.eye.z, 1.0],
+            sun_direction: sun_direction.extend(0.0).into(),
+            sun_color: sun::transmittance(sun_direction).to_array(),
+            turbidity: self.turbidity,
+            brightness: self.brightness,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // After everything opaque, in the same pass
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("sky_uniforms");
+    }
+}
@@ -0,0 +1,326 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use cgmath::{Matrix4, SquareMatrix};
+use playground_math::{Block, BlockWriter, Camera, Layout};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BufferAddress, Color, ColorStateDescriptor, ColorWrite, CommandEncoder,
+    CompareFunction, CullMode, Device, FilterMode, IndexFormat, LoadOp, PrimitiveTopology,
+    RenderPass, RenderPassColorAttachmentDescriptor, RenderPassDepthStencilAttachmentDescriptor,
+    RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SSR_FRAG: Shader = Shader {
+    name: "ssr.frag",
+    source: include_str!("../shaders/ssr.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Roughness, from 0 for a perfect mirror to 1 for nothing reflected at all
+pub const MATERIAL_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+#[derive(Copy, Clone, Debug)]
+struct ReflectionUniforms {
+    projection: Matrix4<f32>,
+    inverse_projection: Matrix4<f32>,
+    steps: u32,
+    refine_steps: u32,
+    max_distance: f32,
+    thickness: f32,
+    strength: f32,
+}
+
+impl Block for ReflectionUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.projection);
+        writer.member(&self.inverse_projection);
+        writer.member(&self.steps);
+        writer.member(&self.refine_steps);
+        writer.member(&self.max_distance);
+        writer.member(&self.thickness);
+        writer.member(&self.strength);
+    }
+}
+
+impl Default for ReflectionUniforms {
+    fn default() -> Self {
+        Self {
+            projection: Matrix4::identity(),
+            inverse_projection: Matrix4::identity(),
+            steps: 0,
+            refine_steps: 0,
+            max_distance: 0.0,
+            thickness: 0.0,
+            strength: 0.0,
+        }
+    }
+}
+
+// `key` drawing into the targets of a `ScreenSpaceReflections` instead. `fragment_shader` has to
+// write the lit color to location 0 and the roughness to location 1.
+pub fn writing_material(key: &PipelineKey, fragment_shader: Shader) -> PipelineKey {
+    let mut key = key.clone();
+    key.fragment_shader = Some(fragment_shader);
+    key.color_states.push(ColorStateDescriptor {
+        format: MATERIAL_FORMAT,
+        color_blend: BlendDescriptor::REPLACE,
+        alpha_blend: BlendDescriptor::REPLACE,
+        write_mask: ColorWrite::ALL,
+    });
+    key
+}
+
+// The scene's targets, and the bind group the reflection pass reads them through
+struct Targets {
+    color: TextureView,
+    material: TextureView,
+    depth: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let color = render_target::create_target(device, size, format, "ssr_color");
+        let material = render_target::create_target(device, size, MATERIAL_FORMAT, "ssr_material");
+        let depth = render_target::create_target(device, size, depth::DEPTH_FORMAT, "ssr_depth");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&color),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&material),
+                },
+                Binding {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&depth),
+                },
+                Binding {
+                    binding: 3,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("ssr_targets"),
+        });
+
+        Self {
+            color,
+            material,
+            depth,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// Screen-space reflections. The scene gets drawn into offscreen targets with a
+// `writing_material` pipeline, leaving its lit color, roughness and depth. `resolve` then copies
+// it into the frame, and for every pixel marches the reflected view ray through the depth. Where
+// the ray goes behind something, what's there gets blended in, less so the rougher the surface.
+// Normals get reconstructed from the depth, so the scene doesn't write them. Only what's on the
+// screen can be reflected.
+pub struct ScreenSpaceReflections {
+    targets: Targets,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    format: TextureFormat,
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    pub enabled: bool,
+    // Along each ray, and then to narrow down where it hit
+    pub steps: u32,
+    pub refine_steps: u32,
+    // How far rays go, and how far behind a surface they still count as hitting it
+    pub max_distance: f32,
+    pub thickness: f32,
+    pub strength: f32,
+}
+
+impl ScreenSpaceReflections {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "ssr_targets", bind_group::SCENE_TARGETS_LAYOUT);
+        // The targets are read texel for texel
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(SSR_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::SCENE_TARGETS_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            ReflectionUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "ssr_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            targets: Targets::new(device, &layout, &sampler, format, size),
+            layout,
+            sampler,
+            format,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            enabled: true,
+            steps: 64,
+            refine_steps: 6,
+            max_distance: 12.0,
+            thickness: 0.5,
+            strength: 0.8,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Screen-space reflections");
+        ui.add(egui::Slider::new(&mut self.steps, 4..=256).text("Steps"));
+        ui.add(egui::Slider::new(&mut self.refine_steps, 0..=12).text("Refine steps"));
+        ui.add(egui::Slider::new(&mut self.max_distance, 1.0..=50.0).text("Max distance"));
+        ui.add(egui::Slider::new(&mut self.thickness, 0.01..=2.0).text("Thickness"));
+        ui.add(egui::Slider::new(&mut self.strength, 0.0..=1.0).text("Strength"));
+    }
+
+    // Has to match the frame, and `camera` the one the scene gets drawn with
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        size: PhysicalSize<u32>,
+        camera: &Camera,
+    ) {
+        if size != self.targets.size {
+            self.targets = Targets::new(device, &self.layout, &self.sampler, self.format, size);
+        }
+
+        let projection = camera.projection_matrix();
+        let uniforms = ReflectionUniforms {
+            projection,
+            inverse_projection: projection.invert().unwrap_or_else(Matrix4::identity),
+            steps: self.steps.max(1),
+            refine_steps: self.refine_steps,
+            max_distance: self.max_distance,
+            thickness: self.thickness,
+            // Still copies the scene into the frame, without marching
+            strength: if self.enabled { self.strength } else { 0.0 },
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the scene's pass, cleared to `clear_color` and to nothing reflecting
+    pub fn attachments(&self, clear_color: Color) -> [RenderPassColorAttachmentDescriptor<'_>; 2] {
+        [
+            RenderPassColorAttachmentDescriptor {
+                attachment: &self.targets.color,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            },
+            RenderPassColorAttachmentDescriptor {
+                attachment: &self.targets.material,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::WHITE,
+            },
+        ]
+    }
+
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.targets.depth,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // Writes every pixel of the frame, so the pass doesn't need to load it
+    pub fn resolve<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.targets.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("ssr_uniforms");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::pipeline::{PipelineKey, Shader};
+use crate::primitives::{self, Cubes, Quad};
+use crate::ssr::{self, ScreenSpaceReflections};
+use crate::uniform::{self, ObjectUniforms, Uniforms};
+use cgmath::{Deg, Matrix4, Vector3, Vector4};
+use playground_math::{Block, BlockWriter, Camera, Layout, Projection};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Color, CommandEncoder, CullMode, Device, LoadOp,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SHADER_VERT: Shader = Shader {
+    name: "shader.vert",
+    source: include_str!("../shaders/shader.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHAPE_MATERIAL_FRAG: Shader = Shader {
+    name: "shape_material.frag",
+    source: include_str!("../shaders/shape_material.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const FLOOR_MATERIAL_FRAG: Shader = Shader {
+    name: "floor_material.frag",
+    source: include_str!("../shaders/floor_material.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Sides of the floor
+const FLOOR_SIZE: f32 = 14.0;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct MaterialUniforms {
+    tint: [f32; 4],
+    roughness: f32,
+}
+
+impl Block for MaterialUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector4::from(self.tint));
+        writer.member(&self.roughness);
+    }
+}
+
+impl MaterialUniforms {
+    fn new([r, g, b]: [f32; 3], roughness: f32) -> Self {
+        Self {
+            tint: [r, g, b, 1.0],
+            roughness,
+        }
+    }
+}
+
+const UNIFORM_KEYS: [&str; 4] = [
+    "ssr_camera",
+    "ssr_floor",
+    "ssr_cube_material",
+    "ssr_floor_material",
+];
+
+// A ring of cubes on a glossy floor, reflected in screen space. Unlike the planar reflection
+// demo the cubes reflect each other too, but whatever is off the screen is missing from the
+// reflections.
+pub struct SsrDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    reflections: ScreenSpaceReflections,
+    floor_tint: [f32; 3],
+    floor_roughness: f32,
+    cube_roughness: f32,
+
+    cube_pipeline: Arc<RenderPipeline>,
+    floor_pipeline: Arc<RenderPipeline>,
+    cubes: Cubes,
+    floor: Quad,
+
+    // The camera, the floor's model matrix and the cubes' and floor's materials
+    uniform_allocations: [Allocation; 4],
+    uniform_bind_groups: [Arc<BindGroup>; 4],
+}
+
+impl SsrDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 3.0, 10.0).into(),
+            target: (0.0, 0.5, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let mut cube_key =
+            ssr::writing_material(&Cubes::pipeline_key(ctx.format), SHAPE_MATERIAL_FRAG);
+        cube_key
+            .bind_group_layouts
+            .push(bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT));
+        let cube_pipeline = ctx.pipelines.get(device, ctx.bind_groups, &cube_key);
+        let floor_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: SHADER_VERT,
+                fragment_shader: Some(FLOOR_MATERIAL_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![Quad::vertex_layout()],
+                cull_mode: CullMode::None,
+                ..cube_key
+            },
+        );
+
+        let mut allocate = |key, size, layout| uniform::allocate(ctx, key, size, layout);
+        let material_size = MaterialUniforms::size(Layout::Std140);
+        let (camera_allocation, camera_bind_group) = allocate(
+            UNIFORM_KEYS[0],
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (floor_allocation, floor_bind_group) = allocate(
+            UNIFORM_KEYS[1],
+            ObjectUniforms::size(Layout::Std140),
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+        );
+        let (cube_material_allocation, cube_material_bind_group) = allocate(
+            UNIFORM_KEYS[2],
+            material_size,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+        );
+        let (floor_material_allocation, floor_material_bind_group) = allocate(
+            UNIFORM_KEYS[3],
+            material_size,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+        );
+
+        let reflections = ScreenSpaceReflections::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            ctx.size,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            reflections,
+            floor_tint: [0.3, 0.32, 0.36],
+            floor_roughness: 0.1,
+            cube_roughness: 0.6,
+            cube_pipeline,
+            floor_pipeline,
+            cubes: Cubes::new(device, &primitives::ring_of_cubes(8, 3.0)),
+            floor: Quad::new(device),
+            uniform_allocations: [
+                camera_allocation,
+                floor_allocation,
+                cube_material_allocation,
+                floor_material_allocation,
+            ],
+            uniform_bind_groups: [
+                camera_bind_group,
+                floor_bind_group,
+                cube_material_bind_group,
+                floor_material_bind_group,
+            ],
+        }
+    }
+}
+
+impl Demo for SsrDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.reflections
+            .prepare(device, encoder, uniform_pool, self.size, &self.camera);
+
+        let mut camera_uniforms = Uniforms::new();
+        camera_uniforms.update_view_proj(&self.camera);
+        // The quad lies down on y = 0, facing up
+        let floor_model = Matrix4::from_angle_x(Deg(-90.0)) * Matrix4::from_scale(FLOOR_SIZE);
+
+        let [camera, floor, cube_material, floor_material] = &self.uniform_allocations;
+        uniform_pool.write(device, encoder, camera, &camera_uniforms.std140());
+        uniform_pool.write(
+            device,
+            encoder,
+            floor,
+            &ObjectUniforms::new(floor_model, 1.0).std140(),
+        );
+        uniform_pool.write(
+            device,
+            encoder,
+            cube_material,
+            &MaterialUniforms::new([1.0; 3], self.cube_roughness).std140(),
+        );
+        uniform_pool.write(
+            device,
+            encoder,
+            floor_material,
+            &MaterialUniforms::new(self.floor_tint, self.floor_roughness).std140(),
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.reflections.ui(ui);
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Floor");
+            ui.color_edit_button_rgb(&mut self.floor_tint);
+        });
+        ui.add(egui::Slider::new(&mut self.floor_roughness, 0.0..=1.0).text("Floor roughness"));
+        ui.add(egui::Slider::new(&mut self.cube_roughness, 0.0..=1.0).text("Cube roughness"));
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let [camera, floor, cube_material, floor_material] = &self.uniform_bind_groups;
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &self.reflections.attachments(clear_color),
+            depth_stencil_attachment: Some(self.reflections.depth_attachment()),
+        });
+        render_pass.push_debug_group("Scene into the reflection targets");
+        render_pass.set_pipeline(&self.cube_pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        render_pass.set_bind_group(1, cube_material, &[]);
+        self.cubes.draw(&mut render_pass);
+
+        render_pass.set_pipeline(&self.floor_pipeline);
+        render_pass.set_bind_group(0, floor_material, &[]);
+        render_pass.set_bind_group(1, camera, &[]);
+        render_pass.set_bind_group(2, floor, &[]);
+        self.floor.draw(&mut render_pass);
+        render_pass.pop_debug_group();
+        drop(render_pass);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.push_debug_group("Reflection resolve");
+        self.reflections.resolve(&mut render_pass);
+        render_pass.pop_debug_group();
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.reflections.release(ctx.uniform_pool, ctx.bind_groups);
+        for (allocation, key) in self.uniform_allocations.iter().zip(&UNIFORM_KEYS) {
+            ctx.uniform_pool.free(*allocation);
+            ctx.bind_groups.invalidate(key);
+        }
+    }
+}
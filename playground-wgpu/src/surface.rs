@@ -0,0 +1,99 @@
+use log::warn;
+use wgpu::{
+    Device, PresentMode, Surface, SwapChain, SwapChainDescriptor, SwapChainOutput, TextureFormat,
+    TextureUsage,
+};
+use winit::dpi::PhysicalSize;
+use winit::window::Window;
+
+// A window together with the surface and swap chain presenting to it. The device and everything
+// created from it is shared between windows.
+pub struct WindowSurface {
+    pub window: Window,
+    surface: Surface,
+    pub sc_desc: SwapChainDescriptor,
+    // Swap chains can't be zero sized, there is none until the window has a size
+    swap_chain: Option<SwapChain>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl WindowSurface {
+    // The surface gets created by the caller, the adapter has to be picked for it first
+    pub fn new(device: &Device, window: Window, surface: Surface) -> Self {
+        let size = window.inner_size();
+        let sc_desc = SwapChainDescriptor {
+            usage: TextureUsage::OUTPUT_ATTACHMENT,
+            format: TextureFormat::Bgra8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode: PresentMode::Fifo,
+        };
+        let mut window_surface = Self {
+            window,
+            surface,
+            sc_desc,
+            swap_chain: None,
+            size,
+        };
+        if !window_surface.is_minimized() {
+            window_surface.recreate_swap_chain(device);
+        }
+        window_surface
+    }
+
+    // Minimized windows report a size of zero on some platforms
+    pub fn is_minimized(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
+    }
+
+    fn recreate_swap_chain(&mut self, device: &Device) {
+        self.swap_chain = Some(device.create_swap_chain(&self.surface, &self.sc_desc));
+    }
+
+    pub fn resize(&mut self, device: &Device, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+
+        // Keep the old swap chain around until the window gets restored, `next_frame` doesn't
+        // touch it in the meantime
+        if self.is_minimized() {
+            return;
+        }
+
+        self.sc_desc.width = new_size.width;
+        self.sc_desc.height = new_size.height;
+        self.recreate_swap_chain(device);
+    }
+
+    // wgpu falls back to Fifo (and logs a warning) when the surface doesn't support the mode
+    pub fn set_present_mode(&mut self, device: &Device, present_mode: PresentMode) {
+        self.sc_desc.present_mode = present_mode;
+        if !self.is_minimized() {
+            self.recreate_swap_chain(device);
+        }
+    }
+
+    // None when there is nothing to draw this frame
+    pub fn next_frame(&mut self, device: &Device) -> Option<SwapChainOutput> {
+        if self.is_minimized() {
+            return None;
+        }
+
+        // A window that started out minimized gets its swap chain here
+        if self.swap_chain.is_none() {
+            self.sc_desc.width = self.size.width;
+            self.sc_desc.height = self.size.height;
+            self.recreate_swap_chain(device);
+        }
+
+        match self.swap_chain.as_mut().unwrap().get_next_texture() {
+            Ok(frame) => Some(frame),
+            Err(_) => {
+                // Timeouts as well as outdated or lost surfaces end up here. Start over with a
+                // fresh swap chain and skip this frame.
+                warn!("Failed to get the next frame, recreating the swap chain");
+                self.recreate_swap_chain(device);
+                None
+            }
+        }
+    }
+}
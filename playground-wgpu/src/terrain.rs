@@ -0,0 +1,637 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use crate::texture::{self, Texture};
+use cgmath::{InnerSpace, Matrix4, Point2, Point3, SquareMatrix, Vector3};
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Rgba, RgbaImage};
+use playground_math::{Aabb, Block, BlockWriter, Camera, Frustum, Layout};
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, Binding, BindingResource, BlendDescriptor, Buffer, BufferAddress,
+    ColorStateDescriptor, ColorWrite, CommandEncoder, CommandEncoderDescriptor, CompareFunction,
+    CullMode, Device, FilterMode, IndexFormat, InputStepMode, Origin3d, PrimitiveTopology, Queue,
+    RenderPass, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, TextureFormat,
+    VertexAttributeDescriptor, VertexFormat,
+};
+
+const TERRAIN_VERT: Shader = Shader {
+    name: "terrain.vert",
+    source: include_str!("../shaders/terrain.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const TERRAIN_FRAG: Shader = Shader {
+    name: "terrain.frag",
+    source: include_str!("../shaders/terrain.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Quads along each side of a tile. Tiles get culled one by one.
+const TILE_QUADS: u32 = 64;
+const LAYER_SIZE: u32 = 128;
+// World size of one repeat of the layer textures
+const LAYER_REPEAT: f32 = 4.0;
+
+// Heights on a regular grid in the XZ plane
+pub struct Heightfield {
+    // Samples along X
+    pub width: u32,
+    // Samples along Z
+    pub depth: u32,
+    // Distance between neighbouring samples
+    pub spacing: f32,
+    // Where the first sample is in the XZ plane
+    pub origin: Point2<f32>,
+    pub heights: Vec<f32>,
+}
+
+impl Heightfield {
+    // Centered on the origin. Black ends up at 0 and white at `height`.
+    pub fn from_image(image: &GrayImage, spacing: f32, height: f32) -> Self {
+        let (width, depth) = image.dimensions();
+        Self {
+            width,
+            depth,
+            spacing,
+            origin: Point2::new(
+                -((width.max(1) - 1) as f32 * spacing) / 2.0,
+                -((depth.max(1) - 1) as f32 * spacing) / 2.0,
+            ),
+            heights: image
+                .pixels()
+                .map(|pixel| pixel[0] as f32 / 255.0 * height)
+                .collect(),
+        }
+    }
+
+    // Any image format works, color gets converted to grayscale
+    pub fn load(path: &Path, spacing: f32, height: f32) -> Result<Self, failure::Error> {
+        let image = image::open(path)?.to_luma();
+        Ok(Self::from_image(&image, spacing, height))
+    }
+
+    // Samples `height` at every world space X and Z on the grid
+    pub fn from_fn(
+        width: u32,
+        depth: u32,
+        spacing: f32,
+        origin: Point2<f32>,
+        height: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let mut heights = Vec::with_capacity((width * depth) as usize);
+        for z in 0..depth {
+            for x in 0..width {
+                heights.push(height(
+                    origin.x + x as f32 * spacing,
+                    origin.y + z as f32 * spacing,
+                ));
+            }
+        }
+
+        Self {
+            width,
+            depth,
+            spacing,
+            origin,
+            heights,
+        }
+    }
+
+    // Samples outside the grid repeat the edge
+    pub fn height(&self, x: i64, z: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let z = z.clamp(0, self.depth as i64 - 1) as usize;
+        self.heights[z * self.width as usize + x]
+    }
+
+    pub fn position(&self, x: u32, z: u32) -> Point3<f32> {
+        Point3::new(
+            self.origin.x + x as f32 * self.spacing,
+            self.height(x as i64, z as i64),
+            self.origin.y + z as f32 * self.spacing,
+        )
+    }
+
+    // From the slope to the neighbouring samples
+    pub fn normal(&self, x: u32, z: u32) -> Vector3<f32> {
+        let (x, z) = (x as i64, z as i64);
+        let dx = self.height(x + 1, z) - self.height(x - 1, z);
+        let dz = self.height(x, z + 1) - self.height(x, z - 1);
+        Vector3::new(-dx, 2.0 * self.spacing, -dz).normalize()
+    }
+
+    pub fn max_height(&self) -> f32 {
+        self.heights.iter().cloned().fold(0.0, f32::max)
+    }
+
+    // How much of each layer covers a sample: sand on the low parts, rock on the steep ones, snow
+    // on top and grass everywhere else. Heights are relative to `max_height`.
+    fn splat(&self, x: u32, z: u32, max_height: f32) -> [u8; 4] {
+        let smoothstep = |edge0: f32, edge1: f32, x: f32| {
+            let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+
+        let height = self.height(x as i64, z as i64) / max_height.max(f32::EPSILON);
+        let slope = 1.0 - self.normal(x, z).y;
+
+        let rock = smoothstep(0.2, 0.35, slope);
+        let snow = smoothstep(0.65, 0.8, height) * (1.0 - rock);
+        let sand = (1.0 - smoothstep(0.04, 0.1, height)) * (1.0 - rock);
+        let grass = (1.0 - rock - snow - sand).max(0.0);
+
+        let weight = |weight: f32| (weight * 255.0).round() as u8;
+        [weight(sand), weight(grass), weight(rock), weight(snow)]
+    }
+}
+
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    let mut h = x
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add(y.wrapping_mul(0xd816_3841))
+        .wrapping_add(seed.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+// Value noise that repeats every `period` cells, so the layer textures tile
+fn tiling_noise(u: f32, v: f32, period: u32, seed: u32) -> f32 {
+    let (x, y) = (u * period as f32, v * period as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let (tx, ty) = (tx * tx * (3.0 - 2.0 * tx), ty * ty * (3.0 - 2.0 * ty));
+    let corner =
+        |dx: u32, dy: u32| hash((x0 as u32 + dx) % period, (y0 as u32 + dy) % period, seed);
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * tx;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * tx;
+    top + (bottom - top) * ty
+}
+
+// A noisy, tiling stand-in for a real ground texture. `color` is sRGB.
+fn layer_image(color: [f32; 3], variation: f32, seed: u32) -> RgbaImage {
+    RgbaImage::from_fn(LAYER_SIZE, LAYER_SIZE, |x, y| {
+        let (u, v) = (x as f32 / LAYER_SIZE as f32, y as f32 / LAYER_SIZE as f32);
+        let noise = tiling_noise(u, v, 8, seed) * 0.5
+            + tiling_noise(u, v, 32, seed + 1) * 0.3
+            + tiling_noise(u, v, 64, seed + 2) * 0.2;
+        let shade = 1.0 + (noise - 0.5) * 2.0 * variation;
+        let channel = |c: f32| ((c * shade).clamp(0.0, 1.0) * 255.0).round() as u8;
+        Rgba([channel(color[0]), channel(color[1]), channel(color[2]), 255])
+    })
+}
+
+// Uploads `image` with its whole mip chain right away, layers are small enough for that
+fn upload_with_mips(
+    device: &Device,
+    queue: &Queue,
+    image: &RgbaImage,
+    format: TextureFormat,
+) -> Texture {
+    let mip_level_count = texture::mip_level_count(image.width(), image.height());
+    let (texture, cmd_buffer) = Texture::from_rgba(device, image, format, mip_level_count);
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("terrain_mip_encoder"),
+    });
+    let mut previous = image.clone();
+    for mip_level in 1..mip_level_count {
+        let width = (previous.width() / 2).max(1);
+        let height = (previous.height() / 2).max(1);
+        let next = imageops::resize(&previous, width, height, FilterType::Triangle);
+        texture::copy_texels_to_texture(
+            device,
+            &mut encoder,
+            &next,
+            width,
+            height,
+            &texture.texture,
+            mip_level,
+            Origin3d::ZERO,
+        );
+        previous = next;
+    }
+    queue.submit(&[cmd_buffer, encoder.finish()]);
+
+    texture
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TerrainVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    // Sand, grass, rock and snow
+    splat: [u8; 4],
+}
+
+unsafe impl bytemuck::Pod for TerrainVertex {}
+
+unsafe impl bytemuck::Zeroable for TerrainVertex {}
+
+#[derive(Copy, Clone, Debug)]
+struct TerrainUniforms {
+    view_proj: Matrix4<f32>,
+    layer_repeat: f32,
+}
+
+impl Block for TerrainUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+        writer.member(&self.layer_repeat);
+    }
+}
+
+impl Default for TerrainUniforms {
+    fn default() -> Self {
+        Self {
+            view_proj: Matrix4::identity(),
+            layer_repeat: 0.0,
+        }
+    }
+}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<TerrainVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                shader_location: 1,
+                format: VertexFormat::Float3,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 6]>() as BufferAddress,
+                shader_location: 2,
+                format: VertexFormat::Uchar4Norm,
+            },
+        ],
+    }
+}
+
+// The geometry of a tile before it's on the GPU, which doesn't need the device so it can be
+// built on any thread
+pub struct TileMesh {
+    vertices: Vec<TerrainVertex>,
+    indices: Vec<u32>,
+    pub bounds: Aabb,
+}
+
+impl TileMesh {
+    // Covers samples `x0..=x1` and `z0..=z1`. Splat weights are picked for heights relative to
+    // `max_height`.
+    pub fn new(
+        heightfield: &Heightfield,
+        (x0, x1): (u32, u32),
+        (z0, z1): (u32, u32),
+        max_height: f32,
+    ) -> Self {
+        let mut vertices = Vec::new();
+        let mut bounds = Aabb::empty();
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                let position = heightfield.position(x, z);
+                bounds = bounds.grow(position);
+                vertices.push(TerrainVertex {
+                    position: position.into(),
+                    normal: heightfield.normal(x, z).into(),
+                    splat: heightfield.splat(x, z, max_height),
+                });
+            }
+        }
+
+        // Counter-clockwise seen from above. Big heightmaps don't fit in 16 bit indices, so
+        // tiles always use 32 bit ones.
+        let row = x1 - x0 + 1;
+        let mut indices: Vec<u32> = Vec::new();
+        for z in 0..z1 - z0 {
+            for x in 0..x1 - x0 {
+                let a = z * row + x;
+                let (b, c, d) = (a + 1, a + row, a + row + 1);
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        Self {
+            vertices,
+            indices,
+            bounds,
+        }
+    }
+}
+
+// A square part of a heightfield with its own geometry, sharing its edge samples with the
+// neighbours
+pub struct Tile {
+    vertex_buffer: Arc<Buffer>,
+    vertex_allocation: Allocation,
+    index_buffer: Arc<Buffer>,
+    index_allocation: Allocation,
+    num_indices: u32,
+    pub bounds: Aabb,
+}
+
+impl Tile {
+    pub fn upload(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        geometry_pool: &mut BufferPool,
+        mesh: &TileMesh,
+    ) -> Self {
+        let vertex_allocation =
+            geometry_pool.upload(device, encoder, bytemuck::cast_slice(&mesh.vertices), 0);
+        let index_allocation =
+            geometry_pool.upload(device, encoder, bytemuck::cast_slice(&mesh.indices), 0);
+
+        Self {
+            vertex_buffer: geometry_pool.shared_buffer(&vertex_allocation),
+            vertex_allocation,
+            index_buffer: geometry_pool.shared_buffer(&index_allocation),
+            index_allocation,
+            num_indices: mesh.indices.len() as u32,
+            bounds: mesh.bounds,
+        }
+    }
+
+    // Expects the terrain material to be bound already
+    pub fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_vertex_buffer(
+            0,
+            &self.vertex_buffer,
+            self.vertex_allocation.offset,
+            self.vertex_allocation.size,
+        );
+        render_pass.set_index_buffer(
+            &self.index_buffer,
+            self.index_allocation.offset,
+            self.index_allocation.size,
+        );
+        draw_stats::record_triangles(self.num_indices, 1);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+
+    pub fn free(self, geometry_pool: &mut BufferPool) {
+        geometry_pool.free(self.vertex_allocation);
+        geometry_pool.free(self.index_allocation);
+    }
+}
+
+// Sand, grass, rock and snow layers blended by the splat weights in the tiles' vertices. Draws
+// into a pass with a `depth::DEPTH_FORMAT` depth attachment.
+pub struct TerrainMaterial {
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    layers_bind_group: Arc<BindGroup>,
+    // Only kept alive for the bind group
+    _layers: Vec<Texture>,
+    _sampler: Sampler,
+}
+
+impl TerrainMaterial {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let layers: Vec<Texture> = [
+            layer_image([0.76, 0.7, 0.5], 0.15, 1),
+            layer_image([0.3, 0.45, 0.18], 0.3, 2),
+            layer_image([0.45, 0.42, 0.4], 0.4, 3),
+            layer_image([0.95, 0.95, 0.97], 0.05, 4),
+        ]
+        .iter()
+        .map(|image| upload_with_mips(device, queue, image, TextureFormat::Rgba8UnormSrgb))
+        .collect();
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        let mut bindings: Vec<Binding> = layers
+            .iter()
+            .enumerate()
+            .map(|(index, texture)| Binding {
+                binding: index as u32,
+                resource: BindingResource::TextureView(&texture.view),
+            })
+            .collect();
+        bindings.push(Binding {
+            binding: layers.len() as u32,
+            resource: BindingResource::Sampler(&sampler),
+        });
+        let layers_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::SPLAT_LAYOUT,
+            "terrain_layers",
+            &bindings,
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            TerrainUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "terrain_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: TERRAIN_VERT,
+                fragment_shader: Some(TERRAIN_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::SPLAT_LAYOUT),
+                ],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint32,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::Back,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: Some(depth::depth_stencil_state()),
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            layers_bind_group,
+            _layers: layers,
+            _sampler: sampler,
+        }
+    }
+
+    pub fn prepare(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        view_proj: Matrix4<f32>,
+    ) {
+        let uniforms = TerrainUniforms {
+            view_proj,
+            layer_repeat: LAYER_REPEAT,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn bind<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.layers_bind_group, &[]);
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("terrain_layers");
+        bind_groups.invalidate("terrain_uniforms");
+    }
+}
+
+// A heightfield split into tiles that get culled one by one
+pub struct Terrain {
+    tiles: Vec<Tile>,
+    // Indices of the tiles inside the frustum from the last `prepare`
+    visible: Vec<usize>,
+    material: TerrainMaterial,
+}
+
+impl Terrain {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        geometry_pool: &mut BufferPool,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        heightfield: &Heightfield,
+    ) -> Self {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("terrain_upload_encoder"),
+        });
+        let mut tiles = Vec::new();
+        let max_height = heightfield.max_height();
+        let last_x = heightfield.width.max(2) - 1;
+        let last_z = heightfield.depth.max(2) - 1;
+        for z0 in (0..last_z).step_by(TILE_QUADS as usize) {
+            for x0 in (0..last_x).step_by(TILE_QUADS as usize) {
+                let mesh = TileMesh::new(
+                    heightfield,
+                    (x0, (x0 + TILE_QUADS).min(last_x)),
+                    (z0, (z0 + TILE_QUADS).min(last_z)),
+                    max_height,
+                );
+                tiles.push(Tile::upload(device, &mut encoder, geometry_pool, &mesh));
+            }
+        }
+        queue.submit(&[encoder.finish()]);
+
+        Self {
+            visible: (0..tiles.len()).collect(),
+            tiles,
+            material: TerrainMaterial::new(
+                device,
+                queue,
+                uniform_pool,
+                bind_groups,
+                pipelines,
+                format,
+            ),
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn visible_tile_count(&self) -> usize {
+        self.visible.len()
+    }
+
+    // Records the uniforms for the next `render` and picks the tiles `camera` can see
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        camera: &Camera,
+    ) {
+        let view_proj = camera.build_view_projection_matrix();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        let tiles = &self.tiles;
+        self.visible = (0..tiles.len())
+            .filter(|&index| frustum.intersects_aabb(&tiles[index].bounds))
+            .collect();
+        self.material
+            .prepare(device, encoder, uniform_pool, view_proj);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        self.material.bind(render_pass);
+        for &index in &self.visible {
+            self.tiles[index].draw(render_pass);
+        }
+    }
+
+    pub fn release(
+        &mut self,
+        geometry_pool: &mut BufferPool,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+    ) {
+        for tile in self.tiles.drain(..) {
+            tile.free(geometry_pool);
+        }
+        self.visible.clear();
+        self.material.release(uniform_pool, bind_groups);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::assets::Assets;
+use crate::buffer_pool::BufferPool;
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::procedural_terrain::{StreamingTerrain, TerrainGenerator};
+use crate::terrain::{Heightfield, Terrain, TerrainMaterial};
+use cgmath::Vector3;
+use image::GrayImage;
+use log::warn;
+use playground_math::{Camera, Projection};
+use serde::{Deserialize, Serialize};
+use wgpu::{
+    Color, CommandEncoder, Device, LoadOp, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const HEIGHTMAP: &str = "heightmap.png";
+// World units between heightmap pixels, and the height of white
+const SPACING: f32 = 0.25;
+const HEIGHT: f32 = 12.0;
+
+// Hills from a heightmap image
+pub struct TerrainDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    terrain: Terrain,
+}
+
+impl TerrainDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (0.0, 20.0, 40.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 500.0,
+            projection: Projection::Perspective,
+        };
+
+        let heightfield = Heightfield::load(&ctx.assets.path(HEIGHTMAP), SPACING, HEIGHT)
+            .unwrap_or_else(|err| {
+                warn!("Using a flat terrain, {} didn't load: {}", HEIGHTMAP, err);
+                Heightfield::from_image(&GrayImage::new(2, 2), SPACING, HEIGHT)
+            });
+        let terrain = Terrain::new(
+            ctx.device,
+            ctx.queue,
+            ctx.geometry_pool,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            &heightfield,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(ctx.device, ctx.size),
+            terrain,
+        }
+    }
+}
+
+impl Demo for TerrainDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        self.terrain
+            .prepare(device, encoder, uniform_pool, &self.camera);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "{} of {} tiles visible",
+            self.terrain.visible_tile_count(),
+            self.terrain.tile_count()
+        ));
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        self.terrain.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.terrain
+            .release(ctx.geometry_pool, ctx.uniform_pool, ctx.bind_groups);
+    }
+}
+
+// What the terrain section of the UI changes
+#[derive(Serialize, Deserialize)]
+struct ProceduralTerrainSettings {
+    view_distance: u32,
+    generator: TerrainGenerator,
+}
+
+// Endless hills from noise, built around the camera as it moves
+pub struct ProceduralTerrainDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    terrain: StreamingTerrain,
+}
+
+impl ProceduralTerrainDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let camera = Camera {
+            eye: (0.0, 45.0, 0.0).into(),
+            target: (0.0, 30.0, -40.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 500.0,
+            projection: Projection::Perspective,
+        };
+
+        let material = TerrainMaterial::new(
+            ctx.device,
+            ctx.queue,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+        );
+
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(ctx.device, ctx.size),
+            terrain: StreamingTerrain::new(material, TerrainGenerator::default()),
+        }
+    }
+}
+
+impl Demo for ProceduralTerrainDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+        self.terrain
+            .update(device, encoder, uniform_pool, &self.camera);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let (loaded, loading) = self.terrain.chunk_counts();
+        ui.label(format!(
+            "{} chunks loaded, {} loading, {} visible",
+            loaded,
+            loading,
+            self.terrain.visible_chunk_count()
+        ));
+        ui.add(egui::Slider::new(&mut self.terrain.view_distance, 1..=16).text("View distance"));
+        self.terrain.generator.ui(ui);
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn settings(&self) -> Option<serde_json::Value> {
+        let settings = ProceduralTerrainSettings {
+            view_distance: self.terrain.view_distance,
+            generator: self.terrain.generator,
+        };
+        serde_json::to_value(settings).ok()
+    }
+
+    // A different generator gets the chunks rebuilt by the next `update`
+    fn apply_settings(&mut self, settings: serde_json::Value) -> Result<(), failure::Error> {
+        let settings: ProceduralTerrainSettings = serde_json::from_value(settings)?;
+        self.terrain.view_distance = settings.view_distance;
+        self.terrain.generator = settings.generator;
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+        self.terrain.render(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        self.terrain.release(ctx.uniform_pool, ctx.bind_groups);
+    }
+}
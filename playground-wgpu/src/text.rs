@@ -0,0 +1,330 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, VertexLayout};
+use crate::texture::Texture;
+use cgmath::Vector2;
+use image::{Rgba, RgbaImage};
+use playground_math::{Block, BlockWriter, Layout};
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, Binding, BindingResource, BlendDescriptor, BlendFactor, BlendOperation,
+    BufferAddress, BufferUsage, ColorStateDescriptor, ColorWrite, CommandEncoder, CompareFunction,
+    CullMode, Device, FilterMode, IndexFormat, InputStepMode, LoadOp, PrimitiveTopology, Queue,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderStage, StoreOp, TextureFormat, TextureView, VertexAttributeDescriptor,
+    VertexFormat,
+};
+use winit::dpi::PhysicalSize;
+
+const TEXT_VERT: Shader = Shader {
+    name: "text.vert",
+    source: include_str!("../shaders/text.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const TEXT_FRAG: Shader = Shader {
+    name: "text.frag",
+    source: include_str!("../shaders/text.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// The atlas holds the 128 ASCII glyphs of font8x8, 16 per row
+const GLYPH_SIZE: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 8;
+
+// Regular non-premultiplied alpha blending
+const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+#[derive(Copy, Clone, Debug, Default)]
+struct TextUniforms {
+    target_size: [f32; 2],
+}
+
+impl Block for TextUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector2::from(self.target_size));
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TextVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for TextVertex {}
+
+unsafe impl bytemuck::Zeroable for TextVertex {}
+
+fn vertex_layout() -> VertexLayout {
+    VertexLayout {
+        stride: mem::size_of::<TextVertex>() as BufferAddress,
+        step_mode: InputStepMode::Vertex,
+        attributes: vec![
+            VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: VertexFormat::Float2,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 2]>() as BufferAddress,
+                shader_location: 1,
+                format: VertexFormat::Float2,
+            },
+            VertexAttributeDescriptor {
+                offset: mem::size_of::<[f32; 4]>() as BufferAddress,
+                shader_location: 2,
+                format: VertexFormat::Float4,
+            },
+        ],
+    }
+}
+
+// White glyphs with their coverage in alpha
+fn glyph_atlas() -> RgbaImage {
+    RgbaImage::from_fn(
+        ATLAS_COLUMNS * GLYPH_SIZE,
+        ATLAS_ROWS * GLYPH_SIZE,
+        |x, y| {
+            let glyph = (y / GLYPH_SIZE * ATLAS_COLUMNS + x / GLYPH_SIZE) as usize;
+            let row = font8x8::legacy::BASIC_LEGACY[glyph][(y % GLYPH_SIZE) as usize];
+            // The lowest bit is the leftmost pixel
+            if row & (1 << (x % GLYPH_SIZE)) != 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([255, 255, 255, 0])
+            }
+        },
+    )
+}
+
+// Debug text drawn straight onto the frame with a built in 8x8 pixel font. Strings get queued
+// during the frame and drawn as one batch of quads by `render`, which forgets them afterwards.
+// Only ASCII is supported, anything else shows up as a question mark.
+pub struct TextRenderer {
+    // Pixels per font pixel
+    pub scale: u32,
+    size: PhysicalSize<u32>,
+    quads: Vec<[TextVertex; 4]>,
+
+    pipeline: Arc<RenderPipeline>,
+    // Only kept alive for `atlas_bind_group`
+    _atlas: Texture,
+    _sampler: Sampler,
+    atlas_bind_group: Arc<BindGroup>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+impl TextRenderer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+        scale_factor: f32,
+    ) -> Self {
+        let (atlas, cmd_buffer) =
+            Texture::from_rgba(device, &glyph_atlas(), TextureFormat::Rgba8Unorm, 1);
+        queue.submit(&[cmd_buffer]);
+
+        // The texture's own sampler is linear, which blurs the pixel font when scaled up
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let atlas_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::TEXTURE_LAYOUT,
+            "text_atlas",
+            &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&atlas.view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            TextUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "text_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: TEXT_VERT,
+                fragment_shader: Some(TEXT_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                ],
+                vertex_buffers: vec![vertex_layout()],
+                index_format: IndexFormat::Uint32,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ALPHA_BLEND,
+                    alpha_blend: ALPHA_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            scale: (scale_factor.round() as u32).max(1),
+            size,
+            quads: Vec::new(),
+            pipeline,
+            _atlas: atlas,
+            _sampler: sampler,
+            atlas_bind_group,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.size = size;
+    }
+
+    // Height of a line of text in pixels
+    pub fn line_height(&self) -> f32 {
+        ((GLYPH_SIZE + 2) * self.scale) as f32
+    }
+
+    // Queues `text` with its top left corner at `x`, `y` (in pixels). `color` is linear, the
+    // text gets a drop shadow so it stays readable on any background.
+    pub fn queue(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        let offset = self.scale as f32;
+        self.queue_glyphs(x + offset, y + offset, text, [0.0, 0.0, 0.0, color[3]]);
+        self.queue_glyphs(x, y, text, color);
+    }
+
+    fn queue_glyphs(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        let advance = (GLYPH_SIZE * self.scale) as f32;
+        let line_height = self.line_height();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let top = y + line_index as f32 * line_height;
+            for (column, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let glyph = if c.is_ascii() { c as u32 } else { '?' as u32 };
+
+                let left = x + column as f32 * advance;
+                let u = (glyph % ATLAS_COLUMNS) as f32 / ATLAS_COLUMNS as f32;
+                let v = (glyph / ATLAS_COLUMNS) as f32 / ATLAS_ROWS as f32;
+                let du = 1.0 / ATLAS_COLUMNS as f32;
+                let dv = 1.0 / ATLAS_ROWS as f32;
+
+                let vertex = |dx: f32, dy: f32| TextVertex {
+                    position: [left + dx * advance, top + dy * advance],
+                    tex_coords: [u + dx * du, v + dy * dv],
+                    color,
+                };
+                self.quads.push([
+                    vertex(0.0, 0.0),
+                    vertex(1.0, 0.0),
+                    vertex(1.0, 1.0),
+                    vertex(0.0, 1.0),
+                ]);
+            }
+        }
+    }
+
+    // Draws everything queued since the last call on top of `target`
+    pub fn render(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        uniform_pool: &BufferPool,
+    ) {
+        let quads = mem::take(&mut self.quads);
+        if quads.is_empty() {
+            return;
+        }
+
+        let uniforms = TextUniforms {
+            target_size: [self.size.width as f32, self.size.height as f32],
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+
+        let indices: Vec<u32> = (0..quads.len() as u32)
+            .flat_map(|quad| {
+                let first = quad * 4;
+                vec![first, first + 1, first + 2, first, first + 2, first + 3]
+            })
+            .collect();
+        let vertex_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&quads), BufferUsage::VERTEX);
+        let index_buffer =
+            device.create_buffer_with_data(bytemuck::cast_slice(&indices), BufferUsage::INDEX);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: wgpu::Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&index_buffer, 0, 0);
+        render_pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
+    }
+}
@@ -1,94 +1,392 @@
-use image::{DynamicImage, GenericImageView};
+use crate::scheduler::{TaskContext, TaskStatus};
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use std::sync::Arc;
 use wgpu::{
-    AddressMode, BufferCopyView, BufferUsage, CommandBuffer, CommandEncoder,
-    CommandEncoderDescriptor, CompareFunction, Device, Extent3d, FilterMode, Origin3d, Sampler,
-    SamplerDescriptor, TextureCopyView, TextureDescriptor, TextureDimension, TextureFormat,
+    BufferCopyView, BufferUsage, CommandBuffer, CommandEncoder, CommandEncoderDescriptor, Device,
+    Extent3d, Origin3d, Queue, TextureCopyView, TextureDescriptor, TextureDimension, TextureFormat,
     TextureUsage, TextureView,
 };
 
+// Rows copied between buffers and textures have to start on a 256 byte boundary
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+pub fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = 4 * width;
+    unpadded.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+// Records a copy of tightly packed 4-byte texels into a region of `texture`
+#[allow(clippy::too_many_arguments)]
+pub fn copy_texels_to_texture(
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    texels: &[u8],
+    width: u32,
+    height: u32,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    origin: Origin3d,
+) {
+    let unpadded = 4 * width as usize;
+    let padded = padded_bytes_per_row(width) as usize;
+
+    let mut data = vec![0; padded * height as usize];
+    for (src, dst) in texels.chunks(unpadded).zip(data.chunks_mut(padded)) {
+        dst[..unpadded].copy_from_slice(src);
+    }
+
+    let buffer = device.create_buffer_with_data(&data, BufferUsage::COPY_SRC);
+
+    encoder.insert_debug_marker(&format!(
+        "Texture upload, {}x{} mip {}",
+        width, height, mip_level
+    ));
+    encoder.copy_buffer_to_texture(
+        BufferCopyView {
+            buffer: &buffer,
+            offset: 0,
+            bytes_per_row: padded as u32,
+            rows_per_image: height,
+        },
+        TextureCopyView {
+            texture,
+            mip_level,
+            array_layer: 0,
+            origin,
+        },
+        Extent3d {
+            width,
+            height,
+            depth: 1,
+        },
+    );
+}
+
+// Number of levels in a full mip chain, down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).leading_zeros()
+}
+
+// Width or height of `mip_level` for a texture that's `size` at level 0
+pub fn mip_size(size: u32, mip_level: u32) -> u32 {
+    (size >> mip_level).max(1)
+}
+
+// Records copies of `count` mip levels from one texture to another on the GPU, starting at
+// `src_level` and `dst_level`, which both have to be `width` by `height`. For moving the levels
+// two textures share when one replaces the other with more or fewer of them.
+#[allow(clippy::too_many_arguments)]
+pub fn copy_mip_levels(
+    encoder: &mut CommandEncoder,
+    src: &wgpu::Texture,
+    src_level: u32,
+    dst: &wgpu::Texture,
+    dst_level: u32,
+    count: u32,
+    width: u32,
+    height: u32,
+) {
+    for level in 0..count {
+        encoder.copy_texture_to_texture(
+            TextureCopyView {
+                texture: src,
+                mip_level: src_level + level,
+                array_layer: 0,
+                origin: Origin3d::ZERO,
+            },
+            TextureCopyView {
+                texture: dst,
+                mip_level: dst_level + level,
+                array_layer: 0,
+                origin: Origin3d::ZERO,
+            },
+            Extent3d {
+                width: mip_size(width, level),
+                height: mip_size(height, level),
+                depth: 1,
+            },
+        );
+    }
+}
+
+// Records uploads of point sampled copies of `base` into mip levels 1 and up. Cheap enough to do
+// as soon as the texture is created, so sampling never reads undefined levels while
+// `generate_mips` replaces them with filtered ones.
+pub fn fill_mips_nearest(
+    device: &Device,
+    encoder: &mut CommandEncoder,
+    texture: &wgpu::Texture,
+    base: &RgbaImage,
+    mip_level_count: u32,
+) {
+    for mip_level in 1..mip_level_count {
+        let width = mip_size(base.width(), mip_level);
+        let height = mip_size(base.height(), mip_level);
+        let level = imageops::resize(base, width, height, FilterType::Nearest);
+        copy_texels_to_texture(
+            device,
+            encoder,
+            &level,
+            width,
+            height,
+            texture,
+            mip_level,
+            Origin3d::ZERO,
+        );
+    }
+}
+
+// A task that downsamples `base` on the CPU and uploads one mip level per step, starting at level
+// 1. Until then levels hold whatever `fill_mips_nearest` put there. Filtering happens on the sRGB
+// encoded values, which is slightly off but hard to notice.
+pub fn generate_mips(
+    texture: Arc<wgpu::Texture>,
+    base: RgbaImage,
+    mip_level_count: u32,
+) -> impl FnMut(&TaskContext) -> TaskStatus {
+    let mut previous = base;
+    let mut mip_level = 1;
+
+    move |ctx| {
+        let width = (previous.width() / 2).max(1);
+        let height = (previous.height() / 2).max(1);
+        let next = imageops::resize(&previous, width, height, FilterType::Triangle);
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("mip_upload_encoder"),
+            });
+        copy_texels_to_texture(
+            ctx.device,
+            &mut encoder,
+            &next,
+            width,
+            height,
+            &texture,
+            mip_level,
+            Origin3d::ZERO,
+        );
+        ctx.queue.submit(&[encoder.finish()]);
+
+        previous = next;
+        mip_level += 1;
+        if mip_level < mip_level_count {
+            TaskStatus::Pending
+        } else {
+            TaskStatus::Done
+        }
+    }
+}
+
+// Size of a single texel, or None for formats that can't be copied texel by texel
+pub fn bytes_per_texel(format: TextureFormat) -> Option<u32> {
+    use TextureFormat::*;
+
+    match format {
+        R8Unorm | R8Snorm | R8Uint | R8Sint => Some(1),
+        R16Uint | R16Sint | R16Float | Rg8Unorm | Rg8Snorm | Rg8Uint | Rg8Sint => Some(2),
+        R32Uint | R32Sint | R32Float | Rg16Uint | Rg16Sint | Rg16Float | Rgba8Unorm
+        | Rgba8UnormSrgb | Rgba8Snorm | Rgba8Uint | Rgba8Sint | Bgra8Unorm | Bgra8UnormSrgb
+        | Rgb10a2Unorm | Rg11b10Float | Depth32Float => Some(4),
+        Rg32Uint | Rg32Sint | Rg32Float | Rgba16Uint | Rgba16Sint | Rgba16Float => Some(8),
+        Rgba32Uint | Rgba32Sint | Rgba32Float => Some(16),
+        Depth24Plus | Depth24PlusStencil8 => None,
+    }
+}
+
+// Whether the format can be read through a regular (filtering) float sampler
+pub fn is_filterable(format: TextureFormat) -> bool {
+    use TextureFormat::*;
+
+    matches!(
+        format,
+        R8Unorm
+            | R8Snorm
+            | R16Float
+            | Rg8Unorm
+            | Rg8Snorm
+            | R32Float
+            | Rg16Float
+            | Rgba8Unorm
+            | Rgba8UnormSrgb
+            | Rgba8Snorm
+            | Bgra8Unorm
+            | Bgra8UnormSrgb
+            | Rgb10a2Unorm
+            | Rg11b10Float
+            | Rg32Float
+            | Rgba16Float
+            | Rgba32Float
+    )
+}
+
+// What a texture was created with, for code that only gets to see the `wgpu::Texture`
+#[derive(Copy, Clone, Debug)]
+pub struct TextureInfo {
+    pub size: Extent3d,
+    pub format: TextureFormat,
+    pub usage: TextureUsage,
+    pub mip_level_count: u32,
+    pub array_layer_count: u32,
+}
+
+// How it gets sampled is up to whoever binds it, see `sampler::SamplerSettings`
 pub struct Texture {
-    pub texture: wgpu::Texture,
+    pub texture: Arc<wgpu::Texture>,
     pub view: TextureView,
-    pub sampler: Sampler,
+    pub info: TextureInfo,
 }
 
 impl Texture {
-    pub fn from_bytes(
+    // All of the mip levels are undefined until something gets copied into them
+    pub fn empty(
         device: &Device,
-        bytes: &[u8],
-    ) -> Result<(Self, CommandBuffer), failure::Error> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, &img)
-    }
-
-    pub fn from_image(
-        device: &Device,
-        img: &DynamicImage,
-    ) -> Result<(Self, CommandBuffer), failure::Error> {
-        let rgba = img.as_rgba8().unwrap();
-        let dimensions = img.dimensions();
-
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        mip_level_count: u32,
+    ) -> Self {
         let size = Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width,
+            height,
             depth: 1,
         };
 
-        let texture = device.create_texture(&TextureDescriptor {
+        let info = TextureInfo {
             size,
+            format,
+            // COPY_SRC allows reading it back for debugging, and copying the mip levels over
+            // when streaming replaces it
+            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST | TextureUsage::COPY_SRC,
+            mip_level_count,
             array_layer_count: 1,
-            mip_level_count: 1,
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            size,
+            array_layer_count: info.array_layer_count,
+            mip_level_count: info.mip_level_count,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsage::SAMPLED | TextureUsage::COPY_DST,
+            format: info.format,
+            usage: info.usage,
             label: Some("texture"),
         });
 
-        let buffer =
-            device.create_buffer_with_data(&rgba.clone().into_raw(), BufferUsage::COPY_SRC);
+        let view = texture.create_default_view();
+
+        Self {
+            texture: Arc::new(texture),
+            view,
+            info,
+        }
+    }
+
+    // `format` has to be one of the 8 bit RGBA formats. Only the first mip level gets uploaded,
+    // see `generate_mips` for the rest.
+    pub fn from_rgba(
+        device: &Device,
+        rgba: &RgbaImage,
+        format: TextureFormat,
+        mip_level_count: u32,
+    ) -> (Self, CommandBuffer) {
+        let (width, height) = rgba.dimensions();
+        let texture = Self::empty(device, width, height, format, mip_level_count);
 
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
             label: Some("texture_buffer_copy_encoder"),
         });
 
-        encoder.copy_buffer_to_texture(
-            BufferCopyView {
-                buffer: &buffer,
-                offset: 0,
-                bytes_per_row: 4 * dimensions.0,
-                rows_per_image: dimensions.1,
-            },
-            TextureCopyView {
-                texture: &texture,
-                mip_level: 0,
-                array_layer: 0,
-                origin: Origin3d::ZERO,
-            },
-            size,
+        copy_texels_to_texture(
+            device,
+            &mut encoder,
+            rgba,
+            width,
+            height,
+            &texture.texture,
+            0,
+            Origin3d::ZERO,
         );
 
-        let cmd_buffer = encoder.finish();
-        let view = texture.create_default_view();
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Nearest,
-            mipmap_filter: FilterMode::Nearest,
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            compare: CompareFunction::Always,
-        });
+        (texture, encoder.finish())
+    }
+}
 
-        Ok((
-            Self {
-                texture,
-                view,
-                sampler,
-            },
-            cmd_buffer,
-        ))
+// Generated textures that stand in for ones that couldn't be loaded, or that a demo doesn't need
+// a real image for. They are created once with the device and shared.
+pub struct Placeholders {
+    // Colored by UV with a checker pattern on top, for looking at texture coordinates
+    pub uv_checker: Arc<Texture>,
+    pub white: Arc<Texture>,
+    pub black: Arc<Texture>,
+    // Pointing straight out of the surface, in a linear format
+    pub flat_normal: Arc<Texture>,
+    // Magenta and black, hard to miss
+    pub missing: Arc<Texture>,
+}
+
+impl Placeholders {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let mut cmd_buffers = Vec::new();
+        let mut create = |rgba: RgbaImage, format| {
+            let (texture, cmd_buffer) = Texture::from_rgba(device, &rgba, format, 1);
+            cmd_buffers.push(cmd_buffer);
+            Arc::new(texture)
+        };
+
+        let uv_checker = create(
+            RgbaImage::from_fn(256, 256, |x, y| {
+                let shade = if (x / 32 + y / 32) % 2 == 0 { 255 } else { 160 };
+                let u = x * shade / 255;
+                let v = y * shade / 255;
+                Rgba([u as u8, v as u8, (shade / 2) as u8, 255])
+            }),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let white = create(
+            RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255])),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let black = create(
+            RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255])),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let flat_normal = create(
+            RgbaImage::from_pixel(1, 1, Rgba([128, 128, 255, 255])),
+            TextureFormat::Rgba8Unorm,
+        );
+        let missing = create(
+            RgbaImage::from_fn(64, 64, |x, y| {
+                if (x / 8 + y / 8) % 2 == 0 {
+                    Rgba([255, 0, 255, 255])
+                } else {
+                    Rgba([0, 0, 0, 255])
+                }
+            }),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        queue.submit(&cmd_buffers);
+
+        Self {
+            uv_checker,
+            white,
+            black,
+            flat_normal,
+            missing,
+        }
+    }
+
+    // All of them, for the texture inspector
+    pub fn named(&self) -> [(&'static str, &Arc<Texture>); 5] {
+        [
+            ("placeholder: uv checker", &self.uv_checker),
+            ("placeholder: white", &self.white),
+            ("placeholder: black", &self.black),
+            ("placeholder: flat normal", &self.flat_normal),
+            ("placeholder: missing", &self.missing),
+        ]
     }
 }
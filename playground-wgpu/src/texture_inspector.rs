@@ -0,0 +1,559 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::overlay::Overlay;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::readback::Readback;
+use crate::texture::{self, TextureInfo};
+use cgmath::Vector4;
+use egui::TextureId;
+use playground_math::{Block, BlockWriter, Layout};
+use std::sync::{Arc, Weak};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BufferAddress, BufferCopyView, ColorStateDescriptor, ColorWrite,
+    CommandEncoder, CompareFunction, CullMode, Device, Extent3d, FilterMode, IndexFormat, LoadOp,
+    Maintain, Origin3d, PrimitiveTopology, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, Sampler, SamplerDescriptor, ShaderStage, StoreOp,
+    TextureAspect, TextureCopyView, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+const TEXTURE_PREVIEW_FRAG: Shader = Shader {
+    name: "texture_preview.frag",
+    source: include_str!("../shaders/texture_preview.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Size of the preview render target, and the largest size a preview is shown at
+const PREVIEW_SIZE: u32 = 256;
+
+const THUMBNAIL_SIZE: f32 = 32.0;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct PreviewUniforms {
+    channels: [f32; 4],
+}
+
+impl Block for PreviewUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&Vector4::from(self.channels));
+    }
+}
+
+struct Entry {
+    name: String,
+    // The inspector doesn't keep textures alive, entries go away with their texture
+    texture: Weak<wgpu::Texture>,
+    info: TextureInfo,
+    thumbnail: Option<TextureId>,
+}
+
+// Only float formats can go through the preview shader's sampler
+fn can_preview(info: &TextureInfo) -> bool {
+    texture::is_filterable(info.format) && info.usage.contains(TextureUsage::SAMPLED)
+}
+
+fn can_read_pixels(info: &TextureInfo) -> bool {
+    texture::bytes_per_texel(info.format).is_some()
+        && info.format != TextureFormat::Depth32Float
+        && info.usage.contains(TextureUsage::COPY_SRC)
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn format_texel(format: TextureFormat, bytes: &[u8]) -> String {
+    let f32_at = |i: usize| {
+        let mut raw = [0; 4];
+        raw.copy_from_slice(&bytes[i * 4..i * 4 + 4]);
+        f32::from_le_bytes(raw)
+    };
+
+    match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Uint => {
+            format!("{} {} {} {}", bytes[0], bytes[1], bytes[2], bytes[3])
+        }
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+            format!("{} {} {} {}", bytes[2], bytes[1], bytes[0], bytes[3])
+        }
+        TextureFormat::R8Unorm | TextureFormat::R8Uint => format!("{}", bytes[0]),
+        TextureFormat::R32Float => format!("{:.4}", f32_at(0)),
+        TextureFormat::R32Uint => {
+            format!(
+                "{}",
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            )
+        }
+        TextureFormat::Rgba32Float => format!(
+            "{:.4} {:.4} {:.4} {:.4}",
+            f32_at(0),
+            f32_at(1),
+            f32_at(2),
+            f32_at(3)
+        ),
+        TextureFormat::Rgba16Float => {
+            let channels: Vec<_> = bytes[..8]
+                .chunks(2)
+                .map(|c| format!("{:.4}", f16_to_f32(u16::from_le_bytes([c[0], c[1]]))))
+                .collect();
+            channels.join(" ")
+        }
+        _ => {
+            let size = texture::bytes_per_texel(format).unwrap_or(4) as usize;
+            let hex: Vec<_> = bytes[..size].iter().map(|b| format!("{:02x}", b)).collect();
+            format!("0x{}", hex.join(""))
+        }
+    }
+}
+
+// Debug window listing registered textures, with a preview of a single mip level and array
+// layer of the selected one and the value of the texel under the cursor
+pub struct TextureInspector {
+    pub open: bool,
+    entries: Vec<Entry>,
+    selected: Option<usize>,
+    channels: [bool; 4],
+    mip_level: u32,
+    array_layer: u32,
+
+    pipeline: Arc<RenderPipeline>,
+    texture_bind_group_layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+    // Only kept alive for `preview_view`
+    _preview: wgpu::Texture,
+    preview_view: TextureView,
+    preview_id: TextureId,
+
+    // Texel the cursor is over, texel currently being read back and the last value read
+    hovered_texel: Option<(u32, u32)>,
+    readback: Readback,
+    readback_texel: Option<((u32, u32), TextureFormat)>,
+    copy_recorded: bool,
+    pixel: Option<((u32, u32), String)>,
+}
+
+impl TextureInspector {
+    pub fn new(
+        device: &Device,
+        overlay: &mut Overlay,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+    ) -> Self {
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            PreviewUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "texture_preview_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let texture_bind_group_layout = bind_groups.layout(
+            device,
+            "texture_bind_group_layout",
+            bind_group::TEXTURE_LAYOUT,
+        );
+
+        let preview_format = TextureFormat::Rgba8UnormSrgb;
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(TEXTURE_PREVIEW_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format: preview_format,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    color_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let preview = device.create_texture(&TextureDescriptor {
+            size: Extent3d {
+                width: PREVIEW_SIZE,
+                height: PREVIEW_SIZE,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: preview_format,
+            usage: TextureUsage::OUTPUT_ATTACHMENT | TextureUsage::SAMPLED,
+            label: Some("texture_preview"),
+        });
+        let preview_view = preview.create_default_view();
+        let preview_id = overlay.register_user_texture(device, &preview_view);
+
+        Self {
+            open: false,
+            entries: Vec::new(),
+            selected: None,
+            channels: [true, true, true, false],
+            mip_level: 0,
+            array_layer: 0,
+            pipeline,
+            texture_bind_group_layout,
+            // Nearest filtering so the preview shows the actual texels of the selected mip
+            sampler: device.create_sampler(&SamplerDescriptor {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Nearest,
+                min_filter: FilterMode::Nearest,
+                mipmap_filter: FilterMode::Nearest,
+                lod_min_clamp: -100.0,
+                lod_max_clamp: 100.0,
+                compare: CompareFunction::Always,
+            }),
+            uniform_allocation,
+            uniform_bind_group,
+            _preview: preview,
+            preview_view,
+            preview_id,
+            hovered_texel: None,
+            // A single texel, padded to a full row
+            readback: Readback::new(device, "texture_inspector_readback", 256),
+            readback_texel: None,
+            copy_recorded: false,
+            pixel: None,
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        device: &Device,
+        overlay: &mut Overlay,
+        name: &str,
+        texture: &Arc<wgpu::Texture>,
+        info: TextureInfo,
+    ) {
+        let thumbnail = if can_preview(&info) {
+            let view = self.create_view(texture, &info, 0, 0);
+            Some(overlay.register_user_texture(device, &view))
+        } else {
+            None
+        };
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            texture: Arc::downgrade(texture),
+            info,
+            thumbnail,
+        });
+    }
+
+    fn create_view(
+        &self,
+        texture: &wgpu::Texture,
+        info: &TextureInfo,
+        mip_level: u32,
+        array_layer: u32,
+    ) -> TextureView {
+        texture.create_view(&TextureViewDescriptor {
+            format: info.format,
+            dimension: TextureViewDimension::D2,
+            aspect: TextureAspect::All,
+            base_mip_level: mip_level,
+            level_count: 1,
+            base_array_layer: array_layer,
+            array_layer_count: 1,
+        })
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = Some(index);
+        self.mip_level = 0;
+        self.array_layer = 0;
+        self.hovered_texel = None;
+        self.pixel = None;
+    }
+
+    pub fn ui(&mut self, ctx: &egui::Context) {
+        let mut open = self.open;
+        egui::Window::new("Textures")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let mut clicked = None;
+                for (index, entry) in self.entries.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        match entry.thumbnail {
+                            Some(id) => ui.image((id, egui::Vec2::splat(THUMBNAIL_SIZE))),
+                            None => {
+                                ui.allocate_exact_size(
+                                    egui::Vec2::splat(THUMBNAIL_SIZE),
+                                    egui::Sense::hover(),
+                                )
+                                .1
+                            }
+                        };
+                        if ui
+                            .selectable_label(self.selected == Some(index), &entry.name)
+                            .clicked()
+                        {
+                            clicked = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = clicked {
+                    self.select(index);
+                }
+
+                ui.separator();
+                self.selected_ui(ui);
+            });
+        self.open = open;
+    }
+
+    fn selected_ui(&mut self, ui: &mut egui::Ui) {
+        let info = match self.selected.and_then(|index| self.entries.get(index)) {
+            Some(entry) => entry.info,
+            None => {
+                ui.label("Select a texture");
+                return;
+            }
+        };
+
+        ui.label(format!(
+            "{}x{}, {:?}, {} mip level(s), {} layer(s)",
+            info.size.width,
+            info.size.height,
+            info.format,
+            info.mip_level_count,
+            info.array_layer_count
+        ));
+        ui.label(format!("Usage: {:?}", info.usage));
+
+        ui.horizontal(|ui| {
+            for (enabled, label) in self.channels.iter_mut().zip(&["R", "G", "B", "A"]) {
+                ui.checkbox(enabled, *label);
+            }
+        });
+        if info.mip_level_count > 1 {
+            ui.add(
+                egui::Slider::new(&mut self.mip_level, 0..=info.mip_level_count - 1)
+                    .text("Mip level"),
+            );
+        }
+        if info.array_layer_count > 1 {
+            ui.add(
+                egui::Slider::new(&mut self.array_layer, 0..=info.array_layer_count - 1)
+                    .text("Array layer"),
+            );
+        }
+
+        if !can_preview(&info) {
+            ui.label("No preview for this format");
+            return;
+        }
+
+        let width = (info.size.width >> self.mip_level).max(1);
+        let height = (info.size.height >> self.mip_level).max(1);
+        let scale = PREVIEW_SIZE as f32 / width.max(height) as f32;
+        let size = egui::vec2(width as f32 * scale, height as f32 * scale);
+
+        let response =
+            ui.add(egui::Image::new((self.preview_id, size)).sense(egui::Sense::hover()));
+
+        self.hovered_texel = response.hover_pos().map(|pos| {
+            let uv = (pos - response.rect.min) / response.rect.size();
+            (
+                ((uv.x * width as f32) as u32).min(width - 1),
+                ((uv.y * height as f32) as u32).min(height - 1),
+            )
+        });
+
+        if !can_read_pixels(&info) {
+            return;
+        }
+        match (&self.hovered_texel, &self.pixel) {
+            (Some(texel), Some((read, value))) if texel == read => {
+                ui.label(format!("({}, {}): {}", texel.0, texel.1, value));
+            }
+            (Some(texel), _) => {
+                ui.label(format!("({}, {}): ...", texel.0, texel.1));
+            }
+            (None, _) => {
+                ui.label("Hover the preview to read a texel");
+            }
+        }
+    }
+
+    // Draws the preview of the selected texture and records the copy of the hovered texel
+    pub fn render(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        overlay: &mut Overlay,
+        uniform_pool: &BufferPool,
+    ) {
+        self.prune(overlay);
+
+        if !self.open {
+            return;
+        }
+
+        let (texture, info) = match self.selected.and_then(|index| self.entries.get(index)) {
+            Some(entry) => match entry.texture.upgrade() {
+                Some(texture) => (texture, entry.info),
+                None => return,
+            },
+            None => return,
+        };
+
+        if can_preview(&info) {
+            let mask = |enabled: bool| if enabled { 1.0 } else { 0.0 };
+            let uniforms = PreviewUniforms {
+                channels: [
+                    mask(self.channels[0]),
+                    mask(self.channels[1]),
+                    mask(self.channels[2]),
+                    mask(self.channels[3]),
+                ],
+            };
+            uniform_pool.write(
+                device,
+                encoder,
+                &self.uniform_allocation,
+                &uniforms.std140(),
+            );
+
+            let view = self.create_view(&texture, &info, self.mip_level, self.array_layer);
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                layout: &self.texture_bind_group_layout,
+                bindings: &[
+                    Binding {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&view),
+                    },
+                    Binding {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+                label: Some("texture_preview_bind_group"),
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: &self.preview_view,
+                    resolve_target: None,
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    clear_color: wgpu::Color::BLACK,
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        // One readback at a time, the next one starts once the previous value arrived
+        if let Some((x, y)) = self.hovered_texel {
+            if can_read_pixels(&info) && !self.readback.is_pending() {
+                encoder.copy_texture_to_buffer(
+                    TextureCopyView {
+                        texture: &texture,
+                        mip_level: self.mip_level,
+                        array_layer: self.array_layer,
+                        origin: Origin3d { x, y, z: 0 },
+                    },
+                    BufferCopyView {
+                        buffer: &self.readback.buffer,
+                        offset: 0,
+                        bytes_per_row: 256,
+                        rows_per_image: 1,
+                    },
+                    Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    },
+                );
+                self.readback_texel = Some(((x, y), info.format));
+                self.copy_recorded = true;
+            }
+        }
+    }
+
+    // Call after submitting the encoder passed to `render`
+    pub fn after_submit(&mut self) {
+        if self.copy_recorded {
+            self.readback.map();
+            self.copy_recorded = false;
+        }
+    }
+
+    // Picks up the texel value once the readback finished
+    pub fn poll(&mut self, device: &Device) {
+        if !self.readback.is_pending() {
+            return;
+        }
+
+        device.poll(Maintain::Poll);
+        if let Some(result) = self.readback.try_read() {
+            self.pixel = match (result, self.readback_texel) {
+                (Ok(mapping), Some((texel, format))) => {
+                    Some((texel, format_texel(format, mapping.as_slice())))
+                }
+                _ => None,
+            };
+        }
+    }
+
+    // Forgets textures that were dropped since they were registered
+    fn prune(&mut self, overlay: &mut Overlay) {
+        let selected = self.selected.map(|index| self.entries[index].name.clone());
+
+        self.entries.retain(|entry| {
+            let alive = entry.texture.upgrade().is_some();
+            if !alive {
+                if let Some(id) = entry.thumbnail {
+                    overlay.free_user_texture(id);
+                }
+            }
+            alive
+        });
+
+        self.selected = selected.and_then(|name| self.entries.iter().position(|e| e.name == name));
+    }
+}
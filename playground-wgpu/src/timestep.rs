@@ -0,0 +1,101 @@
+// Steps a single frame may run, after a stall the simulation drops the time it couldn't catch up
+// on instead of taking ever longer frames to catch up
+const MAX_STEPS: u32 = 8;
+
+// Range of the time scale, slower or faster than this isn't useful for watching anything
+const MIN_SCALE: f32 = 1.0 / 16.0;
+const MAX_SCALE: f32 = 4.0;
+
+// Cuts the time between frames into fixed steps, so simulations behave the same whatever the
+// frame rate. What's left over carries on into the next frame, and `alpha` tells how far the
+// frame is past the last step, for drawing in between it and the one before.
+//
+// The clock can be paused, stepped one step at a time while paused, and slowed down or sped up.
+// Only the simulation sees any of that, the frame time passed to everything else stays real.
+pub struct FixedTimestep {
+    pub paused: bool,
+    // Simulated seconds per real second
+    pub scale: f32,
+    step: f32,
+    accumulator: f32,
+    step_requested: bool,
+}
+
+impl FixedTimestep {
+    pub fn new(rate: f32) -> Self {
+        Self {
+            paused: false,
+            scale: 1.0,
+            step: 1.0 / rate,
+            accumulator: 0.0,
+            step_requested: false,
+        }
+    }
+
+    // Seconds per step
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    // Runs exactly one step on the next `advance`, if paused
+    pub fn request_step(&mut self) {
+        self.step_requested = true;
+    }
+
+    // Halves or doubles the time scale for `direction` -1 or 1
+    pub fn change_scale(&mut self, direction: i32) {
+        self.scale = (self.scale * 2.0f32.powi(direction)).clamp(MIN_SCALE, MAX_SCALE);
+    }
+
+    // Adds `dt` real seconds, returns how many steps are due
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        if self.paused {
+            let steps = self.step_requested as u32;
+            self.step_requested = false;
+            return steps;
+        }
+        self.step_requested = false;
+
+        self.accumulator += dt * self.scale;
+        let steps = (self.accumulator / self.step) as u32;
+        if steps > MAX_STEPS {
+            self.accumulator = 0.0;
+            return MAX_STEPS;
+        }
+        self.accumulator -= steps as f32 * self.step;
+        steps
+    }
+
+    // From 0 right on the last step to 1 right on the next one
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+
+    // For the debug text, nothing while running normally
+    pub fn status(&self) -> Option<String> {
+        if self.paused {
+            Some("Paused".to_string())
+        } else if self.scale != 1.0 {
+            Some(format!("Time x{}", self.scale))
+        } else {
+            None
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.paused, "Paused (Space)");
+            if ui
+                .add_enabled(self.paused, egui::Button::new("Step (.)"))
+                .clicked()
+            {
+                self.request_step();
+            }
+        });
+        ui.add(
+            egui::Slider::new(&mut self.scale, MIN_SCALE..=MAX_SCALE)
+                .logarithmic(true)
+                .text("Time scale ([ and ])"),
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use crate::auto_exposure::{self, AutoExposure};
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use playground_math::{Block, BlockWriter, Layout};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BufferAddress, Color, ColorStateDescriptor, ColorWrite, CommandEncoder,
+    CompareFunction, CullMode, Device, FilterMode, IndexFormat, LoadOp, PrimitiveTopology,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderStage, StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const TONEMAP_FRAG: Shader = Shader {
+    name: "tonemap.frag",
+    source: include_str!("../shaders/tonemap.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// What the scene gets drawn into, with linear colors that can go past 1
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+// How colors past 1 get brought into what the screen can show
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    // Clipped, everything past 1 ends up white
+    None,
+    Reinhard,
+    AcesFilmic,
+    Uncharted2,
+}
+
+impl Operator {
+    pub const ALL: [Operator; 4] = [
+        Operator::None,
+        Operator::Reinhard,
+        Operator::AcesFilmic,
+        Operator::Uncharted2,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::None => "None",
+            Operator::Reinhard => "Reinhard",
+            Operator::AcesFilmic => "ACES filmic",
+            Operator::Uncharted2 => "Uncharted 2",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct TonemapUniforms {
+    exposure: f32,
+    operator: u32,
+    // What the adapted luminance gets exposed to, 0 while auto exposure is off
+    key: f32,
+}
+
+impl Block for TonemapUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.exposure);
+        writer.member(&self.operator);
+        writer.member(&self.key);
+    }
+}
+
+// The HDR target and the bind group the resolve reads it through, replaced together on resize
+struct Target {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Target {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let view = render_target::create_target(device, size, HDR_FORMAT, "hdr_target");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("hdr_target"),
+        });
+
+        Self {
+            view,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// The scene and the custom passes draw into an HDR target instead of the frame, and `resolve`
+// copies it into the frame through the exposure and a tonemapping curve. Everything drawn after
+// that, like the debug lines and text, goes straight into the frame.
+pub struct Tonemapper {
+    target: Target,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    pub operator: Operator,
+    // In stops, every one doubles the brightness. With auto exposure on it's on top of that.
+    pub exposure: f32,
+    pub auto_exposure: AutoExposure,
+}
+
+impl Tonemapper {
+    // `format` is the frame's
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "hdr_target", bind_group::TEXTURE_LAYOUT);
+        // Read texel for texel
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(TONEMAP_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_STORAGE_LAYOUT),
+                ],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: BlendDescriptor::REPLACE,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            TonemapUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "tonemap_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        let target = Target::new(device, &layout, &sampler, size);
+        let auto_exposure = AutoExposure::new(
+            device,
+            uniform_pool,
+            bind_groups,
+            pipelines,
+            &target.view,
+            &sampler,
+            size,
+        );
+
+        Self {
+            target,
+            layout,
+            sampler,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            // Looks the same as drawing into the frame directly, for scenes that stay below 1
+            operator: Operator::None,
+            exposure: 0.0,
+            auto_exposure,
+        }
+    }
+
+    // Where the scene goes instead of the frame
+    pub fn target(&self) -> &TextureView {
+        &self.target.view
+    }
+
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if size != self.target.size {
+            self.target = Target::new(device, &self.layout, &self.sampler, size);
+            self.auto_exposure
+                .set_source(device, &self.target.view, &self.sampler, size);
+        }
+    }
+
+    // `dt` seconds after the last frame
+    pub fn update(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+    ) {
+        let key = if self.auto_exposure.enabled {
+            self.auto_exposure.update(device, encoder, uniform_pool, dt);
+            auto_exposure::KEY
+        } else {
+            0.0
+        };
+        let uniforms = TonemapUniforms {
+            exposure: 2.0f32.powf(self.exposure),
+            operator: self.operator as u32,
+            key,
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let operator = &mut self.operator;
+        egui::ComboBox::from_label("Operator")
+            .selected_text(operator.name())
+            .show_ui(ui, |ui| {
+                for &option in &Operator::ALL {
+                    ui.selectable_value(operator, option, option.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.exposure, -8.0..=8.0).text("Exposure (stops)"));
+        self.auto_exposure.ui(ui);
+    }
+
+    // Writes every pixel of `target`, so it doesn't need to be cleared
+    pub fn resolve(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::BLACK,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.target.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, self.auto_exposure.luminance_bind_group(), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
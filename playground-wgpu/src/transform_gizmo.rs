@@ -0,0 +1,298 @@
+use crate::debug_draw;
+use cgmath::{
+    ElementWise, EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation3,
+    SquareMatrix, Transform as _, Vector3,
+};
+use playground_math::{Color, Ray, Transform};
+use std::f32::consts::PI;
+
+// Handle length as a fraction of the distance to the camera, so it keeps its size on screen
+const SCREEN_SIZE: f32 = 0.2;
+// How close the cursor ray has to pass by a handle to grab it, as a fraction of its length
+const GRAB_DISTANCE: f32 = 0.06;
+const RING_SEGMENTS: u32 = 48;
+// Scale never gets dragged below this
+const MIN_SCALE: f32 = 0.01;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+const AXES: [Vector3<f32>; 3] = [
+    Vector3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    Vector3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    },
+    Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    },
+];
+
+fn axis_color(axis: usize, highlighted: bool) -> Color {
+    match (axis, highlighted) {
+        (_, true) => Color::new(1.0, 0.9, 0.1, 1.0),
+        (0, _) => Color::new(0.9, 0.2, 0.2, 1.0),
+        (1, _) => Color::new(0.3, 0.8, 0.2, 1.0),
+        _ => Color::new(0.2, 0.4, 0.9, 1.0),
+    }
+}
+
+// Two vectors spanning the plane `normal` is perpendicular to
+fn plane_basis(normal: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = normal.cross(helper).normalize();
+    (u, normal.cross(u))
+}
+
+// Where the handles are in world space for one frame
+struct Frame {
+    origin: Point3<f32>,
+    // Translation and rotation happen along the parent's axes, scale along the object's own
+    axes: [Vector3<f32>; 3],
+    length: f32,
+}
+
+impl Frame {
+    fn new(
+        mode: GizmoMode,
+        eye: Point3<f32>,
+        parent_world: &Matrix4<f32>,
+        local: &Transform,
+    ) -> Self {
+        let world = parent_world * local.matrix();
+        let origin = world.transform_point(Point3::origin());
+        let basis = if mode == GizmoMode::Scale {
+            world
+        } else {
+            *parent_world
+        };
+        let axes = [0, 1, 2].map(|axis| {
+            let direction = basis.transform_vector(AXES[axis]);
+            if direction.magnitude2() > 0.0 {
+                direction.normalize()
+            } else {
+                AXES[axis]
+            }
+        });
+
+        Self {
+            origin,
+            axes,
+            length: (origin - eye).magnitude() * SCREEN_SIZE,
+        }
+    }
+
+    // Distance along `axis` of the point on it closest to the ray, and how far that is from the
+    // ray
+    fn along_axis(&self, ray: &Ray, axis: usize) -> Option<(f32, f32)> {
+        let (ray_distance, axis_distance) = ray.closest_to_line(self.origin, self.axes[axis])?;
+        let on_axis = self.origin + self.axes[axis] * axis_distance;
+        Some((axis_distance, (ray.at(ray_distance) - on_axis).magnitude()))
+    }
+
+    // Angle around `axis` of where the ray crosses the ring's plane, and how far that is from
+    // the ring
+    fn around_axis(&self, ray: &Ray, axis: usize) -> Option<(f32, f32)> {
+        let normal = self.axes[axis];
+        let point = ray.at(ray.intersect_plane(self.origin, normal)?);
+        let offset = point - self.origin;
+        let (u, v) = plane_basis(normal);
+        Some((
+            offset.dot(v).atan2(offset.dot(u)),
+            (offset.magnitude() - self.length).abs(),
+        ))
+    }
+
+    // The grabbed axis and where along or around it the ray is
+    fn hit(&self, mode: GizmoMode, ray: &Ray) -> Option<(usize, f32)> {
+        let grab = self.length * GRAB_DISTANCE;
+        let mut closest: Option<(usize, f32, f32)> = None;
+
+        for axis in 0..3 {
+            let (value, miss) = match mode {
+                GizmoMode::Translate | GizmoMode::Scale => match self.along_axis(ray, axis) {
+                    Some((distance, miss)) if (0.0..=self.length).contains(&distance) => {
+                        (distance, miss)
+                    }
+                    _ => continue,
+                },
+                GizmoMode::Rotate => match self.around_axis(ray, axis) {
+                    Some(hit) => hit,
+                    None => continue,
+                },
+            };
+            if miss <= grab && closest.is_none_or(|(_, _, closest)| miss < closest) {
+                closest = Some((axis, value, miss));
+            }
+        }
+
+        closest.map(|(axis, value, _)| (axis, value))
+    }
+
+    fn draw(&self, mode: GizmoMode, highlighted: Option<usize>) {
+        for axis in 0..3 {
+            let color = axis_color(axis, highlighted == Some(axis));
+            let direction = self.axes[axis];
+            let end = self.origin + direction * self.length;
+            let (u, v) = plane_basis(direction);
+
+            match mode {
+                GizmoMode::Translate => {
+                    debug_draw::line(self.origin, end, color);
+                    // Arrow head
+                    let size = self.length * 0.1;
+                    let back = end - direction * size;
+                    for &side in &[u, -u, v, -v] {
+                        debug_draw::line(end, back + side * size * 0.4, color);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let point = |segment: u32| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * 2.0 * PI;
+                        self.origin + (u * angle.cos() + v * angle.sin()) * self.length
+                    };
+                    for segment in 0..RING_SEGMENTS {
+                        debug_draw::line(point(segment), point(segment + 1), color);
+                    }
+                }
+                GizmoMode::Scale => {
+                    debug_draw::line(self.origin, end, color);
+                    // A small square at the end
+                    let size = self.length * 0.05;
+                    let corners = [u + v, u - v, -u - v, -u + v].map(|corner| end + corner * size);
+                    for corner in 0..4 {
+                        debug_draw::line(corners[corner], corners[(corner + 1) % 4], color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Drag {
+    axis: usize,
+    // Distance along the axis or angle around it where the drag started
+    start_value: f32,
+    start: Transform,
+}
+
+// Translate, rotate and scale handles for the selected object, drawn with `debug_draw`.
+// Dragging a handle with the cursor changes the object's local transform.
+pub struct TransformGizmo {
+    pub mode: GizmoMode,
+    drag: Option<Drag>,
+}
+
+impl TransformGizmo {
+    pub fn new() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            drag: None,
+        }
+    }
+
+    // For when the object goes away in the middle of a drag
+    pub fn cancel(&mut self) {
+        self.drag = None;
+    }
+
+    // Handles this frame's input and draws the handles. `ray` is the one under the cursor,
+    // `pressed` and `held` are the state of the button that grabs handles. Returns true if the
+    // press went to a handle, so it shouldn't select anything else.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        eye: Point3<f32>,
+        ray: Option<&Ray>,
+        pressed: bool,
+        held: bool,
+        parent_world: &Matrix4<f32>,
+        local: &mut Transform,
+    ) -> bool {
+        if !held {
+            self.drag = None;
+        }
+
+        let frame = match &self.drag {
+            // Handles stay where they were grabbed, so the drag doesn't feed back into itself
+            Some(drag) => Frame::new(self.mode, eye, parent_world, &drag.start),
+            None => Frame::new(self.mode, eye, parent_world, local),
+        };
+
+        let mut grabbed = false;
+        if let (Some(drag), Some(ray)) = (&self.drag, ray) {
+            self.apply(drag, &frame, ray, parent_world, local);
+        } else if let (true, Some(ray)) = (pressed, ray) {
+            if let Some((axis, start_value)) = frame.hit(self.mode, ray) {
+                self.drag = Some(Drag {
+                    axis,
+                    start_value,
+                    start: *local,
+                });
+                grabbed = true;
+            }
+        }
+
+        let highlighted = match &self.drag {
+            Some(drag) => Some(drag.axis),
+            None => ray.and_then(|ray| frame.hit(self.mode, ray).map(|(axis, _)| axis)),
+        };
+        Frame::new(self.mode, eye, parent_world, local).draw(self.mode, highlighted);
+
+        grabbed
+    }
+
+    fn apply(
+        &self,
+        drag: &Drag,
+        frame: &Frame,
+        ray: &Ray,
+        parent_world: &Matrix4<f32>,
+        local: &mut Transform,
+    ) {
+        let axis = drag.axis;
+        match self.mode {
+            GizmoMode::Translate => {
+                if let Some((distance, _)) = frame.along_axis(ray, axis) {
+                    let world_offset = frame.axes[axis] * (distance - drag.start_value);
+                    let parent_inverse = parent_world.invert().unwrap_or_else(Matrix4::identity);
+                    local.translation =
+                        drag.start.translation + parent_inverse.transform_vector(world_offset);
+                }
+            }
+            GizmoMode::Rotate => {
+                if let Some((angle, _)) = frame.around_axis(ray, axis) {
+                    let rotation =
+                        Quaternion::from_axis_angle(AXES[axis], Rad(angle - drag.start_value));
+                    local.rotation = (rotation * drag.start.rotation).normalize();
+                }
+            }
+            GizmoMode::Scale => {
+                if let Some((distance, _)) = frame.along_axis(ray, axis) {
+                    if drag.start_value > 0.0 {
+                        let factor = (distance / drag.start_value).max(0.0);
+                        let mut scale = Vector3::new(1.0, 1.0, 1.0);
+                        scale[axis] = factor;
+                        local.scale = drag.start.scale.mul_element_wise(scale);
+                        local.scale[axis] = local.scale[axis].max(MIN_SCALE);
+                    }
+                }
+            }
+        }
+    }
+}
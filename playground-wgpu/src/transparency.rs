@@ -0,0 +1,261 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BlendFactor, BlendOperation, Color, ColorStateDescriptor, ColorWrite,
+    CompareFunction, CullMode, Device, FilterMode, IndexFormat, LoadOp, PrimitiveTopology,
+    RenderPass, RenderPassColorAttachmentDescriptor, RenderPipeline, Sampler, SamplerDescriptor,
+    ShaderStage, StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const OIT_RESOLVE_FRAG: Shader = Shader {
+    name: "oit_resolve.frag",
+    source: include_str!("../shaders/oit_resolve.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+pub const ACCUM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+// Regular non-premultiplied alpha blending
+pub const ALPHA_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::SrcAlpha,
+    dst_factor: BlendFactor::OneMinusSrcAlpha,
+    operation: BlendOperation::Add,
+};
+
+// Sums up the weighted colors and weights
+const ACCUMULATE: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::One,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+// Multiplies the target by one minus what the shader writes
+const REVEAL: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::Zero,
+    dst_factor: BlendFactor::OneMinusSrcColor,
+    operation: BlendOperation::Add,
+};
+
+// `key` blending over what's already in its targets. Transparent draws still get hidden by
+// nearer opaque ones, but don't write depth, so they never hide what's drawn after them.
+pub fn blended(key: &PipelineKey) -> PipelineKey {
+    let mut key = key.clone();
+    for color_state in &mut key.color_states {
+        color_state.color_blend = ALPHA_BLEND;
+        color_state.alpha_blend = ALPHA_BLEND;
+    }
+    if let Some(depth_stencil_state) = &mut key.depth_stencil_state {
+        depth_stencil_state.depth_write_enabled = false;
+    }
+    key
+}
+
+// `key` drawing into the targets of a `WeightedBlended` instead. `fragment_shader` has to write
+// the weighted premultiplied color and the weight to location 0 and the alpha to location 1.
+pub fn accumulating(key: &PipelineKey, fragment_shader: Shader) -> PipelineKey {
+    let mut key = blended(key);
+    key.fragment_shader = Some(fragment_shader);
+    key.color_states = vec![
+        ColorStateDescriptor {
+            format: ACCUM_FORMAT,
+            color_blend: ACCUMULATE,
+            alpha_blend: ACCUMULATE,
+            write_mask: ColorWrite::ALL,
+        },
+        ColorStateDescriptor {
+            format: REVEALAGE_FORMAT,
+            color_blend: REVEAL,
+            alpha_blend: REVEAL,
+            write_mask: ColorWrite::ALL,
+        },
+    ];
+    key
+}
+
+// The accumulation and revealage targets, and the bind group the resolve reads them through
+struct Targets {
+    accum: TextureView,
+    revealage: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let accum = render_target::create_target(device, size, ACCUM_FORMAT, "oit_accum");
+        let revealage =
+            render_target::create_target(device, size, REVEALAGE_FORMAT, "oit_revealage");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&accum),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&revealage),
+                },
+                Binding {
+                    binding: 2,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("oit_targets"),
+        });
+
+        Self {
+            accum,
+            revealage,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// Weighted blended order independent transparency. The transparent draws go into two offscreen
+// targets in any order, with an `accumulating` pipeline: a weighted average of their colors and
+// how much of what's behind shows through. `resolve` then blends the average over the frame.
+// Nothing gets sorted, at the price of only approximating the blend where layers overlap.
+pub struct WeightedBlended {
+    targets: Targets,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    resolve_pipeline: Arc<RenderPipeline>,
+}
+
+impl WeightedBlended {
+    pub fn new(
+        device: &Device,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "oit_targets", bind_group::TEXTURE_PAIR_LAYOUT);
+        // The targets are read texel for texel
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+        let resolve_pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(OIT_RESOLVE_FRAG),
+                bind_group_layouts: vec![bind_group::layout_key(bind_group::TEXTURE_PAIR_LAYOUT)],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ALPHA_BLEND,
+                    alpha_blend: ALPHA_BLEND,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        Self {
+            targets: Targets::new(device, &layout, &sampler, size),
+            layout,
+            sampler,
+            resolve_pipeline,
+        }
+    }
+
+    // Has to match the frame, demos catch up on the next update like with their depth buffers
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if size != self.targets.size {
+            self.targets = Targets::new(device, &self.layout, &self.sampler, size);
+        }
+    }
+
+    // For the accumulation pass, cleared to nothing accumulated and everything showing through
+    pub fn attachments(&self) -> [RenderPassColorAttachmentDescriptor<'_>; 2] {
+        [
+            RenderPassColorAttachmentDescriptor {
+                attachment: &self.targets.accum,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::TRANSPARENT,
+            },
+            RenderPassColorAttachmentDescriptor {
+                attachment: &self.targets.revealage,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::WHITE,
+            },
+        ]
+    }
+
+    // Blends what the accumulation pass left over the frame, in a pass loading the frame
+    pub fn resolve<'a>(&'a self, render_pass: &mut RenderPass<'a>) {
+        render_pass.set_pipeline(&self.resolve_pipeline);
+        render_pass.set_bind_group(0, &self.targets.bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// A frame's draws split by whether they blend with what's behind them. The opaque ones go first
+// in any order, then the transparent ones from the back to the front, so each one blends over
+// everything behind it.
+pub struct DrawQueues<T> {
+    pub opaque: Vec<T>,
+    // With their view space depth, larger is further away
+    transparent: Vec<(f32, T)>,
+}
+
+impl<T> DrawQueues<T> {
+    pub fn new() -> Self {
+        Self {
+            opaque: Vec::new(),
+            transparent: Vec::new(),
+        }
+    }
+
+    pub fn push_opaque(&mut self, draw: T) {
+        self.opaque.push(draw);
+    }
+
+    pub fn push_transparent(&mut self, depth: f32, draw: T) {
+        self.transparent.push((depth, draw));
+    }
+
+    // In the order they were pushed, for when the order doesn't matter
+    pub fn transparent_unsorted(&self) -> impl Iterator<Item = &T> {
+        self.transparent.iter().map(|(_, draw)| draw)
+    }
+
+    // Sorted from the back to the front
+    pub fn transparent(&mut self) -> impl Iterator<Item = &T> {
+        self.transparent
+            .sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        self.transparent.iter().map(|(_, draw)| draw)
+    }
+}
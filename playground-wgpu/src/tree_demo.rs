@@ -0,0 +1,1017 @@
+use crate::assets::{self, Assets, Handle, MeshData};
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::culling::{self, CullStats};
+use crate::debug_draw;
+use crate::demo::{Demo, DemoContext};
+use crate::draw_stats;
+use crate::id_buffer;
+use crate::labels::{LabelRenderer, LabelStyle};
+use crate::picking::{self, PickObject};
+use crate::pipeline::{PipelineKey, Shader};
+use crate::render_mode::RenderMode;
+use crate::sampler::SamplerSettings;
+use crate::scene::{Label, Light, Lod, Material, Mesh, Name, Parent, Scene, WorldTransform};
+use crate::selection_outline::SelectionOutline;
+use crate::texture;
+use crate::transparency::{self, DrawQueues, WeightedBlended};
+use crate::uniform::{self, ObjectUniforms, Uniforms};
+use cgmath::{
+    Deg, EuclideanSpace, Euler, Matrix4, Point3, Quaternion, Rotation3, SquareMatrix,
+    Transform as _, Vector3,
+};
+use hecs::Entity;
+use playground_math::{Aabb, Block, Camera, Frustum, Layout, LodDistances, Projection, Transform};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, Binding, BindingResource, BlendDescriptor, BufferAddress, Color,
+    ColorStateDescriptor, ColorWrite, CommandEncoder, CullMode, Device, IndexFormat, InputStepMode,
+    LoadOp, PrimitiveTopology, RenderPass, RenderPassColorAttachmentDescriptor,
+    RenderPassDescriptor, RenderPipeline, ShaderStage, StoreOp, TextureView,
+    VertexAttributeDescriptor, VertexBufferDescriptor, VertexFormat,
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+const SHADER_VERT: Shader = Shader {
+    name: "shader.vert",
+    source: include_str!("../shaders/shader.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHADER_FRAG: Shader = Shader {
+    name: "shader.frag",
+    source: include_str!("../shaders/shader.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const SHADER_OIT_FRAG: Shader = Shader {
+    name: "shader_oit.frag",
+    source: include_str!("../shaders/shader_oit.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const ID_VERT: Shader = Shader {
+    name: "id.vert",
+    source: include_str!("../shaders/id.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const ID_FRAG: Shader = Shader {
+    name: "id.frag",
+    source: include_str!("../shaders/id.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-0.0868241, 0.49240386, 0.0],
+        tex_coords: [0.4131759, 0.00759614],
+    },
+    Vertex {
+        position: [-0.49513406, 0.06958647, 0.0],
+        tex_coords: [0.0048659444, 0.43041354],
+    },
+    Vertex {
+        position: [-0.21918549, -0.44939706, 0.0],
+        tex_coords: [0.28081453, 0.949397057],
+    },
+    Vertex {
+        position: [0.35966998, -0.3473291, 0.0],
+        tex_coords: [0.85967, 0.84732911],
+    },
+    Vertex {
+        position: [0.44147372, 0.2347359, 0.0],
+        tex_coords: [0.9414737, 0.2652641],
+    },
+];
+
+const INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
+// A single triangle between three of the corners, for far away
+const COARSE_INDICES: &[u16] = &[0, 2, 3];
+// Where the trees switch to the coarse pentagon
+const LOD_DISTANCE: f32 = 4.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Vertex {
+    position: [f32; 3],
+    tex_coords: [f32; 2],
+}
+
+impl Vertex {
+    fn descriptor<'a>() -> VertexBufferDescriptor<'a> {
+        VertexBufferDescriptor {
+            // How wide is the Vertex
+            stride: mem::size_of::<Vertex>() as BufferAddress,
+            // How often should it move to the next vertex
+            step_mode: InputStepMode::Vertex,
+            // Attributes of our vertex data
+            attributes: &[
+                VertexAttributeDescriptor {
+                    // Where does the attribute start?
+                    offset: 0,
+                    // Where to store the attribute, ex: layout(location=0) in vec3 x would be position
+                    shader_location: 0,
+                    // Shape of the attribute, corresponds to vec3 in shader
+                    format: VertexFormat::Float3,
+                },
+                VertexAttributeDescriptor {
+                    // Where does the attribute start?
+                    offset: mem::size_of::<[f32; 3]>() as BufferAddress,
+                    // Where to store the attribute, ex: layout(location=1) in vec3 x would be color
+                    shader_location: 1,
+                    // Shape of the attribute, corresponds to vec3 in shader
+                    format: VertexFormat::Float2,
+                },
+            ],
+        }
+    }
+}
+
+// Plain old data: Can be interpreted as &[u8]
+unsafe impl bytemuck::Pod for Vertex {}
+
+// We can use std::mem::zeroed()
+unsafe impl bytemuck::Zeroable for Vertex {}
+
+// What the camera section of the UI changes
+#[derive(Serialize, Deserialize)]
+struct TreeSettings {
+    eye: [f32; 3],
+    target: [f32; 3],
+    fovy: f32,
+    // Changed by the orientation gizmo when looking straight up or down
+    #[serde(default = "default_up")]
+    up: [f32; 3],
+}
+
+fn default_up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+// Component holding an entity's world matrix on the GPU, for everything with a mesh
+struct ObjectBinding {
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+// Everything needed to draw one object, collected before drawing so the draws can be sorted
+struct ObjectDraw<'a> {
+    mesh: &'a assets::Mesh,
+    material: &'a Material,
+    binding: &'a ObjectBinding,
+}
+
+impl<'a> ObjectDraw<'a> {
+    // With the mesh's edges as a line list when `lines` is set
+    fn render(&self, render_pass: &mut RenderPass<'a>, assets: &'a Assets, lines: bool) {
+        let mesh = self.mesh;
+        let bind_group = assets.texture_bind_group(self.material.texture, self.material.sampler);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_bind_group(2, &self.binding.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(
+            0,
+            &mesh.vertex_buffer,
+            mesh.vertex_allocation.offset,
+            mesh.vertex_allocation.size,
+        );
+        let (index_allocation, num_indices) = if lines {
+            (mesh.line_index_allocation, mesh.num_line_indices)
+        } else {
+            (mesh.index_allocation, mesh.num_indices)
+        };
+        render_pass.set_index_buffer(
+            &mesh.index_buffer,
+            index_allocation.offset,
+            index_allocation.size,
+        );
+        draw_stats::record_triangles(num_indices, 1);
+        render_pass.draw_indexed(0..num_indices, 0, 0..1);
+    }
+}
+
+fn object_bind_group_key(entity: Entity) -> String {
+    format!("object uniforms {}", entity.id())
+}
+
+// The textured pentagon from the tutorial, a few times over in a small hierarchy, looked at
+// through a perspective camera
+pub struct TreeDemo {
+    // One permutation per render mode
+    render_pipelines: HashMap<RenderMode, Arc<RenderPipeline>>,
+    render_mode: RenderMode,
+    // For the objects that aren't fully opaque, drawn after the rest
+    blended_pipeline: Arc<RenderPipeline>,
+    // Instead of sorting them and blending them one by one
+    order_independent: bool,
+    oit_pipeline: Arc<RenderPipeline>,
+    oit: WeightedBlended,
+    size: PhysicalSize<u32>,
+    id_pipeline: Arc<RenderPipeline>,
+
+    // Every tree uses the pentagon, or the coarse one from further away
+    pentagon: Handle<assets::Mesh>,
+    coarse_pentagon: Handle<assets::Mesh>,
+    scene: Scene,
+    labels: LabelRenderer,
+    show_labels: bool,
+    show_debug_shapes: bool,
+    selected: Option<Entity>,
+    outline: SelectionOutline,
+    // Skip objects outside the view, off to compare
+    culling: bool,
+    // Counted while rendering, which only gets `&self`
+    cull_stats: Cell<CullStats>,
+
+    // Texture
+    diffuse_texture: Handle<texture::Texture>,
+
+    // Camera
+    camera: Camera,
+
+    // Uniforms
+    uniforms: Uniforms,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+}
+
+impl TreeDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+
+        // Load the tree picture, it shows up once it's decoded
+        let diffuse_texture =
+            ctx.assets
+                .load_texture(device, "happy-tree.png", SamplerSettings::default());
+
+        let camera = Camera {
+            eye: (0.0, 1.0, 2.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            // The window can be minimized (and zero sized) while the demo gets created
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let mut uniforms = Uniforms::new();
+        uniforms.update_view_proj(&camera);
+
+        let scaled = |x: f32, y: f32, scale: f32| Transform {
+            // Slightly behind the parent, there's no depth buffer to sort out the overlap
+            translation: Vector3::new(x, y, -0.1),
+            scale: Vector3::new(scale, scale, scale),
+            ..Transform::identity()
+        };
+        let pentagon_data = |indices: &'static [u16]| {
+            move || {
+                Ok(MeshData {
+                    vertices: bytemuck::cast_slice(VERTICES).to_vec(),
+                    indices: indices.to_vec(),
+                    positions: VERTICES
+                        .iter()
+                        .map(|vertex| vertex.position.into())
+                        .collect(),
+                })
+            }
+        };
+        let pentagon = ctx.assets.load_mesh("pentagon", pentagon_data(INDICES));
+        let coarse_pentagon = ctx
+            .assets
+            .load_mesh("coarse pentagon", pentagon_data(COARSE_INDICES));
+
+        let mut scene = Scene::new();
+        let root = scene.spawn("happy tree", None, Transform::identity());
+        let left = scene.spawn("left tree", Some(root), scaled(-0.75, -0.25, 0.5));
+        let right = scene.spawn("right tree", Some(root), scaled(0.75, -0.25, 0.5));
+        let tiny = scene.spawn("tiny tree", Some(right), scaled(0.0, 0.75, 0.5));
+        for &tree in &[root, left, right, tiny] {
+            let material = Material {
+                texture: diffuse_texture,
+                sampler: SamplerSettings::default(),
+                // See-through, to show off the transparent pass
+                opacity: if tree == tiny { 0.5 } else { 1.0 },
+            };
+            let label = Label {
+                text: scene.name(tree),
+                style: LabelStyle::default(),
+                // Just above the pentagon's top corner
+                offset: Vector3::new(0.0, 0.6, 0.0),
+            };
+            let lod = Lod {
+                meshes: vec![pentagon, coarse_pentagon],
+                distances: LodDistances::new(vec![LOD_DISTANCE], 0.1),
+                level: 0,
+            };
+            scene
+                .world
+                .insert(tree, (Mesh(pentagon), lod, material, label))
+                .unwrap();
+        }
+        let sun = scene.spawn("sun", None, Transform::identity());
+        let light = Light {
+            color: playground_math::Color::WHITE,
+            intensity: 1.0,
+        };
+        scene.world.insert_one(sun, light).unwrap();
+        scene.update_world_matrices();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("buffer_upload_encoder"),
+        });
+
+        let uniform_allocation = ctx.uniform_pool.upload(
+            device,
+            &mut encoder,
+            &uniforms.std140(),
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let object_allocations: Vec<(Entity, Allocation)> = scene
+            .world
+            .query::<(Entity, &WorldTransform, &Material)>()
+            .with::<&Mesh>()
+            .iter()
+            .map(|(entity, world, material)| {
+                let allocation = ctx.uniform_pool.upload(
+                    device,
+                    &mut encoder,
+                    &ObjectUniforms::new(world.0, material.opacity).std140(),
+                    wgpu::BIND_BUFFER_ALIGNMENT,
+                );
+                (entity, allocation)
+            })
+            .collect();
+
+        ctx.queue.submit(&[encoder.finish()]);
+
+        let uniform_bind_group = ctx.bind_groups.bind_group(
+            device,
+            bind_group::UNIFORM_LAYOUT,
+            "uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: ctx.uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset
+                            + Uniforms::size(Layout::Std140) as BufferAddress,
+                },
+            }],
+        );
+
+        for (entity, uniform_allocation) in object_allocations {
+            let uniform_bind_group = ctx.bind_groups.bind_group(
+                device,
+                bind_group::OBJECT_UNIFORM_LAYOUT,
+                &object_bind_group_key(entity),
+                &[Binding {
+                    binding: 0,
+                    resource: BindingResource::Buffer {
+                        buffer: ctx.uniform_pool.buffer(&uniform_allocation),
+                        range: uniform_allocation.offset
+                            ..uniform_allocation.offset
+                                + ObjectUniforms::size(Layout::Std140) as BufferAddress,
+                    },
+                }],
+            );
+            let binding = ObjectBinding {
+                uniform_allocation,
+                uniform_bind_group,
+            };
+            scene.world.insert_one(entity, binding).unwrap();
+        }
+
+        let solid = PipelineKey {
+            vertex_shader: SHADER_VERT,
+            fragment_shader: Some(SHADER_FRAG),
+            bind_group_layouts: vec![
+                bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+            ],
+            vertex_buffers: vec![Vertex::descriptor().into()],
+            // Use 16-bit integers for indexing
+            index_format: IndexFormat::Uint16,
+            // We're drawing a list of triangles
+            primitive_topology: PrimitiveTopology::TriangleList,
+            cull_mode: CullMode::Back,
+            // Describes how colors are stored and processed throughout the pipeline
+            color_states: vec![ColorStateDescriptor {
+                format: ctx.format,
+                alpha_blend: BlendDescriptor::REPLACE,
+                color_blend: BlendDescriptor::REPLACE,
+                write_mask: ColorWrite::ALL,
+            }],
+            depth_stencil_state: None,
+            sample_count: 1,
+        };
+        let render_pipelines = RenderMode::ALL
+            .iter()
+            .map(|&mode| {
+                let key = mode.pipeline_key(&solid);
+                (mode, ctx.pipelines.get(device, ctx.bind_groups, &key))
+            })
+            .collect();
+        let blended_pipeline =
+            ctx.pipelines
+                .get(device, ctx.bind_groups, &transparency::blended(&solid));
+        let oit_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &transparency::accumulating(&solid, SHADER_OIT_FRAG),
+        );
+        let oit =
+            WeightedBlended::new(device, ctx.bind_groups, ctx.pipelines, ctx.format, ctx.size);
+        let outline = SelectionOutline::new(
+            device,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+            ctx.size,
+            Vertex::descriptor().into(),
+        );
+
+        let id_pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: ID_VERT,
+                fragment_shader: Some(ID_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![Vertex::descriptor().into()],
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::Back,
+                color_states: vec![ColorStateDescriptor {
+                    format: id_buffer::ID_FORMAT,
+                    alpha_blend: BlendDescriptor::REPLACE,
+                    color_blend: BlendDescriptor::REPLACE,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let labels = LabelRenderer::new(
+            device,
+            ctx.queue,
+            ctx.uniform_pool,
+            ctx.bind_groups,
+            ctx.pipelines,
+            ctx.format,
+        );
+
+        ctx.buffer_inspector.register(
+            "uniforms",
+            &ctx.uniform_pool.shared_buffer(&uniform_allocation),
+            uniform_allocation.offset,
+            uniform_allocation.size,
+            uniform::UNIFORMS_LAYOUT,
+        );
+
+        Self {
+            render_pipelines,
+            render_mode: RenderMode::Solid,
+            blended_pipeline,
+            order_independent: false,
+            oit_pipeline,
+            oit,
+            size: ctx.size,
+            id_pipeline,
+            pentagon,
+            coarse_pentagon,
+            scene,
+            labels,
+            show_labels: true,
+            show_debug_shapes: false,
+            selected: None,
+            outline,
+            culling: true,
+            cull_stats: Cell::new(CullStats::default()),
+            diffuse_texture,
+            camera,
+            uniforms,
+            uniform_allocation,
+            uniform_bind_group,
+        }
+    }
+}
+
+impl TreeDemo {
+    // Whether the mesh at `world` is in view, always when culling is off
+    fn in_view(&self, frustum: &Frustum, mesh: &assets::Mesh, world: &WorldTransform) -> bool {
+        !self.culling || culling::is_visible(frustum, &mesh.bounding_sphere, &mesh.bounds, &world.0)
+    }
+}
+
+// Transform controls for an entity, with its children nested inside
+fn entity_ui(ui: &mut egui::Ui, scene: &mut Scene, entity: Entity) {
+    let children = scene.children(entity);
+
+    ui.collapsing(scene.name(entity), |ui| {
+        if let Ok(mut local) = scene.world.get::<&mut Transform>(entity) {
+            transform_ui(ui, &mut local);
+        }
+        if let Ok(mut light) = scene.world.get::<&mut Light>(entity) {
+            let mut color = [light.color.r, light.color.g, light.color.b];
+            ui.horizontal(|ui| {
+                ui.label("Light color");
+                ui.color_edit_button_rgb(&mut color);
+            });
+            light.color.r = color[0];
+            light.color.g = color[1];
+            light.color.b = color[2];
+            ui.add(egui::Slider::new(&mut light.intensity, 0.0..=10.0).text("Intensity"));
+        }
+        if let Ok(mut material) = scene.world.get::<&mut Material>(entity) {
+            ui.add(egui::Slider::new(&mut material.opacity, 0.0..=1.0).text("Opacity"));
+            material.sampler.ui(ui);
+        }
+        if let Ok(mut label) = scene.world.get::<&mut Label>(entity) {
+            label_ui(ui, &mut label);
+        }
+        if let Ok(lod) = scene.world.get::<&Lod>(entity) {
+            ui.label(format!(
+                "Level of detail {} of {}",
+                lod.level,
+                lod.meshes.len() - 1
+            ));
+        }
+
+        for child in children {
+            entity_ui(ui, scene, child);
+        }
+    });
+}
+
+fn label_ui(ui: &mut egui::Ui, label: &mut Label) {
+    ui.horizontal(|ui| {
+        ui.label("Label");
+        ui.text_edit_singleline(&mut label.text);
+    });
+
+    let style = &mut label.style;
+    ui.add(egui::Slider::new(&mut style.size, 0.02..=0.5).text("Text size"));
+    let mut color = [style.color.r, style.color.g, style.color.b];
+    let mut outline_color = [
+        style.outline_color.r,
+        style.outline_color.g,
+        style.outline_color.b,
+    ];
+    ui.horizontal(|ui| {
+        ui.label("Text color");
+        ui.color_edit_button_rgb(&mut color);
+        ui.label("Outline");
+        ui.color_edit_button_rgb(&mut outline_color);
+    });
+    style.color = playground_math::Color::new(color[0], color[1], color[2], style.color.a);
+    style.outline_color = playground_math::Color::new(
+        outline_color[0],
+        outline_color[1],
+        outline_color[2],
+        style.outline_color.a,
+    );
+    ui.add(egui::Slider::new(&mut style.outline_width, 0.0..=2.0).text("Outline width"));
+    ui.add(egui::Slider::new(&mut style.shadow_color.a, 0.0..=1.0).text("Shadow opacity"));
+    ui.add(egui::Slider::new(&mut style.shadow_softness, 0.0..=4.0).text("Shadow softness"));
+}
+
+fn transform_ui(ui: &mut egui::Ui, local: &mut Transform) {
+    ui.horizontal(|ui| {
+        ui.label("Translation");
+        ui.add(egui::DragValue::new(&mut local.translation.x).speed(0.05));
+        ui.add(egui::DragValue::new(&mut local.translation.y).speed(0.05));
+        ui.add(egui::DragValue::new(&mut local.translation.z).speed(0.05));
+    });
+
+    // Only around Z, which is the way the trees face
+    let mut angle = Deg::from(Euler::from(local.rotation).z).0;
+    if ui
+        .add(egui::Slider::new(&mut angle, -180.0..=180.0).text("Rotation"))
+        .changed()
+    {
+        local.rotation = Quaternion::from_angle_z(Deg(angle));
+    }
+
+    let mut scale = local.scale.x;
+    if ui
+        .add(egui::Slider::new(&mut scale, 0.1..=2.0).text("Scale"))
+        .changed()
+    {
+        local.scale = Vector3::new(scale, scale, scale);
+    }
+}
+
+impl Demo for TreeDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.oit.resize(device, self.size);
+        self.outline
+            .prepare(device, encoder, uniform_pool, self.size);
+        self.uniforms.update_view_proj(&self.camera);
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &self.uniforms.std140(),
+        );
+
+        self.scene.update_world_matrices();
+        self.scene.update_lods(self.camera.eye);
+        let mut objects = self
+            .scene
+            .world
+            .query::<(&WorldTransform, &Material, &ObjectBinding)>();
+        for (world, material, binding) in objects.iter() {
+            uniform_pool.write(
+                device,
+                encoder,
+                &binding.uniform_allocation,
+                &ObjectUniforms::new(world.0, material.opacity).std140(),
+            );
+        }
+
+        if self.show_labels {
+            let mut labels = self.scene.world.query::<(&WorldTransform, &Label)>();
+            for (world, label) in labels.iter() {
+                let anchor = world.0.transform_point(Point3::from_vec(label.offset));
+                self.labels.queue(anchor, &label.text, &label.style);
+            }
+        }
+        if self.show_debug_shapes {
+            let mut shapes = self
+                .scene
+                .world
+                .query::<(&WorldTransform, Option<&Mesh>, Option<&Light>)>();
+            for (world, mesh, light) in shapes.iter() {
+                debug_draw::axes(&world.0, 0.25);
+                // Every mesh is the pentagon, which doesn't have to be loaded to know its bounds
+                if mesh.is_some() {
+                    let bounds = Aabb::from_points(
+                        VERTICES
+                            .iter()
+                            .map(|vertex| world.0.transform_point(vertex.position.into())),
+                    );
+                    debug_draw::aabb(&bounds, playground_math::Color::new(1.0, 1.0, 0.0, 1.0));
+                }
+                if let Some(light) = light {
+                    let position = world.0.transform_point(Point3::new(0.0, 0.0, 0.0));
+                    debug_draw::sphere(position, 0.1, light.color);
+                }
+            }
+        }
+        self.labels
+            .prepare(device, encoder, uniform_pool, &self.camera);
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let camera = &mut self.camera;
+        ui.collapsing("Camera", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Eye");
+                ui.add(egui::DragValue::new(&mut camera.eye.x).speed(0.05));
+                ui.add(egui::DragValue::new(&mut camera.eye.y).speed(0.05));
+                ui.add(egui::DragValue::new(&mut camera.eye.z).speed(0.05));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Target");
+                ui.add(egui::DragValue::new(&mut camera.target.x).speed(0.05));
+                ui.add(egui::DragValue::new(&mut camera.target.y).speed(0.05));
+                ui.add(egui::DragValue::new(&mut camera.target.z).speed(0.05));
+            });
+            ui.add(egui::Slider::new(&mut camera.fovy, 10.0..=120.0).text("Field of view"));
+            let mut orthographic = camera.projection == Projection::Orthographic;
+            if ui.checkbox(&mut orthographic, "Orthographic").changed() {
+                camera.projection = camera.projection.toggled();
+            }
+        });
+
+        let scene = &mut self.scene;
+        let show_labels = &mut self.show_labels;
+        let show_debug_shapes = &mut self.show_debug_shapes;
+        let culling = &mut self.culling;
+        let order_independent = &mut self.order_independent;
+        let outline = &mut self.outline;
+        let cull_stats = self.cull_stats.get();
+        ui.collapsing("Scene", |ui| {
+            ui.checkbox(show_labels, "Labels");
+            ui.checkbox(order_independent, "Order independent transparency")
+                .on_hover_text("Weighted blended, instead of sorting back to front");
+            outline.ui(ui);
+            ui.checkbox(show_debug_shapes, "Axes, bounds and lights");
+            ui.checkbox(culling, "Frustum culling");
+            cull_stats.ui(ui);
+            for root in scene.roots() {
+                entity_ui(ui, scene, root);
+            }
+        });
+    }
+
+    fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    fn set_selected(&mut self, name: Option<&str>) {
+        self.selected = name.and_then(|name| self.scene.find(name));
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn pick(
+        &self,
+        assets: &Assets,
+        cursor: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    ) -> Option<String> {
+        let ray = picking::cursor_ray(&self.uniforms.view_proj(), cursor, size)?;
+
+        // The meshes in world space, where their entities currently are
+        let mut objects = self.scene.world.query::<(&Name, &WorldTransform, &Mesh)>();
+        let pick_objects: Vec<PickObject> = objects
+            .iter()
+            .map(|(name, world, mesh)| {
+                let mesh = assets.mesh(mesh.0);
+                PickObject {
+                    name: name.0.clone(),
+                    bounds: mesh.bounds.transform(&world.0),
+                    triangles: mesh
+                        .triangles
+                        .iter()
+                        .map(|triangle| triangle.map(|point| world.0.transform_point(point)))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let hit = picking::pick(&ray, &pick_objects)?;
+        Some(pick_objects[hit.object].name.clone())
+    }
+
+    fn settings(&self) -> Option<serde_json::Value> {
+        let settings = TreeSettings {
+            eye: self.camera.eye.into(),
+            target: self.camera.target.into(),
+            fovy: self.camera.fovy,
+            up: self.camera.up.into(),
+        };
+        serde_json::to_value(settings).ok()
+    }
+
+    fn apply_settings(&mut self, settings: serde_json::Value) -> Result<(), failure::Error> {
+        let settings: TreeSettings = serde_json::from_value(settings)?;
+        self.camera.eye = settings.eye.into();
+        self.camera.target = settings.target.into();
+        self.camera.fovy = settings.fovy;
+        self.camera.up = settings.up.into();
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        assets: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        // Has to outlive the pass, which keeps borrowing the components
+        let mut objects = self
+            .scene
+            .world
+            .query::<(&Mesh, &Material, &WorldTransform, &ObjectBinding)>();
+        let frustum = Frustum::from_view_proj(&self.uniforms.view_proj());
+        let mut stats = CullStats::default();
+
+        // Further away is more negative z in view space
+        let view = Matrix4::look_at(self.camera.eye, self.camera.target, self.camera.up);
+        let mut queues = DrawQueues::new();
+        for (mesh, material, world, binding) in objects.iter() {
+            let mesh = assets.mesh(mesh.0);
+            let visible = self.in_view(&frustum, mesh, world);
+            stats.count(visible);
+            if !visible {
+                continue;
+            }
+            let sphere = mesh.bounding_sphere.transform(&world.0);
+            let pixels = self.camera.projected_size(&sphere) * self.size.height as f32;
+            assets.want_texture(material.texture, pixels);
+            let draw = ObjectDraw {
+                mesh,
+                material,
+                binding,
+            };
+            // The debug render modes draw everything opaque
+            if material.opacity < 1.0 && self.render_mode == RenderMode::Solid {
+                let center = world.0.transform_point(mesh.bounding_sphere.center);
+                queues.push_transparent(-view.transform_point(center).z, draw);
+            } else {
+                queues.push_opaque(draw);
+            }
+        }
+        self.cull_stats.set(stats);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipelines[&self.render_mode]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        let lines = self.render_mode.draws_lines();
+        render_pass.push_debug_group("Opaque");
+        for draw in &queues.opaque {
+            draw.render(&mut render_pass, assets, lines);
+        }
+        render_pass.pop_debug_group();
+
+        if self.order_independent {
+            drop(render_pass);
+            let mut accumulate_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &self.oit.attachments(),
+                depth_stencil_attachment: None,
+            });
+            accumulate_pass.set_pipeline(&self.oit_pipeline);
+            accumulate_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            accumulate_pass.push_debug_group("Transparent, accumulated");
+            for draw in queues.transparent_unsorted() {
+                draw.render(&mut accumulate_pass, assets, false);
+            }
+            accumulate_pass.pop_debug_group();
+            drop(accumulate_pass);
+
+            render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: target,
+                    resolve_target: None,
+                    load_op: LoadOp::Load,
+                    store_op: StoreOp::Store,
+                    clear_color,
+                }],
+                depth_stencil_attachment: None,
+            });
+            render_pass.insert_debug_marker("Transparency resolve");
+            self.oit.resolve(&mut render_pass);
+        } else {
+            render_pass.push_debug_group("Transparent, sorted");
+            render_pass.set_pipeline(&self.blended_pipeline);
+            for draw in queues.transparent() {
+                draw.render(&mut render_pass, assets, false);
+            }
+            render_pass.pop_debug_group();
+        }
+
+        render_pass.insert_debug_marker("Labels");
+        self.labels.render(&mut render_pass);
+        drop(render_pass);
+
+        let selected = self.selected.and_then(|entity| {
+            let mesh = self.scene.world.get::<&Mesh>(entity).ok()?;
+            let binding = self.scene.world.get::<&ObjectBinding>(entity).ok()?;
+            Some((assets.mesh(mesh.0), binding.uniform_bind_group.clone()))
+        });
+        if let Some((mesh, object_bind_group)) = &selected {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[RenderPassColorAttachmentDescriptor {
+                    attachment: target,
+                    resolve_target: None,
+                    load_op: LoadOp::Load,
+                    store_op: StoreOp::Store,
+                    clear_color,
+                }],
+                depth_stencil_attachment: Some(self.outline.attachment()),
+            });
+            render_pass.push_debug_group("Selection outline");
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, object_bind_group, &[]);
+            render_pass.set_vertex_buffer(
+                0,
+                &mesh.vertex_buffer,
+                mesh.vertex_allocation.offset,
+                mesh.vertex_allocation.size,
+            );
+            render_pass.set_index_buffer(
+                &mesh.index_buffer,
+                mesh.index_allocation.offset,
+                mesh.index_allocation.size,
+            );
+            self.outline.mark(&mut render_pass);
+            draw_stats::record_triangles(mesh.num_indices, 1);
+            render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+            self.outline.outline(&mut render_pass);
+            draw_stats::record_triangles(mesh.num_indices, 1);
+            render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+            render_pass.pop_debug_group();
+        }
+    }
+
+    fn transform_mut(&mut self, name: &str) -> Option<(Matrix4<f32>, &mut Transform)> {
+        let entity = self.scene.find(name)?;
+        let world = &self.scene.world;
+        let parent_world = match world.get::<&Parent>(entity) {
+            Ok(parent) => world.get::<&WorldTransform>(parent.0).ok()?.0,
+            Err(_) => Matrix4::identity(),
+        };
+        let local = self
+            .scene
+            .world
+            .query_one_mut::<&mut Transform>(entity)
+            .ok()?;
+        Some((parent_world, local))
+    }
+
+    fn render_ids(&self, assets: &Assets, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut objects = self
+            .scene
+            .world
+            .query::<(Entity, &Mesh, &WorldTransform, &ObjectBinding)>();
+        let frustum = Frustum::from_view_proj(&self.uniforms.view_proj());
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color: Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&self.id_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+
+        // IDs are one past the entity's ID
+        for (entity, mesh, world, binding) in objects.iter() {
+            let mesh = assets.mesh(mesh.0);
+            if !self.in_view(&frustum, mesh, world) {
+                continue;
+            }
+            let id = entity.id() + 1;
+            render_pass.set_bind_group(1, &binding.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(
+                0,
+                &mesh.vertex_buffer,
+                mesh.vertex_allocation.offset,
+                mesh.vertex_allocation.size,
+            );
+            render_pass.set_index_buffer(
+                &mesh.index_buffer,
+                mesh.index_allocation.offset,
+                mesh.index_allocation.size,
+            );
+            render_pass.draw_indexed(0..mesh.num_indices, 0, id..id + 1);
+        }
+    }
+
+    fn object_name(&self, id: u32) -> Option<String> {
+        let id = id.checked_sub(1)?;
+        let mut names = self.scene.world.query::<(Entity, &Name)>();
+        names
+            .iter()
+            .find(|(entity, _)| entity.id() == id)
+            .map(|(_, name)| name.0.clone())
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        ctx.assets.release_mesh(self.pentagon, ctx.geometry_pool);
+        ctx.assets
+            .release_mesh(self.coarse_pentagon, ctx.geometry_pool);
+        ctx.assets.release_texture(self.diffuse_texture);
+        ctx.uniform_pool.free(self.uniform_allocation);
+        self.labels.release(ctx.uniform_pool, ctx.bind_groups);
+        self.outline.release(ctx.uniform_pool, ctx.bind_groups);
+        for (entity, binding) in self.scene.world.query_mut::<(Entity, &ObjectBinding)>() {
+            ctx.uniform_pool.free(binding.uniform_allocation);
+            ctx.bind_groups.invalidate(&object_bind_group_key(entity));
+        }
+        ctx.bind_groups.invalidate("uniforms");
+        ctx.buffer_inspector.unregister("uniforms");
+    }
+}
@@ -1,24 +1,255 @@
-use cgmath::{Matrix4, SquareMatrix};
-use crate::camera::Camera;
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_inspector::{Field, FieldType, StructLayout};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::DemoContext;
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+use playground_math::{sun, Block, BlockWriter, Camera, Layout};
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupLayoutEntry, Binding, BindingResource, BufferAddress, CommandEncoder,
+    Device,
+};
 
-#[repr(C)]
+// For looking at the uniform buffer in the buffer inspector
+pub const UNIFORMS_LAYOUT: StructLayout = StructLayout {
+    name: "Uniforms",
+    stride: 64,
+    fields: &[Field {
+        name: "view_proj",
+        offset: 0,
+        ty: FieldType::Mat4,
+    }],
+};
+
+// Uploaded through `Block::std140`, which pads it like the shaders' uniform block
 #[derive(Copy, Clone, Debug)]
 pub struct Uniforms {
     view_proj: Matrix4<f32>,
 }
 
-unsafe impl bytemuck::Pod for Uniforms {}
+impl Block for Uniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.view_proj);
+    }
+}
 
-unsafe impl bytemuck::Zeroable for Uniforms {}
+impl Default for Uniforms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Uniforms {
     pub fn new() -> Self {
         Self {
-            view_proj: Matrix4::identity()
+            view_proj: Matrix4::identity(),
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix();
     }
-}
\ No newline at end of file
+
+    // For views that aren't a plain camera, like reflections
+    pub fn set_view_proj(&mut self, view_proj: Matrix4<f32>) {
+        self.view_proj = view_proj;
+    }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.view_proj
+    }
+}
+
+// Per object, next to the shared `Uniforms`
+#[derive(Copy, Clone, Debug)]
+pub struct ObjectUniforms {
+    model: Matrix4<f32>,
+    opacity: f32,
+}
+
+impl ObjectUniforms {
+    pub fn new(model: Matrix4<f32>, opacity: f32) -> Self {
+        Self { model, opacity }
+    }
+}
+
+impl Block for ObjectUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.model);
+        writer.member(&self.opacity);
+    }
+}
+
+impl Default for ObjectUniforms {
+    fn default() -> Self {
+        Self::new(Matrix4::identity(), 1.0)
+    }
+}
+
+// The clock, the same for every shader during a frame. Shaders declare it as
+//
+//     uniform FrameUniforms {
+//         float u_time;
+//         float u_delta_time;
+//         uint u_frame;
+//     };
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameUniforms {
+    // Seconds since the start
+    pub time: f32,
+    // Seconds since the last frame
+    pub delta_time: f32,
+    pub frame: u32,
+}
+
+impl Block for FrameUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.time);
+        writer.member(&self.delta_time);
+        writer.member(&self.frame);
+    }
+}
+
+// A directional light and the ambient light, for the fragment shader through a
+// `bind_group::FRAGMENT_UNIFORM_LAYOUT`. Shaders declare it as
+//
+//     uniform LightUniforms {
+//         // The way the light travels, in w nothing
+//         vec4 u_light_direction;
+//         // Already scaled by the intensity
+//         vec4 u_light_color;
+//         vec4 u_ambient;
+//     };
+#[derive(Copy, Clone, Debug)]
+pub struct LightUniforms {
+    // The way the light travels
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub ambient: f32,
+}
+
+impl LightUniforms {
+    // Sunlight at `time_of_day` hours, `intensity` times what makes it through the atmosphere
+    pub fn sun(time_of_day: f32, intensity: f32, ambient: f32) -> Self {
+        let towards_sun = sun::direction(time_of_day);
+        let transmittance = sun::transmittance(towards_sun);
+        Self {
+            direction: -towards_sun,
+            color: [
+                transmittance.r * intensity,
+                transmittance.g * intensity,
+                transmittance.b * intensity,
+            ],
+            ambient,
+        }
+    }
+}
+
+impl Block for LightUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        let [red, green, blue] = self.color;
+        writer.member(&self.direction.extend(0.0));
+        writer.member(&Vector4::new(red, green, blue, 1.0));
+        writer.member(&Vector4::new(self.ambient, self.ambient, self.ambient, 1.0));
+    }
+}
+
+impl Default for LightUniforms {
+    fn default() -> Self {
+        Self {
+            direction: -Vector3::unit_y(),
+            color: [1.0, 1.0, 1.0],
+            ambient: 0.0,
+        }
+    }
+}
+
+// `FrameUniforms` in an allocation that lives as long as the app, so demos can hold on to the
+// bind group. It's laid out as a `bind_group::OBJECT_UNIFORM_LAYOUT`, for animating in the vertex
+// or the fragment shader.
+pub struct FrameClock {
+    uniforms: FrameUniforms,
+    allocation: Allocation,
+    bind_group: Arc<BindGroup>,
+}
+
+impl FrameClock {
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+    ) -> Self {
+        let allocation = uniform_pool.allocate(
+            device,
+            FrameUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let bind_group = bind_groups.bind_group(
+            device,
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+            "frame",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&allocation),
+                    range: allocation.offset..allocation.offset + allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            uniforms: FrameUniforms::default(),
+            allocation,
+            bind_group,
+        }
+    }
+
+    // Uploads the clock for a frame `dt` seconds after the last one, `time` seconds in
+    pub fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        dt: f32,
+        time: f32,
+    ) {
+        self.uniforms = FrameUniforms {
+            time,
+            delta_time: dt,
+            frame: self.uniforms.frame.wrapping_add(1),
+        };
+        uniform_pool.write(device, encoder, &self.allocation, &self.uniforms.std140());
+    }
+
+    pub fn bind_group(&self) -> &Arc<BindGroup> {
+        &self.bind_group
+    }
+}
+
+// `size` bytes from the uniform pool and a bind group with `layout` around them, cached under
+// `key`. Both have to be given back when the demo gets released.
+pub fn allocate(
+    ctx: &mut DemoContext,
+    key: &str,
+    size: usize,
+    layout: &[BindGroupLayoutEntry],
+) -> (Allocation, Arc<BindGroup>) {
+    let allocation = ctx.uniform_pool.allocate(
+        ctx.device,
+        size as BufferAddress,
+        wgpu::BIND_BUFFER_ALIGNMENT,
+    );
+    let bind_group = ctx.bind_groups.bind_group(
+        ctx.device,
+        layout,
+        key,
+        &[Binding {
+            binding: 0,
+            resource: BindingResource::Buffer {
+                buffer: ctx.uniform_pool.buffer(&allocation),
+                range: allocation.offset..allocation.offset + allocation.size,
+            },
+        }],
+    );
+    (allocation, bind_group)
+}
@@ -0,0 +1,390 @@
+use crate::sampler::SamplerSettings;
+use crate::texture::{self, Texture};
+use failure::bail;
+use log::error;
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread;
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource, CommandEncoder,
+    Device, Origin3d, TextureFormat,
+};
+
+// Decoded frames that can wait for playback to get to them. The decoder blocks when they're all
+// waiting, so it never gets far ahead.
+const FRAME_QUEUE: usize = 4;
+
+// What `ffprobe` says about a file's first video stream
+#[derive(Copy, Clone, Debug)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f32,
+    // In seconds, if the container knows
+    pub duration: Option<f32>,
+}
+
+fn probe(path: &Path) -> Result<VideoInfo, failure::Error> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,r_frame_rate:format=duration",
+            "-of",
+            "default=noprint_wrappers=1",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let (mut width, mut height, mut frame_rate, mut duration) = (None, None, None, None);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut parts = line.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("width"), Some(value)) => width = value.parse().ok(),
+            (Some("height"), Some(value)) => height = value.parse().ok(),
+            // A fraction like 30000/1001
+            (Some("r_frame_rate"), Some(value)) => {
+                let mut parts = value.splitn(2, '/');
+                let numerator: Option<f32> = parts.next().and_then(|part| part.parse().ok());
+                let denominator = parts.next().map_or(Some(1.0), |part| part.parse().ok());
+                frame_rate = match (numerator, denominator) {
+                    (Some(numerator), Some(denominator)) if denominator > 0.0 => {
+                        Some(numerator / denominator)
+                    }
+                    _ => None,
+                };
+            }
+            // "N/A" for streams that don't know
+            (Some("duration"), Some(value)) => duration = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    match (width, height, frame_rate) {
+        (Some(width), Some(height), Some(frame_rate)) if width > 0 && height > 0 => Ok(VideoInfo {
+            width,
+            height,
+            frame_rate: if frame_rate > 0.0 { frame_rate } else { 30.0 },
+            duration,
+        }),
+        _ => bail!("no video stream"),
+    }
+}
+
+struct Frame {
+    pixels: Vec<u8>,
+    // Seconds from the start of the video
+    time: f32,
+}
+
+// Runs on a thread of its own until the video ends or nobody's receiving anymore. ffmpeg turns
+// whatever the file is into tightly packed RGBA frames at a constant rate, starting at `start`
+// seconds in. Rotation metadata is ignored and the size forced to what ffprobe found, so every
+// frame is exactly the size of the texture.
+fn decode_frames(
+    path: &Path,
+    info: VideoInfo,
+    start: f32,
+    frames: &SyncSender<Result<Frame, String>>,
+) -> Result<(), failure::Error> {
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-v", "error", "-noautorotate", "-ss"])
+        .arg(format!("{:.3}", start))
+        .arg("-i")
+        .arg(path)
+        .args(["-an", "-sn", "-vsync", "cfr", "-r"])
+        .arg(format!("{}", info.frame_rate))
+        .arg("-s")
+        .arg(format!("{}x{}", info.width, info.height))
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = ffmpeg.stdout.take().unwrap();
+
+    let frame_size = info.width as usize * info.height as usize * 4;
+    for index in 0.. {
+        let mut pixels = vec![0; frame_size];
+        match stdout.read_exact(&mut pixels) {
+            Ok(()) => {}
+            // Ran out in the middle of a frame or right at the end of one, either way that's it
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        }
+        let time = start + index as f32 / info.frame_rate;
+        if frames.send(Ok(Frame { pixels, time })).is_err() {
+            ffmpeg.kill()?;
+            break;
+        }
+    }
+
+    ffmpeg.wait()?;
+    Ok(())
+}
+
+// The receiver gets the frames, then an error instead if decoding failed, and disconnects once
+// the decoder is done
+fn start_decoding(path: &Path, info: VideoInfo, start: f32) -> Receiver<Result<Frame, String>> {
+    let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE);
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        if let Err(err) = decode_frames(&path, info, start, &sender) {
+            error!("Failed to decode {}: {}", path.display(), err);
+            let _ = sender.send(Err(err.to_string()));
+        }
+    });
+    receiver
+}
+
+// Minutes and seconds, like 1:05
+fn timestamp(seconds: f32) -> String {
+    let seconds = seconds.max(0.0) as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// File names of the files in `dir` that look like videos, sorted
+pub fn find_videos(dir: &Path) -> Vec<String> {
+    let extensions = ["mp4", "mkv", "webm", "mov", "avi", "gif", "y4m"];
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| match path.extension().and_then(|ext| ext.to_str()) {
+                    Some(extension) => extensions.contains(&extension.to_lowercase().as_str()),
+                    None => false,
+                })
+                .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// A video file playing into a texture. An `ffmpeg` process found on the PATH decodes it on a
+// background thread, and `update` uploads whichever frame playback has got to. Frames get
+// skipped when decoding falls behind, and seeking starts decoding over from the new position.
+// Playback stops when decoding fails or gives nothing at all, instead of starting ffmpeg over
+// and over.
+pub struct Video {
+    path: PathBuf,
+    pub info: VideoInfo,
+    texture: Texture,
+    bind_group: BindGroup,
+    frames: Receiver<Result<Frame, String>>,
+    // Decoded but not due yet
+    next_frame: Option<Frame>,
+    // Playback position in seconds
+    time: f32,
+    // Where the current decode started, and whether it has given any frames
+    start: f32,
+    decoded: bool,
+    // Whether the texture has anything in it yet
+    has_frame: bool,
+    finished: bool,
+    error: Option<String>,
+
+    pub playing: bool,
+    pub looping: bool,
+    pub speed: f32,
+}
+
+impl Video {
+    // `layout` has to be a `bind_group::TEXTURE_LAYOUT`, for `bind_group`. Fails when ffprobe
+    // can't be run or finds no video in the file.
+    pub fn open(
+        device: &Device,
+        layout: &BindGroupLayout,
+        path: &Path,
+    ) -> Result<Self, failure::Error> {
+        let info = probe(path)?;
+        let texture = Texture::empty(
+            device,
+            info.width,
+            info.height,
+            TextureFormat::Rgba8UnormSrgb,
+            1,
+        );
+        let sampler = device.create_sampler(&SamplerSettings::SMOOTH.descriptor());
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&texture.view),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("video"),
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            info,
+            texture,
+            bind_group,
+            frames: start_decoding(path, info, 0.0),
+            next_frame: None,
+            time: 0.0,
+            start: 0.0,
+            decoded: false,
+            has_frame: false,
+            finished: false,
+            error: None,
+            playing: true,
+            looping: true,
+            speed: 1.0,
+        })
+    }
+
+    pub fn has_frame(&self) -> bool {
+        self.has_frame
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.info.width as f32 / self.info.height as f32
+    }
+
+    // For a `bind_group::TEXTURE_LAYOUT` slot
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    // Dropping the old receiver stops its decoder the next time it has a frame ready
+    pub fn seek(&mut self, time: f32) {
+        let time = match self.info.duration {
+            Some(duration) => time.max(0.0).min(duration),
+            None => time.max(0.0),
+        };
+        self.frames = start_decoding(&self.path, self.info, time);
+        self.next_frame = None;
+        self.time = time;
+        self.start = time;
+        self.decoded = false;
+        self.finished = false;
+        self.error = None;
+    }
+
+    // Moves playback on by `dt` seconds of real time
+    pub fn step(&mut self, dt: f32) {
+        if self.playing {
+            self.time += dt * self.speed;
+        }
+    }
+
+    // Uploads the newest frame playback has got to, if it isn't in the texture already
+    pub fn update(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        let mut due = None;
+        loop {
+            if self.next_frame.is_none() {
+                match self.frames.try_recv() {
+                    Ok(Ok(frame)) => {
+                        self.next_frame = Some(frame);
+                        self.decoded = true;
+                    }
+                    Ok(Err(err)) => self.error = Some(err),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.finished = true;
+                        break;
+                    }
+                }
+            }
+            match self.next_frame.take() {
+                Some(frame) if frame.time <= self.time => due = Some(frame),
+                frame => {
+                    self.next_frame = frame;
+                    break;
+                }
+            }
+        }
+
+        if let Some(frame) = due {
+            texture::copy_texels_to_texture(
+                device,
+                encoder,
+                &frame.pixels,
+                self.info.width,
+                self.info.height,
+                &self.texture.texture,
+                0,
+                Origin3d::ZERO,
+            );
+            self.has_frame = true;
+        }
+
+        // Out of frames, and the last one has been shown. Nothing at all from the start of the
+        // video means looping would only get nothing again.
+        if self.finished && self.next_frame.is_none() {
+            if !self.decoded && self.start == 0.0 && self.error.is_none() {
+                self.error = Some("no frames decoded".to_string());
+            }
+            if self.looping && self.error.is_none() {
+                self.seek(0.0);
+            } else {
+                self.playing = false;
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let play = if self.playing { "Pause" } else { "Play" };
+            if ui.button(play).clicked() {
+                if !self.playing && self.finished {
+                    self.seek(0.0);
+                }
+                self.playing = !self.playing;
+            }
+            if ui.button("Restart").clicked() {
+                self.seek(0.0);
+            }
+            ui.checkbox(&mut self.looping, "Loop");
+        });
+
+        match self.info.duration {
+            // Seeking restarts ffmpeg, so only once the slider is let go of
+            Some(duration) => {
+                let mut time = self.time.min(duration);
+                let response = ui.add(
+                    egui::Slider::new(&mut time, 0.0..=duration)
+                        .text(timestamp(duration))
+                        .custom_formatter(|seconds, _| timestamp(seconds as f32)),
+                );
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    self.seek(time);
+                }
+            }
+            None => {
+                ui.label(timestamp(self.time));
+            }
+        }
+
+        ui.add(
+            egui::Slider::new(&mut self.speed, 0.25..=4.0)
+                .logarithmic(true)
+                .text("Speed"),
+        );
+        ui.label(format!(
+            "{}x{} at {:.2} fps",
+            self.info.width, self.info.height, self.info.frame_rate
+        ));
+        if let Some(error) = &self.error {
+            ui.label(format!("Failed to decode: {}", error));
+        }
+    }
+}
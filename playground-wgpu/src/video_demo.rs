@@ -0,0 +1,257 @@
+use crate::assets::Assets;
+use crate::bind_group;
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::demo::{Demo, DemoContext};
+use crate::depth::DepthBuffer;
+use crate::pipeline::{PipelineKey, Shader};
+use crate::primitives::{Cubes, Quad};
+use crate::uniform::{self, ObjectUniforms, Uniforms};
+use crate::video::{self, Video};
+use cgmath::{Matrix4, Vector3};
+use log::error;
+use playground_math::{Block, Camera, Layout, Projection};
+use std::path::PathBuf;
+use std::sync::Arc;
+use wgpu::{
+    BindGroup, BindGroupLayout, Color, CommandEncoder, CullMode, Device, LoadOp,
+    RenderPassColorAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, ShaderStage,
+    StoreOp, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const SHADER_VERT: Shader = Shader {
+    name: "shader.vert",
+    source: include_str!("../shaders/shader.vert"),
+    stage: ShaderStage::VERTEX,
+};
+
+const SHADER_FRAG: Shader = Shader {
+    name: "shader.frag",
+    source: include_str!("../shaders/shader.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Height of the screen, the width follows the video's aspect
+const SCREEN_HEIGHT: f32 = 4.5;
+
+const UNIFORM_KEYS: [&str; 2] = ["video_camera", "video_screen"];
+
+// A video from the resources' `videos` folder playing on a screen, with play, pause, seeking and
+// speed in the debug window. Decoding needs `ffmpeg` and `ffprobe` on the PATH.
+pub struct VideoDemo {
+    camera: Camera,
+    size: PhysicalSize<u32>,
+    depth: DepthBuffer,
+    dir: PathBuf,
+    names: Vec<String>,
+    selected: Option<String>,
+    // What `selected` was when the video got opened, even if opening it failed
+    opened: Option<String>,
+    video: Option<Video>,
+    error: Option<String>,
+    texture_layout: Arc<BindGroupLayout>,
+
+    pipeline: Arc<RenderPipeline>,
+    screen: Quad,
+    // The camera and the screen's model matrix
+    uniform_allocations: [Allocation; 2],
+    uniform_bind_groups: [Arc<BindGroup>; 2],
+}
+
+impl VideoDemo {
+    pub fn new(ctx: &mut DemoContext) -> Self {
+        let device = ctx.device;
+        let camera = Camera {
+            eye: (0.0, 0.0, 6.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: ctx.size.width.max(1) as f32 / ctx.size.height.max(1) as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+        };
+
+        let texture_layout = ctx
+            .bind_groups
+            .layout(device, "video", bind_group::TEXTURE_LAYOUT);
+        let pipeline = ctx.pipelines.get(
+            device,
+            ctx.bind_groups,
+            &PipelineKey {
+                vertex_shader: SHADER_VERT,
+                fragment_shader: Some(SHADER_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::UNIFORM_LAYOUT),
+                    bind_group::layout_key(bind_group::OBJECT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: vec![Quad::vertex_layout()],
+                // Seen from behind, the picture is mirrored
+                cull_mode: CullMode::None,
+                ..Cubes::pipeline_key(ctx.format)
+            },
+        );
+
+        let (camera_allocation, camera_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[0],
+            Uniforms::size(Layout::Std140),
+            bind_group::UNIFORM_LAYOUT,
+        );
+        let (screen_allocation, screen_bind_group) = uniform::allocate(
+            ctx,
+            UNIFORM_KEYS[1],
+            ObjectUniforms::size(Layout::Std140),
+            bind_group::OBJECT_UNIFORM_LAYOUT,
+        );
+
+        let dir = ctx.assets.path("videos");
+        let names = video::find_videos(&dir);
+        Self {
+            camera,
+            size: ctx.size,
+            depth: DepthBuffer::new(device, ctx.size),
+            // Starts playing the first one
+            selected: names.first().cloned(),
+            names,
+            dir,
+            opened: None,
+            video: None,
+            error: None,
+            texture_layout,
+            pipeline,
+            screen: Quad::new(device),
+            uniform_allocations: [camera_allocation, screen_allocation],
+            uniform_bind_groups: [camera_bind_group, screen_bind_group],
+        }
+    }
+}
+
+impl Demo for VideoDemo {
+    fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        self.size = size;
+    }
+
+    fn step(&mut self, _: &Device, _: &mut CommandEncoder, _: &BufferPool, dt: f32) {
+        if let Some(video) = &mut self.video {
+            video.step(dt);
+        }
+    }
+
+    fn update(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        _alpha: f32,
+    ) {
+        self.depth.resize(device, self.size);
+
+        if self.selected != self.opened {
+            self.opened = self.selected.clone();
+            self.video = None;
+            self.error = None;
+            if let Some(name) = &self.selected {
+                let path = self.dir.join(name);
+                match Video::open(device, &self.texture_layout, &path) {
+                    Ok(video) => self.video = Some(video),
+                    Err(err) => {
+                        error!("Failed to open {}: {}", path.display(), err);
+                        self.error = Some(err.to_string());
+                    }
+                }
+            }
+        }
+
+        let aspect = match &mut self.video {
+            Some(video) => {
+                video.update(device, encoder);
+                video.aspect()
+            }
+            None => 16.0 / 9.0,
+        };
+
+        let mut camera = Uniforms::new();
+        camera.update_view_proj(&self.camera);
+        let screen = Matrix4::from_nonuniform_scale(SCREEN_HEIGHT * aspect, SCREEN_HEIGHT, 1.0);
+        let [camera_allocation, screen_allocation] = &self.uniform_allocations;
+        uniform_pool.write(device, encoder, camera_allocation, &camera.std140());
+        uniform_pool.write(
+            device,
+            encoder,
+            screen_allocation,
+            &ObjectUniforms::new(screen, 1.0).std140(),
+        );
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        let selected = &mut self.selected;
+        let names = &self.names;
+        egui::ComboBox::from_label("Video")
+            .selected_text(selected.as_deref().unwrap_or("None"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, None, "None");
+                for name in names {
+                    ui.selectable_value(selected, Some(name.clone()), name.as_str());
+                }
+            });
+        if ui.button("Rescan").clicked() {
+            self.names = video::find_videos(&self.dir);
+        }
+        if self.names.is_empty() {
+            ui.label(format!("No videos in {}", self.dir.display()));
+        }
+        if let Some(error) = &self.error {
+            ui.label(format!("Failed to open: {}", error));
+        }
+
+        if let Some(video) = &mut self.video {
+            ui.separator();
+            video.ui(ui);
+        }
+    }
+
+    fn camera(&mut self) -> Option<&mut Camera> {
+        Some(&mut self.camera)
+    }
+
+    fn render(
+        &self,
+        _: &Assets,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        clear_color: Color,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::Store,
+                clear_color,
+            }],
+            depth_stencil_attachment: Some(self.depth.attachment()),
+        });
+
+        // Nothing to show until the first frame has been decoded
+        let video = match &self.video {
+            Some(video) if video.has_frame() => video,
+            _ => return,
+        };
+        let [camera, screen] = &self.uniform_bind_groups;
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, video.bind_group(), &[]);
+        render_pass.set_bind_group(1, camera, &[]);
+        render_pass.set_bind_group(2, screen, &[]);
+        self.screen.draw(&mut render_pass);
+    }
+
+    fn release(&mut self, ctx: &mut DemoContext) {
+        for (allocation, key) in self.uniform_allocations.iter().zip(&UNIFORM_KEYS) {
+            ctx.uniform_pool.free(*allocation);
+            ctx.bind_groups.invalidate(key);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::bind_group::{self, BindGroupCache};
+use crate::buffer_pool::{Allocation, BufferPool};
+use crate::depth;
+use crate::draw_stats;
+use crate::pipeline::{PipelineCache, PipelineKey, Shader, FULLSCREEN_VERT};
+use crate::render_target;
+use crate::shadow::ShadowMap;
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+use playground_math::{Block, BlockWriter, Camera, Layout};
+use std::sync::Arc;
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupLayout, Binding, BindingResource,
+    BlendDescriptor, BlendFactor, BlendOperation, BufferAddress, Color, ColorStateDescriptor,
+    ColorWrite, CommandEncoder, CompareFunction, CullMode, Device, FilterMode, IndexFormat, LoadOp,
+    PrimitiveTopology, RenderPassColorAttachmentDescriptor,
+    RenderPassDepthStencilAttachmentDescriptor, RenderPassDescriptor, RenderPipeline, Sampler,
+    SamplerDescriptor, ShaderStage, StoreOp, TextureFormat, TextureView,
+};
+use winit::dpi::PhysicalSize;
+
+const VOLUMETRIC_LIGHT_FRAG: Shader = Shader {
+    name: "volumetric_light.frag",
+    source: include_str!("../shaders/volumetric_light.frag"),
+    stage: ShaderStage::FRAGMENT,
+};
+
+// Adds the scattered light onto the scene, leaving its alpha alone
+const ADDITIVE_BLEND: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::One,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+const KEEP_ALPHA: BlendDescriptor = BlendDescriptor {
+    src_factor: BlendFactor::Zero,
+    dst_factor: BlendFactor::One,
+    operation: BlendOperation::Add,
+};
+
+#[derive(Copy, Clone, Debug)]
+struct VolumetricUniforms {
+    inverse_view_proj: Matrix4<f32>,
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    density: f32,
+    anisotropy: f32,
+    max_distance: f32,
+    steps: u32,
+}
+
+impl Block for VolumetricUniforms {
+    fn write(&self, writer: &mut BlockWriter) {
+        writer.member(&self.inverse_view_proj);
+        writer.member(&Vector4::from(self.camera_position));
+        writer.member(&Vector4::from(self.light_direction));
+        writer.member(&Vector4::from(self.light_color));
+        writer.member(&self.density);
+        writer.member(&self.anisotropy);
+        writer.member(&self.max_distance);
+        writer.member(&self.steps);
+    }
+}
+
+impl Default for VolumetricUniforms {
+    fn default() -> Self {
+        Self {
+            inverse_view_proj: Matrix4::identity(),
+            camera_position: [0.0; 4],
+            light_direction: [0.0; 4],
+            light_color: [0.0; 4],
+            density: 0.0,
+            anisotropy: 0.0,
+            max_distance: 0.0,
+            steps: 0,
+        }
+    }
+}
+
+// The scene's depth and the bind group the march reads it through
+struct Targets {
+    depth: TextureView,
+    bind_group: BindGroup,
+    size: PhysicalSize<u32>,
+}
+
+impl Targets {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let depth =
+            render_target::create_target(device, size, depth::DEPTH_FORMAT, "volumetric_depth");
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout,
+            bindings: &[
+                Binding {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth),
+                },
+                Binding {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("volumetric_depth"),
+        });
+
+        Self {
+            depth,
+            bind_group,
+            size,
+        }
+    }
+}
+
+// Light shafts through a participating medium, like dust or fog. The scene gets drawn with
+// `depth_attachment`, and `render` marches from the camera to whatever every pixel hit, adding up
+// the light scattered towards the camera wherever the shadow map says the light gets through.
+pub struct VolumetricLight {
+    targets: Targets,
+    layout: Arc<BindGroupLayout>,
+    sampler: Sampler,
+    pipeline: Arc<RenderPipeline>,
+    uniform_allocation: Allocation,
+    uniform_bind_group: Arc<BindGroup>,
+
+    pub enabled: bool,
+    // How much light gets scattered and absorbed per unit
+    pub density: f32,
+    // From -1 scattering back towards the light to 1 scattering onwards, 0 is the same everywhere
+    pub anisotropy: f32,
+    // Scales the light's color for the shafts only
+    pub intensity: f32,
+    pub steps: u32,
+    // How far rays into the sky go
+    pub max_distance: f32,
+}
+
+impl VolumetricLight {
+    // Adds onto `format` targets
+    pub fn new(
+        device: &Device,
+        uniform_pool: &mut BufferPool,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        format: TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        let layout = bind_groups.layout(device, "volumetric_depth", bind_group::TEXTURE_LAYOUT);
+        // The depth can't be filtered
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            mipmap_filter: FilterMode::Nearest,
+            lod_min_clamp: -100.0,
+            lod_max_clamp: 100.0,
+            compare: CompareFunction::Always,
+        });
+
+        let pipeline = pipelines.get(
+            device,
+            bind_groups,
+            &PipelineKey {
+                vertex_shader: FULLSCREEN_VERT,
+                fragment_shader: Some(VOLUMETRIC_LIGHT_FRAG),
+                bind_group_layouts: vec![
+                    bind_group::layout_key(bind_group::TEXTURE_LAYOUT),
+                    bind_group::layout_key(bind_group::SHADOW_LAYOUT),
+                    bind_group::layout_key(bind_group::FRAGMENT_UNIFORM_LAYOUT),
+                ],
+                vertex_buffers: Vec::new(),
+                index_format: IndexFormat::Uint16,
+                primitive_topology: PrimitiveTopology::TriangleList,
+                cull_mode: CullMode::None,
+                color_states: vec![ColorStateDescriptor {
+                    format,
+                    color_blend: ADDITIVE_BLEND,
+                    alpha_blend: KEEP_ALPHA,
+                    write_mask: ColorWrite::ALL,
+                }],
+                depth_stencil_state: None,
+                sample_count: 1,
+            },
+        );
+
+        let uniform_allocation = uniform_pool.allocate(
+            device,
+            VolumetricUniforms::size(Layout::Std140) as BufferAddress,
+            wgpu::BIND_BUFFER_ALIGNMENT,
+        );
+        let uniform_bind_group = bind_groups.bind_group(
+            device,
+            bind_group::FRAGMENT_UNIFORM_LAYOUT,
+            "volumetric_uniforms",
+            &[Binding {
+                binding: 0,
+                resource: BindingResource::Buffer {
+                    buffer: uniform_pool.buffer(&uniform_allocation),
+                    range: uniform_allocation.offset
+                        ..uniform_allocation.offset + uniform_allocation.size,
+                },
+            }],
+        );
+
+        Self {
+            targets: Targets::new(device, &layout, &sampler, size),
+            layout,
+            sampler,
+            pipeline,
+            uniform_allocation,
+            uniform_bind_group,
+            enabled: true,
+            density: 0.08,
+            anisotropy: 0.6,
+            intensity: 1.0,
+            steps: 48,
+            max_distance: 30.0,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Light shafts");
+        ui.add(egui::Slider::new(&mut self.density, 0.0..=0.5).text("Density"));
+        ui.add(egui::Slider::new(&mut self.anisotropy, -0.9..=0.9).text("Anisotropy"));
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=10.0).text("Intensity"));
+        ui.add(egui::Slider::new(&mut self.steps, 8..=128).text("Steps"));
+        ui.add(egui::Slider::new(&mut self.max_distance, 1.0..=100.0).text("Max distance"));
+    }
+
+    // Has to match the frame, and `camera` the one the scene gets drawn with. `light_direction` is
+    // the way the light travels and `light_color` its color scaled by its intensity.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        uniform_pool: &BufferPool,
+        size: PhysicalSize<u32>,
+        camera: &Camera,
+        light_direction: Vector3<f32>,
+        light_color: [f32; 3],
+    ) {
+        if size != self.targets.size {
+            self.targets = Targets::new(device, &self.layout, &self.sampler, size);
+        }
+
+        let [red, green, blue] = light_color;
+        let uniforms = VolumetricUniforms {
+            inverse_view_proj: camera
+                .build_view_projection_matrix()
+                .invert()
+                .unwrap_or_else(Matrix4::identity),
+            camera_position: [camera.eye.x, camera.eye.y, camera.eye.z, 1.0],
+            light_direction: light_direction.extend(0.0).into(),
+            light_color: [
+                red * self.intensity,
+                green * self.intensity,
+                blue * self.intensity,
+                1.0,
+            ],
+            density: self.density,
+            anisotropy: self.anisotropy,
+            max_distance: self.max_distance,
+            steps: self.steps.max(1),
+        };
+        uniform_pool.write(
+            device,
+            encoder,
+            &self.uniform_allocation,
+            &uniforms.std140(),
+        );
+    }
+
+    // For the scene's pass, which the march stops at
+    pub fn depth_attachment(&self) -> RenderPassDepthStencilAttachmentDescriptor<'_> {
+        RenderPassDepthStencilAttachmentDescriptor {
+            attachment: &self.targets.depth,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+            clear_depth: 1.0,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            clear_stencil: 0,
+        }
+    }
+
+    // Adds the shafts onto `target`, which already has the scene in it. `shadow_map` has to be
+    // the light's given to `prepare`.
+    pub fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        shadow_map: &ShadowMap,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                load_op: LoadOp::Load,
+                store_op: StoreOp::Store,
+                clear_color: Color::TRANSPARENT,
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.push_debug_group("Volumetric light");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.targets.bind_group, &[]);
+        render_pass.set_bind_group(1, shadow_map.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+        draw_stats::record_triangles(3, 1);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+
+    pub fn release(&self, uniform_pool: &mut BufferPool, bind_groups: &mut BindGroupCache) {
+        uniform_pool.free(self.uniform_allocation);
+        bind_groups.invalidate("volumetric_uniforms");
+    }
+}
@@ -0,0 +1,101 @@
+use crate::input::Input;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::ModifiersState;
+use winit::window::{Fullscreen, Window};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+// Switches between windowed and fullscreen modes: "cycle_window_mode" (F11) cycles through all of
+// them, "toggle_fullscreen" (Enter) with Alt held toggles between windowed and borderless. The
+// swap chain follows through the resize events this causes.
+pub struct WindowModes {
+    mode: WindowMode,
+    modifiers: ModifiersState,
+    // Where the window was before leaving windowed mode
+    windowed_size: Option<PhysicalSize<u32>>,
+    windowed_position: Option<PhysicalPosition<i32>>,
+}
+
+impl WindowModes {
+    pub fn new() -> Self {
+        Self {
+            mode: WindowMode::Windowed,
+            modifiers: ModifiersState::default(),
+            windowed_size: None,
+            windowed_position: None,
+        }
+    }
+
+    // From the device events, which keep track of the modifiers even while unfocused
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    // Once a frame, after the input for it came in
+    pub fn update(&mut self, window: &Window, input: &Input) {
+        if input.pressed("cycle_window_mode") {
+            let mode = match self.mode {
+                WindowMode::Windowed => WindowMode::Borderless,
+                WindowMode::Borderless => WindowMode::Exclusive,
+                WindowMode::Exclusive => WindowMode::Windowed,
+            };
+            self.set(window, mode);
+        }
+        if input.pressed("toggle_fullscreen") && self.modifiers.alt() {
+            let mode = match self.mode {
+                WindowMode::Windowed => WindowMode::Borderless,
+                _ => WindowMode::Windowed,
+            };
+            self.set(window, mode);
+        }
+    }
+
+    pub fn set(&mut self, window: &Window, mode: WindowMode) {
+        if mode == self.mode {
+            return;
+        }
+
+        if self.mode == WindowMode::Windowed {
+            self.windowed_size = Some(window.inner_size());
+            self.windowed_position = window.outer_position().ok();
+        }
+
+        match mode {
+            WindowMode::Windowed => {
+                window.set_fullscreen(None);
+                if let Some(size) = self.windowed_size {
+                    window.set_inner_size(size);
+                }
+                if let Some(position) = self.windowed_position {
+                    window.set_outer_position(position);
+                }
+            }
+            WindowMode::Borderless => {
+                window.set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor())))
+            }
+            WindowMode::Exclusive => {
+                // The largest mode of the current monitor, at its highest refresh rate
+                let video_mode = window.current_monitor().video_modes().max_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width * size.height, mode.refresh_rate())
+                });
+
+                match video_mode {
+                    Some(video_mode) => {
+                        window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode)))
+                    }
+                    // Some platforms don't report video modes
+                    None => window
+                        .set_fullscreen(Some(Fullscreen::Borderless(window.current_monitor()))),
+                }
+            }
+        }
+
+        self.mode = mode;
+    }
+}